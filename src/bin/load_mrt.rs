@@ -12,10 +12,11 @@ use rayon::{
     prelude::*,
 };
 use rotonda_store::{
+    bgp::PathAttributesMeta,
     prefix_record::{Meta, Record, RouteStatus},
     rib::{
         config::{
-            Config, MemoryOnlyConfig, PersistHistoryConfig,
+            Config, HybridConfig, MemoryOnlyConfig, PersistHistoryConfig,
             PersistOnlyConfig, PersistStrategy, WriteAheadConfig,
         },
         StarCastRib,
@@ -24,46 +25,13 @@ use rotonda_store::{
 };
 use routecore::{
     bgp::{
-        aspath::HopPath,
-        message::{update_builder::StandardCommunitiesList, PduParseInfo},
-        path_attributes::OwnedPathAttributes,
+        aspath::HopPath, message::update_builder::StandardCommunitiesList,
     },
     mrt::{MrtFile, RibEntryIterator, TableDumpIterator},
 };
 
 use rand::seq::SliceRandom;
 
-#[derive(Clone, Debug)]
-struct PaBytes(Vec<u8>);
-
-impl std::fmt::Display for PaBytes {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.0)
-    }
-}
-
-impl AsRef<[u8]> for PaBytes {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_ref()
-    }
-}
-
-impl From<Vec<u8>> for PaBytes {
-    fn from(value: Vec<u8>) -> Self {
-        Self(value)
-    }
-}
-
-impl Meta for PaBytes {
-    type Orderable<'a> = u32;
-
-    type TBI = u32;
-
-    fn as_orderable(&self, _tbi: Self::TBI) -> Self::Orderable<'_> {
-        todo!()
-    }
-}
-
 #[derive(Copy, Clone, Default)]
 struct UpsertCounters {
     unique_prefixes: usize,
@@ -227,7 +195,7 @@ fn par_load_prefixes(
 
 fn mt_parse_and_insert_table<C: Config + Sync>(
     tables: TableDumpIterator<&[u8]>,
-    store: Option<&StarCastRib<PaBytes, C>>,
+    store: Option<&StarCastRib<PathAttributesMeta, C>>,
     ltime: u64,
 ) -> (UpsertCounters, Vec<Prefix>) {
     let persist_strategy =
@@ -243,7 +211,7 @@ fn mt_parse_and_insert_table<C: Config + Sync>(
                 // cnt += 1;
                 // let (prefix, peer_idx, pa_bytes) = e;
                 let mui = peer_idx.into();
-                let val = PaBytes(pa_bytes);
+                let val = PathAttributesMeta::from(pa_bytes);
                 let mut persisted_routes = 0;
 
                 if let Some(store) = store {
@@ -293,8 +261,11 @@ fn mt_parse_and_insert_table<C: Config + Sync>(
                         }
                         // old prefix, old mui
                         (false, false) => {
-                            if persist_strategy != PersistStrategy::MemoryOnly
-                            {
+                            if !matches!(
+                                persist_strategy,
+                                PersistStrategy::MemoryOnly
+                                    | PersistStrategy::Hybrid
+                            ) {
                                 persisted_prefixes.push(prefix);
                                 persisted_routes = 1;
                             }
@@ -339,7 +310,7 @@ fn mt_parse_and_insert_table<C: Config + Sync>(
 
 fn st_parse_and_insert_table<C: Config>(
     entries: RibEntryIterator<&[u8]>,
-    store: Option<&StarCastRib<PaBytes, C>>,
+    store: Option<&StarCastRib<PathAttributesMeta, C>>,
     ltime: u64,
 ) -> UpsertCounters {
     let mut counters = UpsertCounters::default();
@@ -349,7 +320,7 @@ fn st_parse_and_insert_table<C: Config>(
     for (_, peer_idx, _, prefix, pamap) in entries {
         cnt += 1;
         let mui = peer_idx.into();
-        let val = PaBytes(pamap);
+        let val = PathAttributesMeta::from(pamap);
 
         if let Some(store) = store {
             insert(store, &prefix, mui, ltime, RouteStatus::Active, val)
@@ -370,7 +341,7 @@ fn st_parse_and_insert_table<C: Config>(
 
 fn mt_prime_store<C: Config + Sync>(
     prefixes: &Vec<(Prefix, u16)>,
-    store: &StarCastRib<PaBytes, C>,
+    store: &StarCastRib<PathAttributesMeta, C>,
 ) -> UpsertCounters {
     let t0 = std::time::Instant::now();
 
@@ -383,7 +354,7 @@ fn mt_prime_store<C: Config + Sync>(
                 p.1 as u32,
                 0,
                 RouteStatus::InActive,
-                PaBytes(vec![]),
+                PathAttributesMeta::from(vec![]),
             )
             .map(counter_update(&mut acc))
             .unwrap()
@@ -403,7 +374,7 @@ fn mt_prime_store<C: Config + Sync>(
 
 fn st_prime_store<C: Config>(
     prefixes: &Vec<(Prefix, u16)>,
-    store: &StarCastRib<PaBytes, C>,
+    store: &StarCastRib<PathAttributesMeta, C>,
 ) -> UpsertCounters {
     let mut counters = UpsertCounters::default();
 
@@ -414,7 +385,7 @@ fn st_prime_store<C: Config>(
             p.1 as u32,
             0,
             RouteStatus::InActive,
-            PaBytes(vec![]),
+            PathAttributesMeta::from(vec![]),
         )
         .map(counter_update(&mut counters))
         .unwrap();
@@ -423,7 +394,7 @@ fn st_prime_store<C: Config>(
     counters
 }
 
-type Stores<C> = Vec<StarCastRib<PaBytes, C>>;
+type Stores<C> = Vec<StarCastRib<PathAttributesMeta, C>>;
 
 // Create all the stores necessary, and if at least one is created, create
 // a reference to the first one.
@@ -431,7 +402,7 @@ fn create_stores<'a, C: Config + Sync>(
     stores: &'a mut Stores<C>,
     args: &'a Cli,
     store_config: C,
-) -> Option<&'a StarCastRib<PaBytes, C>> {
+) -> Option<&'a StarCastRib<PathAttributesMeta, C>> {
     match &args {
         a if a.single_store && a.parse_only => {
             eprintln!(
@@ -442,7 +413,7 @@ fn create_stores<'a, C: Config + Sync>(
         }
         a if a.single_store => {
             stores.push(
-                StarCastRib::<PaBytes, C>::new_with_config(
+                StarCastRib::<PathAttributesMeta, C>::new_with_config(
                     store_config.clone(),
                 )
                 .unwrap(),
@@ -461,8 +432,10 @@ fn create_stores<'a, C: Config + Sync>(
         }
         _ => {
             for _ in &args.mrt_files {
-                stores
-                    .push(StarCastRib::<PaBytes, C>::try_default().unwrap());
+                stores.push(
+                    StarCastRib::<PathAttributesMeta, C>::try_default()
+                        .unwrap(),
+                );
             }
             println!("Number of created stores: {}", stores.len());
             println!("store config: {:?}", store_config);
@@ -473,7 +446,7 @@ fn create_stores<'a, C: Config + Sync>(
 }
 
 fn exec_for_store<'a, C: Config + Sync>(
-    mut store: Option<&'a StarCastRib<PaBytes, C>>,
+    mut store: Option<&'a StarCastRib<PathAttributesMeta, C>>,
     inner_stores: &'a Stores<C>,
     args: &'a Cli,
 ) {
@@ -609,10 +582,7 @@ fn exec_for_store<'a, C: Config + Sync>(
                     .unwrap();
                 println!("LEN {} prefix: {}", max_len, pfx);
                 for rec in recs {
-                    let pa = OwnedPathAttributes::from((
-                        PduParseInfo::modern(),
-                        rec.meta.0.to_vec(),
-                    ));
+                    let pa = rec.meta.path_attributes();
                     print!(
                         "({})\tp[{}]",
                         rec.multi_uniq_id,
@@ -631,9 +601,12 @@ fn exec_for_store<'a, C: Config + Sync>(
                     };
                 }
             }
-            values.iter().filter(|v| v.meta.0.is_empty()).for_each(|v| {
-                println!("withdraw for {}, mui {}", pfx, v.multi_uniq_id)
-            })
+            values
+                .iter()
+                .filter(|v| v.meta.as_ref().is_empty())
+                .for_each(|v| {
+                    println!("withdraw for {}, mui {}", pfx, v.multi_uniq_id)
+                })
         }
     }
 }
@@ -688,6 +661,16 @@ fn main() {
                 store_config,
             );
         }
+        Some(a) if a == &"hybrid".to_string() => {
+            let mut store_config = HybridConfig::default();
+            store_config.set_persist_path("/tmp/rotonda/".into());
+            let mut inner_stores: Stores<HybridConfig> = vec![];
+            create_stores::<HybridConfig>(
+                &mut inner_stores,
+                &args,
+                store_config,
+            );
+        }
         None => {
             let mut store_config = PersistHistoryConfig::default();
             store_config.set_persist_path("/tmp/rotonda/".into());
@@ -713,7 +696,7 @@ fn main() {
     //     }
     //     a if a.single_store => {
     //         inner_stores.push(
-    //             MultiThreadedStore::<PaBytes, C>::new_with_config(
+    //             MultiThreadedStore::<PathAttributesMeta, C>::new_with_config(
     //                 store_config.clone(),
     //             )
     //             .unwrap(),
@@ -731,7 +714,7 @@ fn main() {
     //     _ => {
     //         for _ in &args.mrt_files {
     //             inner_stores.push(
-    //                 MultiThreadedStore::<PaBytes>::try_default().unwrap(),
+    //                 MultiThreadedStore::<PathAttributesMeta>::try_default().unwrap(),
     //             );
     //         }
     //         println!("Number of created stores: {}", inner_stores.len());
@@ -24,6 +24,10 @@ impl<V: Value, const ROOT_SIZE: usize, const STRIDES_PER_BUCKET: usize>
         }))
     }
 
+    pub(crate) fn roots(&self) -> &[V] {
+        &self.0
+    }
+
     // There cannot be a root node for a prefix length that has NO slots,
     // STRIDES_PER_BICKET (a instance wide const) should always be bigger
     // than 0.
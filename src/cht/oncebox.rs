@@ -99,6 +99,31 @@ impl<T> OnceBoxSlice<T> {
         }
     }
 
+    // The initialized elements of the slice, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        let slice = if ptr.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(ptr, self.size) }
+        };
+        slice.iter().filter_map(|inner| inner.get())
+    }
+
+    // The number of slots of the slice, or zero if it was never allocated.
+    pub fn allocated_slots(&self) -> usize {
+        if self.ptr.load(Ordering::Relaxed).is_null() {
+            0
+        } else {
+            self.size
+        }
+    }
+
+    // The size in bytes of one slot of the slice.
+    pub fn slot_bytes() -> usize {
+        std::mem::size_of::<OnceBox<T>>()
+    }
+
     // This is a bit tricky: the caller of this method should make sure that
     // the slice has enough elements. For performance reasons we are NOT
     // checking that here.
@@ -143,7 +168,9 @@ impl<T> OnceBoxSlice<T> {
                 // return current.
                 assert!(!current.is_null());
                 let _ = unsafe {
-                    Box::from_raw(slice::from_raw_parts_mut(ptr, self.size))
+                    Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                        ptr, self.size,
+                    ))
                 };
                 current
             }
@@ -158,7 +185,9 @@ impl<T> Drop for OnceBoxSlice<T> {
         let ptr = self.ptr.swap(null_mut(), Ordering::Relaxed);
         if !ptr.is_null() {
             let _ = unsafe {
-                Box::from_raw(slice::from_raw_parts_mut(ptr, self.size))
+                Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                    ptr, self.size,
+                ))
             };
         }
     }
@@ -107,6 +107,10 @@ pub use types::prefix_record;
 /// Error types returned by a RIB
 pub use types::errors;
 
+/// A [Meta](prefix_record::Meta) type for the path attributes of BGP routes
+#[cfg(feature = "bgp")]
+pub use types::bgp;
+
 /// Trait that defines the AFIs 1 (IPv4) and 2 (IPv6).
 pub use types::af::AddressFamily;
 
@@ -0,0 +1,93 @@
+//------------ PersistBatch --------------------------------------------------
+
+// The records to be written to the persist tree in one go, with one sequence
+// number, instead of one write, and one sequence number, per record. The
+// records are kept sorted on their key. A later record for a key replaces
+// an earlier one in the batch, like it would in the tree, so all the keys in
+// a batch are unique, and sharing a sequence number is safe.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+
+use zerocopy::IntoBytes;
+
+use crate::prefix_record::Meta;
+use crate::types::prefix_record::ValueHeader;
+use crate::types::{AddressFamily, PrefixId, Record};
+
+use super::{LongKey, MuiIndexKey, ShortKey};
+
+pub(crate) struct PersistBatch<AF: AddressFamily> {
+    pub(super) records: BTreeMap<Vec<u8>, Vec<u8>>,
+    // The keys in the mui index for the records with a long key.
+    pub(super) index_keys: BTreeSet<Vec<u8>>,
+    // The newest ltime of the records with a long key.
+    pub(super) newest_ltime: u64,
+    _af: PhantomData<AF>,
+}
+
+impl<AF: AddressFamily> Default for PersistBatch<AF> {
+    fn default() -> Self {
+        Self {
+            records: BTreeMap::new(),
+            index_keys: BTreeSet::new(),
+            newest_ltime: 0,
+            _af: PhantomData,
+        }
+    }
+}
+
+impl<AF: AddressFamily> PersistBatch<AF> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub(crate) fn push_w_long_key<M: Meta>(
+        &mut self,
+        prefix: PrefixId<AF>,
+        record: &Record<M>,
+    ) {
+        let key = LongKey::from((
+            prefix,
+            record.multi_uniq_id,
+            record.ltime,
+            record.status,
+        ));
+        self.newest_ltime = self.newest_ltime.max(record.ltime);
+        self.index_keys.insert(MuiIndexKey::for_record_key(&key));
+        self.records
+            .insert(key.as_bytes().to_vec(), record.meta.as_ref().to_vec());
+    }
+
+    pub(crate) fn push_w_short_key<M: Meta>(
+        &mut self,
+        prefix: PrefixId<AF>,
+        record: &Record<M>,
+    ) {
+        let mut value = ValueHeader {
+            ltime: record.ltime,
+            status: record.status,
+        }
+        .as_bytes()
+        .to_vec();
+        value.extend_from_slice(record.meta.as_ref());
+
+        self.records.insert(
+            ShortKey::from((prefix, record.multi_uniq_id))
+                .as_bytes()
+                .to_vec(),
+            value,
+        );
+    }
+
+    // Whether the batch has a record with a short key for the (prefix, mui)
+    // pair.
+    pub(crate) fn has_short_key(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+    ) -> bool {
+        self.records
+            .contains_key(ShortKey::from((prefix, mui)).as_bytes())
+    }
+}
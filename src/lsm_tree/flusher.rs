@@ -0,0 +1,142 @@
+//------------ BackgroundFlusher ---------------------------------------------
+
+// A thread that flushes the active memtables of a set of lsm trees to disk,
+// as soon as a memtable reaches a size threshold, or when a time interval
+// has passed since the last flush. The thread holds handles to the trees,
+// so it doesn't borrow from the RIB that owns the trees. The same thread
+// runs the pruning of historical records, if a retention policy asks for
+// that.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::{error, trace};
+use lsm_tree::{AbstractTree, SequenceNumberCounter};
+
+use crate::rib::config::FlushConfig;
+
+use super::{flush_tree, SnapshotRegistry};
+
+// The maximum time between two checks of the size of the memtables.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// A pruning pass, and the interval it should be run with.
+pub(crate) type PruneTask = (Duration, Box<dyn Fn() + Send>);
+
+// A tree to flush, with its sequence number counter and its snapshots.
+pub(crate) type TreeHandle =
+    (lsm_tree::Tree, SequenceNumberCounter, SnapshotRegistry);
+
+#[derive(Debug)]
+pub(crate) struct BackgroundFlusher {
+    stop_tx: Mutex<Option<Sender<()>>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl BackgroundFlusher {
+    pub(crate) fn start(
+        trees: Vec<TreeHandle>,
+        config: Option<FlushConfig>,
+        prune_task: Option<PruneTask>,
+    ) -> std::io::Result<Self> {
+        // A zero interval would have the thread spin.
+        if config.is_some_and(|c| c.interval.is_zero()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The flush interval cannot be zero",
+            ));
+        }
+        if prune_task.as_ref().is_some_and(|t| t.0.is_zero()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The prune interval cannot be zero",
+            ));
+        }
+
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let poll_interval = config
+            .iter()
+            .map(|c| c.interval)
+            .chain(prune_task.iter().map(|t| t.0))
+            .fold(POLL_INTERVAL, Duration::min);
+
+        let handle = std::thread::Builder::new()
+            .name("rotonda-store-flusher".to_string())
+            .spawn(move || {
+                let mut last_flush = Instant::now();
+                let mut last_prune = Instant::now();
+
+                // Both a stop message and a dropped sender end the thread.
+                while let Err(RecvTimeoutError::Timeout) =
+                    stop_rx.recv_timeout(poll_interval)
+                {
+                    if let Some(config) = config {
+                        let interval_passed =
+                            last_flush.elapsed() >= config.interval;
+
+                        for (tree, seqno, snapshots) in &trees {
+                            let size = tree.active_memtable_size();
+                            if size >= config.memtable_size
+                                || (interval_passed && size > 0)
+                            {
+                                trace!("background flush of {} bytes", size);
+                                if let Err(err) =
+                                    flush_tree(tree, seqno, snapshots)
+                                {
+                                    error!(
+                                        "background flush failed: {}",
+                                        err
+                                    );
+                                }
+                            }
+                        }
+
+                        if interval_passed {
+                            last_flush = Instant::now();
+                        }
+                    }
+
+                    if let Some((interval, prune)) = &prune_task {
+                        if last_prune.elapsed() >= *interval {
+                            prune();
+                            last_prune = Instant::now();
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            stop_tx: Mutex::new(Some(stop_tx)),
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    // Stop the thread, and wait for it to finish a flush, or a pruning
+    // pass, that may be in progress. Stopping an already stopped flusher
+    // does nothing.
+    pub(crate) fn stop(&self) {
+        if let Ok(mut stop_tx) = self.stop_tx.lock() {
+            if let Some(stop_tx) = stop_tx.take() {
+                let _ = stop_tx.send(());
+            }
+        }
+
+        let handle = match self.handle.lock() {
+            Ok(mut handle) => handle.take(),
+            Err(handle) => handle.into_inner().take(),
+        };
+        if let Some(handle) = handle {
+            if handle.join().is_err() {
+                error!("background flusher panicked");
+            }
+        }
+    }
+}
+
+impl Drop for BackgroundFlusher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
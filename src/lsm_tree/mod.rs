@@ -1,13 +1,16 @@
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use inetnum::addr::Prefix;
 use log::trace;
-use lsm_tree::{AbstractTree, KvPair};
+use lsm_tree::{AbstractTree, KvPair, SequenceNumberCounter};
 use roaring::RoaringBitmap;
 use zerocopy::{
-    FromBytes, Immutable, IntoBytes, KnownLayout, NativeEndian, TryFromBytes,
-    Unaligned, U32, U64,
+    BigEndian, FromBytes, Immutable, IntoBytes, KnownLayout, NativeEndian,
+    TryFromBytes, Unaligned, U32, U64,
 };
 
 use crate::errors::{FatalError, FatalResult, PrefixStoreError};
@@ -17,6 +20,16 @@ use crate::types::prefix_record::{ValueHeader, ZeroCopyRecord};
 use crate::types::{AddressFamily, Record};
 use crate::types::{PrefixId, RouteStatus};
 
+mod batch;
+mod flusher;
+mod retention;
+mod snapshots;
+
+pub(crate) use batch::PersistBatch;
+pub(crate) use flusher::{BackgroundFlusher, PruneTask, TreeHandle};
+pub(crate) use retention::HistoryPruner;
+pub(crate) use snapshots::SnapshotRegistry;
+
 //------------ Key -----------------------------------------------------------
 
 // The type of key used to create entries in the LsmTree. Can be short or
@@ -34,6 +47,14 @@ pub(crate) trait Key<AF: AddressFamily, const KEY_SIZE: usize>:
         LongKey::try_ref_from_bytes(bytes.as_bytes()).map_err(|_| FatalError)
     }
 
+    // Try to extract a header from a complete record, i.e. the key and the
+    // value concatenated. Short keys are followed by a ValueHeader in the
+    // value, so a record always starts with something that has the layout
+    // of a LongKey.
+    fn record_header(rec: &[u8]) -> Result<&LongKey<AF>, FatalError> {
+        rec.get(..KEY_SIZE).ok_or(FatalError).and_then(Self::header)
+    }
+
     // Try to extract a header for writing. If this somehow fails, we most
     //probably cannot write to it anymore. This is fatal. The application
     //should exit, data integrity (on disk) should be verified.
@@ -102,6 +123,38 @@ impl<AF: AddressFamily> From<(PrefixId<AF>, u32, u64, RouteStatus)>
     }
 }
 
+//------------ MuiIndexKey ---------------------------------------------------
+
+// The key for the secondary index on (mui, ltime) of the records with a
+// long key. The complete key in the index is this key, followed by the long
+// key of the record in the primary tree. The fields are big-endian, so that
+// the index is sorted on mui, and then on ltime. The index entries have no
+// values.
+
+#[derive(Debug, KnownLayout, Immutable, FromBytes, Unaligned, IntoBytes)]
+#[repr(C)]
+struct MuiIndexKey {
+    mui: U32<BigEndian>,
+    ltime: U64<BigEndian>,
+}
+
+impl MuiIndexKey {
+    const SIZE: usize = std::mem::size_of::<Self>();
+
+    // The complete key in the index for the long key of a record.
+    fn for_record_key<AF: AddressFamily>(key: &LongKey<AF>) -> Vec<u8> {
+        let mut index_key = MuiIndexKey {
+            mui: u32::from(key.mui).into(),
+            ltime: u64::from(key.ltime).into(),
+        }
+        .as_bytes()
+        .to_vec();
+        index_key.extend_from_slice(key.as_bytes());
+
+        index_key
+    }
+}
+
 //------------ LsmTree -------------------------------------------------------
 
 // The log-structured merge tree that backs the persistent store (on disk).
@@ -119,6 +172,21 @@ pub struct LsmTree<
     const KEY_SIZE: usize,
 > {
     tree: lsm_tree::Tree,
+    // The secondary index on (mui, ltime) for the records with a long key,
+    // see MuiIndexKey. It lives in a separate tree in a subdirectory of the
+    // primary tree. Only the strategies that keep historical records have
+    // one.
+    mui_index: Option<lsm_tree::Tree>,
+    // Every write to the trees gets its own sequence number, so that an
+    // overwrite, or a removal, of a key always shadows the earlier versions
+    // of that key, also if those were already flushed to disk. The records
+    // in a PersistBatch share one, their keys are unique within the batch.
+    seqno: SequenceNumberCounter,
+    // The snapshots of the trees that are being read.
+    snapshots: SnapshotRegistry,
+    // The newest ltime of the records with a long key, the age of records
+    // for a retention policy is relative to this.
+    newest_ltime: Arc<AtomicU64>,
     counters: Counters,
     _af: PhantomData<AF>,
     _k: PhantomData<K>,
@@ -127,30 +195,184 @@ pub struct LsmTree<
 impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
     LsmTree<AF, K, KEY_SIZE>
 {
-    pub fn new(persist_path: &Path) -> FatalResult<LsmTree<AF, K, KEY_SIZE>> {
-        if let Ok(tree) = lsm_tree::Config::new(persist_path).open() {
-            Ok(LsmTree::<AF, K, KEY_SIZE> {
-                tree,
-                counters: Counters::default(),
-                _af: PhantomData,
-                _k: PhantomData,
-            })
+    pub fn new(
+        persist_path: &Path,
+        with_mui_index: bool,
+    ) -> FatalResult<LsmTree<AF, K, KEY_SIZE>> {
+        let tree = lsm_tree::Config::new(persist_path)
+            .open()
+            .map_err(|_| FatalError)?;
+        let mui_index = if with_mui_index {
+            Some(
+                lsm_tree::Config::new(persist_path.join("mui_index"))
+                    .open()
+                    .map_err(|_| FatalError)?,
+            )
         } else {
-            Err(FatalError)
+            None
+        };
+
+        let seqno = SequenceNumberCounter::new(
+            tree.get_highest_seqno()
+                .max(mui_index.as_ref().and_then(|i| i.get_highest_seqno()))
+                .map_or(0, |s| s + 1),
+        );
+        let lsm_tree = LsmTree::<AF, K, KEY_SIZE> {
+            tree,
+            mui_index,
+            seqno,
+            snapshots: SnapshotRegistry::default(),
+            newest_ltime: Arc::new(AtomicU64::new(0)),
+            counters: Counters::default(),
+            _af: PhantomData,
+            _k: PhantomData,
+        };
+
+        let Some(mui_index) = &lsm_tree.mui_index else {
+            return Ok(lsm_tree);
+        };
+
+        // A store that was created without the index gets it here.
+        if mui_index.is_empty(None, None).map_err(|_| FatalError)?
+            && !lsm_tree.tree.is_empty(None, None).map_err(|_| FatalError)?
+        {
+            lsm_tree.rebuild_mui_index(mui_index)?;
+        }
+
+        for kv in mui_index.iter(None, None) {
+            let (k, _) = kv.map_err(|_| FatalError)?;
+            let (index_key, _) =
+                MuiIndexKey::ref_from_prefix(&k).map_err(|_| FatalError)?;
+            lsm_tree
+                .newest_ltime
+                .fetch_max(index_key.ltime.into(), Ordering::Relaxed);
         }
+
+        Ok(lsm_tree)
     }
 
     fn insert(&self, key: &[u8], value: &[u8]) -> (u32, u32) {
-        self.tree.insert::<&[u8], &[u8]>(key, value, 0)
+        self.tree
+            .insert::<&[u8], &[u8]>(key, value, self.seqno.next())
     }
 
-    // This is not production code yet. To be re-evaluated if it does become
-    // production code.
-    #[allow(clippy::indexing_slicing)]
-    pub fn _remove(&self, key: &[u8]) {
-        self.tree.remove_weak(key, 0);
-        // the first byte of the prefix holds the length of the prefix.
-        self.counters._dec_prefixes_count(key[0]);
+    // Insert a record with a long key, and its entry in the mui index.
+    fn insert_w_long_key(&self, key: &LongKey<AF>, value: &[u8]) {
+        self.newest_ltime
+            .fetch_max(key.ltime.into(), Ordering::AcqRel);
+        if let Some(mui_index) = &self.mui_index {
+            mui_index.insert::<Vec<u8>, &[u8]>(
+                MuiIndexKey::for_record_key(key),
+                &[],
+                self.seqno.next(),
+            );
+        }
+        self.insert(key.as_bytes(), value);
+    }
+
+    fn rebuild_mui_index(
+        &self,
+        mui_index: &lsm_tree::Tree,
+    ) -> FatalResult<()> {
+        for kv in self.tree.iter(None, None) {
+            let (k, _) = kv.map_err(|_| FatalError)?;
+            // Short keys are not indexed.
+            if let Ok(key) = LongKey::<AF>::try_ref_from_bytes(&k) {
+                mui_index.insert::<Vec<u8>, &[u8]>(
+                    MuiIndexKey::for_record_key(key),
+                    &[],
+                    self.seqno.next(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // Remove all the entries that have a key starting with `key_prefix`. A
+    // regular tombstone is used, a weak tombstone could resurrect older
+    // versions of a key that was overwritten. Returns the number of removed
+    // entries.
+    fn remove_with_key_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> FatalResult<usize> {
+        let keys = self
+            .tree
+            .prefix(key_prefix, None, None)
+            .map(|kv| kv.map(|(k, _)| k).map_err(|_| FatalError))
+            .collect::<FatalResult<Vec<_>>>()?;
+
+        for key in &keys {
+            if let (Some(mui_index), Ok(long_key)) =
+                (&self.mui_index, LongKey::<AF>::try_ref_from_bytes(key))
+            {
+                mui_index.remove(
+                    MuiIndexKey::for_record_key(long_key),
+                    self.seqno.next(),
+                );
+            }
+            self.tree.remove(key.as_ref(), self.seqno.next());
+        }
+
+        Ok(keys.len())
+    }
+
+    // Remove all the records, current and historical, for a (prefix, mui)
+    // pair.
+    pub(crate) fn remove_records_for_prefix_mui(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+    ) -> FatalResult<usize> {
+        self.remove_with_key_prefix(ShortKey::from((prefix, mui)).as_bytes())
+    }
+
+    // Remove all the records, for all muis, for a prefix.
+    pub(crate) fn remove_records_for_prefix(
+        &self,
+        prefix: PrefixId<AF>,
+    ) -> FatalResult<usize> {
+        self.remove_with_key_prefix(prefix.as_bytes())
+    }
+
+    // Whether there are any records for this prefix, or for this (prefix,
+    // mui) pair if a mui is specified.
+    pub(crate) fn has_records(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: Option<u32>,
+    ) -> bool {
+        if let Some(mui) = mui {
+            self.tree
+                .prefix(ShortKey::from((prefix, mui)).as_bytes(), None, None)
+                .next()
+                .is_some()
+        } else {
+            self.tree
+                .prefix(prefix.as_bytes(), None, None)
+                .next()
+                .is_some()
+        }
+    }
+
+    // All the unique muis that have records for this prefix.
+    pub(crate) fn muis_for_prefix(
+        &self,
+        prefix: PrefixId<AF>,
+    ) -> FatalResult<Vec<u32>> {
+        let mut muis = vec![];
+        for kv in self.tree.prefix(prefix.as_bytes(), None, None) {
+            let (k, v) = kv.map_err(|_| FatalError)?;
+            let rec = [k, v].concat();
+            let mui = u32::from(K::record_header(&rec)?.mui);
+            // Keys are sorted on mui after the prefix.
+            if muis.last() != Some(&mui) {
+                muis.push(mui);
+            }
+        }
+
+        Ok(muis)
     }
 
     // Based on the properties of the lsm_tree we can assume that the key and
@@ -353,9 +575,9 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
         for rkv in self.tree.prefix(key_b.as_bytes(), None, None) {
             if let Ok(kvs) = rkv {
                 let kv = [kvs.0, kvs.1].concat();
-                if let Ok(h) = K::header(&kv) {
+                if let Ok(h) = K::record_header(&kv) {
                     if let Ok(r) = &res {
-                        if let Ok(h_res) = K::header(r) {
+                        if let Ok(h_res) = K::record_header(r) {
                             if h_res.ltime < h.ltime {
                                 res = Ok(kv);
                             }
@@ -393,26 +615,159 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
             .collect::<Vec<_>>()
     }
 
-    pub fn flush_to_disk(&self) -> Result<(), lsm_tree::Error> {
-        let segment = self.tree.flush_active_memtable(0);
-
-        if let Ok(Some(segment)) = segment {
-            self.tree.register_segments(&[segment])?;
-            self.tree.compact(
-                std::sync::Arc::new(lsm_tree::compaction::Leveled::default()),
-                0,
-            )?;
+    // All the records with a long key for a mui, with an ltime in
+    // `ltime_range`, ordered by ltime, through the mui index. The records
+    // are complete, i.e. the key and the value concatenated.
+    pub(crate) fn records_for_mui(
+        &self,
+        mui: u32,
+        ltime_range: impl RangeBounds<u64>,
+    ) -> impl Iterator<Item = FatalResult<Vec<u8>>> + '_ {
+        let start = match ltime_range.start_bound() {
+            Bound::Included(t) => Some(*t),
+            Bound::Excluded(t) => t.checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let end = match ltime_range.end_bound() {
+            Bound::Included(t) => Some(*t),
+            Bound::Excluded(t) => t.checked_sub(1),
+            Bound::Unbounded => Some(u64::MAX),
+        };
+
+        let index_iter = match (&self.mui_index, start, end) {
+            (Some(mui_index), Some(start), Some(end)) if start <= end => {
+                let from = MuiIndexKey {
+                    mui: mui.into(),
+                    ltime: start.into(),
+                }
+                .as_bytes()
+                .to_vec();
+                // Every complete key in the index for `end` sorts before
+                // this one.
+                let mut to = MuiIndexKey {
+                    mui: mui.into(),
+                    ltime: end.into(),
+                }
+                .as_bytes()
+                .to_vec();
+                to.extend_from_slice(&[u8::MAX; KEY_SIZE]);
+                Some(mui_index.range(from..=to, None, None))
+            }
+            _ => None,
         };
 
+        index_iter.into_iter().flatten().filter_map(|kv| {
+            let key = match kv {
+                Ok((k, _)) => k,
+                Err(_) => return Some(Err(FatalError)),
+            };
+            let Some(record_key) = key.get(MuiIndexKey::SIZE..) else {
+                return Some(Err(FatalError));
+            };
+            match self.tree.get(record_key, None) {
+                Ok(Some(value)) => Some(Ok([record_key, &value].concat())),
+                Ok(None) => None,
+                Err(_) => Some(Err(FatalError)),
+            }
+        })
+    }
+
+    // The metadata of the most recent record with metadata for a (prefix,
+    // mui) pair, that is not newer than `ltime`. Status changes are persisted
+    // as records without metadata, this is the metadata they apply to.
+    pub(crate) fn meta_before(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+        ltime: u64,
+    ) -> FatalResult<Option<Vec<u8>>> {
+        let mut res: Option<(u64, Vec<u8>)> = None;
+        for kv in self.tree.prefix(
+            ShortKey::from((prefix, mui)).as_bytes(),
+            None,
+            None,
+        ) {
+            let (k, v) = kv.map_err(|_| FatalError)?;
+            let rec = [k, v].concat();
+            let rec_ltime = u64::from(K::record_header(&rec)?.ltime);
+            let meta = rec.get(KEY_SIZE..).unwrap_or_default();
+            if !meta.is_empty()
+                && rec_ltime <= ltime
+                && res.as_ref().is_none_or(|(t, _)| *t <= rec_ltime)
+            {
+                res = Some((rec_ltime, meta.to_vec()));
+            }
+        }
+
+        Ok(res.map(|(_, meta)| meta))
+    }
+
+    // Write a copy of the trees as they are at the moment of calling, to a
+    // new store in `dest`, and return that store. Writes to this store can
+    // continue while the copy runs, these are not part of the copy.
+    pub(crate) fn snapshot_to(&self, dest: &Path) -> FatalResult<Self> {
+        let (seqno, _snapshot) = self.snapshots.open(&self.seqno);
+        let snapshot =
+            LsmTree::<AF, K, KEY_SIZE>::new(dest, self.mui_index.is_some())?;
+
+        let index = self.mui_index.iter().zip(&snapshot.mui_index);
+        for (src, dst) in
+            [(&self.tree, &snapshot.tree)].into_iter().chain(index)
+        {
+            for kv in src.snapshot(seqno).iter() {
+                let (k, v) = kv.map_err(|_| FatalError)?;
+                dst.insert(k, v, snapshot.seqno.next());
+            }
+        }
+
+        snapshot.flush_to_disk().map_err(|_| FatalError)?;
+
+        Ok(snapshot)
+    }
+
+    // A pruner for the historical records in this tree, that can be moved
+    // to another thread. See HistoryPruner.
+    pub(crate) fn history_pruner(
+        &self,
+        keep_latest: bool,
+    ) -> HistoryPruner<AF> {
+        HistoryPruner {
+            tree: self.tree.clone(),
+            mui_index: self.mui_index.clone(),
+            seqno: self.seqno.clone(),
+            snapshots: self.snapshots.clone(),
+            newest_ltime: Arc::clone(&self.newest_ltime),
+            keep_latest,
+            _af: PhantomData,
+        }
+    }
+
+    pub fn flush_to_disk(&self) -> Result<(), lsm_tree::Error> {
+        flush_tree(&self.tree, &self.seqno, &self.snapshots)?;
+        if let Some(mui_index) = &self.mui_index {
+            flush_tree(mui_index, &self.seqno, &self.snapshots)?;
+        }
         Ok(())
     }
 
+    // Handles to the underlying trees, with their sequence number counter
+    // and their snapshots, for the background flusher.
+    pub(crate) fn tree_handles(&self) -> Vec<TreeHandle> {
+        std::iter::once(&self.tree)
+            .chain(&self.mui_index)
+            .map(|tree| {
+                (tree.clone(), self.seqno.clone(), self.snapshots.clone())
+            })
+            .collect()
+    }
+
     pub fn approximate_len(&self) -> usize {
         self.tree.approximate_len()
     }
 
     pub fn disk_space(&self) -> u64 {
         self.tree.disk_space()
+            + self.mui_index.as_ref().map_or(0, |i| i.disk_space())
     }
 
     pub fn prefixes_count(&self) -> usize {
@@ -440,41 +795,36 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
         prefix: PrefixId<AF>,
         record: &Record<M>,
     ) {
-        self.insert(
-            LongKey::from((
+        self.insert_w_long_key(
+            &LongKey::from((
                 prefix,
                 record.multi_uniq_id,
                 record.ltime,
                 record.status,
-            ))
-            .as_bytes(),
+            )),
             record.meta.as_ref(),
         );
     }
 
-    pub(crate) fn persist_record_w_short_key<M: Meta>(
-        &self,
-        prefix: PrefixId<AF>,
-        record: &Record<M>,
-    ) {
-        trace!("Record to persist {}", record);
-        let mut value = ValueHeader {
-            ltime: record.ltime,
-            status: record.status,
+    // Write all the records in the batch, and their entries in the mui
+    // index, with one sequence number.
+    pub(crate) fn write_batch(&self, batch: PersistBatch<AF>) {
+        if batch.is_empty() {
+            return;
+        }
+        trace!("write batch of {} records", batch.records.len());
+
+        let seqno = self.seqno.next();
+        self.newest_ltime
+            .fetch_max(batch.newest_ltime, Ordering::AcqRel);
+        if let Some(mui_index) = &self.mui_index {
+            for index_key in batch.index_keys {
+                mui_index.insert::<Vec<u8>, &[u8]>(index_key, &[], seqno);
+            }
+        }
+        for (key, value) in batch.records {
+            self.tree.insert(key, value, seqno);
         }
-        .as_bytes()
-        .to_vec();
-
-        trace!("header in bytes {:?}", value);
-
-        value.extend_from_slice(record.meta.as_ref());
-
-        trace!("value complete {:?}", value);
-
-        self.insert(
-            ShortKey::from((prefix, record.multi_uniq_id)).as_bytes(),
-            &value,
-        );
     }
 
     pub(crate) fn rewrite_header_for_record(
@@ -488,12 +838,10 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
         let key = ShortKey::from((record.prefix, record.multi_uniq_id));
         trace!("insert key {:?}", key);
 
-        header
-            .as_bytes()
-            .to_vec()
-            .extend_from_slice(record.meta.as_ref());
+        let mut value = header.as_bytes().to_vec();
+        value.extend_from_slice(record.meta.as_ref());
 
-        self.insert(key.as_bytes(), header.as_bytes());
+        self.insert(key.as_bytes(), &value);
 
         Ok(())
     }
@@ -503,10 +851,10 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
         prefix: PrefixId<AF>,
         mui: u32,
         ltime: u64,
+        status: RouteStatus,
     ) {
-        self.insert(
-            LongKey::from((prefix, mui, ltime, RouteStatus::Withdrawn))
-                .as_bytes(),
+        self.insert_w_long_key(
+            &LongKey::from((prefix, mui, ltime, status)),
             &[],
         );
     }
@@ -521,6 +869,20 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize>
             _k: PhantomData,
         }
     }
+
+    // Iterate over the most recent record for every (prefix, mui) pair in
+    // the tree. Used to rebuild the in-memory structures of a RIB from an
+    // existing persisted store.
+    pub(crate) fn most_recent_records_iter(
+        &self,
+    ) -> impl Iterator<Item = FatalResult<Vec<u8>>> + '_ {
+        MostRecentRecordIter::<AF, K, KEY_SIZE> {
+            tree_iter: self.tree.iter(None, None),
+            next_rec: None,
+            _af: PhantomData,
+            _k: PhantomData,
+        }
+    }
 }
 
 impl<
@@ -535,6 +897,31 @@ impl<
     }
 }
 
+// Flush the active memtable of the tree to a new segment on disk, and
+// compact the tree. The flush registers the new segment with the tree.
+//
+// The versions of a key that are shadowed by a newer version, or by a
+// tombstone, are dropped by the flush and the compaction, if they are older
+// than the current sequence number, and no snapshot of the tree is open, see
+// SnapshotRegistry. With a threshold of zero nothing is ever dropped, except
+// tombstones in the last level, which would bring the removed versions back.
+fn flush_tree(
+    tree: &lsm_tree::Tree,
+    seqno: &SequenceNumberCounter,
+    snapshots: &SnapshotRegistry,
+) -> Result<(), lsm_tree::Error> {
+    let (gc_seqno, _snapshots) = snapshots.gc_threshold(seqno);
+
+    if let Some(_segment) = tree.flush_active_memtable(gc_seqno)? {
+        tree.compact(
+            std::sync::Arc::new(lsm_tree::compaction::Leveled::default()),
+            gc_seqno,
+        )?;
+    };
+
+    Ok(())
+}
+
 // Iterator for all items in a lsm tree partition. The iterator used for
 // this will scann through the entire tree, and there's no way to start at a
 // specified offset.
@@ -583,8 +970,9 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize> Iterator
         };
 
         if let Some(mut r_rec) = rec {
-            let outer_pfx = if let Some(Ok(Ok(rr))) =
-                r_rec.first().map(|v| v.as_ref().map(|h| K::header(h)))
+            let outer_pfx = if let Some(Ok(Ok(rr))) = r_rec
+                .first()
+                .map(|v| v.as_ref().map(|h| K::record_header(h)))
             {
                 rr.prefix
             } else {
@@ -592,13 +980,13 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize> Iterator
             };
 
             for (k, v) in self.tree_iter.by_ref().flatten() {
-                let header = K::header(&k);
+                let rec = [k, v].concat();
 
-                if let Ok(h) = header {
+                if let Ok(h) = K::record_header(&rec) {
                     if h.prefix == outer_pfx {
-                        r_rec.push(Ok([k, v].concat()));
+                        r_rec.push(Ok(rec));
                     } else {
-                        self.cur_rec = Some(vec![Ok([k, v].concat())]);
+                        self.cur_rec = Some(vec![Ok(rec)]);
                         break;
                     }
                 } else {
@@ -613,6 +1001,90 @@ impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize> Iterator
     }
 }
 
+// Iterator that returns the record with the highest ltime for each (prefix,
+// mui) pair in the tree, as concatenated key and value bytes. All keys start
+// with the prefix, followed by the mui, so all the records for a (prefix,
+// mui) pair are adjacent in the tree, regardless of the type of key used.
+// A status change is persisted as a record without metadata, if that is the
+// most recent record it gets the metadata of the newest record before it
+// that has metadata.
+pub(crate) struct MostRecentRecordIter<
+    AF: AddressFamily,
+    K: Key<AF, KEY_SIZE>,
+    const KEY_SIZE: usize,
+> {
+    next_rec: Option<Vec<u8>>,
+    tree_iter:
+        Box<dyn DoubleEndedIterator<Item = Result<KvPair, lsm_tree::Error>>>,
+    _af: PhantomData<AF>,
+    _k: PhantomData<K>,
+}
+
+impl<AF: AddressFamily, K: Key<AF, KEY_SIZE>, const KEY_SIZE: usize> Iterator
+    for MostRecentRecordIter<AF, K, KEY_SIZE>
+{
+    type Item = FatalResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut most_recent = match self.next_rec.take() {
+            Some(rec) => rec,
+            None => match self.tree_iter.next()? {
+                Ok((k, v)) => [k, v].concat(),
+                Err(_) => return Some(Err(FatalError)),
+            },
+        };
+
+        // The ltime and the metadata of the newest record with metadata.
+        let mut meta: Option<(u64, Vec<u8>)> = None;
+        let mut keep_meta = |rec: &[u8], ltime: u64| {
+            let rec_meta = rec.get(KEY_SIZE..).unwrap_or_default();
+            if !rec_meta.is_empty()
+                && meta.as_ref().is_none_or(|(t, _)| *t <= ltime)
+            {
+                meta = Some((ltime, rec_meta.to_vec()));
+            }
+        };
+        match K::record_header(&most_recent) {
+            Ok(cur) => keep_meta(&most_recent, cur.ltime.into()),
+            Err(_) => return Some(Err(FatalError)),
+        }
+
+        for kv in self.tree_iter.by_ref() {
+            let rec = if let Ok((k, v)) = kv {
+                [k, v].concat()
+            } else {
+                return Some(Err(FatalError));
+            };
+
+            let (cur, new) = match (
+                K::record_header(&most_recent),
+                K::record_header(&rec),
+            ) {
+                (Ok(cur), Ok(new)) => (cur, new),
+                _ => return Some(Err(FatalError)),
+            };
+
+            if cur.prefix != new.prefix || cur.mui != new.mui {
+                self.next_rec = Some(rec);
+                break;
+            }
+
+            keep_meta(&rec, new.ltime.into());
+            if u64::from(new.ltime) > u64::from(cur.ltime) {
+                most_recent = rec;
+            }
+        }
+
+        if most_recent.len() == KEY_SIZE {
+            if let Some((_, meta)) = meta {
+                most_recent.extend_from_slice(&meta);
+            }
+        }
+
+        Some(Ok(most_recent))
+    }
+}
+
 // pub(crate) struct MoreSpecificPrefixIter<
 //     'a,
 //     AF: AddressFamily + 'a,
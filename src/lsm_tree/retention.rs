@@ -0,0 +1,131 @@
+//------------ HistoryPruner -------------------------------------------------
+
+// Removes the persisted records with a long key that have expired according
+// to a retention policy, together with their entries in the mui index. The
+// pruner holds handles to the trees, so that it can be moved to the
+// background thread, without borrowing from the RIB that owns the trees.
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use log::trace;
+use lsm_tree::{AbstractTree, SequenceNumberCounter};
+use zerocopy::{IntoBytes, TryFromBytes};
+
+use crate::errors::{FatalError, FatalResult};
+use crate::rib::config::RetentionPolicy;
+use crate::stats::PruneReport;
+use crate::AddressFamily;
+
+use super::{LongKey, MuiIndexKey, SnapshotRegistry};
+
+pub(crate) struct HistoryPruner<AF: AddressFamily> {
+    pub(super) tree: lsm_tree::Tree,
+    pub(super) mui_index: Option<lsm_tree::Tree>,
+    pub(super) seqno: SequenceNumberCounter,
+    pub(super) snapshots: SnapshotRegistry,
+    // The newest ltime in the tree, the age of records is relative to this.
+    pub(super) newest_ltime: Arc<AtomicU64>,
+    // Whether the most recent record for a (prefix, mui) pair is the
+    // current record, that may never be removed. This is the case for the
+    // WriteAhead strategy.
+    pub(super) keep_latest: bool,
+    pub(super) _af: PhantomData<AF>,
+}
+
+// A persisted version of a record, as far as pruning is concerned.
+struct Version<AF: AddressFamily> {
+    key: LongKey<AF>,
+    // The size of the key and the value in bytes.
+    size: usize,
+    // Status changes are persisted as records without metadata.
+    has_meta: bool,
+}
+
+impl<AF: AddressFamily> HistoryPruner<AF> {
+    pub(crate) fn prune(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> FatalResult<PruneReport> {
+        let newest_ltime = self.newest_ltime.load(Ordering::Acquire);
+        let mut expired = vec![];
+        let mut versions: Vec<Version<AF>> = vec![];
+
+        // The keys are sorted on (prefix, mui) first, so all the versions
+        // for a pair are consecutive. Writes that come in while pruning are
+        // not part of this snapshot, and are left alone.
+        let (seqno, _snapshot) = self.snapshots.open(&self.seqno);
+        for kv in self.tree.snapshot(seqno).iter() {
+            let (k, v) = kv.map_err(|_| FatalError)?;
+            // Short keys do not have versions.
+            let Ok(key) = LongKey::<AF>::try_ref_from_bytes(&k) else {
+                continue;
+            };
+            if versions.first().is_some_and(|f| {
+                f.key.prefix != key.prefix || f.key.mui != key.mui
+            }) {
+                expired.extend(self.expired_versions(
+                    std::mem::take(&mut versions),
+                    policy,
+                    newest_ltime,
+                ));
+            }
+            versions.push(Version {
+                key: *key,
+                size: k.len() + v.len(),
+                has_meta: !v.is_empty(),
+            });
+        }
+        expired.extend(self.expired_versions(versions, policy, newest_ltime));
+
+        let mut report = PruneReport::default();
+        for version in expired {
+            if let Some(mui_index) = &self.mui_index {
+                mui_index.remove(
+                    MuiIndexKey::for_record_key(&version.key),
+                    self.seqno.next(),
+                );
+            }
+            self.tree.remove(version.key.as_bytes(), self.seqno.next());
+            report.removed_records += 1;
+            report.reclaimed_bytes += version.size as u64;
+        }
+        trace!("pruned {:?}", report);
+
+        Ok(report)
+    }
+
+    // The versions for one (prefix, mui) pair that should be removed.
+    fn expired_versions(
+        &self,
+        mut versions: Vec<Version<AF>>,
+        policy: &RetentionPolicy,
+        newest_ltime: u64,
+    ) -> Vec<Version<AF>> {
+        versions.sort_by_key(|v| std::cmp::Reverse(u64::from(v.key.ltime)));
+
+        // Newest first, so that a kept status change can keep the older
+        // record that it applies to.
+        let mut needs_meta = false;
+        let mut expired = vec![];
+        for (i, version) in versions.into_iter().enumerate() {
+            let too_old = policy.max_age.is_some_and(|max_age| {
+                newest_ltime.saturating_sub(version.key.ltime.into())
+                    > max_age
+            });
+            let too_many = policy.max_versions.is_some_and(|max| i >= max);
+            let keep = (self.keep_latest && i == 0)
+                || !(too_old || too_many)
+                || (needs_meta && version.has_meta);
+
+            if keep {
+                needs_meta = !version.has_meta;
+            } else {
+                expired.push(version);
+            }
+        }
+
+        expired
+    }
+}
@@ -0,0 +1,94 @@
+//------------ SnapshotRegistry ----------------------------------------------
+
+// Keeps track of the snapshots of the trees that are being read, e.g. for a
+// copy of the store, or for pruning, so that a flush, or a compaction, does
+// not drop the versions of keys that they still have to read.
+//
+// A flush, and a compaction, drop the versions of a key that are older than
+// their threshold, if there is a newer version. A snapshot reads the newest
+// version that is older than its sequence number, which can be far older
+// than that number, while the newer version that shadows it is written
+// after the snapshot was taken. No threshold keeps those versions, other
+// than zero, so nothing is dropped while a snapshot is open.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use lsm_tree::{SeqNo, SequenceNumberCounter};
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SnapshotRegistry {
+    // The number of open snapshots. The lock is held during a flush, so
+    // that a snapshot cannot be opened while versions are being dropped.
+    open: Arc<Mutex<usize>>,
+}
+
+impl SnapshotRegistry {
+    // Open a snapshot at the current sequence number. The snapshot stays
+    // open until the returned guard is dropped.
+    pub(crate) fn open(
+        &self,
+        seqno: &SequenceNumberCounter,
+    ) -> (SeqNo, SnapshotGuard) {
+        *self.lock() += 1;
+        (seqno.get(), SnapshotGuard(self.clone()))
+    }
+
+    // The threshold for a flush, or a compaction, below which shadowed
+    // versions may be dropped, with the lock that keeps snapshots from
+    // being opened until it is dropped.
+    pub(crate) fn gc_threshold(
+        &self,
+        seqno: &SequenceNumberCounter,
+    ) -> (SeqNo, MutexGuard<'_, usize>) {
+        let open = self.lock();
+        match *open {
+            0 => (seqno.get(), open),
+            _ => (0, open),
+        }
+    }
+
+    // The counter is always consistent, also if a thread panicked while
+    // holding the lock, so a poisoned lock is still used.
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.open.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub(crate) struct SnapshotGuard(SnapshotRegistry);
+
+impl Drop for SnapshotGuard {
+    fn drop(&mut self) {
+        *self.0.lock() -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsm_tree::AbstractTree;
+
+    use super::*;
+    use crate::lsm_tree::flush_tree;
+
+    #[test]
+    fn test_flush_keeps_snapshot_versions() -> lsm_tree::Result<()> {
+        let dir = format!("/tmp/rotonda/gc-snapshot-{}", std::process::id());
+        let _ = std::fs::remove_dir_all(&dir);
+        let tree = lsm_tree::Config::new(&dir).open()?;
+        let seqno = SequenceNumberCounter::default();
+        let snapshots = SnapshotRegistry::default();
+
+        tree.insert("a", "old", seqno.next());
+        let (snapshot_seqno, snapshot) = snapshots.open(&seqno);
+        tree.insert("a", "new", seqno.next());
+
+        // The old version is shadowed, but is still read from the open
+        // snapshot after a flush.
+        flush_tree(&tree, &seqno, &snapshots)?;
+        let old = tree.snapshot(snapshot_seqno).get("a")?;
+        assert_eq!(old.as_deref(), Some(&b"old"[..]));
+        drop(snapshot);
+
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use crossbeam_epoch::{Atomic, Guard, Owned};
@@ -12,7 +13,8 @@ use roaring::RoaringBitmap;
 use crate::cht::{nodeset_size, prev_node_size};
 use crate::errors::{FatalError, FatalResult};
 use crate::prefix_record::Meta;
-use crate::stats::{Counters, UpsertReport};
+use crate::rib::path_selection::{PathCandidate, PathSelector};
+use crate::stats::{ChtLevelReport, Counters, UpsertReport};
 #[cfg(test)]
 use crate::test_types::NoMeta;
 use crate::types::RouteStatus;
@@ -45,7 +47,7 @@ impl<M: Send + Sync + Debug + Display + Meta> MultiMap<M> {
     #[allow(clippy::type_complexity)]
     fn acquire_write_lock(
         &self,
-    ) -> FatalResult<(MutexGuard<HashMap<u32, MultiMapValue<M>>>, usize)>
+    ) -> FatalResult<(MutexGuard<'_, HashMap<u32, MultiMapValue<M>>>, usize)>
     {
         let mut retry_count: usize = 0;
         let backoff = Backoff::new();
@@ -65,7 +67,7 @@ impl<M: Send + Sync + Debug + Display + Meta> MultiMap<M> {
 
     fn acquire_read_guard(
         &self,
-    ) -> MutexGuard<HashMap<u32, MultiMapValue<M>>> {
+    ) -> MutexGuard<'_, HashMap<u32, MultiMapValue<M>>> {
         let backoff = Backoff::new();
 
         loop {
@@ -82,6 +84,57 @@ impl<M: Send + Sync + Debug + Display + Meta> MultiMap<M> {
         record_map.len()
     }
 
+    // The number of records, the bytes allocated for the map that holds
+    // them, and the total length of their metadata.
+    pub(crate) fn memory(&self) -> (usize, usize, usize) {
+        let record_map = self.acquire_read_guard();
+        // The Arc with the Mutex, and the table of the HashMap.
+        let map_bytes = 2 * size_of::<usize>()
+            + size_of::<Mutex<HashMap<u32, MultiMapValue<M>>>>()
+            + hash_table_bytes(
+                record_map.capacity(),
+                size_of::<(u32, MultiMapValue<M>)>(),
+            );
+        let meta_bytes =
+            record_map.values().map(|r| r.meta().as_ref().len()).sum();
+
+        (record_map.len(), map_bytes, meta_bytes)
+    }
+
+    // The estimated number of bytes a record with this metadata takes in a
+    // map: its entry, and the length of its metadata. This is what the
+    // memory budget of the Hybrid strategy is compared against.
+    pub(crate) fn record_bytes(meta: &M) -> usize {
+        size_of::<(u32, MultiMapValue<M>)>() + meta.as_ref().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let record_map = self.acquire_read_guard();
+        record_map.is_empty()
+    }
+
+    pub fn contains(&self, mui: u32) -> bool {
+        let record_map = self.acquire_read_guard();
+        record_map.contains_key(&mui)
+    }
+
+    // Returns the muis for all the records in this map.
+    pub fn muis(&self) -> Vec<u32> {
+        let record_map = self.acquire_read_guard();
+        record_map.keys().copied().collect()
+    }
+
+    // Remove the record for this mui from the HashMap. Returns the removed
+    // record, if any.
+    pub(crate) fn remove_record(
+        &self,
+        mui: u32,
+    ) -> FatalResult<Option<MultiMapValue<M>>> {
+        let (mut record_map, _retry_count) = self.acquire_write_lock()?;
+
+        Ok(record_map.remove(&mui))
+    }
+
     pub fn get_record_for_mui(
         &self,
         mui: u32,
@@ -98,14 +151,72 @@ impl<M: Send + Sync + Debug + Display + Meta> MultiMap<M> {
         })
     }
 
-    pub fn best_backup(&self, tbi: M::TBI) -> (Option<u32>, Option<u32>) {
+    pub(crate) fn best_backup(
+        &self,
+        args: &PathSelectionArgs<'_, M>,
+    ) -> (Option<u32>, Option<u32>) {
         let record_map = self.acquire_read_guard();
-        let ord_routes = record_map
+        let candidates = record_map
             .iter()
-            .map(|r| (r.1.meta().as_orderable(tbi), *r.0));
-        let (best, bckup) =
-            routecore::bgp::path_selection::best_backup_generic(ord_routes);
-        (best.map(|b| b.1), bckup.map(|b| b.1))
+            .map(|(mui, r)| PathCandidate {
+                multi_uniq_id: *mui,
+                ltime: r.logical_time(),
+                status: if args.withdrawn_muis.contains(*mui) {
+                    RouteStatus::Withdrawn
+                } else {
+                    r.route_status()
+                },
+                meta: r.meta(),
+            })
+            .filter(|c| !args.active_only || c.status == RouteStatus::Active)
+            .collect::<Vec<_>>();
+        args.selector.best_backup(&candidates, args.tbi)
+    }
+
+    // The muis of the best route, as selected by the path selector, and of
+    // the routes that are equivalent to it according to
+    // `Meta::multipath_equivalent`, in order of preference, and for equally
+    // preferred routes in order of mui, at most `max_paths` of them.
+    // Withdrawn routes, locally or globally, are left out.
+    pub(crate) fn multipath(
+        &self,
+        args: &PathSelectionArgs<'_, M>,
+        max_paths: usize,
+    ) -> Vec<u32> {
+        let record_map = self.acquire_read_guard();
+        let candidates = record_map
+            .iter()
+            .filter(|(mui, r)| {
+                r.route_status() == RouteStatus::Active
+                    && !args.withdrawn_muis.contains(**mui)
+            })
+            .map(|(mui, r)| PathCandidate {
+                multi_uniq_id: *mui,
+                ltime: r.logical_time(),
+                status: RouteStatus::Active,
+                meta: r.meta(),
+            })
+            .collect::<Vec<_>>();
+
+        let (best, _) = args.selector.best_backup(&candidates, args.tbi);
+        let Some(best) =
+            candidates.iter().find(|c| Some(c.multi_uniq_id) == best)
+        else {
+            return vec![];
+        };
+        let best_route = best.meta.as_orderable(args.tbi);
+        let mut ord_routes = candidates
+            .iter()
+            .filter(|c| c.multi_uniq_id != best.multi_uniq_id)
+            .map(|c| (c.meta.as_orderable(args.tbi), c.multi_uniq_id))
+            .filter(|(r, _)| M::multipath_equivalent(&best_route, r))
+            .collect::<Vec<_>>();
+        ord_routes.sort();
+
+        std::iter::once(best.multi_uniq_id)
+            .chain(ord_routes.iter().map(|(_, mui)| *mui))
+            .take(max_paths)
+            .collect()
     }
 
     pub(crate) fn get_record_for_mui_with_rewritten_status(
@@ -344,8 +455,31 @@ impl<M: Meta> Clone for MultiMap<M> {
     }
 }
 
+// The bytes allocated by a HashMap with this capacity, for entries of
+// `entry_bytes`. The table of a HashMap has a power of two of buckets, of
+// which at most 7/8 are used, and a control byte for every bucket, plus a
+// group of 16 of them.
+fn hash_table_bytes(capacity: usize, entry_bytes: usize) -> usize {
+    let buckets = match capacity {
+        0 => return 0,
+        1..=7 => capacity + 1,
+        _ => capacity / 7 * 8,
+    };
+    buckets * (entry_bytes + 1) + 16
+}
+
 // ----------- Prefix related structs ---------------------------------------
 
+// The arguments for a best and backup path selection: the tie breaker info,
+// the selector of the RIB, whether to leave out the withdrawn records, and
+// the globally withdrawn muis.
+pub(crate) struct PathSelectionArgs<'a, M: Meta> {
+    pub tbi: M::TBI,
+    pub selector: &'a dyn PathSelector<M>,
+    pub active_only: bool,
+    pub withdrawn_muis: &'a RoaringBitmap,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct PathSelections {
     pub(crate) path_selection_muis: (Option<u32>, Option<u32>),
@@ -373,6 +507,19 @@ pub struct StoredPrefix<AF: AddressFamily, M: Meta> {
     pub record_map: MultiMap<M>,
     // (mui of best path entry, mui of backup path entry) from the record_map
     path_selections: Atomic<PathSelections>,
+    // the muis of the multipath entries from the record_map, null if it was
+    // never calculated. Tagged with 1 if outdated, like path_selections.
+    multipath: Atomic<Vec<u32>>,
+    // Whether the records of this prefix were evicted to the persist tree,
+    // for the Hybrid strategy. This only changes while the record map is
+    // locked, see `evict` and `fault_in`.
+    spilled: AtomicBool,
+    // Set on every access to the records of this prefix, and cleared by
+    // the eviction passes of the Hybrid strategy, so that a prefix is only
+    // evicted if it was not accessed since the previous pass.
+    referenced: AtomicBool,
+    // The number of live EvictionPins for this prefix.
+    pins: AtomicUsize,
     // the reference to the next set of records for this prefix, if any.
     pub next_bucket: PrefixSet<AF, M>,
 }
@@ -411,6 +558,10 @@ impl<AF: AddressFamily, M: Meta> StoredPrefix<AF, M> {
             path_selections: Atomic::init(PathSelections {
                 path_selection_muis: (None, None),
             }),
+            multipath: Atomic::null(),
+            spilled: AtomicBool::new(false),
+            referenced: AtomicBool::new(true),
+            pins: AtomicUsize::new(0),
             record_map: MultiMap::new(rec_map),
             next_bucket,
         }
@@ -441,6 +592,18 @@ impl<AF: AddressFamily, M: Meta> StoredPrefix<AF, M> {
 
         if unsafe { current.as_ref() } == Some(&path_selections) {
             debug!("unchanged path_selections");
+            // The selection is still up to date with the changed records.
+            if current.tag() == 1 {
+                self.path_selections
+                    .compare_exchange(
+                        current,
+                        current.with_tag(0),
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                        guard,
+                    )
+                    .map_err(|_| PrefixStoreError::PathSelectionOutdated)?;
+            }
             return Ok(());
         }
 
@@ -454,6 +617,9 @@ impl<AF: AddressFamily, M: Meta> StoredPrefix<AF, M> {
                 guard,
             )
             .map_err(|_| PrefixStoreError::PathSelectionOutdated)?;
+        if !current.is_null() {
+            unsafe { guard.defer_destroy(current) };
+        }
         Ok(())
     }
 
@@ -461,6 +627,11 @@ impl<AF: AddressFamily, M: Meta> StoredPrefix<AF, M> {
         &self,
         guard: &Guard,
     ) -> Result<(), PrefixStoreError> {
+        self.multipath
+            .fetch_update(Ordering::Acquire, Ordering::Acquire, guard, |p| {
+                Some(p.with_tag(1))
+            })
+            .map_err(|_| PrefixStoreError::StoreNotReadyError)?;
         self.path_selections
             .fetch_update(Ordering::Acquire, Ordering::Acquire, guard, |p| {
                 Some(p.with_tag(1))
@@ -473,12 +644,12 @@ impl<AF: AddressFamily, M: Meta> StoredPrefix<AF, M> {
         self.path_selections.load(Ordering::Acquire, guard).tag() == 1
     }
 
-    pub fn calculate_and_store_best_backup<'a>(
+    pub(crate) fn calculate_and_store_best_backup<'a>(
         &'a self,
-        tbi: &M::TBI,
+        args: &PathSelectionArgs<'_, M>,
         guard: &'a Guard,
     ) -> Result<(Option<u32>, Option<u32>), PrefixStoreError> {
-        let path_selection_muis = self.record_map.best_backup(*tbi);
+        let path_selection_muis = self.record_map.best_backup(args);
 
         self.set_path_selections(
             PathSelections {
@@ -489,7 +660,210 @@ impl<AF: AddressFamily, M: Meta> StoredPrefix<AF, M> {
 
         Ok(path_selection_muis)
     }
+
+    // The bytes of this stored prefix, and of its path selections, without
+    // its record map and its next bucket.
+    pub(crate) fn value_bytes(&self, guard: &Guard) -> usize {
+        let multipath =
+            unsafe { self.multipath.load(Ordering::Acquire, guard).as_ref() }
+                .map_or(0, |m| {
+                    size_of::<Vec<u32>>() + m.capacity() * size_of::<u32>()
+                });
+
+        size_of::<Self>() + size_of::<PathSelections>() + multipath
+    }
+
+    // The muis of the last calculated multipath, or `None` if it was never
+    // calculated.
+    pub(crate) fn get_multipath(&self, guard: &Guard) -> Option<Vec<u32>> {
+        let multipath = self.multipath.load(Ordering::Acquire, guard);
+
+        unsafe { multipath.as_ref() }.cloned()
+    }
+
+    pub(crate) fn is_multipath_outdated(&self, guard: &Guard) -> bool {
+        self.multipath.load(Ordering::Acquire, guard).tag() == 1
+    }
+
+    // Keep the records of this prefix in memory for as long as the
+    // returned pin lives, and mark them as accessed. Records that were
+    // already evicted have to be loaded with `fault_in` after pinning.
+    pub(crate) fn pin(&self) -> EvictionPin<'_> {
+        self.pins.fetch_add(1, Ordering::SeqCst);
+        self.referenced.store(true, Ordering::Relaxed);
+        EvictionPin(&self.pins)
+    }
+
+    // Whether the records of this prefix were accessed since the previous
+    // call. The mark is cleared.
+    pub(crate) fn take_referenced(&self) -> bool {
+        self.referenced.swap(false, Ordering::Relaxed)
+    }
+
+    // Mark the records of this prefix as evicted, without evicting any.
+    // Used for the records that are found in the persist tree when it is
+    // opened.
+    pub(crate) fn set_spilled(&self) -> FatalResult<()> {
+        let _record_map = self.record_map.acquire_write_lock()?;
+        self.spilled.store(true, Ordering::Release);
+
+        Ok(())
+    }
+
+    // Move all the records of this prefix out of memory, by handing them
+    // to `spill`. The record map stays locked until `spill` returns, so
+    // that readers either find the records in memory, or the prefix marked
+    // as evicted. Returns the number of evicted records, and their
+    // estimated size in bytes.
+    pub(crate) fn evict(
+        &self,
+        spill: impl FnOnce(&[Record<M>]),
+    ) -> FatalResult<(usize, usize)> {
+        let (mut record_map, _retry_count) =
+            self.record_map.acquire_write_lock()?;
+        // The pins are checked while the map is locked. A thread that pins
+        // the prefix after this check waits for the lock in `fault_in`, and
+        // then finds the prefix marked as evicted, and loads the records
+        // again.
+        if record_map.is_empty() || self.pins.load(Ordering::SeqCst) > 0 {
+            return Ok((0, 0));
+        }
+
+        // Taking the map, instead of draining it, frees its table.
+        let records = std::mem::take(&mut *record_map)
+            .into_iter()
+            .map(|(mui, rec)| Record::from((mui, &rec)))
+            .collect::<Vec<_>>();
+        spill(&records);
+        self.spilled.store(true, Ordering::Release);
+
+        Ok((
+            records.len(),
+            records
+                .iter()
+                .map(|r| MultiMap::<M>::record_bytes(&r.meta))
+                .sum(),
+        ))
+    }
+
+    // Hand copies of the records of this prefix that are in memory to
+    // `spill`, and keep them in memory. The record map stays locked until
+    // `spill` returns, so that no records are evicted, or loaded back, in
+    // between. Returns the number of spilled records.
+    pub(crate) fn spill_resident(
+        &self,
+        spill: impl FnOnce(&[Record<M>]),
+    ) -> FatalResult<usize> {
+        let (record_map, _retry_count) =
+            self.record_map.acquire_write_lock()?;
+        if record_map.is_empty() {
+            return Ok(0);
+        }
+
+        let records = record_map
+            .iter()
+            .map(|(mui, rec)| Record::from((*mui, rec)))
+            .collect::<Vec<_>>();
+        spill(&records);
+
+        Ok(records.len())
+    }
+
+    // All the records of this prefix, the ones in memory, and the evicted
+    // ones as returned by `load`. The record map stays locked while they
+    // are read, so that no records are evicted, or loaded back, in between.
+    pub(crate) fn all_records(
+        &self,
+        load: impl FnOnce() -> FatalResult<Vec<Record<M>>>,
+    ) -> FatalResult<Vec<Record<M>>> {
+        let (record_map, _retry_count) =
+            self.record_map.acquire_write_lock()?;
+
+        let mut records = record_map
+            .iter()
+            .map(|(mui, rec)| Record::from((*mui, rec)))
+            .collect::<Vec<_>>();
+        if self.spilled.load(Ordering::Acquire) {
+            records.extend(
+                load()?
+                    .into_iter()
+                    .filter(|r| !record_map.contains_key(&r.multi_uniq_id)),
+            );
+        }
+
+        Ok(records)
+    }
+
+    // Load the evicted records of this prefix, as returned by `load`, back
+    // into memory, if it has any. A record that was upserted after the
+    // eviction is newer than the evicted one, and is kept. Returns the
+    // number of loaded records, and their estimated size in bytes.
+    pub(crate) fn fault_in(
+        &self,
+        load: impl FnOnce() -> FatalResult<Vec<Record<M>>>,
+    ) -> FatalResult<(usize, usize)> {
+        // The flag is only read while the map is locked. Checking it
+        // before would race with an eviction that already took the
+        // records, but did not mark the prefix as evicted yet.
+        let (mut record_map, _retry_count) =
+            self.record_map.acquire_write_lock()?;
+        if !self.spilled.load(Ordering::Acquire) {
+            return Ok((0, 0));
+        }
+
+        let mut loaded = (0, 0);
+        for record in load()? {
+            if let Entry::Vacant(entry) =
+                record_map.entry(record.multi_uniq_id)
+            {
+                loaded.0 += 1;
+                loaded.1 += MultiMap::<M>::record_bytes(&record.meta);
+                entry.insert(MultiMapValue::from(record));
+            }
+        }
+        self.spilled.store(false, Ordering::Release);
+
+        Ok(loaded)
+    }
+
+    pub(crate) fn calculate_and_store_multipath(
+        &self,
+        args: &PathSelectionArgs<'_, M>,
+        max_paths: usize,
+        guard: &Guard,
+    ) -> Result<Vec<u32>, PrefixStoreError> {
+        let muis = self.record_map.multipath(args, max_paths);
+
+        let current = self.multipath.load(Ordering::SeqCst, guard);
+        self.multipath
+            .compare_exchange(
+                current,
+                Owned::new(muis.clone()).with_tag(0),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            )
+            .map_err(|_| PrefixStoreError::PathSelectionOutdated)?;
+        if !current.is_null() {
+            unsafe { guard.defer_destroy(current) };
+        }
+
+        Ok(muis)
+    }
 }
+
+//------------ EvictionPin --------------------------------------------------
+
+// Keeps the records of a stored prefix from being evicted while it lives,
+// see StoredPrefix::pin.
+pub(crate) struct EvictionPin<'a>(&'a AtomicUsize);
+
+impl Drop for EvictionPin<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 //------------ PrefixSet ----------------------------------------------------
 
 // The PrefixSet is the ARRAY that holds all the child prefixes in a node.
@@ -506,6 +880,41 @@ pub struct PrefixSet<AF: AddressFamily, M: Meta>(
     pub OnceBoxSlice<StoredPrefix<AF, M>>,
 );
 
+impl<AF: AddressFamily, M: Meta> PrefixSet<AF, M> {
+    // Add the memory used by this set, as a bucket at `level`, and by the
+    // sets of its prefixes, at the levels below it, to `levels`.
+    pub(crate) fn add_memory(
+        &self,
+        level: u8,
+        levels: &mut Vec<ChtLevelReport>,
+        guard: &Guard,
+    ) {
+        let slots = self.0.allocated_slots();
+        if slots == 0 {
+            return;
+        }
+        let prefixes = self.0.iter().collect::<Vec<_>>();
+
+        let report = ChtLevelReport::for_level(levels, level);
+        report.add_bucket(
+            slots,
+            prefixes.len(),
+            OnceBoxSlice::<StoredPrefix<AF, M>>::slot_bytes(),
+        );
+        for prefix in &prefixes {
+            let (records, map_bytes, meta_bytes) = prefix.record_map.memory();
+            report.value_bytes += prefix.value_bytes(guard);
+            report.records += records;
+            report.record_map_bytes += map_bytes;
+            report.meta_bytes += meta_bytes;
+        }
+
+        for prefix in prefixes {
+            prefix.next_bucket.add_memory(level + 1, levels, guard);
+        }
+    }
+}
+
 impl<AF: AddressFamily, M: Meta> Value for PrefixSet<AF, M> {
     fn init_with_p2_children(p2_size: usize) -> Self {
         let size = if p2_size == 0 { 0 } else { 1 << p2_size };
@@ -590,7 +999,7 @@ impl<AF: AddressFamily, M: Meta, const ROOT_SIZE: usize>
         &self,
         prefix: PrefixId<AF>,
         record: Record<M>,
-        update_path_selections: Option<M::TBI>,
+        update_path_selections: Option<PathSelectionArgs<'_, M>>,
         guard: &Guard,
     ) -> Result<(UpsertReport, Option<MultiMapValue<M>>), PrefixStoreError>
     {
@@ -645,6 +1054,14 @@ impl<AF: AddressFamily, M: Meta, const ROOT_SIZE: usize>
                     }
                     prefix_is_new = false;
 
+                    // All the records for this prefix may have been removed
+                    // earlier, in which case this prefix counts as a new
+                    // one again.
+                    if stored_prefix.record_map.is_empty() {
+                        self.counters
+                            .inc_prefixes_count(stored_prefix.prefix.len());
+                    }
+
                     // Update the already existing record_map with our
                     // caller's record.
                     stored_prefix.set_ps_outdated(guard)?;
@@ -656,14 +1073,14 @@ impl<AF: AddressFamily, M: Meta, const ROOT_SIZE: usize>
 
                     // if the mui is new, we didn't overwrite an existing
                     // route, so that's a new one!
-                    if mui_count.is_none() {
-                        mui_is_new = true;
+                    mui_is_new = mui_count.is_none();
+                    if mui_is_new {
                         self.counters.inc_routes_count();
                     };
 
-                    if let Some(tbi) = update_path_selections {
+                    if let Some(args) = update_path_selections {
                         stored_prefix
-                            .calculate_and_store_best_backup(&tbi, guard)?;
+                            .calculate_and_store_best_backup(&args, guard)?;
                     }
 
                     (mui_count, retry_count)
@@ -820,10 +1237,94 @@ impl<AF: AddressFamily, M: Meta, const ROOT_SIZE: usize>
         }
     }
 
+    // Remove the record for a (prefix, mui) pair. The StoredPrefix itself
+    // stays in the CHT, since its slot cannot be freed, but it will be
+    // left with an empty record map if this was its last record.
+    pub(crate) fn remove_record(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+        guard: &Guard,
+    ) -> Result<Option<Record<M>>, PrefixStoreError> {
+        let stored_prefix = if let (Some(sp), _) =
+            self.non_recursive_retrieve_prefix(prefix)
+        {
+            sp
+        } else {
+            return Ok(None);
+        };
+
+        let removed = stored_prefix
+            .record_map
+            .remove_record(mui)
+            .map_err(|_| PrefixStoreError::FatalError)?
+            .map(|rec| Record::from((mui, &rec)));
+
+        if removed.is_some() {
+            // The best and backup path may have been based on this record.
+            stored_prefix.set_ps_outdated(guard)?;
+            self.counters.dec_routes_count();
+            if stored_prefix.record_map.is_empty() {
+                self.counters.dec_prefixes_count(prefix.len());
+            }
+        }
+
+        Ok(removed)
+    }
+
+    // Whether there are any records for this prefix in the CHT, or for
+    // this (prefix, mui) pair if a mui is specified.
+    pub(crate) fn has_records(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: Option<u32>,
+    ) -> bool {
+        self.non_recursive_retrieve_prefix(prefix)
+            .0
+            .is_some_and(|sp| match mui {
+                Some(mui) => sp.record_map.contains(mui),
+                None => !sp.record_map.is_empty(),
+            })
+    }
+
+    // All the muis that have a record for this prefix in the CHT.
+    pub(crate) fn muis_for_prefix(&self, prefix: PrefixId<AF>) -> Vec<u32> {
+        self.non_recursive_retrieve_prefix(prefix)
+            .0
+            .map(|sp| sp.record_map.muis())
+            .unwrap_or_default()
+    }
+
+    // All the stored prefixes with this length, by walking the root for
+    // this length, and the buckets hanging off of it, depth-first. The
+    // prefixes are in no particular order.
+    pub(crate) fn stored_prefixes_for_len(
+        &self,
+        len: u8,
+    ) -> impl Iterator<Item = &StoredPrefix<AF, M>> {
+        let mut stack =
+            self.bush.root_for_len(len).0.iter().collect::<Vec<_>>();
+        std::iter::from_fn(move || {
+            let stored_prefix = stack.pop()?;
+            stack.extend(stored_prefix.next_bucket.0.iter());
+            Some(stored_prefix)
+        })
+    }
+
     pub(crate) fn prefixes_count(&self) -> usize {
         self.counters.prefixes_count().iter().sum()
     }
 
+    // The memory used by the prefixes and their records, per level of the
+    // CHT.
+    pub(crate) fn memory_report(&self, guard: &Guard) -> Vec<ChtLevelReport> {
+        let mut levels = vec![];
+        for root in self.bush.roots() {
+            root.add_memory(0, &mut levels, guard);
+        }
+        levels
+    }
+
     pub(crate) fn routes_count(&self) -> usize {
         self.counters.nodes_count()
     }
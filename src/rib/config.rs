@@ -16,10 +16,18 @@
 //! let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(config);
 //! ```
 
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::prefix_record::Meta;
+use crate::rib::index::{IndexFactory, SecondaryIndex};
+use crate::rib::path_selection::{PathSelector, SharedPathSelector};
+
 /// Defines where records are stored: in-memory and/or persisted (to disk),
 /// and, whether new records for a unique (prefix, mui) pair are overwritten
 /// or persisted ("historical records").
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PersistStrategy {
     /// Current records are stored both in-memory and persisted. Historical
     /// records are persisted.
@@ -33,6 +41,12 @@ pub enum PersistStrategy {
     /// Current records are persisted immediately. No records are stored in
     /// memory. Historical records are discarded when newer records appear.
     PersistOnly,
+    /// Current records are stored in-memory, as long as they fit in the
+    /// memory budget. When the budget is exceeded, the records of the
+    /// prefixes that were not accessed recently are evicted to disk, and
+    /// they are loaded back into memory when they are accessed again.
+    /// Historical records are discarded when newer records appear.
+    Hybrid,
 }
 
 pub trait Config: Clone + Default + std::fmt::Debug {
@@ -43,6 +57,202 @@ pub trait Config: Clone + Default + std::fmt::Debug {
     fn persist_path(&self) -> Option<String>;
     /// Set the path to the directory that will be used to persist records to
     fn set_persist_path(&mut self, path: String);
+    /// Returns the settings for the background flusher, if it should be
+    /// started.
+    fn flush_config(&self) -> Option<FlushConfig> {
+        None
+    }
+    /// Set the settings for the background flusher, or `None` to disable
+    /// it. Configurations that do not persist records ignore this.
+    fn set_flush_config(&mut self, _flush_config: Option<FlushConfig>) {}
+    /// Returns the retention policy for the persisted historical records,
+    /// if any.
+    fn retention_policy(&self) -> Option<RetentionPolicy> {
+        None
+    }
+    /// Set the retention policy for the persisted historical records, or
+    /// `None` to keep all of them. Configurations that do not persist
+    /// historical records ignore this.
+    fn set_retention_policy(
+        &mut self,
+        _retention_policy: Option<RetentionPolicy>,
+    ) {
+    }
+    /// Returns the number of bytes the in-memory records may take, before
+    /// they are evicted to disk, if any.
+    fn memory_budget(&self) -> Option<usize> {
+        None
+    }
+    /// Set the number of bytes the in-memory records may take.
+    /// Configurations that do not evict records ignore this.
+    fn set_memory_budget(&mut self, _memory_budget: usize) {}
+}
+
+//------------ MetaConfig ----------------------------------------------------
+
+/// The settings of a RIB that depend on the type of the metadata of its
+/// records: the secondary indexes, the path selector, and the tie breaker
+/// info for the automatic path selection.
+///
+/// The `*Config` structs are not generic over the type of the metadata, so
+/// these settings are passed in separately, when a RIB is created with
+/// [new_with_meta_config](super::StarCastRib::new_with_meta_config), or
+/// opened with [open_with_meta_config](
+/// super::StarCastRib::open_with_meta_config).
+///
+/// ```
+/// use rotonda_store::test_types::PrefixAs;
+/// use rotonda_store::rib::StarCastRib;
+/// use rotonda_store::rib::config::{MemoryOnlyConfig, MetaConfig};
+///
+/// let mut meta_config = MetaConfig::<PrefixAs>::default();
+/// meta_config.set_auto_path_selection(Some(()));
+/// let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+///     MemoryOnlyConfig,
+///     meta_config,
+/// );
+/// ```
+pub struct MetaConfig<M: Meta> {
+    pub(crate) indexes: Vec<(String, IndexFactory<M>)>,
+    pub(crate) path_selector: Option<SharedPathSelector<M>>,
+    pub(crate) auto_path_selection: Option<M::TBI>,
+}
+
+impl<M: Meta> MetaConfig<M> {
+    /// Register a secondary index under `name`, replacing the index that
+    /// was registered earlier under the same name, if any.
+    ///
+    /// Every RIB that is created with these settings gets its own, empty,
+    /// index, that is filled with the records that are restored from
+    /// disk, if any, and that is kept up to date on every insert and
+    /// removal. The index is queried with [lookup_index](
+    /// super::StarCastRib::lookup_index).
+    pub fn add_index<I: SecondaryIndex<M>>(&mut self, name: &str, index: I) {
+        self.indexes.retain(|(n, _)| n != name);
+        self.indexes
+            .push((name.to_string(), IndexFactory::new(index)));
+    }
+
+    /// Set the [PathSelector] for the best and backup path selection, and
+    /// for the multipath selection.
+    ///
+    /// The selector replaces the [DefaultPathSelector](
+    /// super::path_selection::DefaultPathSelector), for both address
+    /// families.
+    pub fn set_path_selector<S: PathSelector<M> + 'static>(
+        &mut self,
+        selector: S,
+    ) {
+        self.path_selector = Some(SharedPathSelector::new(selector));
+    }
+
+    /// Enable the automatic best and backup path selection, with `tbi` as
+    /// the default tie breaker info, or disable it with `None`.
+    ///
+    /// With the automatic path selection, every change to the set of
+    /// candidates for the best path of a prefix recalculates its best and
+    /// backup path right away: inserts, removals and changes of the local
+    /// status of a record, and, for all the prefixes with a record for the
+    /// `multi_uniq_id`, changes of the global status of a `multi_uniq_id`
+    /// and [purge_mui](super::StarCastRib::purge_mui). Inserts that pass in
+    /// their own tie breaker info use that instead. All the path selections
+    /// of such a RIB leave out the records that are withdrawn, locally or
+    /// globally, so that the [PathSelector] only gets the active ones.
+    pub fn set_auto_path_selection(&mut self, tbi: Option<M::TBI>) {
+        self.auto_path_selection = tbi;
+    }
+}
+
+impl<M: Meta> Default for MetaConfig<M> {
+    fn default() -> Self {
+        Self {
+            indexes: vec![],
+            path_selector: None,
+            auto_path_selection: None,
+        }
+    }
+}
+
+impl<M: Meta> Clone for MetaConfig<M> {
+    fn clone(&self) -> Self {
+        Self {
+            indexes: self.indexes.clone(),
+            path_selector: self.path_selector.clone(),
+            auto_path_selection: self.auto_path_selection,
+        }
+    }
+}
+
+impl<M: Meta> std::fmt::Debug for MetaConfig<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetaConfig")
+            .field(
+                "indexes",
+                &self.indexes.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            )
+            .field("path_selector", &self.path_selector.is_some())
+            .field("auto_path_selection", &self.auto_path_selection.is_some())
+            .finish()
+    }
+}
+
+//------------ FlushConfig ---------------------------------------------------
+
+/// Settings for a background thread that flushes the persisted records from
+/// memory to disk, without the need to call
+/// [flush_to_disk](super::StarCastRib::flush_to_disk) manually.
+///
+/// The flusher flushes the data for an address family as soon as its
+/// in-memory part reaches `memtable_size` bytes, or when `interval` has
+/// passed since the last flush, whatever comes first. The flusher runs until
+/// [shutdown](super::StarCastRib::shutdown) is called on the RIB, or the RIB
+/// is dropped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlushConfig {
+    /// The size in bytes of the in-memory part of the persisted records for
+    /// an address family that triggers a flush.
+    pub memtable_size: u32,
+    /// The maximum time between two flushes. Creating a RIB with a zero
+    /// interval fails.
+    pub interval: Duration,
+}
+
+impl Default for FlushConfig {
+    fn default() -> Self {
+        Self {
+            memtable_size: 64 * 1024 * 1024,
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+//------------ RetentionPolicy -----------------------------------------------
+
+/// A policy for removing persisted historical records, so that the disk
+/// usage of the `WriteAhead` and `PersistHistory` strategies does not grow
+/// without bound.
+///
+/// A persisted record is removed if it is older than `max_age`, or if there
+/// are more than `max_versions` newer persisted records for the same
+/// `(prefix, mui)` pair. The age of a record is the difference between its
+/// ltime and the newest ltime in the persisted store. For the `WriteAhead`
+/// strategy the most recent persisted record for a `(prefix, mui)` pair is
+/// the current record, and that one is never removed. A record that is the
+/// target of a persisted status change is kept as long as that status change
+/// is kept.
+///
+/// Records are removed by calling
+/// [prune_history](super::StarCastRib::prune_history), or by a background
+/// thread, if `prune_interval` is set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// The maximum age of a persisted record, in ltime units.
+    pub max_age: Option<u64>,
+    /// The maximum number of persisted records for a `(prefix, mui)` pair.
+    pub max_versions: Option<usize>,
+    /// The interval between two pruning passes in the background, or `None`
+    /// to only prune on request. Creating a RIB with a zero interval fails.
+    pub prune_interval: Option<Duration>,
 }
 
 //------------ MemoryOnlyConfig ----------------------------------------------
@@ -83,6 +293,7 @@ impl Default for MemoryOnlyConfig {
 #[derive(Clone, Debug)]
 pub struct PersistOnlyConfig {
     persist_path: String,
+    flush_config: Option<FlushConfig>,
 }
 
 impl Config for PersistOnlyConfig {
@@ -97,12 +308,21 @@ impl Config for PersistOnlyConfig {
     fn set_persist_path(&mut self, path: String) {
         self.persist_path = path;
     }
+
+    fn flush_config(&self) -> Option<FlushConfig> {
+        self.flush_config
+    }
+
+    fn set_flush_config(&mut self, flush_config: Option<FlushConfig>) {
+        self.flush_config = flush_config;
+    }
 }
 
 impl Default for PersistOnlyConfig {
     fn default() -> Self {
         Self {
             persist_path: "/tmp/rotonda/".to_string(),
+            flush_config: None,
         }
     }
 }
@@ -117,6 +337,8 @@ impl Default for PersistOnlyConfig {
 #[derive(Clone, Debug)]
 pub struct WriteAheadConfig {
     persist_path: String,
+    flush_config: Option<FlushConfig>,
+    retention_policy: Option<RetentionPolicy>,
 }
 
 impl Config for WriteAheadConfig {
@@ -131,12 +353,33 @@ impl Config for WriteAheadConfig {
     fn set_persist_path(&mut self, path: String) {
         self.persist_path = path;
     }
+
+    fn flush_config(&self) -> Option<FlushConfig> {
+        self.flush_config
+    }
+
+    fn set_flush_config(&mut self, flush_config: Option<FlushConfig>) {
+        self.flush_config = flush_config;
+    }
+
+    fn retention_policy(&self) -> Option<RetentionPolicy> {
+        self.retention_policy
+    }
+
+    fn set_retention_policy(
+        &mut self,
+        retention_policy: Option<RetentionPolicy>,
+    ) {
+        self.retention_policy = retention_policy;
+    }
 }
 
 impl Default for WriteAheadConfig {
     fn default() -> Self {
         Self {
             persist_path: "/tmp/rotonda/".to_string(),
+            flush_config: None,
+            retention_policy: None,
         }
     }
 }
@@ -150,6 +393,8 @@ impl Default for WriteAheadConfig {
 #[derive(Clone, Debug)]
 pub struct PersistHistoryConfig {
     persist_path: String,
+    flush_config: Option<FlushConfig>,
+    retention_policy: Option<RetentionPolicy>,
 }
 
 impl Config for PersistHistoryConfig {
@@ -164,12 +409,94 @@ impl Config for PersistHistoryConfig {
     fn set_persist_path(&mut self, path: String) {
         self.persist_path = path;
     }
+
+    fn flush_config(&self) -> Option<FlushConfig> {
+        self.flush_config
+    }
+
+    fn set_flush_config(&mut self, flush_config: Option<FlushConfig>) {
+        self.flush_config = flush_config;
+    }
+
+    fn retention_policy(&self) -> Option<RetentionPolicy> {
+        self.retention_policy
+    }
+
+    fn set_retention_policy(
+        &mut self,
+        retention_policy: Option<RetentionPolicy>,
+    ) {
+        self.retention_policy = retention_policy;
+    }
 }
 
 impl Default for PersistHistoryConfig {
     fn default() -> Self {
         Self {
             persist_path: "/tmp/rotonda/".to_string(),
+            flush_config: None,
+            retention_policy: None,
+        }
+    }
+}
+
+//------------ HybridConfig --------------------------------------------------
+
+/// A configuration that sets the persist strategy to
+/// `PersistStrategy::Hybrid`: Records for unique `(prefix, mui)` pairs are
+/// kept in memory, newer records will overwrite existing records. In other
+/// words, no historical records are preserved.
+///
+/// The size of the in-memory records is kept under `memory_budget` bytes.
+/// As soon as an insert, or a read, takes it over the budget, the records of
+/// the prefixes that were not accessed since the previous eviction are moved
+/// to disk, until they take less than 90% of the budget. The records of an
+/// evicted prefix are loaded back into memory when they are accessed again.
+/// The size of a record is estimated as the size of its entry in the record
+/// map, plus the length of its metadata.
+#[derive(Clone, Debug)]
+pub struct HybridConfig {
+    persist_path: String,
+    flush_config: Option<FlushConfig>,
+    memory_budget: usize,
+}
+
+impl Config for HybridConfig {
+    fn persist_strategy(&self) -> PersistStrategy {
+        PersistStrategy::Hybrid
+    }
+
+    fn persist_path(&self) -> Option<String> {
+        Some(self.persist_path.clone())
+    }
+
+    fn set_persist_path(&mut self, path: String) {
+        self.persist_path = path;
+    }
+
+    fn flush_config(&self) -> Option<FlushConfig> {
+        self.flush_config
+    }
+
+    fn set_flush_config(&mut self, flush_config: Option<FlushConfig>) {
+        self.flush_config = flush_config;
+    }
+
+    fn memory_budget(&self) -> Option<usize> {
+        Some(self.memory_budget)
+    }
+
+    fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+    }
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self {
+            persist_path: "/tmp/rotonda/".to_string(),
+            flush_config: None,
+            memory_budget: 1024 * 1024 * 1024,
         }
     }
 }
@@ -0,0 +1,406 @@
+//------------ Secondary Indexes ---------------------------------------------
+
+// Secondary indexes map keys extracted from the metadata of records, e.g.
+// the origin AS, the communities, or the next hop of a route, to the
+// (prefix, mui) pairs of the records that carry them, so that the records
+// for a key can be found without scanning the whole RIB. The muis for a
+// prefix are kept in a roaring bitmap, like the tree bitmap does for the
+// muis in a node.
+//
+// An index holds the keys for all the records in the RIB, including the
+// withdrawn ones. The status of the records is only checked on lookup, so
+// that withdrawing, or activating, a record or a mui, globally, does not
+// touch the indexes.
+
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+use inetnum::addr::Prefix;
+use roaring::RoaringBitmap;
+
+use crate::errors::{FatalError, FatalResult};
+use crate::prefix_record::Meta;
+
+/// An extractor for the keys of a secondary index on a RIB.
+///
+/// An index is registered in the [MetaConfig](
+/// crate::rib::config::MetaConfig) of a RIB with [add_index](
+/// crate::rib::config::MetaConfig::add_index), and is queried with
+/// [lookup_index](crate::rib::StarCastRib::lookup_index), that returns the
+/// records that have the requested key.
+///
+/// This trait is implemented for closures that take a reference to the
+/// metadata of a record, and that return its keys, so that an index on
+/// the origin AS of a route can be registered as:
+///
+/// ```
+/// # use rotonda_store::rib::StarCastRib;
+/// # use rotonda_store::rib::config::{MemoryOnlyConfig, MetaConfig};
+/// # use rotonda_store::prefix_record::Meta;
+/// # use rotonda_store::test_types::PrefixAs;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut meta_config = MetaConfig::<PrefixAs>::default();
+/// meta_config.add_index("origin_asn", |meta: &PrefixAs| {
+///     meta.origin_asn().into_iter().collect::<Vec<_>>()
+/// });
+/// let rib = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+///     MemoryOnlyConfig,
+///     meta_config,
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait SecondaryIndex<M: Meta>: Send + Sync + 'static {
+    /// The type of the keys of the index.
+    type Key: Clone + Eq + Hash + Send + Sync + 'static;
+
+    /// Returns the keys for the metadata of a record. A record can have
+    /// any number of keys, duplicate keys are ignored.
+    fn keys(&self, meta: &M) -> Vec<Self::Key>;
+}
+
+impl<M, K, F> SecondaryIndex<M> for F
+where
+    M: Meta,
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    F: Fn(&M) -> Vec<K> + Send + Sync + 'static,
+{
+    type Key = K;
+
+    fn keys(&self, meta: &M) -> Vec<K> {
+        self(meta)
+    }
+}
+
+// The keys of one index, and the (prefix, mui) pairs for every key, with
+// the keys for every (prefix, mui) pair, so that the old keys of a record
+// can be removed without its old metadata, and the prefixes for every mui
+// with keys, so that a mui can be removed without scanning all the
+// records.
+struct IndexMaps<K> {
+    by_key: HashMap<K, HashMap<Prefix, RoaringBitmap>>,
+    by_record: HashMap<Prefix, HashMap<u32, Vec<K>>>,
+    by_mui: HashMap<u32, HashSet<Prefix>>,
+}
+
+impl<K: Clone + Eq + Hash> IndexMaps<K> {
+    fn new() -> Self {
+        Self {
+            by_key: HashMap::new(),
+            by_record: HashMap::new(),
+            by_mui: HashMap::new(),
+        }
+    }
+
+    fn set(&mut self, prefix: Prefix, mui: u32, keys: Vec<K>) {
+        self.remove(prefix, mui);
+        if keys.is_empty() {
+            return;
+        }
+        for key in &keys {
+            self.by_key
+                .entry(key.clone())
+                .or_default()
+                .entry(prefix)
+                .or_default()
+                .insert(mui);
+        }
+        self.by_record.entry(prefix).or_default().insert(mui, keys);
+        self.by_mui.entry(mui).or_default().insert(prefix);
+    }
+
+    fn remove(&mut self, prefix: Prefix, mui: u32) {
+        let Some(muis) = self.by_record.get_mut(&prefix) else {
+            return;
+        };
+        let Some(keys) = muis.remove(&mui) else {
+            return;
+        };
+        if muis.is_empty() {
+            self.by_record.remove(&prefix);
+        }
+        if let Some(prefixes) = self.by_mui.get_mut(&mui) {
+            prefixes.remove(&prefix);
+            if prefixes.is_empty() {
+                self.by_mui.remove(&mui);
+            }
+        }
+        for key in keys {
+            let Some(prefixes) = self.by_key.get_mut(&key) else {
+                continue;
+            };
+            if let Some(muis) = prefixes.get_mut(&prefix) {
+                muis.remove(mui);
+                if muis.is_empty() {
+                    prefixes.remove(&prefix);
+                }
+            }
+            if prefixes.is_empty() {
+                self.by_key.remove(&key);
+            }
+        }
+    }
+}
+
+// An index with its maps, with the type of its keys erased, so that the
+// indexes with different types of keys can live together in a RIB. The
+// index itself is shared by all the RIBs that are created from the same
+// configuration, every RIB has its own maps.
+struct IndexStore<K, I> {
+    index: Arc<I>,
+    maps: RwLock<IndexMaps<K>>,
+}
+
+trait ErasedIndex<M: Meta>: Send + Sync {
+    fn set(
+        &self,
+        prefix: Prefix,
+        mui: u32,
+        meta: Option<&M>,
+    ) -> FatalResult<()>;
+    fn remove_prefix(&self, prefix: Prefix) -> FatalResult<()>;
+    fn prefixes_for_mui(&self, mui: u32) -> FatalResult<Vec<Prefix>>;
+    // The maps of the index, as a `RwLock<IndexMaps<K>>`.
+    fn maps_any(&self) -> &dyn Any;
+}
+
+impl<M, K, I> ErasedIndex<M> for IndexStore<K, I>
+where
+    M: Meta,
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    I: SecondaryIndex<M, Key = K>,
+{
+    fn set(
+        &self,
+        prefix: Prefix,
+        mui: u32,
+        meta: Option<&M>,
+    ) -> FatalResult<()> {
+        let mut keys = meta.map(|m| self.index.keys(m)).unwrap_or_default();
+        let mut seen = HashSet::new();
+        keys.retain(|k| seen.insert(k.clone()));
+
+        self.maps
+            .write()
+            .map_err(|_| FatalError)?
+            .set(prefix, mui, keys);
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: Prefix) -> FatalResult<()> {
+        let mut maps = self.maps.write().map_err(|_| FatalError)?;
+        let muis = maps
+            .by_record
+            .get(&prefix)
+            .map(|muis| muis.keys().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for mui in muis {
+            maps.remove(prefix, mui);
+        }
+        Ok(())
+    }
+
+    fn prefixes_for_mui(&self, mui: u32) -> FatalResult<Vec<Prefix>> {
+        let maps = self.maps.read().map_err(|_| FatalError)?;
+        Ok(maps
+            .by_mui
+            .get(&mui)
+            .map(|prefixes| prefixes.iter().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn maps_any(&self) -> &dyn Any {
+        &self.maps
+    }
+}
+
+// Creates the empty index for a RIB, from an index that was registered in
+// its configuration.
+pub(crate) struct IndexFactory<M: Meta>(
+    Arc<dyn Fn() -> Box<dyn ErasedIndex<M>> + Send + Sync>,
+);
+
+impl<M: Meta> IndexFactory<M> {
+    pub(crate) fn new<I: SecondaryIndex<M>>(index: I) -> Self {
+        let index = Arc::new(index);
+        Self(Arc::new(move || {
+            Box::new(IndexStore {
+                index: Arc::clone(&index),
+                maps: RwLock::new(IndexMaps::new()),
+            })
+        }))
+    }
+}
+
+impl<M: Meta> Clone for IndexFactory<M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+// The number of locks that serialize the changes to the records, and
+// their keys, per prefix.
+const LOCK_SHARDS: usize = 64;
+
+// All the secondary indexes of a RIB, by name.
+pub(crate) struct SecondaryIndexes<M: Meta> {
+    indexes: Vec<(String, Box<dyn ErasedIndex<M>>)>,
+    // Serialize the changes to the records of a prefix together with the
+    // updates of their keys, so that the indexes of a record end up with
+    // the keys of the record that was written last, if the record is
+    // changed by multiple threads at the same time. The prefixes are
+    // spread over the locks by their hash.
+    locks: Vec<Mutex<()>>,
+    hasher: RandomState,
+}
+
+impl<M: Meta> SecondaryIndexes<M> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    // Create the empty indexes for a RIB, from the indexes with their
+    // names in its configuration.
+    pub(crate) fn new(factories: &[(String, IndexFactory<M>)]) -> Self {
+        Self {
+            indexes: factories
+                .iter()
+                .map(|(name, factory)| (name.clone(), (factory.0)()))
+                .collect(),
+            locks: (0..LOCK_SHARDS).map(|_| Mutex::new(())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, prefix: &Prefix) -> usize {
+        self.hasher.hash_one(prefix) as usize % LOCK_SHARDS
+    }
+
+    // The shards are always below LOCK_SHARDS, the number of locks.
+    #[allow(clippy::indexing_slicing)]
+    fn lock_shard(&self, shard: usize) -> MutexGuard<'_, ()> {
+        self.locks[shard]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Lock the changes to the records of a prefix, or `None` if there are
+    // no indexes to keep up to date. The lock is held while the records
+    // are changed, and until the indexes are updated. The locks only
+    // guard the order of the updates, so a poisoned lock is still used.
+    pub(crate) fn lock(&self, prefix: &Prefix) -> Option<MutexGuard<'_, ()>> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.lock_shard(self.shard(prefix)))
+    }
+
+    // Lock the changes to the records of all the `prefixes`, e.g. for a
+    // batch of inserts. The locks are taken in the order of the shards, so
+    // that two batches cannot deadlock.
+    pub(crate) fn lock_all<'a>(
+        &self,
+        prefixes: impl Iterator<Item = &'a Prefix>,
+    ) -> Vec<MutexGuard<'_, ()>> {
+        if self.is_empty() {
+            return vec![];
+        }
+        let mut shards = prefixes.map(|p| self.shard(p)).collect::<Vec<_>>();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+            .into_iter()
+            .map(|shard| self.lock_shard(shard))
+            .collect()
+    }
+
+    // Fill the indexes with the keys of `records`, e.g. the records that
+    // were restored from disk.
+    pub(crate) fn fill(
+        &self,
+        records: impl Iterator<Item = FatalResult<(Prefix, u32, M)>>,
+    ) -> FatalResult<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        for rec in records {
+            let (prefix, mui, meta) = rec?;
+            for (_, index) in &self.indexes {
+                index.set(prefix, mui, Some(&meta))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Set the keys for the (prefix, mui) pair in all the indexes to the
+    // keys of `meta`, the metadata of the record that was just written, or
+    // remove them if the record was removed. The caller holds the lock
+    // for the prefix.
+    pub(crate) fn set(
+        &self,
+        prefix: Prefix,
+        mui: u32,
+        meta: Option<&M>,
+    ) -> FatalResult<()> {
+        for (_, index) in &self.indexes {
+            index.set(prefix, mui, meta)?;
+        }
+        Ok(())
+    }
+
+    // Remove the keys for all the records of the prefix. The caller holds
+    // the lock for the prefix.
+    pub(crate) fn remove_prefix(&self, prefix: Prefix) -> FatalResult<()> {
+        for (_, index) in &self.indexes {
+            index.remove_prefix(prefix)?;
+        }
+        Ok(())
+    }
+
+    // Update the keys for the records of the mui, after its records were
+    // removed, to the keys of the current record for every prefix that
+    // `current` reads from the RIB. Records for the mui can be inserted
+    // while it is removed, so the current record is read under the lock
+    // for the prefix.
+    pub(crate) fn remove_mui(
+        &self,
+        mui: u32,
+        current: impl Fn(&Prefix) -> FatalResult<Option<M>>,
+    ) -> FatalResult<()> {
+        let mut prefixes = HashSet::new();
+        for (_, index) in &self.indexes {
+            prefixes.extend(index.prefixes_for_mui(mui)?);
+        }
+        for prefix in prefixes {
+            let _lock = self.lock(&prefix);
+            let meta = current(&prefix)?;
+            self.set(prefix, mui, meta.as_ref())?;
+        }
+        Ok(())
+    }
+
+    // The (prefix, muis) pairs for a key in the index with this name and
+    // type of keys, or `None` if there is no such index.
+    pub(crate) fn lookup<K: Eq + Hash + 'static>(
+        &self,
+        name: &str,
+        key: &K,
+    ) -> Option<FatalResult<Vec<(Prefix, RoaringBitmap)>>> {
+        let (_, index) = self.indexes.iter().find(|(n, _)| n == name)?;
+        let maps = index.maps_any().downcast_ref::<RwLock<IndexMaps<K>>>()?;
+        Some(maps.read().map_err(|_| FatalError).map(|maps| {
+            maps.by_key
+                .get(key)
+                .map(|prefixes| {
+                    prefixes
+                        .iter()
+                        .map(|(p, muis)| (*p, muis.clone()))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }))
+    }
+}
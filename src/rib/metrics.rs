@@ -0,0 +1,51 @@
+//------------ Metrics -------------------------------------------------------
+
+// Rendering of the counters of a RIB in the Prometheus text exposition
+// format, see StarCastRib::write_metrics. The metrics are written straight
+// into the writer, so that a scrape into a reused buffer doesn't allocate.
+
+use std::fmt::{self, Display, Write};
+
+// The prefix for the names of all the metrics of a RIB.
+const PREFIX: &str = "rotonda_store_";
+
+pub(crate) struct MetricsWriter<'a, W> {
+    target: &'a mut W,
+}
+
+impl<'a, W: Write> MetricsWriter<'a, W> {
+    pub(crate) fn new(target: &'a mut W) -> Self {
+        Self { target }
+    }
+
+    // The HELP and TYPE lines for a metric, that should be followed by all
+    // its samples.
+    pub(crate) fn header(
+        &mut self,
+        name: &str,
+        metric_type: &str,
+        help: &str,
+    ) -> fmt::Result {
+        writeln!(self.target, "# HELP {PREFIX}{name} {help}")?;
+        writeln!(self.target, "# TYPE {PREFIX}{name} {metric_type}")
+    }
+
+    // A sample for a metric, with its labels. The values of the labels are
+    // not escaped.
+    pub(crate) fn sample(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &dyn Display)],
+        value: impl Display,
+    ) -> fmt::Result {
+        write!(self.target, "{PREFIX}{name}")?;
+        for (i, (label, label_value)) in labels.iter().enumerate() {
+            let sep = if i == 0 { '{' } else { ',' };
+            write!(self.target, "{sep}{label}=\"{label_value}\"")?;
+        }
+        if !labels.is_empty() {
+            self.target.write_char('}')?;
+        }
+        writeln!(self.target, " {value}")
+    }
+}
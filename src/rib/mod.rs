@@ -1,7 +1,15 @@
 pub mod config;
+pub mod index;
+pub(crate) mod metrics;
+pub mod mrt;
+pub mod pagination;
+pub mod path_selection;
+pub mod rov;
+pub(crate) mod snapshot;
 pub(crate) mod starcast;
 pub(crate) mod starcast_af;
 pub(crate) mod starcast_af_query;
+pub mod subscription;
 
 pub(crate) use starcast::BIT_SPAN_SIZE;
 pub(crate) use starcast::STRIDE_SIZE;
@@ -0,0 +1,191 @@
+//------------ MRT Export ----------------------------------------------------
+
+// Writes the current records of a RIB as an MRT TABLE_DUMP_V2 file (RFC
+// 6396, section 4.3): one PEER_INDEX_TABLE message, followed by one
+// RIB_IPV4_UNICAST, or RIB_IPV6_UNICAST, message per prefix. The metadata
+// of a record is written verbatim as the BGP path attributes of its RIB
+// entry, which is how `load_mrt` stores them.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr};
+
+use inetnum::addr::Prefix;
+
+use crate::prefix_record::{Meta, Record};
+
+// MRT type and subtypes (RFC 6396, section 4).
+const TABLE_DUMP_V2: u16 = 13;
+const PEER_INDEX_TABLE: u16 = 1;
+const RIB_IPV4_UNICAST: u16 = 2;
+const RIB_IPV6_UNICAST: u16 = 4;
+
+// Flags in the peer type of a peer entry.
+const PEER_TYPE_IPV6: u8 = 0x01;
+const PEER_TYPE_AS4: u8 = 0x02;
+
+/// A BGP peer in the PEER_INDEX_TABLE of an MRT export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MrtPeer {
+    /// The BGP Identifier of the peer.
+    pub bgp_id: Ipv4Addr,
+    /// The IP address of the peer.
+    pub addr: IpAddr,
+    /// The AS number of the peer.
+    pub asn: u32,
+}
+
+/// The peers for an MRT export, keyed on the `mui` of the records that
+/// were received from them.
+///
+/// The peers are written to the PEER_INDEX_TABLE ordered by `mui`, so the
+/// peer index of a RIB entry is the position of its `mui` in `peers`.
+#[derive(Clone, Debug)]
+pub struct MrtPeerIndex {
+    /// The BGP Identifier of the collector.
+    pub collector_bgp_id: Ipv4Addr,
+    /// The optional name of the view, may be empty.
+    pub view_name: String,
+    /// The peer for each `mui`.
+    pub peers: BTreeMap<u32, MrtPeer>,
+}
+
+pub(crate) struct MrtWriter<W: Write> {
+    writer: W,
+    // The timestamp for the common header of all messages.
+    timestamp: u32,
+    // The sequence number of the next RIB message.
+    seq_number: u32,
+    // The index in the PEER_INDEX_TABLE for each mui.
+    peer_indexes: BTreeMap<u32, u16>,
+}
+
+impl<W: Write> MrtWriter<W> {
+    // Create a writer, and write the PEER_INDEX_TABLE.
+    pub(crate) fn new(
+        writer: W,
+        peer_index: &MrtPeerIndex,
+        timestamp: u32,
+    ) -> io::Result<Self> {
+        let peer_count = u16::try_from(peer_index.peers.len())
+            .map_err(|_| invalid_data("Too many peers for an MRT export"))?;
+        let view_name_len = u16::try_from(peer_index.view_name.len())
+            .map_err(|_| invalid_data("View name too long"))?;
+
+        let mut msg = vec![];
+        msg.extend_from_slice(&peer_index.collector_bgp_id.octets());
+        msg.extend_from_slice(&view_name_len.to_be_bytes());
+        msg.extend_from_slice(peer_index.view_name.as_bytes());
+        msg.extend_from_slice(&peer_count.to_be_bytes());
+        for peer in peer_index.peers.values() {
+            // The AS numbers are always written as four octets.
+            match peer.addr {
+                IpAddr::V4(addr) => {
+                    msg.push(PEER_TYPE_AS4);
+                    msg.extend_from_slice(&peer.bgp_id.octets());
+                    msg.extend_from_slice(&addr.octets());
+                }
+                IpAddr::V6(addr) => {
+                    msg.push(PEER_TYPE_AS4 | PEER_TYPE_IPV6);
+                    msg.extend_from_slice(&peer.bgp_id.octets());
+                    msg.extend_from_slice(&addr.octets());
+                }
+            }
+            msg.extend_from_slice(&peer.asn.to_be_bytes());
+        }
+
+        let mut mrt_writer = Self {
+            writer,
+            timestamp,
+            seq_number: 0,
+            peer_indexes: peer_index
+                .peers
+                .keys()
+                .zip(0..)
+                .map(|(mui, idx)| (*mui, idx))
+                .collect(),
+        };
+        mrt_writer.write_message(PEER_INDEX_TABLE, &msg)?;
+
+        Ok(mrt_writer)
+    }
+
+    // Write a RIB message with an entry for each record of the prefix that
+    // has a peer in the index. Records without a peer are skipped, and no
+    // message is written if none of the records have one. Returns the
+    // number of written entries.
+    pub(crate) fn write_rib<M: Meta>(
+        &mut self,
+        prefix: Prefix,
+        records: &[Record<M>],
+    ) -> io::Result<usize> {
+        let mut entries = vec![];
+        let mut entry_count = 0_u16;
+        for rec in records {
+            let Some(peer_idx) = self.peer_indexes.get(&rec.multi_uniq_id)
+            else {
+                continue;
+            };
+            let attributes = rec.meta.as_ref();
+            let attr_len = u16::try_from(attributes.len()).map_err(|_| {
+                invalid_data("Path attributes too long for an MRT export")
+            })?;
+            entry_count = entry_count.checked_add(1).ok_or_else(|| {
+                invalid_data("Too many records for a prefix")
+            })?;
+
+            entries.extend_from_slice(&peer_idx.to_be_bytes());
+            // The originated time is the ltime, that should hold seconds
+            // since the Unix epoch.
+            let originated = u32::try_from(rec.ltime).map_err(|_| {
+                invalid_data("Ltime too large for the originated time")
+            })?;
+            entries.extend_from_slice(&originated.to_be_bytes());
+            entries.extend_from_slice(&attr_len.to_be_bytes());
+            entries.extend_from_slice(attributes);
+        }
+
+        if entry_count == 0 {
+            return Ok(0);
+        }
+
+        let (subtype, addr_bytes) = match prefix.addr() {
+            IpAddr::V4(addr) => (RIB_IPV4_UNICAST, addr.octets().to_vec()),
+            IpAddr::V6(addr) => (RIB_IPV6_UNICAST, addr.octets().to_vec()),
+        };
+        let mut msg = vec![];
+        msg.extend_from_slice(&self.seq_number.to_be_bytes());
+        msg.push(prefix.len());
+        msg.extend(
+            addr_bytes
+                .into_iter()
+                .take(usize::from(prefix.len()).div_ceil(8)),
+        );
+        msg.extend_from_slice(&entry_count.to_be_bytes());
+        msg.extend_from_slice(&entries);
+
+        self.write_message(subtype, &msg)?;
+        self.seq_number = self.seq_number.wrapping_add(1);
+
+        Ok(entry_count.into())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // Write a message with its common header.
+    fn write_message(&mut self, subtype: u16, msg: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(msg.len())
+            .map_err(|_| invalid_data("MRT message too long"))?;
+        self.writer.write_all(&self.timestamp.to_be_bytes())?;
+        self.writer.write_all(&TABLE_DUMP_V2.to_be_bytes())?;
+        self.writer.write_all(&subtype.to_be_bytes())?;
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(msg)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,134 @@
+//------------ Pagination ----------------------------------------------------
+
+// Paging through the RIB, across requests, without holding on to an epoch
+// guard. A page is a run of records, in (prefix, mui) order, i.e. the order
+// of the sorted iterators with the records of a prefix ordered by mui. The
+// cursor for the next page is the last (prefix, mui) pair on a page, so that
+// the next page can start right after it, even if the RIB changed in
+// between.
+
+use std::net::IpAddr;
+
+use inetnum::addr::Prefix;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::FatalResult;
+use crate::prefix_record::{Meta, PrefixRecord, Record};
+
+/// The position in the RIB to resume a paginated iteration from.
+///
+/// A cursor is returned with every [Page] that is not the last one. It can
+/// be serialized, e.g. into a token for the next request of an API client,
+/// and deserialized again to request the next page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "CursorRepr", into = "CursorRepr")]
+pub struct PageCursor {
+    prefix: Prefix,
+    mui: u32,
+}
+
+impl PageCursor {
+    pub(crate) fn new(prefix: Prefix, mui: u32) -> Self {
+        Self { prefix, mui }
+    }
+
+    pub(crate) fn prefix(&self) -> Prefix {
+        self.prefix
+    }
+
+    pub(crate) fn mui(&self) -> u32 {
+        self.mui
+    }
+}
+
+// The serialized form of a cursor. Deserializing checks that the prefix is
+// valid.
+#[derive(Serialize, Deserialize)]
+struct CursorRepr {
+    addr: IpAddr,
+    len: u8,
+    mui: u32,
+}
+
+impl From<PageCursor> for CursorRepr {
+    fn from(cursor: PageCursor) -> Self {
+        Self {
+            addr: cursor.prefix.addr(),
+            len: cursor.prefix.len(),
+            mui: cursor.mui,
+        }
+    }
+}
+
+impl TryFrom<CursorRepr> for PageCursor {
+    type Error = String;
+
+    fn try_from(repr: CursorRepr) -> Result<Self, Self::Error> {
+        Prefix::new(repr.addr, repr.len)
+            .map(|prefix| PageCursor::new(prefix, repr.mui))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// A page of records from the RIB, as returned by
+/// [prefixes_page](crate::rib::StarCastRib::prefixes_page) and
+/// [more_specifics_page](crate::rib::StarCastRib::more_specifics_page).
+#[derive(Clone, Debug)]
+pub struct Page<M: Meta> {
+    /// The prefixes with their records, ordered by prefix address and
+    /// length, and the records for a prefix ordered by mui. The records for
+    /// a prefix can be split over two pages.
+    pub records: Vec<PrefixRecord<M>>,
+    /// The cursor to request the next page with, or `None` if this is the
+    /// last page.
+    pub next_cursor: Option<PageCursor>,
+}
+
+// Collect a page of at most `limit` records from `prefixes`, whose records
+// must be ordered by mui, and must not be empty.
+pub(crate) fn collect_page<M: Meta>(
+    mut prefixes: impl Iterator<Item = FatalResult<(Prefix, Vec<Record<M>>)>>,
+    limit: usize,
+) -> FatalResult<Page<M>> {
+    let limit = limit.max(1);
+    let mut records = vec![];
+    let mut count = 0;
+    let mut last = None;
+
+    while count < limit {
+        let Some(res) = prefixes.next() else {
+            return Ok(Page {
+                records,
+                next_cursor: None,
+            });
+        };
+        let (prefix, mut recs) = res?;
+        let split = recs.len() > limit - count;
+        recs.truncate(limit - count);
+        count += recs.len();
+        last = recs
+            .last()
+            .map(|r| PageCursor::new(prefix, r.multi_uniq_id));
+        records.push(PrefixRecord::new(prefix, recs));
+
+        // The rest of the records for this prefix are for the next page.
+        if split {
+            return Ok(Page {
+                records,
+                next_cursor: last,
+            });
+        }
+    }
+
+    // The page is full, there is a next page only if there are more
+    // records.
+    let next_cursor = match prefixes.next().transpose()? {
+        Some(_) => last,
+        None => None,
+    };
+
+    Ok(Page {
+        records,
+        next_cursor,
+    })
+}
@@ -0,0 +1,98 @@
+//------------ Path Selection ------------------------------------------------
+
+// The selection of the best and backup path for a prefix from its records
+// is delegated to a PathSelector, that is set in the config of the RIB. The
+// default selector orders the records with the `Orderable` of their
+// metadata, like the BGP Decision Process in routecore does for BGP routes.
+
+use std::sync::Arc;
+
+use crate::prefix_record::{Meta, RouteStatus};
+
+/// A record for a prefix, as a candidate for the best and backup path.
+#[derive(Debug)]
+pub struct PathCandidate<'a, M> {
+    pub multi_uniq_id: u32,
+    pub ltime: u64,
+    /// The status of the record. This is `Withdrawn` if the record, or its
+    /// `multi_uniq_id`, globally, is withdrawn.
+    pub status: RouteStatus,
+    pub meta: &'a M,
+}
+
+/// A strategy for selecting the best and backup path for a prefix.
+///
+/// A selector is set in the [MetaConfig](crate::rib::config::MetaConfig)
+/// of a RIB with [set_path_selector](
+/// crate::rib::config::MetaConfig::set_path_selector), and is used by
+/// [calculate_and_store_best_and_backup_path](
+/// crate::rib::StarCastRib::calculate_and_store_best_and_backup_path), by
+/// inserts that update the path selections, and for the best path of
+/// [calculate_and_store_multipath](
+/// crate::rib::StarCastRib::calculate_and_store_multipath). A RIB without a
+/// selector uses the [DefaultPathSelector]. For a RIB with the automatic
+/// path selection, see [set_auto_path_selection](
+/// crate::rib::config::MetaConfig::set_auto_path_selection), the withdrawn
+/// records are left out of the candidates.
+pub trait PathSelector<M: Meta>: Send + Sync {
+    /// Returns the `multi_uniq_id`s of the best and the backup path from
+    /// the records for a prefix, in no particular order. The tie breaker
+    /// info is the one that was passed in for the selection.
+    fn best_backup(
+        &self,
+        candidates: &[PathCandidate<'_, M>],
+        tbi: M::TBI,
+    ) -> (Option<u32>, Option<u32>);
+}
+
+/// The default [PathSelector], that selects the best and backup path with
+/// `best_backup_generic` from routecore, on the `Orderable` of the
+/// metadata of the records.
+///
+/// The status of the records is not taken into account.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultPathSelector;
+
+impl<M: Meta> PathSelector<M> for DefaultPathSelector {
+    fn best_backup(
+        &self,
+        candidates: &[PathCandidate<'_, M>],
+        tbi: M::TBI,
+    ) -> (Option<u32>, Option<u32>) {
+        let ord_routes = candidates
+            .iter()
+            .map(|c| (c.meta.as_orderable(tbi), c.multi_uniq_id));
+        let (best, backup) =
+            routecore::bgp::path_selection::best_backup_generic(ord_routes);
+        (best.map(|b| b.1), backup.map(|b| b.1))
+    }
+}
+
+// The selector of a RIB, shared by its address families.
+pub(crate) struct SharedPathSelector<M>(Arc<dyn PathSelector<M>>);
+
+impl<M: Meta> SharedPathSelector<M> {
+    pub(crate) fn new(selector: impl PathSelector<M> + 'static) -> Self {
+        Self(Arc::new(selector))
+    }
+}
+
+impl<M> Clone for SharedPathSelector<M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<M> std::ops::Deref for SharedPathSelector<M> {
+    type Target = dyn PathSelector<M>;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl<M> std::fmt::Debug for SharedPathSelector<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedPathSelector")
+    }
+}
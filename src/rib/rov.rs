@@ -0,0 +1,399 @@
+//------------ Route Origin Validation ---------------------------------------
+
+// RPKI Route Origin Validation (RFC 6811) of the records in a RIB against a
+// table of Validated ROA Payloads (VRPs). The VRP prefixes are indexed with
+// a tree bitmap per address family, the same one that backs the RIB, so that
+// finding the VRPs that cover a route is a walk over the less-specifics of
+// its prefix. The (max length, origin ASN) pairs for each VRP prefix live in
+// a plain HashMap, since the table is only read once it is loaded.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use inetnum::addr::Prefix;
+use inetnum::asn::Asn;
+use serde_derive::Deserialize;
+
+use crate::match_options::{MatchType, QueryResult};
+use crate::prefix_record::{Meta, PrefixRecord, Record, RecordSet};
+use crate::types::errors::PrefixStoreError;
+use crate::types::PrefixId;
+use crate::{AddressFamily, IPv4, IPv6, TreeBitMap};
+
+/// The route origin validation status of a route, as defined in RFC 6811.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RovStatus {
+    /// There is a VRP that covers the prefix of the route, and that matches
+    /// its origin AS and length.
+    Valid,
+    /// There are VRPs that cover the prefix of the route, but none of them
+    /// matches its origin AS and length.
+    Invalid,
+    /// There is no VRP that covers the prefix of the route.
+    NotFound,
+}
+
+impl fmt::Display for RovStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RovStatus::Valid => write!(f, "valid"),
+            RovStatus::Invalid => write!(f, "invalid"),
+            RovStatus::NotFound => write!(f, "not-found"),
+        }
+    }
+}
+
+/// A Validated ROA Payload: the prefixes up to `max_len` within `prefix`
+/// may be originated by `asn`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Vrp {
+    pub prefix: Prefix,
+    pub max_len: u8,
+    pub asn: Asn,
+}
+
+//------------ VrpTable ------------------------------------------------------
+
+/// A table of VRPs to validate the origins of routes against.
+///
+/// A table can be loaded from the JSON or CSV exports of the VRPs of
+/// relying party software like rpki-client and Routinator, or filled with
+/// [insert](Self::insert). Routes can be validated one by one with
+/// [validate](Self::validate), or in bulk with the records returned by a
+/// RIB, through the origin AS that [Meta::origin_asn] extracts from their
+/// metadata.
+#[derive(Debug)]
+pub struct VrpTable {
+    v4: TreeBitMap<IPv4, 9>,
+    v6: TreeBitMap<IPv6, 33>,
+    // The (max length, origin ASN) pairs for every VRP prefix.
+    vrps: HashMap<Prefix, Vec<(u8, Asn)>>,
+    len: usize,
+}
+
+impl VrpTable {
+    /// Create a new, empty, VRP table.
+    pub fn new() -> Result<Self, PrefixStoreError> {
+        Ok(Self {
+            v4: TreeBitMap::new()
+                .map_err(|_| PrefixStoreError::StoreNotReadyError)?,
+            v6: TreeBitMap::new()
+                .map_err(|_| PrefixStoreError::StoreNotReadyError)?,
+            vrps: HashMap::new(),
+            len: 0,
+        })
+    }
+
+    /// Create a VRP table from a JSON export, in the format of
+    /// rpki-client's `json` output, or Routinator's `json` and `jsonext`
+    /// output formats, i.e. an object with a `roas` array of objects with
+    /// the `asn`, `prefix` and `maxLength` fields. The `asn` can be a
+    /// number, or a string like `"AS65000"`. All other fields are ignored.
+    pub fn from_json_reader(
+        reader: impl Read,
+    ) -> Result<Self, Box<dyn Error>> {
+        let export: JsonExport = serde_json::from_reader(reader)?;
+        let mut table = Self::new()?;
+        for roa in export.roas {
+            let asn = match roa.asn {
+                JsonAsn::Number(asn) => Asn::from_u32(asn),
+                JsonAsn::Text(asn) => Asn::from_str(&asn)
+                    .map_err(|_| format!("invalid ASN in VRP: {}", asn))?,
+            };
+            let prefix = Prefix::from_str(&roa.prefix).map_err(|e| {
+                format!("invalid prefix in VRP: {}: {}", roa.prefix, e)
+            })?;
+            table.insert(Vrp {
+                prefix,
+                max_len: roa.max_length,
+                asn,
+            })?;
+        }
+
+        Ok(table)
+    }
+
+    /// Create a VRP table from a CSV export, in the format of rpki-client's
+    /// `csv` output, or Routinator's `csv` output format, i.e. lines with
+    /// the origin ASN, the prefix, the max length and the trust anchor,
+    /// e.g. `AS65000,192.0.2.0/24,24,ripe`, with an optional header line.
+    /// Any fields after the max length are ignored.
+    pub fn from_csv_reader(
+        reader: impl Read,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut table = Self::new()?;
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split(',').map(str::trim);
+            let asn = fields.next().unwrap_or_default();
+            let asn = match Asn::from_str(asn) {
+                Ok(asn) => asn,
+                // The header line
+                Err(_) if i == 0 => continue,
+                Err(_) => {
+                    return Err(format!(
+                        "invalid ASN in VRP on line {}: {}",
+                        i + 1,
+                        asn
+                    )
+                    .into());
+                }
+            };
+            let (Some(prefix), Some(max_len)) =
+                (fields.next(), fields.next())
+            else {
+                return Err(
+                    format!("missing fields on line {}", i + 1).into()
+                );
+            };
+            table.insert(Vrp {
+                prefix: Prefix::from_str(prefix).map_err(|e| {
+                    format!("invalid prefix on line {}: {}", i + 1, e)
+                })?,
+                max_len: u8::from_str(max_len).map_err(|e| {
+                    format!("invalid max length on line {}: {}", i + 1, e)
+                })?,
+                asn,
+            })?;
+        }
+
+        Ok(table)
+    }
+
+    /// Create a VRP table from a file with a JSON export, see
+    /// [from_json_reader](Self::from_json_reader).
+    pub fn load_json<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::from_json_reader(std::fs::File::open(path)?)
+    }
+
+    /// Create a VRP table from a file with a CSV export, see
+    /// [from_csv_reader](Self::from_csv_reader).
+    pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::from_csv_reader(std::fs::File::open(path)?)
+    }
+
+    /// Add a VRP to the table. Adding a VRP that is already in the table
+    /// does nothing.
+    ///
+    /// Returns a `PrefixLengthInvalid` error if the max length of the VRP is
+    /// smaller than the length of its prefix, or larger than the length of
+    /// an address.
+    pub fn insert(&mut self, vrp: Vrp) -> Result<(), PrefixStoreError> {
+        let max_bits = if vrp.prefix.is_v4() {
+            IPv4::BITS
+        } else {
+            IPv6::BITS
+        };
+        if vrp.max_len < vrp.prefix.len() || vrp.max_len > max_bits {
+            return Err(PrefixStoreError::PrefixLengthInvalid);
+        }
+
+        let entries = self.vrps.entry(vrp.prefix).or_default();
+        if entries.contains(&(vrp.max_len, vrp.asn)) {
+            return Ok(());
+        }
+        if entries.is_empty() {
+            if vrp.prefix.is_v4() {
+                self.v4.set_prefix_exists(PrefixId::from(vrp.prefix), 0)?;
+            } else {
+                self.v6.set_prefix_exists(PrefixId::from(vrp.prefix), 0)?;
+            }
+        }
+        entries.push((vrp.max_len, vrp.asn));
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Returns the number of VRPs in the table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the table has no VRPs.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Validate a route, with the prefix and the origin AS of the route.
+    ///
+    /// Returns the [RovStatus] of the route, as defined in RFC 6811. VRPs
+    /// for AS0 never match a route (RFC 6483).
+    pub fn validate(&self, prefix: &Prefix, origin: Asn) -> RovStatus {
+        self.validate_origin(prefix, Some(origin))
+    }
+
+    /// Validate all the records of a prefix, through the origin AS that
+    /// [Meta::origin_asn] extracts from their metadata. Records without an
+    /// origin AS are `Invalid` if there are VRPs for their prefix, and
+    /// `NotFound` otherwise.
+    pub fn validate_prefix_record<M: Meta>(
+        &self,
+        prefix_record: PrefixRecord<M>,
+    ) -> ValidatedPrefixRecord<M> {
+        let prefix = prefix_record.prefix;
+        ValidatedPrefixRecord {
+            prefix,
+            records: self.validate_records(&prefix, prefix_record.meta),
+        }
+    }
+
+    /// Validate all the records in the result of a query on a RIB, see
+    /// [validate_prefix_record](Self::validate_prefix_record).
+    pub fn validate_query_result<M: Meta>(
+        &self,
+        query_result: QueryResult<M>,
+    ) -> RovQueryResult<M> {
+        let validate_set = |record_set: Option<RecordSet<M>>| {
+            record_set.map(|rs| {
+                rs.v4
+                    .into_iter()
+                    .chain(rs.v6)
+                    .map(|pr| self.validate_prefix_record(pr))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        RovQueryResult {
+            match_type: query_result.match_type,
+            prefix: query_result.prefix,
+            records: match query_result.prefix {
+                Some(prefix) => {
+                    self.validate_records(&prefix, query_result.records)
+                }
+                None => vec![],
+            },
+            less_specifics: validate_set(query_result.less_specifics),
+            more_specifics: validate_set(query_result.more_specifics),
+            history: validate_set(query_result.history),
+        }
+    }
+
+    fn validate_records<M: Meta>(
+        &self,
+        prefix: &Prefix,
+        records: Vec<Record<M>>,
+    ) -> Vec<ValidatedRecord<M>> {
+        records
+            .into_iter()
+            .map(|record| ValidatedRecord {
+                rov_status: self
+                    .validate_origin(prefix, record.meta.origin_asn()),
+                record,
+            })
+            .collect()
+    }
+
+    // RFC 6811, section 2: a route is Valid if a covering VRP matches its
+    // origin and length, Invalid if there are covering VRPs, but none
+    // match, and NotFound if there are none. A route without an origin
+    // (e.g. with an AS_SET at the end of its path) can not be matched.
+    fn validate_origin(
+        &self,
+        prefix: &Prefix,
+        origin: Option<Asn>,
+    ) -> RovStatus {
+        let covering: Box<dyn Iterator<Item = Prefix>> = if prefix.is_v4() {
+            Box::new(covering_prefixes(&self.v4, PrefixId::from(*prefix)))
+        } else {
+            Box::new(covering_prefixes(&self.v6, PrefixId::from(*prefix)))
+        };
+
+        let mut status = RovStatus::NotFound;
+        for vrp_prefix in covering {
+            for (max_len, asn) in
+                self.vrps.get(&vrp_prefix).into_iter().flatten()
+            {
+                if origin == Some(*asn)
+                    && *asn != Asn::from_u32(0)
+                    && prefix.len() <= *max_len
+                {
+                    return RovStatus::Valid;
+                }
+                status = RovStatus::Invalid;
+            }
+        }
+
+        status
+    }
+}
+
+// The VRP prefixes in the tree that cover this prefix, i.e. the prefix
+// itself, its less-specifics, and the default route.
+fn covering_prefixes<AF: AddressFamily, const ROOT_SIZE: usize>(
+    tree: &TreeBitMap<AF, ROOT_SIZE>,
+    prefix: PrefixId<AF>,
+) -> impl Iterator<Item = Prefix> + '_ {
+    let default_route = PrefixId::new(<AF as AddressFamily>::zero(), 0);
+    std::iter::once(prefix)
+        .filter(|p| p.len() > 0 && tree.prefix_exists(*p))
+        .chain(tree.less_specific_prefix_iter(prefix))
+        .chain(tree.default_route_exists().then_some(default_route))
+        .map(Prefix::from)
+}
+
+//------------ Validated Records ---------------------------------------------
+
+/// A record, with the route origin validation status of its route.
+#[derive(Clone, Debug)]
+pub struct ValidatedRecord<M> {
+    pub record: Record<M>,
+    pub rov_status: RovStatus,
+}
+
+/// A prefix with its validated records.
+#[derive(Clone, Debug)]
+pub struct ValidatedPrefixRecord<M> {
+    pub prefix: Prefix,
+    pub records: Vec<ValidatedRecord<M>>,
+}
+
+/// The result of a query on a RIB, with the route origin validation status
+/// for every record, as returned by
+/// [match_prefix_with_rov](crate::rib::StarCastRib::match_prefix_with_rov).
+///
+/// The fields are the same as those of a [QueryResult], with the less- and
+/// more-specifics, and the history, as lists of prefixes, the IPv4 prefixes
+/// first.
+#[derive(Clone, Debug)]
+pub struct RovQueryResult<M> {
+    pub match_type: MatchType,
+    pub prefix: Option<Prefix>,
+    pub records: Vec<ValidatedRecord<M>>,
+    pub less_specifics: Option<Vec<ValidatedPrefixRecord<M>>>,
+    pub more_specifics: Option<Vec<ValidatedPrefixRecord<M>>>,
+    pub history: Option<Vec<ValidatedPrefixRecord<M>>>,
+}
+
+//------------ Exports -------------------------------------------------------
+
+#[derive(Deserialize)]
+struct JsonExport {
+    roas: Vec<JsonVrp>,
+}
+
+#[derive(Deserialize)]
+struct JsonVrp {
+    asn: JsonAsn,
+    prefix: String,
+    #[serde(rename = "maxLength")]
+    max_length: u8,
+}
+
+// rpki-client exports ASNs as numbers, Routinator as "AS" strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonAsn {
+    Number(u32),
+    Text(String),
+}
@@ -0,0 +1,110 @@
+//------------ Snapshot ------------------------------------------------------
+
+// A snapshot of a persisted RIB is a directory with a copy of the persist
+// trees for both address families, in `ipv4/` and `ipv6/`, and a manifest
+// in `manifest.json`. For the PersistHistory strategy the current records
+// only live in memory, these are written to separate trees in
+// `current/ipv4/` and `current/ipv6/`. For the Hybrid strategy all the
+// records, the ones in memory and the evicted ones, are written to the
+// persist trees as if they were all evicted. The manifest is written last,
+// so a snapshot directory without one is incomplete.
+
+use std::path::Path;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::config::{Config, PersistStrategy};
+
+const MANIFEST_FILE: &str = "manifest.json";
+pub(crate) const CURRENT_DIR: &str = "current";
+
+// The version of the layout of a snapshot directory.
+const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SnapshotManifest {
+    pub version: u32,
+    pub config: ManifestConfig,
+    pub v4: AfManifest,
+    pub v6: AfManifest,
+}
+
+// The configuration of the RIB the snapshot was taken from.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ManifestConfig {
+    pub persist_strategy: PersistStrategy,
+    pub persist_path: Option<String>,
+    pub flush_memtable_size: Option<u32>,
+    pub flush_interval_ms: Option<u64>,
+}
+
+// The counters for the current records in the snapshot of one address
+// family, that are checked on restore, and the globally withdrawn muis at
+// the start of the snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AfManifest {
+    pub prefixes_count: Vec<usize>,
+    pub routes_count: usize,
+    pub withdrawn_muis: Vec<u32>,
+}
+
+impl SnapshotManifest {
+    pub(crate) fn new<C: Config>(
+        config: &C,
+        v4: AfManifest,
+        v6: AfManifest,
+    ) -> Self {
+        let flush_config = config.flush_config();
+
+        Self {
+            version: SNAPSHOT_VERSION,
+            config: ManifestConfig {
+                persist_strategy: config.persist_strategy(),
+                persist_path: config.persist_path(),
+                flush_memtable_size: flush_config.map(|f| f.memtable_size),
+                flush_interval_ms: flush_config
+                    .map(|f| f.interval.as_millis() as u64),
+            },
+            v4,
+            v6,
+        }
+    }
+
+    pub(crate) fn read(snapshot_dir: &Path) -> std::io::Result<Self> {
+        let manifest =
+            std::fs::read_to_string(snapshot_dir.join(MANIFEST_FILE))?;
+        let manifest: Self = serde_json::from_str(&manifest)?;
+        if manifest.version != SNAPSHOT_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unsupported snapshot version {}", manifest.version),
+            ));
+        }
+
+        Ok(manifest)
+    }
+
+    // Write the manifest through a temporary file, so that a manifest is
+    // either complete, or absent.
+    pub(crate) fn write(&self, snapshot_dir: &Path) -> std::io::Result<()> {
+        let tmp = snapshot_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp, snapshot_dir.join(MANIFEST_FILE))
+    }
+}
+
+// Copy a directory with all its contents.
+pub(crate) fn copy_dir_all(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let dst = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &dst)?;
+        } else {
+            std::fs::copy(entry.path(), dst)?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,20 +1,42 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::RangeBounds;
+use std::path::Path;
+
 use crossbeam_epoch::Guard;
 use inetnum::addr::Prefix;
+use log::{error, trace};
 use rand::prelude::*;
+#[cfg(feature = "rayon")]
+use rayon::iter::ParallelIterator;
 
 use crate::{
     epoch,
     errors::{FatalError, FatalResult},
-    match_options::{MatchOptions, QueryResult},
-    prefix_record::{Meta, PrefixRecord, Record},
-    rib::config::Config,
+    match_options::{MatchOptions, QueryResult, RangeMatch},
+    prefix_record::{Meta, PrefixRecord, Record, RouteStatus},
+    rib::config::{Config, MetaConfig},
     types::{errors::PrefixStoreError, PrefixId},
     AddressFamily, IPv4, IPv6,
 };
 
+use super::index::SecondaryIndexes;
+use super::metrics::MetricsWriter;
+use super::mrt::{MrtPeerIndex, MrtWriter};
+use super::pagination::{collect_page, Page, PageCursor};
+use super::rov::{RovQueryResult, VrpTable};
+use super::snapshot::{copy_dir_all, SnapshotManifest, CURRENT_DIR};
 use super::starcast_af::StarCastAfRib;
-use crate::rib::config::PersistStrategy;
-use crate::stats::{StoreStats, UpsertCounters, UpsertReport};
+use super::subscription::{
+    EventHub, Families, OverflowPolicy, RibEvent, RibEventKind, Subscription,
+    SubscriptionFilter,
+};
+use crate::lsm_tree::{BackgroundFlusher, PruneTask};
+use crate::rib::config::{PersistStrategy, RetentionPolicy};
+use crate::stats::{
+    BatchUpsertReport, EvictionCounters, MemoryReport, PruneReport,
+    StoreStats, UpsertCounters, UpsertReport,
+};
 
 pub const STRIDE_SIZE: u8 = 4;
 pub const BIT_SPAN_SIZE: u8 = 32;
@@ -59,6 +81,12 @@ pub struct StarCastRib<M: Meta, C: Config> {
     v4: StarCastAfRib<IPv4, M, 9, 33, C, 18>,
     v6: StarCastAfRib<IPv6, M, 33, 129, C, 30>,
     config: C,
+    flusher: Option<BackgroundFlusher>,
+    events: EventHub,
+    indexes: SecondaryIndexes<M>,
+    // The tie breaker info for the automatic best and backup path
+    // selection, if it is enabled.
+    auto_path_selection: Option<M::TBI>,
 }
 
 impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
@@ -82,6 +110,18 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
     /// permissions, etc.
     pub fn new_with_config(
         config: C,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new_with_meta_config(config, MetaConfig::default())
+    }
+
+    /// Create a new RIB with the specified [configuration](
+    /// crate::rib::config), and the settings that depend on the type of
+    /// the metadata in `meta_config`, e.g. the secondary indexes.
+    ///
+    /// See `new_with_config` for when creation may fail.
+    pub fn new_with_meta_config(
+        config: C,
+        meta_config: MetaConfig<M>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let rng = rand::rng();
         let uuid: String = rng
@@ -89,22 +129,194 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
             .take(12)
             .map(char::from)
             .collect();
+        let store_dir = config
+            .persist_path()
+            .map(|path| format!("{}/{}", path, uuid));
+
+        Self::init_with_store_dir(config, &meta_config, store_dir)
+    }
+
+    /// Open an existing persisted RIB with the specified [configuration](
+    /// crate::rib::config).
+    ///
+    /// Contrary to `new_with_config`, the persist path of the configuration
+    /// is used as the store directory as is, so that a RIB can be reopened
+    /// across restarts. The prefixes, the `mui`s per prefix, and the
+    /// counters are rebuilt from the persisted records. For the
+    /// `WriteAhead` strategy the most recent record for each `(prefix,
+    /// mui)` pair is loaded back into memory as well.
+    ///
+    /// Note that only records that were written to disk with
+    /// `flush_to_disk` can be recovered. For the `PersistHistory` strategy
+    /// the current records are not persisted, so only the prefixes that
+    /// have historical records will be restored. For the `Hybrid` strategy
+    /// the records that were evicted to disk, and the records that were in
+    /// memory when [shutdown](Self::shutdown) was called, will be restored.
+    /// These are all restored as evicted records, and are loaded back into
+    /// memory when they are accessed.
+    ///
+    /// The global status of the `mui`s is not persisted, so all `mui`s are
+    /// `Active` in the reopened RIB. Use `mark_mui_as_withdrawn` to
+    /// withdraw them again.
+    ///
+    /// Returns an error if the store directory does not exist, or if the
+    /// configuration does not have a persist path, e.g. for the
+    /// `MemoryOnly` strategy.
+    pub fn open(config: C) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open_with_meta_config(config, MetaConfig::default())
+    }
+
+    /// Open an existing persisted RIB with the specified [configuration](
+    /// crate::rib::config), and the settings that depend on the type of
+    /// the metadata in `meta_config`. The secondary indexes are filled with
+    /// the restored records.
+    ///
+    /// See `open` for the details on what gets restored.
+    pub fn open_with_meta_config(
+        config: C,
+        meta_config: MetaConfig<M>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let store_dir = Self::store_dir(&config)?;
+
+        for af_dir in ["ipv4", "ipv6"] {
+            if !Path::new(&store_dir).join(af_dir).is_dir() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("No persisted store found in {}", store_dir),
+                )
+                .into());
+            }
+        }
+
+        Self::open_store_dir(config, &meta_config, store_dir)
+    }
+
+    /// Open an existing persisted RIB with the specified [configuration](
+    /// crate::rib::config), or create a new one if there is no store
+    /// in the persist path yet.
+    ///
+    /// See `open` for the details on what gets restored.
+    pub fn open_or_create(
+        config: C,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open_or_create_with_meta_config(config, MetaConfig::default())
+    }
+
+    /// Open an existing persisted RIB, or create a new one, like
+    /// `open_or_create`, with the settings that depend on the type of the
+    /// metadata in `meta_config`.
+    pub fn open_or_create_with_meta_config(
+        config: C,
+        meta_config: MetaConfig<M>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let store_dir = Self::store_dir(&config)?;
+
+        Self::open_store_dir(config, &meta_config, store_dir)
+    }
+
+    fn store_dir(config: &C) -> Result<String, std::io::Error> {
+        match config.persist_strategy() {
+            PersistStrategy::MemoryOnly => None,
+            _ => config.persist_path(),
+        }
+        .ok_or_else(|| std::io::Error::other("Missing persistence path"))
+    }
+
+    fn open_store_dir(
+        config: C,
+        meta_config: &MetaConfig<M>,
+        store_dir: String,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let rib =
+            Self::init_with_store_dir(config, meta_config, Some(store_dir))?;
+
+        rib.v4.restore_from_persist_tree()?;
+        rib.v6.restore_from_persist_tree()?;
+        rib.fill_indexes()?;
+
+        Ok(rib)
+    }
+
+    fn init_with_store_dir(
+        config: C,
+        meta_config: &MetaConfig<M>,
+        store_dir: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut config_v4 = config.clone();
         let mut config_v6 = config.clone();
 
-        if let Some(path) = config_v4.persist_path() {
-            let pp = format!("{}/{}/ipv4/", path, uuid);
-            config_v4.set_persist_path(pp);
-        };
+        if let Some(store_dir) = store_dir {
+            config_v4.set_persist_path(format!("{}/ipv4/", store_dir));
+            config_v6.set_persist_path(format!("{}/ipv6/", store_dir));
+        }
 
-        if let Some(path) = config_v6.persist_path() {
-            config_v6.set_persist_path(format!("{}/{}/ipv6/", path, uuid));
+        let indexes = SecondaryIndexes::new(&meta_config.indexes);
+
+        let mut v4 = StarCastAfRib::new(config_v4)?;
+        let mut v6 = StarCastAfRib::new(config_v6)?;
+        if let Some(selector) = &meta_config.path_selector {
+            v4.path_selector = selector.clone();
+            v6.path_selector = selector.clone();
         }
+        let auto_path_selection = meta_config.auto_path_selection;
+        v4.active_paths_only = auto_path_selection.is_some();
+        v6.active_paths_only = auto_path_selection.is_some();
+
+        let prune_task: Option<PruneTask> = match (
+            config.retention_policy(),
+            v4.history_pruner(),
+            v6.history_pruner(),
+        ) {
+            (
+                Some(
+                    policy @ RetentionPolicy {
+                        prune_interval: Some(interval),
+                        ..
+                    },
+                ),
+                Some(v4_pruner),
+                Some(v6_pruner),
+            ) => {
+                let prune = move || {
+                    for report in
+                        [v4_pruner.prune(&policy), v6_pruner.prune(&policy)]
+                    {
+                        match report {
+                            Ok(report) => trace!("pruned {:?}", report),
+                            Err(err) => error!("pruning failed: {}", err),
+                        }
+                    }
+                };
+                Some((interval, Box::new(prune)))
+            }
+            _ => None,
+        };
+
+        let flusher = match (&v4.persist_tree, config.flush_config()) {
+            (Some(v4_tree), flush_config)
+                if flush_config.is_some() || prune_task.is_some() =>
+            {
+                let mut trees = v4_tree.tree_handles();
+                trees.extend(
+                    v6.persist_tree.iter().flat_map(|t| t.tree_handles()),
+                );
+                Some(BackgroundFlusher::start(
+                    trees,
+                    flush_config,
+                    prune_task,
+                )?)
+            }
+            _ => None,
+        };
 
         Ok(Self {
-            v4: StarCastAfRib::new(config_v4)?,
-            v6: StarCastAfRib::new(config_v6)?,
+            v4,
+            v6,
             config,
+            flusher,
+            events: EventHub::default(),
+            indexes,
+            auto_path_selection,
         })
     }
 
@@ -143,6 +355,42 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         }
     }
 
+    /// Query the RIB for a matching prefix with options, as the RIB was at
+    /// the logical time `ltime`.
+    ///
+    /// For every `(prefix, multi_uniq_id)` the newest record with an
+    /// `ltime` lower than, or equal to, the requested `ltime` is selected,
+    /// with the [RouteStatus](crate::prefix_record::RouteStatus) it had at
+    /// that moment. Prefixes without any records at that moment do not
+    /// match. The global status of a `multi_uniq_id` is not taken into
+    /// account, and the `include_history` option is ignored.
+    ///
+    /// Only the `WriteAhead` and `PersistHistory` strategies keep the
+    /// historical records needed for this. For the other strategies only
+    /// the current records that are not newer than `ltime` are considered.
+    ///
+    /// Returns a [QueryResult] that may contain one or more prefixes, with or
+    /// without their associated records.
+    pub fn match_prefix_at(
+        &'a self,
+        search_pfx: &Prefix,
+        options: &MatchOptions,
+        ltime: u64,
+    ) -> FatalResult<QueryResult<M>> {
+        match search_pfx.addr() {
+            std::net::IpAddr::V4(_addr) => self.v4.match_prefix_at(
+                PrefixId::<IPv4>::from(*search_pfx),
+                options,
+                ltime,
+            ),
+            std::net::IpAddr::V6(_addr) => self.v6.match_prefix_at(
+                PrefixId::<IPv6>::from(*search_pfx),
+                options,
+                ltime,
+            ),
+        }
+    }
+
     /// Search the RIB for a prefix.
     ///
     /// Returns a bool indicating whether the prefix was found. Regardless of the chosen persist strategy
@@ -192,7 +440,9 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
     ///
     /// This method takes all the records for a prefix, i.e. all the records
     /// for different values of `mui` for this prefix, and calculates the best
-    /// path for them.
+    /// path for them, with the [PathSelector](
+    /// crate::rib::path_selection::PathSelector) of the RIB, see
+    /// [set_path_selector](crate::rib::config::MetaConfig::set_path_selector).
     ///
     /// Returns the values of `mui` for the best path, and the backup path,
     /// respectively.
@@ -205,7 +455,8 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         tbi: &<M as Meta>::TBI,
         guard: &Guard,
     ) -> Result<(Option<u32>, Option<u32>), PrefixStoreError> {
-        match search_pfx.addr() {
+        let old_best = self.best_path_mui_for_events(search_pfx);
+        let res = match search_pfx.addr() {
             std::net::IpAddr::V4(addr) => {
                 self.v4.calculate_and_store_best_and_backup_path(
                     PrefixId::<IPv4>::new(
@@ -226,7 +477,12 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
                     guard,
                 )
             }
+        };
+        if let (Ok(_), Some(previous)) = (&res, old_best) {
+            self.send_best_path_change(search_pfx, previous);
         }
+
+        res
     }
 
     /// Determine if a best path selection is based on stale records.
@@ -258,6 +514,97 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         }
     }
 
+    /// Return the previously calculated multipath for a prefix, if any.
+    ///
+    /// Returns the records for the `mui`s of the multipath, in order of
+    /// preference. Records that were withdrawn, or removed, since the
+    /// multipath was calculated are left out.
+    ///
+    /// Returns `None` if the prefix was not found in the RIB. Returns a
+    /// [BestPathNotFound](crate::errors::PrefixStoreError::BestPathNotFound)
+    /// error if the multipath was never calculated.
+    pub fn multipath(
+        &'a self,
+        search_pfx: &Prefix,
+        guard: &Guard,
+    ) -> Option<Result<Vec<Record<M>>, PrefixStoreError>> {
+        match search_pfx.addr() {
+            std::net::IpAddr::V4(_addr) => self
+                .v4
+                .multipath(PrefixId::<IPv4>::from(*search_pfx), guard),
+            std::net::IpAddr::V6(_addr) => self
+                .v6
+                .multipath(PrefixId::<IPv6>::from(*search_pfx), guard),
+        }
+    }
+
+    /// Calculate the multipath for a prefix.
+    ///
+    /// This method selects the best path from the active records for a
+    /// prefix with the [PathSelector](
+    /// crate::rib::path_selection::PathSelector) of the RIB, like
+    /// [calculate_and_store_best_and_backup_path](
+    /// Self::calculate_and_store_best_and_backup_path) does, and the paths
+    /// that are equivalent to it according to [Meta::multipath_equivalent],
+    /// e.g. for BGP multipath or add-path export, ordered by the `Orderable`
+    /// of their metadata. Records that are equally preferred are ordered by
+    /// `mui`. Withdrawn records, locally or globally, are left out.
+    ///
+    /// Returns the values of `mui` for the multipath, at most `max_paths`
+    /// of them, in order of preference.
+    /// Returns a [StoreNotReady](
+    /// crate::errors::PrefixStoreError::StoreNotReadyError) if the prefix
+    /// does not exist.
+    pub fn calculate_and_store_multipath(
+        &self,
+        search_pfx: &Prefix,
+        tbi: &<M as Meta>::TBI,
+        max_paths: usize,
+        guard: &Guard,
+    ) -> Result<Vec<u32>, PrefixStoreError> {
+        match search_pfx.addr() {
+            std::net::IpAddr::V4(_addr) => {
+                self.v4.calculate_and_store_multipath(
+                    PrefixId::<IPv4>::from(*search_pfx),
+                    tbi,
+                    max_paths,
+                    guard,
+                )
+            }
+            std::net::IpAddr::V6(_addr) => {
+                self.v6.calculate_and_store_multipath(
+                    PrefixId::<IPv6>::from(*search_pfx),
+                    tbi,
+                    max_paths,
+                    guard,
+                )
+            }
+        }
+    }
+
+    /// Determine if a multipath selection is based on stale records.
+    ///
+    /// Returns `Ok(true)` if the records have been updated since the last
+    /// multipath selection was performed.
+    /// Returns a [StoreNotReady](crate::errors::PrefixStoreError) if the
+    /// prefix cannot be found in the RIB.
+    pub fn is_multipath_outdated(
+        &self,
+        search_pfx: &Prefix,
+        guard: &Guard,
+    ) -> Result<bool, PrefixStoreError> {
+        match search_pfx.addr() {
+            std::net::IpAddr::V4(_addr) => self.v4.is_multipath_outdated(
+                PrefixId::<IPv4>::from(*search_pfx),
+                guard,
+            ),
+            std::net::IpAddr::V6(_addr) => self.v6.is_multipath_outdated(
+                PrefixId::<IPv6>::from(*search_pfx),
+                guard,
+            ),
+        }
+    }
+
     /// Request all more specific prefixes in the RIB for a certain
     /// prefix, including the prefix itself.
     ///
@@ -454,6 +801,128 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
             .chain(right.into_iter().flatten())
     }
 
+    /// Request an iterator over all more specific prefixes in the RIB for a
+    /// certain prefix, including the prefix itself, ordered by address,
+    /// and for the same address by prefix length.
+    ///
+    /// This is the same as [more_specifics_iter_from](
+    /// Self::more_specifics_iter_from), but ordered. The prefixes are found
+    /// by walking the nodes of the tree bitmap depth-first, without
+    /// collecting them first.
+    ///
+    /// Returns an iterator over [PrefixRecord].
+    pub fn more_specifics_iter_from_sorted(
+        &'a self,
+        search_pfx: &Prefix,
+        mui: Option<u32>,
+        include_withdrawn: bool,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+        let (left, right) = match search_pfx.addr() {
+            std::net::IpAddr::V4(_addr) => (
+                Some(
+                    self.v4
+                        .more_specifics_iter_from_sorted(
+                            PrefixId::<IPv4>::from(*search_pfx),
+                            mui,
+                            include_withdrawn,
+                            guard,
+                        )
+                        .map(|r| r.map(PrefixRecord::from)),
+                ),
+                None,
+            ),
+            std::net::IpAddr::V6(_addr) => (
+                None,
+                Some(
+                    self.v6
+                        .more_specifics_iter_from_sorted(
+                            PrefixId::<IPv6>::from(*search_pfx),
+                            mui,
+                            include_withdrawn,
+                            guard,
+                        )
+                        .map(|r| r.map(PrefixRecord::from)),
+                ),
+            ),
+        };
+
+        left.into_iter()
+            .flatten()
+            .chain(right.into_iter().flatten())
+    }
+
+    /// Request an iterator over all prefixes in the RIB for an address
+    /// range, from `start` up to and including `end`.
+    ///
+    /// The range does not have to line up with prefix boundaries. It is
+    /// split up in the smallest set of prefixes that covers it exactly, and
+    /// the more specifics of these prefixes are returned, ordered by these
+    /// prefixes. With [RangeMatch::Overlapping] the less specific prefixes
+    /// that cover part of the range are returned as well, before the
+    /// others. With [RangeMatch::Contained] only the prefixes that lie
+    /// completely within the range are returned.
+    ///
+    /// If a `mui` is specified only prefixes for that particular `mui`
+    /// are returned. If `None` is specified all prefixes, regardless of
+    /// their `mui` will be included in the returned result.
+    ///
+    /// if `include_withdrawn` is set to `true`, all prefixes that have a
+    /// status of `Withdrawn` will included in the returned result.
+    ///
+    /// Returns an [InvalidRange](crate::errors::PrefixStoreError::InvalidRange)
+    /// error if `start` lies after `end`, or if they are from different
+    /// address families. Otherwise returns an iterator over [PrefixRecord].
+    pub fn prefixes_in_range(
+        &'a self,
+        start: IpAddr,
+        end: IpAddr,
+        range_match: RangeMatch,
+        mui: Option<u32>,
+        include_withdrawn: bool,
+        guard: &'a Guard,
+    ) -> Result<
+        impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a,
+        PrefixStoreError,
+    > {
+        let blocks = range_to_prefixes(start, end)?;
+        let covering_blocks = match range_match {
+            RangeMatch::Overlapping => blocks.clone(),
+            RangeMatch::Contained => vec![],
+        };
+
+        // The less specifics of a block, other than the block itself, do not
+        // lie completely within the range, since the blocks are as large as
+        // possible. They can cover more than one block, though.
+        let mut seen = HashSet::new();
+        let covering = covering_blocks
+            .into_iter()
+            .flat_map(move |block| {
+                self.less_specifics_iter_from(
+                    &block,
+                    mui,
+                    include_withdrawn,
+                    guard,
+                )
+                .filter(move |r| {
+                    r.as_ref().map_or(true, |r| r.prefix != block)
+                })
+            })
+            .filter(move |r| {
+                r.as_ref().map_or(true, |r| seen.insert(r.prefix))
+            });
+        let contained = blocks.into_iter().flat_map(move |block| {
+            self.more_specifics_iter_from(
+                &block,
+                mui,
+                include_withdrawn,
+                guard,
+            )
+        });
+
+        Ok(covering.chain(contained))
+    }
+
     /// Request an iterator over all IPv4 prefixes in the RIB for a certain
     /// `mui`.
     ///
@@ -526,7 +995,10 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
     /// the RIB.
     ///
     /// If `update_path_selections` is passed in with the tie breaker info
-    /// then perform a best path selection.
+    /// then perform a best path selection. Otherwise, if the automatic path
+    /// selection is enabled, see [set_auto_path_selection](
+    /// crate::rib::config::MetaConfig::set_auto_path_selection), perform a best
+    /// path selection with its tie breaker info.
     ///
     /// Returns an iterator over [PrefixRecord].
     pub fn insert(
@@ -535,7 +1007,28 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         record: Record<M>,
         update_path_selections: Option<M::TBI>,
     ) -> Result<UpsertReport, PrefixStoreError> {
-        match prefix.addr() {
+        let mui = record.multi_uniq_id;
+        let wants_insert =
+            self.events.wants(Some(prefix), Families::Both, Some(mui));
+        let old_status = wants_insert
+            .then(|| self.local_status(prefix, mui))
+            .transpose()
+            .map_err(|_| PrefixStoreError::FatalError)?
+            .flatten();
+        // The automatic path selection is done after the insert, so that it
+        // also covers new prefixes.
+        let auto_tbi = match update_path_selections {
+            Some(_) => None,
+            None => self.auto_path_selection,
+        };
+        let old_best = update_path_selections
+            .or(auto_tbi)
+            .and_then(|_| self.best_path_mui_for_events(prefix));
+        let (new_status, ltime) = (record.status, record.ltime);
+        let meta = (!self.indexes.is_empty()).then(|| record.meta.clone());
+
+        let index_lock = self.indexes.lock(prefix);
+        let res = match prefix.addr() {
             std::net::IpAddr::V4(_addr) => self.v4.insert(
                 PrefixId::<IPv4>::from(*prefix),
                 record,
@@ -546,54 +1039,589 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
                 record,
                 update_path_selections,
             ),
+        };
+
+        if res.is_ok() {
+            self.update_indexes(prefix, mui, meta.as_ref());
         }
-    }
+        drop(index_lock);
 
-    /// Request an iterator over all prefixes in the RIB.
-    ///
-    /// Returns an iterator over [PrefixRecord].
-    pub fn prefixes_iter(
-        &'a self,
-        guard: &'a Guard,
-    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
-        self.v4
-            .prefixes_iter(guard)
-            .map(|r| r.map(PrefixRecord::from))
-            .chain(
-                self.v6
-                    .prefixes_iter(guard)
-                    .map(|r| r.map(PrefixRecord::from)),
-            )
+        if res.is_ok() {
+            if let Some(tbi) = auto_tbi {
+                self.select_paths(prefix, &tbi)?;
+            }
+            if wants_insert {
+                self.events.send(
+                    RibEvent {
+                        kind: RibEventKind::Insert,
+                        prefix: Some(*prefix),
+                        mui,
+                        old_status,
+                        new_status,
+                        ltime: Some(ltime),
+                    },
+                    Families::Both,
+                );
+            }
+            if let Some(previous) = old_best {
+                self.send_best_path_change(prefix, previous);
+            }
+        }
+
+        res
     }
 
-    /// Request an iterator over all IPv4 prefixes in the RIB.
+    /// Insert a batch of prefixes with their [Record](
+    /// crate::prefix_record::Record) into the RIB.
     ///
-    /// Returns an iterator over [PrefixRecord].
-    pub fn prefixes_iter_v4(
-        &'a self,
-        guard: &'a Guard,
-    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+    /// This does the same as calling [insert](Self::insert) for every item,
+    /// but is faster for large batches, e.g. when loading a full table. The
+    /// records are inserted per address family, ordered on prefix length,
+    /// with one epoch guard, and the records to persist are written to
+    /// disk in one go: for the `WriteAhead` strategy before the records are
+    /// stored in memory, for the other strategies at the end. The records
+    /// for the same prefix are inserted in the order of the batch. A
+    /// failing insert does not stop the batch.
+    ///
+    /// If `update_path_selections` is passed in with the tie breaker info
+    /// then perform a best path selection for every insert. Otherwise, if
+    /// the automatic path selection is enabled, perform a best path
+    /// selection with its tie breaker info once for every prefix in the
+    /// batch, after all the inserts.
+    ///
+    /// Returns a [BatchUpsertReport] with the aggregated results, and the
+    /// position in the batch, and the error, of every record that could not
+    /// be inserted.
+    pub fn insert_batch(
+        &self,
+        records: impl IntoIterator<Item = (Prefix, Record<M>)>,
+        update_path_selections: Option<M::TBI>,
+    ) -> BatchUpsertReport {
+        let mut v4_records = vec![];
+        let mut v6_records = vec![];
+        // The (prefix, mui) pairs to update the secondary indexes for.
+        let mut indexed = vec![];
+        // The events for the inserts, in the order of the batch, with the
+        // status of the (prefix, mui) pair before the insert. That is the
+        // status of an earlier record in the batch for the same pair, if
+        // any.
+        let mut events = vec![];
+        let mut statuses = HashMap::new();
+        let mut best_paths = HashMap::new();
+        let auto_tbi = match update_path_selections {
+            Some(_) => None,
+            None => self.auto_path_selection,
+        };
+        // The prefixes for the automatic path selection, with the position
+        // of their last record in the batch.
+        let mut auto_selected = HashMap::new();
+        for (idx, (prefix, record)) in records.into_iter().enumerate() {
+            let mui = record.multi_uniq_id;
+            if !self.indexes.is_empty() {
+                indexed.push((idx, prefix, mui, record.meta.clone()));
+            }
+            if self.events.wants(Some(&prefix), Families::Both, Some(mui)) {
+                // The insert itself may still succeed, the event then goes
+                // out without the old status.
+                let old_status =
+                    *statuses.entry((prefix, mui)).or_insert_with(|| {
+                        self.local_status(&prefix, mui)
+                            .inspect_err(|err| {
+                                error!("status of {} failed: {}", prefix, err)
+                            })
+                            .ok()
+                            .flatten()
+                    });
+                statuses.insert((prefix, mui), Some(record.status));
+                events.push((
+                    idx,
+                    RibEvent {
+                        kind: RibEventKind::Insert,
+                        prefix: Some(prefix),
+                        mui,
+                        old_status,
+                        new_status: record.status,
+                        ltime: Some(record.ltime),
+                    },
+                ));
+            }
+            if auto_tbi.is_some() {
+                auto_selected.insert(prefix, idx);
+            }
+            if update_path_selections.or(auto_tbi).is_some()
+                && !best_paths.contains_key(&prefix)
+            {
+                if let Some(best) = self.best_path_mui_for_events(&prefix) {
+                    best_paths.insert(prefix, best);
+                }
+            }
+            match prefix.addr() {
+                std::net::IpAddr::V4(_addr) => v4_records.push((
+                    idx,
+                    PrefixId::<IPv4>::from(prefix),
+                    record,
+                )),
+                std::net::IpAddr::V6(_addr) => v6_records.push((
+                    idx,
+                    PrefixId::<IPv6>::from(prefix),
+                    record,
+                )),
+            }
+        }
+
+        let index_locks =
+            self.indexes.lock_all(indexed.iter().map(|(_, p, _, _)| p));
+        let mut report = BatchUpsertReport::default();
         self.v4
-            .prefixes_iter(guard)
-            .map(|r| r.map(PrefixRecord::from))
+            .insert_batch(v4_records, update_path_selections, &mut report);
+        self.v6
+            .insert_batch(v6_records, update_path_selections, &mut report);
+        report.errors.sort_by_key(|(idx, _)| *idx);
+
+        for (idx, prefix, mui, meta) in indexed {
+            if report
+                .errors
+                .binary_search_by_key(&idx, |(i, _)| *i)
+                .is_err()
+            {
+                self.update_indexes(&prefix, mui, Some(&meta));
+            }
+        }
+        drop(index_locks);
+        if let Some(tbi) = auto_tbi {
+            for (prefix, idx) in auto_selected {
+                if let Err(err) = self.select_paths(&prefix, &tbi) {
+                    report.errors.push((idx, err));
+                }
+            }
+        }
+        report.errors.sort_by_key(|(idx, _)| *idx);
+
+        // The errors are sorted, so the events for failed inserts can be
+        // skipped in one pass.
+        let mut errors = report.errors.iter().map(|(idx, _)| *idx).peekable();
+        for (idx, event) in events {
+            while errors.next_if(|e| *e < idx).is_some() {}
+            if errors.next_if_eq(&idx).is_some() {
+                continue;
+            }
+            self.events.send(event, Families::Both);
+        }
+        for (prefix, previous) in best_paths {
+            self.send_best_path_change(&prefix, previous);
+        }
+
+        report
     }
 
-    /// Request an iterator over all IPv6 prefixes in the RIB.
+    /// Subscribe to the changes in the RIB.
     ///
-    /// Returns an iterator over [PrefixRecord].
-    pub fn prefixes_iter_v6(
-        &'a self,
-        guard: &'a Guard,
-    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
-        self.v6
-            .prefixes_iter(guard)
-            .map(|r| r.map(PrefixRecord::from))
+    /// The subscription receives a [RibEvent](
+    /// crate::rib::subscription::RibEvent) for every insert, for every
+    /// change of the local status of a record, for every change of the
+    /// global status of a `mui`, and for every change of the best path of a
+    /// prefix, that passes the `filter`. Events are only sent for changes
+    /// that succeeded, after they were made.
+    ///
+    /// The events are sent over a channel that holds at most `capacity`
+    /// events. The RIB never waits for a subscriber, the `overflow_policy`
+    /// determines what happens if the channel is full.
+    pub fn subscribe(
+        &self,
+        filter: SubscriptionFilter,
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Subscription {
+        self.events.subscribe(filter, capacity, overflow_policy)
     }
 
-    /// Request an iterator over all persisted prefixes.
+    /// Request all the records that have `key` in the secondary index
+    /// registered under `name` in the [MetaConfig](
+    /// crate::rib::config::MetaConfig) of the RIB, see
+    /// [add_index](crate::rib::config::MetaConfig::add_index).
     ///
-    /// Returns an over [PrefixRecord].
-    pub fn persist_prefixes_iter(
+    /// If `include_withdrawn` is passed in as `true`, records with the
+    /// status `Withdrawn`, locally or globally, are returned as well.
+    ///
+    /// Returns the records for every prefix as a [PrefixRecord], ordered by
+    /// prefix address and length, or an [IndexNotFound](
+    /// crate::errors::PrefixStoreError::IndexNotFound) error if there is
+    /// no index with this name and type of keys.
+    pub fn lookup_index<K: Eq + std::hash::Hash + 'static>(
+        &self,
+        name: &str,
+        key: &K,
+        include_withdrawn: bool,
+    ) -> Result<Vec<PrefixRecord<M>>, PrefixStoreError> {
+        let mut prefixes = self
+            .indexes
+            .lookup(name, key)
+            .ok_or(PrefixStoreError::IndexNotFound)?
+            .map_err(|_| PrefixStoreError::FatalError)?;
+        prefixes.sort_by_key(|(p, _)| (p.addr(), p.len()));
+
+        let mut prefix_records = vec![];
+        for (prefix, muis) in prefixes {
+            let mut records = self
+                .get_records_for_prefix(&prefix, None, include_withdrawn)
+                .map_err(|_| PrefixStoreError::FatalError)?
+                .unwrap_or_default();
+            records.retain(|r| muis.contains(r.multi_uniq_id));
+            if !records.is_empty() {
+                prefix_records.push(PrefixRecord::new(prefix, records));
+            }
+        }
+
+        Ok(prefix_records)
+    }
+
+    /// Remove the record for the combination of (prefix, multi_uniq_id)
+    /// from the RIB, both from memory and from the persisted store,
+    /// including all historical records for this combination.
+    ///
+    /// If this was the last record for the prefix, the prefix itself is
+    /// removed as well. Returns a `PrefixNotFound` error if there was no
+    /// record for the combination.
+    pub fn remove(
+        &self,
+        prefix: &Prefix,
+        mui: u32,
+    ) -> Result<(), PrefixStoreError> {
+        let old_best = self.auto_best_path_for_events(prefix);
+        let index_lock = self.indexes.lock(prefix);
+        match prefix.addr() {
+            std::net::IpAddr::V4(_addr) => {
+                self.v4.remove(PrefixId::<IPv4>::from(*prefix), mui)
+            }
+            std::net::IpAddr::V6(_addr) => {
+                self.v6.remove(PrefixId::<IPv6>::from(*prefix), mui)
+            }
+        }?;
+        self.update_indexes(prefix, mui, None);
+        drop(index_lock);
+        self.auto_select_paths(prefix, old_best)
+    }
+
+    /// Remove a prefix with all of its records, for all `multi_uniq_id`s,
+    /// from the RIB, both from memory and from the persisted store.
+    ///
+    /// Returns the number of `multi_uniq_id`s that were removed, or a
+    /// `PrefixNotFound` error if the prefix has no records.
+    pub fn remove_prefix(
+        &self,
+        prefix: &Prefix,
+    ) -> Result<usize, PrefixStoreError> {
+        let _index_lock = self.indexes.lock(prefix);
+        let removed = match prefix.addr() {
+            std::net::IpAddr::V4(_addr) => {
+                self.v4.remove_prefix(PrefixId::<IPv4>::from(*prefix))
+            }
+            std::net::IpAddr::V6(_addr) => {
+                self.v6.remove_prefix(PrefixId::<IPv6>::from(*prefix))
+            }
+        }?;
+        if let Err(err) = self.indexes.remove_prefix(*prefix) {
+            error!("index removal for {} failed: {}", prefix, err);
+        }
+
+        Ok(removed)
+    }
+
+    /// Request an iterator over all prefixes in the RIB.
+    ///
+    /// Returns an iterator over [PrefixRecord].
+    pub fn prefixes_iter(
+        &'a self,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+        self.v4
+            .prefixes_iter(guard)
+            .map(|r| r.map(PrefixRecord::from))
+            .chain(
+                self.v6
+                    .prefixes_iter(guard)
+                    .map(|r| r.map(PrefixRecord::from)),
+            )
+    }
+
+    /// Request an iterator over all prefixes in the RIB, ordered by
+    /// address, and for the same address by prefix length. The IPv4
+    /// prefixes come before the IPv6 prefixes.
+    ///
+    /// This is the same as [prefixes_iter](Self::prefixes_iter), but
+    /// ordered, e.g. for exports, diffs and dumps. The prefixes are found by
+    /// walking the nodes of the tree bitmap depth-first, without collecting
+    /// them first.
+    ///
+    /// Returns an iterator over [PrefixRecord].
+    pub fn prefixes_iter_sorted(
+        &'a self,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+        self.v4
+            .prefixes_iter_sorted(guard)
+            .map(|r| r.map(PrefixRecord::from))
+            .chain(
+                self.v6
+                    .prefixes_iter_sorted(guard)
+                    .map(|r| r.map(PrefixRecord::from)),
+            )
+    }
+
+    /// Query the RIB for a matching prefix with options, and validate the
+    /// origins of all the returned records against a [VrpTable].
+    ///
+    /// This is the same query as [match_prefix](Self::match_prefix), with
+    /// the route origin validation status for every record, that
+    /// [VrpTable::validate_query_result] determines from the origin AS that
+    /// [Meta::origin_asn] extracts from the metadata of the record.
+    ///
+    /// Returns a [RovQueryResult].
+    pub fn match_prefix_with_rov(
+        &'a self,
+        search_pfx: &Prefix,
+        options: &MatchOptions,
+        vrp_table: &VrpTable,
+        guard: &'a Guard,
+    ) -> FatalResult<RovQueryResult<M>> {
+        self.match_prefix(search_pfx, options, guard)
+            .map(|res| vrp_table.validate_query_result(res))
+    }
+
+    /// Request a parallel iterator over all prefixes in the RIB.
+    ///
+    /// This returns the same prefixes as
+    /// [prefixes_iter](Self::prefixes_iter), and the default route, if
+    /// present, in no particular order. The work is split over the
+    /// per-length roots of the hash tables that back the RIB, so that
+    /// full-table jobs, like statistics, exports and best path calculations,
+    /// can use all available cores. Every worker pins its own epoch guard,
+    /// so no guard is needed.
+    ///
+    /// Only available with the `rayon` feature.
+    ///
+    /// Returns a rayon [ParallelIterator] over [PrefixRecord].
+    #[cfg(feature = "rayon")]
+    pub fn par_prefixes_iter(
+        &self,
+    ) -> impl ParallelIterator<Item = FatalResult<PrefixRecord<M>>> + '_
+    where
+        C: Sync,
+    {
+        self.v4
+            .par_prefixes_iter()
+            .map(|r| r.map(PrefixRecord::from))
+            .chain(
+                self.v6
+                    .par_prefixes_iter()
+                    .map(|r| r.map(PrefixRecord::from)),
+            )
+    }
+
+    /// Request a page of at most `limit` records from the RIB, starting
+    /// right after `cursor`, or at the start if `cursor` is `None`.
+    ///
+    /// The records are in the order of [prefixes_iter_sorted](
+    /// Self::prefixes_iter_sorted), and for a prefix ordered by mui. The
+    /// records for a prefix can be split over two pages. The returned
+    /// [Page] holds the cursor for the next page, if there is one. A cursor
+    /// is a position in the RIB, not a snapshot: no epoch guard is held
+    /// between pages, and pages stay well-defined if the RIB changes in
+    /// between. Records that are inserted after the cursor are returned on
+    /// later pages, records that are inserted before it are not. A record
+    /// is never returned twice, unless it is removed and inserted again.
+    ///
+    /// A `limit` of 0 is treated as 1. Withdrawn records are included, see
+    /// [prefixes_iter](Self::prefixes_iter).
+    pub fn prefixes_page(
+        &self,
+        cursor: Option<&PageCursor>,
+        limit: usize,
+    ) -> FatalResult<Page<M>> {
+        let guard = &epoch::pin();
+        let (v4_after, v6_after) = match cursor {
+            None => (None, None),
+            Some(c) => match c.prefix().is_v4() {
+                true => (
+                    Some((PrefixId::<IPv4>::from(c.prefix()), c.mui())),
+                    None,
+                ),
+                false => (
+                    None,
+                    Some((PrefixId::<IPv6>::from(c.prefix()), c.mui())),
+                ),
+            },
+        };
+        // A cursor for an IPv6 prefix means that the IPv4 prefixes are done.
+        let v4_prefixes = (v6_after.is_none())
+            .then(|| {
+                self.v4.more_specifics_page_iter(
+                    PrefixId::new(IPv4::zero(), 0),
+                    v4_after,
+                    guard,
+                )
+            })
+            .into_iter()
+            .flatten()
+            .map(|r| r.map(|(p, recs)| (Prefix::from(p), recs)));
+        let v6_prefixes = self
+            .v6
+            .more_specifics_page_iter(
+                PrefixId::new(IPv6::zero(), 0),
+                v6_after,
+                guard,
+            )
+            .map(|r| r.map(|(p, recs)| (Prefix::from(p), recs)));
+
+        collect_page(v4_prefixes.chain(v6_prefixes), limit)
+    }
+
+    /// Request a page of at most `limit` records for the more specific
+    /// prefixes in the RIB for a certain prefix, including the prefix
+    /// itself, starting right after `cursor`, or at the start if `cursor`
+    /// is `None`.
+    ///
+    /// The records are in the order of [more_specifics_iter_from_sorted](
+    /// Self::more_specifics_iter_from_sorted), and for a prefix ordered by
+    /// mui. See [prefixes_page](Self::prefixes_page) for how the pages and
+    /// cursors behave.
+    pub fn more_specifics_page(
+        &self,
+        search_pfx: &Prefix,
+        cursor: Option<&PageCursor>,
+        limit: usize,
+    ) -> FatalResult<Page<M>> {
+        let guard = &epoch::pin();
+        // A cursor for the other address family has no records for this
+        // prefix.
+        let cursor =
+            cursor.filter(|c| c.prefix().is_v4() == search_pfx.is_v4());
+        match search_pfx.is_v4() {
+            true => collect_page(
+                self.v4
+                    .more_specifics_page_iter(
+                        PrefixId::<IPv4>::from(*search_pfx),
+                        cursor.map(|c| {
+                            (PrefixId::<IPv4>::from(c.prefix()), c.mui())
+                        }),
+                        guard,
+                    )
+                    .map(|r| r.map(|(p, recs)| (Prefix::from(p), recs))),
+                limit,
+            ),
+            false => collect_page(
+                self.v6
+                    .more_specifics_page_iter(
+                        PrefixId::<IPv6>::from(*search_pfx),
+                        cursor.map(|c| {
+                            (PrefixId::<IPv6>::from(c.prefix()), c.mui())
+                        }),
+                        guard,
+                    )
+                    .map(|r| r.map(|(p, recs)| (Prefix::from(p), recs))),
+                limit,
+            ),
+        }
+    }
+
+    /// Request an iterator over all prefixes in the RIB, with their
+    /// records as they were at the logical time `ltime`. See
+    /// [match_prefix_at](Self::match_prefix_at) for how the records are
+    /// selected. Prefixes without any records at that moment are skipped.
+    ///
+    /// Returns an iterator over [PrefixRecord].
+    pub fn prefixes_iter_at(
+        &'a self,
+        ltime: u64,
+    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+        self.v4
+            .prefixes_iter_at(ltime)
+            .map(|r| r.map(PrefixRecord::from))
+            .chain(
+                self.v6
+                    .prefixes_iter_at(ltime)
+                    .map(|r| r.map(PrefixRecord::from)),
+            )
+    }
+
+    /// Request an iterator over all the historical records for a `mui`,
+    /// across all prefixes, with an ltime in `ltime_range`, e.g. for
+    /// analyzing what a peer sent during an incident.
+    ///
+    /// The records are ordered by ltime, IPv4 and IPv6 prefixes
+    /// interleaved. A status change of a record is returned as a separate
+    /// record, with the metadata of the record it applies to. Only the
+    /// `WriteAhead` and `PersistHistory` strategies keep historical
+    /// records, for the other strategies this iterator is always empty.
+    /// Note that for `PersistHistory` the current records are not part of
+    /// the history.
+    ///
+    /// Returns an iterator over tuples of the prefix and the [Record].
+    pub fn history_for_mui(
+        &'a self,
+        mui: u32,
+        ltime_range: impl RangeBounds<u64>,
+    ) -> impl Iterator<Item = FatalResult<(Prefix, Record<M>)>> + 'a {
+        let range = (
+            ltime_range.start_bound().cloned(),
+            ltime_range.end_bound().cloned(),
+        );
+        let mut v4 = self
+            .v4
+            .history_for_mui(mui, range)
+            .map(|r| r.map(|(p, rec)| (Prefix::from(p), rec)))
+            .peekable();
+        let mut v6 = self
+            .v6
+            .history_for_mui(mui, range)
+            .map(|r| r.map(|(p, rec)| (Prefix::from(p), rec)))
+            .peekable();
+
+        // Merge the two ordered iterators, errors go first.
+        std::iter::from_fn(move || {
+            let next_is_v4 = match (v4.peek(), v6.peek()) {
+                (Some(Ok((_, r4))), Some(Ok((_, r6)))) => {
+                    r4.ltime <= r6.ltime
+                }
+                (Some(_), Some(Err(_))) | (None, _) => false,
+                (Some(_), _) => true,
+            };
+            if next_is_v4 {
+                v4.next()
+            } else {
+                v6.next()
+            }
+        })
+    }
+
+    /// Request an iterator over all IPv4 prefixes in the RIB.
+    ///
+    /// Returns an iterator over [PrefixRecord].
+    pub fn prefixes_iter_v4(
+        &'a self,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+        self.v4
+            .prefixes_iter(guard)
+            .map(|r| r.map(PrefixRecord::from))
+    }
+
+    /// Request an iterator over all IPv6 prefixes in the RIB.
+    ///
+    /// Returns an iterator over [PrefixRecord].
+    pub fn prefixes_iter_v6(
+        &'a self,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<PrefixRecord<M>>> + 'a {
+        self.v6
+            .prefixes_iter(guard)
+            .map(|r| r.map(PrefixRecord::from))
+    }
+
+    /// Request an iterator over all persisted prefixes.
+    ///
+    /// Returns an over [PrefixRecord].
+    pub fn persist_prefixes_iter(
         &'a self,
     ) -> impl Iterator<Item = Result<PrefixRecord<M>, FatalError>> + 'a {
         self.v4
@@ -660,7 +1688,11 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
         ltime: u64,
     ) -> Result<(), PrefixStoreError> {
-        match prefix.addr() {
+        let old_status = self
+            .status_for_events(prefix, mui)
+            .map_err(|_| PrefixStoreError::FatalError)?;
+        let old_best = self.auto_best_path_for_events(prefix);
+        let res = match prefix.addr() {
             std::net::IpAddr::V4(_addr) => {
                 self.v4.mark_mui_as_withdrawn_for_prefix(
                     PrefixId::<IPv4>::from(*prefix),
@@ -675,7 +1707,19 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
                     ltime,
                 )
             }
+        };
+        if let (Ok(_), Some(old_status)) = (&res, old_status) {
+            self.send_status_change(
+                prefix,
+                mui,
+                old_status,
+                RouteStatus::Withdrawn,
+                ltime,
+            );
         }
+        res?;
+
+        self.auto_select_paths(prefix, old_best)
     }
 
     /// Change the local status of the record for the combination of
@@ -688,7 +1732,9 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
         ltime: u64,
     ) -> FatalResult<()> {
-        match prefix.addr() {
+        let old_status = self.status_for_events(prefix, mui)?;
+        let old_best = self.auto_best_path_for_events(prefix);
+        let res = match prefix.addr() {
             std::net::IpAddr::V4(_addr) => {
                 self.v4.mark_mui_as_active_for_prefix(
                     PrefixId::<IPv4>::from(*prefix),
@@ -703,7 +1749,20 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
                     ltime,
                 )
             }
+        };
+        if let (Ok(_), Some(old_status)) = (&res, old_status) {
+            self.send_status_change(
+                prefix,
+                mui,
+                old_status,
+                RouteStatus::Active,
+                ltime,
+            );
         }
+        res?;
+
+        self.auto_select_paths(prefix, old_best)
+            .map_err(|_| FatalError)
     }
 
     /// Change the status of all records for IPv4 prefixes for this
@@ -715,8 +1774,15 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
     ) -> Result<(), PrefixStoreError> {
         let guard = &epoch::pin();
+        let was_active = self.v4.is_mui_active(mui, guard);
 
-        self.v4.mark_mui_as_active(mui, guard)
+        self.v4.mark_mui_as_active(mui, guard)?;
+        self.send_mui_status_change(mui, was_active, true, Families::V4);
+        if !was_active {
+            self.auto_select_paths_for_mui(mui, Families::V4)?;
+        }
+
+        Ok(())
     }
 
     /// Change the status of all records for IPv4 prefixes for this
@@ -730,8 +1796,15 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
     ) -> Result<(), PrefixStoreError> {
         let guard = &epoch::pin();
+        let was_active = self.v4.is_mui_active(mui, guard);
 
-        self.v4.mark_mui_as_withdrawn(mui, guard)
+        self.v4.mark_mui_as_withdrawn(mui, guard)?;
+        self.send_mui_status_change(mui, was_active, false, Families::V4);
+        if was_active {
+            self.auto_select_paths_for_mui(mui, Families::V4)?;
+        }
+
+        Ok(())
     }
 
     /// Change the status of all records for IPv6 prefixes for this
@@ -744,8 +1817,15 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
     ) -> Result<(), PrefixStoreError> {
         let guard = &epoch::pin();
+        let was_active = self.v6.is_mui_active(mui, guard);
 
-        self.v6.mark_mui_as_active(mui, guard)
+        self.v6.mark_mui_as_active(mui, guard)?;
+        self.send_mui_status_change(mui, was_active, true, Families::V6);
+        if !was_active {
+            self.auto_select_paths_for_mui(mui, Families::V6)?;
+        }
+
+        Ok(())
     }
 
     /// Change the status of all records for IPv6 prefixes for this
@@ -760,8 +1840,15 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
     ) -> Result<(), PrefixStoreError> {
         let guard = &epoch::pin();
+        let was_active = self.v6.is_mui_active(mui, guard);
+
+        self.v6.mark_mui_as_withdrawn(mui, guard)?;
+        self.send_mui_status_change(mui, was_active, false, Families::V6);
+        if was_active {
+            self.auto_select_paths_for_mui(mui, Families::V6)?;
+        }
 
-        self.v6.mark_mui_as_withdrawn(mui, guard)
+        Ok(())
     }
 
     /// Change the status of all records for this `multi_uniq_id` to
@@ -776,13 +1863,82 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         mui: u32,
     ) -> Result<(), PrefixStoreError> {
         let guard = &epoch::pin();
+        let was_active_v4 = self.v4.is_mui_active(mui, guard);
+        let was_active_v6 = self.v6.is_mui_active(mui, guard);
+        let was_active = was_active_v4 || was_active_v6;
 
         let res_v4 = self.v4.mark_mui_as_withdrawn(mui, guard);
         let res_v6 = self.v6.mark_mui_as_withdrawn(mui, guard);
 
+        match (&res_v4, &res_v6) {
+            (Ok(_), Ok(_)) => self.send_mui_status_change(
+                mui,
+                was_active,
+                false,
+                Families::Both,
+            ),
+            (Ok(_), Err(_)) => self.send_mui_status_change(
+                mui,
+                was_active,
+                false,
+                Families::V4,
+            ),
+            (Err(_), Ok(_)) => self.send_mui_status_change(
+                mui,
+                was_active,
+                false,
+                Families::V6,
+            ),
+            (Err(_), Err(_)) => {}
+        }
+        if res_v4.is_ok() && was_active_v4 {
+            self.auto_select_paths_for_mui(mui, Families::V4)?;
+        }
+        if res_v6.is_ok() && was_active_v6 {
+            self.auto_select_paths_for_mui(mui, Families::V6)?;
+        }
+
         res_v4.and(res_v6)
     }
 
+    /// Remove all records, current and historical, for this
+    /// `multi_uniq_id` from the RIB, both from memory and from the
+    /// persisted store, e.g. when a BGP session goes away for good.
+    /// Prefixes that are left without any records are removed as well, and
+    /// the global `Withdrawn` status of the `multi_uniq_id` is cleared.
+    ///
+    /// This can be called while other threads are inserting. Records for
+    /// this `multi_uniq_id` that are inserted while the purge is running may
+    /// or may not be removed.
+    ///
+    /// Returns the number of records, current and historical, that were
+    /// removed.
+    pub fn purge_mui(&self, mui: u32) -> Result<usize, PrefixStoreError> {
+        // The prefixes for the automatic path selection, with their best
+        // path before the purge.
+        let old_bests = match self.auto_path_selection {
+            Some(_) => self
+                .prefixes_for_mui(mui, Families::Both)?
+                .into_iter()
+                .map(|p| (p, self.best_path_mui_for_events(&p)))
+                .collect(),
+            None => vec![],
+        };
+
+        let purged = self.v4.purge_mui(mui)? + self.v6.purge_mui(mui)?;
+        if let Err(err) = self
+            .indexes
+            .remove_mui(mui, |prefix| self.current_meta(prefix, mui))
+        {
+            error!("index removal for mui {} failed: {}", mui, err);
+        }
+        for (prefix, old_best) in old_bests {
+            self.auto_select_paths(&prefix, old_best)?;
+        }
+
+        Ok(purged)
+    }
+
     /// Request whether the global status for IPv4 prefixes and the specified
     /// `multi_uniq_id` is set to `Withdrawn`.
     pub fn mui_is_withdrawn_v4(&self, mui: u32) -> bool {
@@ -863,6 +2019,28 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         self.v6.routes_count()
     }
 
+    /// Request the counters for the eviction of records to disk, for both
+    /// address families.
+    ///
+    /// Only the `Hybrid` strategy evicts records, for the other strategies
+    /// the counters are all zero. See [HybridConfig](
+    /// crate::rib::config::HybridConfig) for how records get evicted.
+    pub fn eviction_counters(&self) -> EvictionCounters {
+        let mut counters = self.v4.eviction_counters();
+        counters += self.v6.eviction_counters();
+        counters
+    }
+
+    /// Request the counters for the eviction of IPv4 records to disk.
+    pub fn eviction_counters_v4(&self) -> EvictionCounters {
+        self.v4.eviction_counters()
+    }
+
+    /// Request the counters for the eviction of IPv6 records to disk.
+    pub fn eviction_counters_v6(&self) -> EvictionCounters {
+        self.v6.eviction_counters()
+    }
+
     /// Request the number of nodes in the store.
     ///
     /// Note that this counter may be lower than the actual
@@ -907,6 +2085,240 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         }
     }
 
+    /// Request the memory used by the in-memory data structures of the RIB.
+    ///
+    /// This walks all the buckets of the chained hash tables that hold the
+    /// nodes of the tree bitmap, and the prefixes with their records, and
+    /// returns a [MemoryReport] with the allocated and occupied slots, and
+    /// the bytes used, per address family and per level of the tables. The
+    /// bytes of the hash maps with the records are computed from their
+    /// capacity. Records that only live in the persisted store are not part
+    /// of the report, see [disk_space](Self::disk_space) for those.
+    ///
+    /// The walk takes time proportional to the number of nodes and prefixes
+    /// in the RIB, and can run while other threads read and write.
+    ///
+    /// Returns a [FatalError](crate::errors::PrefixStoreError::FatalError)
+    /// if a bitmap index of the tree bitmap cannot be read.
+    pub fn memory_report(&self) -> Result<MemoryReport, PrefixStoreError> {
+        Ok(MemoryReport {
+            v4: self
+                .v4
+                .memory_report()
+                .map_err(|_| PrefixStoreError::FatalError)?,
+            v6: self
+                .v6
+                .memory_report()
+                .map_err(|_| PrefixStoreError::FatalError)?,
+        })
+    }
+
+    /// Request the metrics of the RIB, in the Prometheus text exposition
+    /// format.
+    ///
+    /// See [write_metrics](Self::write_metrics) for the metrics, and for
+    /// rendering them into a reused buffer.
+    pub fn metrics(&self) -> String {
+        let mut metrics = String::with_capacity(4096);
+        // Writing to a String does not fail.
+        let _ = self.write_metrics(&mut metrics);
+        metrics
+    }
+
+    /// Write the metrics of the RIB, in the Prometheus text exposition
+    /// format, to `target`.
+    ///
+    /// The metrics are, with a `family` label of `ipv4` or `ipv6`:
+    ///
+    /// * `rotonda_store_prefixes`: the number of prefixes, with a `len`
+    ///   label for every prefix length that has prefixes,
+    /// * `rotonda_store_routes`: the number of (prefix, mui) pairs,
+    /// * `rotonda_store_nodes`: the number of nodes in the tree bitmap,
+    /// * `rotonda_store_persisted_items`: the approximate number of items in
+    ///   the persisted store,
+    /// * `rotonda_store_disk_bytes`: the disk space used by the persisted
+    ///   store,
+    /// * `rotonda_store_withdrawn_muis`: the number of globally withdrawn
+    ///   `multi_uniq_id`s,
+    /// * `rotonda_store_cas_retries_total`: the cumulative number of
+    ///   compare-and-swap retries of the inserts, i.e. the sum of the
+    ///   `cas_count` of their [UpsertReport]s. High numbers indicate
+    ///   contention,
+    /// * `rotonda_store_resident_bytes`,
+    ///   `rotonda_store_evicted_records_total`,
+    ///   `rotonda_store_faulted_in_records_total` and
+    ///   `rotonda_store_eviction_passes_total`: the [EvictionCounters] of
+    ///   the `Hybrid` strategy.
+    ///
+    /// The counters are read without locking, so the metrics are cheap to
+    /// render on every scrape. Rendering into a `String` that is cleared
+    /// and reused for every scrape does not allocate.
+    pub fn write_metrics(
+        &self,
+        target: &mut impl std::fmt::Write,
+    ) -> std::fmt::Result {
+        let guard = &epoch::pin();
+        let mut w = MetricsWriter::new(target);
+
+        w.header(
+            "prefixes",
+            "gauge",
+            "The number of prefixes per address family and prefix length.",
+        )?;
+        for (len, count) in self.v4.counters.prefixes_per_len() {
+            w.sample(
+                "prefixes",
+                &[("family", &"ipv4"), ("len", &len)],
+                count,
+            )?;
+        }
+        for (len, count) in self.v6.counters.prefixes_per_len() {
+            w.sample(
+                "prefixes",
+                &[("family", &"ipv6"), ("len", &len)],
+                count,
+            )?;
+        }
+
+        w.header(
+            "routes",
+            "gauge",
+            "The number of (prefix, mui) pairs per address family.",
+        )?;
+        w.sample(
+            "routes",
+            &[("family", &"ipv4")],
+            self.v4.counters.routes_count(),
+        )?;
+        w.sample(
+            "routes",
+            &[("family", &"ipv6")],
+            self.v6.counters.routes_count(),
+        )?;
+
+        w.header(
+            "nodes",
+            "gauge",
+            "The number of tree bitmap nodes per address family.",
+        )?;
+        w.sample("nodes", &[("family", &"ipv4")], self.v4.get_nodes_count())?;
+        w.sample("nodes", &[("family", &"ipv6")], self.v6.get_nodes_count())?;
+
+        w.header(
+            "persisted_items",
+            "gauge",
+            "The approximate number of persisted items per address family.",
+        )?;
+        w.sample(
+            "persisted_items",
+            &[("family", &"ipv4")],
+            self.v4.approx_persisted_items(),
+        )?;
+        w.sample(
+            "persisted_items",
+            &[("family", &"ipv6")],
+            self.v6.approx_persisted_items(),
+        )?;
+
+        w.header(
+            "disk_bytes",
+            "gauge",
+            "The disk space used by the persisted store per address family.",
+        )?;
+        w.sample("disk_bytes", &[("family", &"ipv4")], self.v4.disk_space())?;
+        w.sample("disk_bytes", &[("family", &"ipv6")], self.v6.disk_space())?;
+
+        w.header(
+            "withdrawn_muis",
+            "gauge",
+            "The number of globally withdrawn muis per address family.",
+        )?;
+        w.sample(
+            "withdrawn_muis",
+            &[("family", &"ipv4")],
+            self.v4.tree_bitmap.withdrawn_muis_bmin(guard).len(),
+        )?;
+        w.sample(
+            "withdrawn_muis",
+            &[("family", &"ipv6")],
+            self.v6.tree_bitmap.withdrawn_muis_bmin(guard).len(),
+        )?;
+
+        w.header(
+            "cas_retries_total",
+            "counter",
+            "The cumulative number of compare-and-swap retries of the \
+             inserts per address family.",
+        )?;
+        w.sample(
+            "cas_retries_total",
+            &[("family", &"ipv4")],
+            self.v4.counters.cas_count(),
+        )?;
+        w.sample(
+            "cas_retries_total",
+            &[("family", &"ipv6")],
+            self.v6.counters.cas_count(),
+        )?;
+
+        let evictions = [
+            ("ipv4", self.v4.eviction_counters()),
+            ("ipv6", self.v6.eviction_counters()),
+        ];
+        w.header(
+            "resident_bytes",
+            "gauge",
+            "The estimated size of the in-memory records per address family.",
+        )?;
+        for (family, c) in &evictions {
+            w.sample(
+                "resident_bytes",
+                &[("family", family)],
+                c.resident_bytes,
+            )?;
+        }
+        w.header(
+            "evicted_records_total",
+            "counter",
+            "The cumulative number of records evicted to disk per address \
+             family.",
+        )?;
+        for (family, c) in &evictions {
+            w.sample(
+                "evicted_records_total",
+                &[("family", family)],
+                c.evicted_records,
+            )?;
+        }
+        w.header(
+            "faulted_in_records_total",
+            "counter",
+            "The cumulative number of evicted records loaded back into \
+             memory per address family.",
+        )?;
+        for (family, c) in &evictions {
+            w.sample(
+                "faulted_in_records_total",
+                &[("family", family)],
+                c.faulted_in_records,
+            )?;
+        }
+        w.header(
+            "eviction_passes_total",
+            "counter",
+            "The cumulative number of eviction passes per address family.",
+        )?;
+        for (family, c) in &evictions {
+            w.sample(
+                "eviction_passes_total",
+                &[("family", family)],
+                c.eviction_passes,
+            )?;
+        }
+
+        Ok(())
+    }
+
     // Disk Persistence
 
     /// Request the persist strategy as set in the [configuration](
@@ -960,6 +2372,271 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
         Ok(())
     }
 
+    /// Stop the background flusher, if it was configured, and flush all
+    /// persisted records from memory to disk.
+    ///
+    /// The RIB can still be used after a shutdown, but records will only
+    /// be flushed to disk with explicit calls to
+    /// [flush_to_disk](Self::flush_to_disk). Calling this method on a RIB
+    /// that does not persist records does nothing.
+    ///
+    /// For the `Hybrid` strategy the records that are in memory are written
+    /// to disk as well, next to the evicted records, so that all records
+    /// can be restored when the RIB is [opened](Self::open) again. The
+    /// records stay in memory.
+    pub fn shutdown(&self) -> Result<(), PrefixStoreError> {
+        if let Some(flusher) = &self.flusher {
+            flusher.stop();
+        }
+
+        match self.persist_strategy() {
+            PersistStrategy::MemoryOnly => Ok(()),
+            PersistStrategy::Hybrid => {
+                self.v4
+                    .spill_resident_records()
+                    .and_then(|_| self.v6.spill_resident_records())
+                    .map_err(|_| PrefixStoreError::PersistFailed)?;
+                self.flush_to_disk()
+            }
+            _ => self.flush_to_disk(),
+        }
+    }
+
+    /// Write a snapshot of the persisted RIB to `dest_dir`, e.g. for a
+    /// backup.
+    ///
+    /// The snapshot contains a copy of the persisted records for both
+    /// address families, and a manifest with the configuration, the
+    /// counters for the records in the snapshot, and the globally withdrawn
+    /// `mui`s. The RIB can be read from, and written to, while the snapshot
+    /// is taken. For the `WriteAhead` and `PersistOnly` strategies the copy
+    /// of the records for an address family is consistent, it contains
+    /// exactly the records written before the copy started.
+    ///
+    /// For the `PersistHistory` strategy the current records, that only
+    /// live in memory, are written to the snapshot as well, before the
+    /// historical records are copied. These are not copied at the same
+    /// moment: a record that is replaced while the snapshot is taken can
+    /// show up both as a current record and as a historical record, and
+    /// records written while the snapshot is taken may be part of it. For
+    /// the `Hybrid` strategy every record, in memory or evicted, is written
+    /// to the snapshot. Every prefix is copied as a whole, but prefixes
+    /// that are written to while the snapshot is taken may be copied before,
+    /// or after, the write.
+    ///
+    /// The snapshot can be turned into a RIB again with
+    /// [restore](Self::restore).
+    ///
+    /// Returns an error for the `MemoryOnly` strategy, or if `dest_dir`
+    /// exists and is not empty.
+    pub fn snapshot(
+        &self,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dest_dir = dest_dir.as_ref();
+
+        if self.persist_strategy() == PersistStrategy::MemoryOnly {
+            return Err(std::io::Error::other(
+                "Cannot snapshot a RIB without persisted records",
+            )
+            .into());
+        }
+        if dest_dir.is_dir() && dest_dir.read_dir()?.next().is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", dest_dir.display()),
+            )
+            .into());
+        }
+        std::fs::create_dir_all(dest_dir)?;
+
+        let current_dir = dest_dir.join(CURRENT_DIR);
+        let v4 = self
+            .v4
+            .snapshot(&dest_dir.join("ipv4"), &current_dir.join("ipv4"))?;
+        let v6 = self
+            .v6
+            .snapshot(&dest_dir.join("ipv6"), &current_dir.join("ipv6"))?;
+
+        SnapshotManifest::new(&self.config, v4, v6).write(dest_dir)?;
+
+        Ok(())
+    }
+
+    /// Create a RIB from a snapshot, that was written by
+    /// [snapshot](Self::snapshot), with the specified [configuration](
+    /// crate::rib::config).
+    ///
+    /// The persisted records in the snapshot are copied to the persist
+    /// path of the configuration, which is used as the store directory as
+    /// is, like [open](Self::open) does. The in-memory structures, and the
+    /// globally withdrawn `mui`s are restored from the snapshot. The
+    /// snapshot itself is left untouched, so it can be restored multiple
+    /// times.
+    ///
+    /// Returns an error if the snapshot is incomplete, if it was taken with
+    /// another persist strategy than the one in the configuration, if
+    /// there already is a store in the persist path, or if the restored
+    /// records do not match the counters in the manifest.
+    pub fn restore(
+        snapshot_dir: impl AsRef<Path>,
+        config: C,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::restore_with_meta_config(
+            snapshot_dir,
+            config,
+            MetaConfig::default(),
+        )
+    }
+
+    /// Create a RIB from a snapshot, like `restore`, with the settings that
+    /// depend on the type of the metadata in `meta_config`. The secondary
+    /// indexes are filled with the restored records.
+    pub fn restore_with_meta_config(
+        snapshot_dir: impl AsRef<Path>,
+        config: C,
+        meta_config: MetaConfig<M>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let snapshot_dir = snapshot_dir.as_ref();
+        let manifest = SnapshotManifest::read(snapshot_dir)?;
+
+        if manifest.config.persist_strategy != config.persist_strategy() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Snapshot has persist strategy {:?}, expected {:?}",
+                    manifest.config.persist_strategy,
+                    config.persist_strategy()
+                ),
+            )
+            .into());
+        }
+
+        let store_dir = Self::store_dir(&config)?;
+        for af_dir in ["ipv4", "ipv6"] {
+            if Path::new(&store_dir).join(af_dir).exists() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("A persisted store exists in {}", store_dir),
+                )
+                .into());
+            }
+        }
+        for af_dir in ["ipv4", "ipv6"] {
+            copy_dir_all(
+                &snapshot_dir.join(af_dir),
+                &Path::new(&store_dir).join(af_dir),
+            )?;
+        }
+
+        let rib =
+            Self::init_with_store_dir(config, &meta_config, Some(store_dir))?;
+
+        if rib.persist_strategy() == PersistStrategy::PersistHistory {
+            let current_dir = snapshot_dir.join(CURRENT_DIR);
+            rib.v4.restore_current_records(&current_dir.join("ipv4"))?;
+            rib.v6.restore_current_records(&current_dir.join("ipv6"))?;
+        } else {
+            rib.v4.restore_from_persist_tree()?;
+            rib.v6.restore_from_persist_tree()?;
+        }
+        if !rib.v4.matches_manifest(&manifest.v4)
+            || !rib.v6.matches_manifest(&manifest.v6)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "The records in the snapshot do not match its manifest",
+            )
+            .into());
+        }
+
+        rib.fill_indexes()?;
+
+        let guard = &epoch::pin();
+        for mui in manifest.v4.withdrawn_muis {
+            rib.v4.mark_mui_as_withdrawn(mui, guard)?;
+        }
+        for mui in manifest.v6.withdrawn_muis {
+            rib.v6.mark_mui_as_withdrawn(mui, guard)?;
+        }
+
+        Ok(rib)
+    }
+
+    /// Remove the persisted historical records that have expired according
+    /// to the [retention policy](crate::rib::config::RetentionPolicy) of the
+    /// configuration.
+    ///
+    /// This does nothing for RIBs without a retention policy, or for the
+    /// `MemoryOnly` and `PersistOnly` strategies, that do not keep
+    /// historical records. The RIB can be read from, and written to, while
+    /// pruning. The disk space is reclaimed as the trees get compacted, e.g.
+    /// after a [flush_to_disk](Self::flush_to_disk).
+    ///
+    /// Returns a [PruneReport] with the number of removed records, and the
+    /// number of bytes they took.
+    pub fn prune_history(&self) -> Result<PruneReport, PrefixStoreError> {
+        let mut report = self.v4.prune_history()?;
+        report += self.v6.prune_history()?;
+
+        Ok(report)
+    }
+
+    /// Write the current RIB to `writer` as an MRT TABLE_DUMP_V2 file, e.g.
+    /// to reload it later, or to hand it to bgpdump-style tooling.
+    ///
+    /// The file starts with a PEER_INDEX_TABLE built from `peer_index`,
+    /// followed by a RIB_IPV4_UNICAST or RIB_IPV6_UNICAST message for every
+    /// prefix with active records. Every record becomes a RIB entry for the
+    /// peer of its `mui`, with its `ltime` as the originated time, and its
+    /// metadata as the BGP path attributes. The `ltime`s should thus be
+    /// seconds since the Unix epoch, and the metadata should hold path
+    /// attributes encoded with four octet AS numbers, which is what the
+    /// `load_mrt` binary stores. Withdrawn records, and records for a `mui`
+    /// that has no peer in `peer_index`, are left out. Nothing is buffered,
+    /// so a [BufWriter](std::io::BufWriter) is recommended.
+    ///
+    /// Returns the number of written RIB entries, or an error if the
+    /// `ltime` of a written record does not fit in the 32 bits of the
+    /// originated time.
+    pub fn export_mrt(
+        &self,
+        writer: impl std::io::Write,
+        peer_index: &MrtPeerIndex,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let guard = &epoch::pin();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        let mut mrt_writer = MrtWriter::new(writer, peer_index, timestamp)?;
+
+        let v4_recs = self.v4.prefixes_iter(guard).map(|r| {
+            r.map(|(p, mut recs)| {
+                recs.retain(|r| {
+                    !self.v4.mui_is_withdrawn(r.multi_uniq_id, guard)
+                });
+                (p, recs)
+            })
+        });
+        let v6_recs = self.v6.prefixes_iter(guard).map(|r| {
+            r.map(|(p, mut recs)| {
+                recs.retain(|r| {
+                    !self.v6.mui_is_withdrawn(r.multi_uniq_id, guard)
+                });
+                (p, recs)
+            })
+        });
+        let mut entries = 0;
+        for res in v4_recs.chain(v6_recs) {
+            let (prefix, mut recs) = res?;
+            recs.retain(|r| r.status == RouteStatus::Active);
+            entries += mrt_writer.write_rib(prefix, &recs)?;
+        }
+        mrt_writer.flush()?;
+
+        Ok(entries)
+    }
+
     /// Request the approximate number of items that are persisted
     /// to disk, for IPv4 and IPv6 respectively.
     pub fn approx_persisted_items(&self) -> (usize, usize) {
@@ -974,4 +2651,355 @@ impl<'a, M: Meta, C: Config> StarCastRib<M, C> {
     pub fn disk_space(&self) -> u64 {
         self.v4.disk_space() + self.v6.disk_space()
     }
+
+    // Fill the secondary indexes with the keys of the records that were
+    // restored from disk.
+    fn fill_indexes(&self) -> Result<(), PrefixStoreError> {
+        let guard = &epoch::pin();
+        let records = self
+            .v4
+            .prefixes_iter_sorted(guard)
+            .chain(self.v6.prefixes_iter_sorted(guard))
+            .flat_map(|r| match r {
+                Ok((prefix, records)) => records
+                    .into_iter()
+                    .map(|r| Ok((prefix, r.multi_uniq_id, r.meta)))
+                    .collect(),
+                Err(err) => vec![Err(err)],
+            });
+        self.indexes
+            .fill(records)
+            .map_err(|_| PrefixStoreError::FatalError)
+    }
+
+    // Update the keys for the (prefix, mui) pair in the secondary indexes
+    // to the keys of `meta`, the metadata of the record that was just
+    // written, or remove them if the record was removed. The record is
+    // changed already, so a failing index does not fail the change.
+    fn update_indexes(&self, prefix: &Prefix, mui: u32, meta: Option<&M>) {
+        if let Err(err) = self.indexes.set(*prefix, mui, meta) {
+            error!("index update for {} mui {} failed: {}", prefix, mui, err);
+        }
+    }
+
+    // The metadata of the current record for the (prefix, mui) pair, if
+    // any.
+    fn current_meta(
+        &self,
+        prefix: &Prefix,
+        mui: u32,
+    ) -> FatalResult<Option<M>> {
+        Ok(self
+            .get_records_for_prefix(prefix, Some(mui), true)?
+            .and_then(|recs| recs.into_iter().next())
+            .map(|r| r.meta))
+    }
+
+    // The local status of the record for the (prefix, mui) pair, or `None`
+    // if there is no record.
+    fn local_status(
+        &self,
+        prefix: &Prefix,
+        mui: u32,
+    ) -> FatalResult<Option<RouteStatus>> {
+        match prefix.is_v4() {
+            true => {
+                self.v4.local_status(PrefixId::<IPv4>::from(*prefix), mui)
+            }
+            false => {
+                self.v6.local_status(PrefixId::<IPv6>::from(*prefix), mui)
+            }
+        }
+    }
+
+    // The local status of the record for the (prefix, mui) pair, if there
+    // is a record, and a subscriber is interested in changes to it.
+    fn status_for_events(
+        &self,
+        prefix: &Prefix,
+        mui: u32,
+    ) -> FatalResult<Option<RouteStatus>> {
+        if !self.events.wants(Some(prefix), Families::Both, Some(mui)) {
+            return Ok(None);
+        }
+        self.local_status(prefix, mui)
+    }
+
+    // The stored best path for a prefix, if any.
+    fn stored_best_path(&self, prefix: &Prefix) -> Option<Record<M>> {
+        let guard = &epoch::pin();
+        match prefix.is_v4() {
+            true => self.v4.best_path(PrefixId::<IPv4>::from(*prefix), guard),
+            false => {
+                self.v6.best_path(PrefixId::<IPv6>::from(*prefix), guard)
+            }
+        }
+        .and_then(|r| r.ok())
+    }
+
+    // The mui of the best path for a prefix, as `Some(None)` if it has no
+    // best path, or `None` if no subscriber is interested in best path
+    // changes for the prefix.
+    fn best_path_mui_for_events(
+        &self,
+        prefix: &Prefix,
+    ) -> Option<Option<u32>> {
+        if !self.events.wants(Some(prefix), Families::Both, None) {
+            return None;
+        }
+        Some(self.stored_best_path(prefix).map(|r| r.multi_uniq_id))
+    }
+
+    // The mui of the best path for a prefix, like
+    // `best_path_mui_for_events`, if the automatic path selection is
+    // enabled.
+    fn auto_best_path_for_events(
+        &self,
+        prefix: &Prefix,
+    ) -> Option<Option<u32>> {
+        self.auto_path_selection
+            .and_then(|_| self.best_path_mui_for_events(prefix))
+    }
+
+    // Calculate and store the best and backup path for the prefix. A
+    // prefix without records has nothing to select from, and is skipped.
+    fn select_paths(
+        &self,
+        prefix: &Prefix,
+        tbi: &M::TBI,
+    ) -> Result<(), PrefixStoreError> {
+        let guard = &epoch::pin();
+        loop {
+            let res = match prefix.addr() {
+                std::net::IpAddr::V4(_addr) => {
+                    self.v4.calculate_and_store_best_and_backup_path(
+                        PrefixId::<IPv4>::from(*prefix),
+                        tbi,
+                        guard,
+                    )
+                }
+                std::net::IpAddr::V6(_addr) => {
+                    self.v6.calculate_and_store_best_and_backup_path(
+                        PrefixId::<IPv6>::from(*prefix),
+                        tbi,
+                        guard,
+                    )
+                }
+            };
+            match res {
+                Ok(_) | Err(PrefixStoreError::StoreNotReadyError) => {
+                    return Ok(())
+                }
+                // Another thread stored a path selection for the prefix
+                // in the meantime, possibly for records that are changed
+                // since. Select again from the current records.
+                Err(PrefixStoreError::PathSelectionOutdated) => {
+                    trace!("path selection for {} outdated, retry", prefix);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Recalculate the best and backup path for the prefix, if the
+    // automatic path selection is enabled, and send an event if the best
+    // path changed from `old_best`.
+    fn auto_select_paths(
+        &self,
+        prefix: &Prefix,
+        old_best: Option<Option<u32>>,
+    ) -> Result<(), PrefixStoreError> {
+        let Some(tbi) = self.auto_path_selection else {
+            return Ok(());
+        };
+        self.select_paths(prefix, &tbi)?;
+        if let Some(previous) = old_best {
+            self.send_best_path_change(prefix, previous);
+        }
+
+        Ok(())
+    }
+
+    // Recalculate the best and backup paths for all the prefixes in the
+    // address families that have a record for the mui, if the automatic
+    // path selection is enabled.
+    fn auto_select_paths_for_mui(
+        &self,
+        mui: u32,
+        families: Families,
+    ) -> Result<(), PrefixStoreError> {
+        if self.auto_path_selection.is_none() {
+            return Ok(());
+        }
+        for prefix in self.prefixes_for_mui(mui, families)? {
+            let old_best = self.best_path_mui_for_events(&prefix);
+            self.auto_select_paths(&prefix, old_best)?;
+        }
+
+        Ok(())
+    }
+
+    // The prefixes in the address families that have a record for the mui.
+    fn prefixes_for_mui(
+        &self,
+        mui: u32,
+        families: Families,
+    ) -> Result<Vec<Prefix>, PrefixStoreError> {
+        let mut prefixes = vec![];
+        if families != Families::V6 {
+            prefixes.extend(
+                self.v4
+                    .prefixes_for_mui(mui)
+                    .map_err(|_| PrefixStoreError::FatalError)?
+                    .into_iter()
+                    .map(Prefix::from),
+            );
+        }
+        if families != Families::V4 {
+            prefixes.extend(
+                self.v6
+                    .prefixes_for_mui(mui)
+                    .map_err(|_| PrefixStoreError::FatalError)?
+                    .into_iter()
+                    .map(Prefix::from),
+            );
+        }
+        Ok(prefixes)
+    }
+
+    // Send an event for a new best path for the prefix, if it differs from
+    // the previous best path, or for the loss of the previous best path, if
+    // the prefix has none anymore.
+    fn send_best_path_change(&self, prefix: &Prefix, previous: Option<u32>) {
+        let Some(best) = self.stored_best_path(prefix) else {
+            if let Some(previous) = previous {
+                self.events.send(
+                    RibEvent {
+                        kind: RibEventKind::BestPathLost,
+                        prefix: Some(*prefix),
+                        mui: previous,
+                        old_status: None,
+                        new_status: RouteStatus::Withdrawn,
+                        ltime: None,
+                    },
+                    Families::Both,
+                );
+            }
+            return;
+        };
+        if Some(best.multi_uniq_id) == previous {
+            return;
+        }
+        self.events.send(
+            RibEvent {
+                kind: RibEventKind::BestPathChange { previous },
+                prefix: Some(*prefix),
+                mui: best.multi_uniq_id,
+                old_status: None,
+                new_status: best.status,
+                ltime: Some(best.ltime),
+            },
+            Families::Both,
+        );
+    }
+
+    fn send_status_change(
+        &self,
+        prefix: &Prefix,
+        mui: u32,
+        old_status: RouteStatus,
+        new_status: RouteStatus,
+        ltime: u64,
+    ) {
+        self.events.send(
+            RibEvent {
+                kind: RibEventKind::StatusChange,
+                prefix: Some(*prefix),
+                mui,
+                old_status: Some(old_status),
+                new_status,
+                ltime: Some(ltime),
+            },
+            Families::Both,
+        );
+    }
+
+    fn send_mui_status_change(
+        &self,
+        mui: u32,
+        was_active: bool,
+        active: bool,
+        families: Families,
+    ) {
+        let status = |active| match active {
+            true => RouteStatus::Active,
+            false => RouteStatus::Withdrawn,
+        };
+        self.events.send(
+            RibEvent {
+                kind: match active {
+                    true => RibEventKind::MuiActivated,
+                    false => RibEventKind::MuiWithdrawn,
+                },
+                prefix: None,
+                mui,
+                old_status: Some(status(was_active)),
+                new_status: status(active),
+                ltime: None,
+            },
+            families,
+        );
+    }
+}
+
+// Split up the address range from `start` up to and including `end` in the
+// smallest set of prefixes that covers it exactly, ordered by address.
+fn range_to_prefixes(
+    start: IpAddr,
+    end: IpAddr,
+) -> Result<Vec<Prefix>, PrefixStoreError> {
+    let (start, end, bits) = match (start, end) {
+        (IpAddr::V4(start), IpAddr::V4(end)) => {
+            (u32::from(start) as u128, u32::from(end) as u128, 32)
+        }
+        (IpAddr::V6(start), IpAddr::V6(end)) => {
+            (u128::from(start), u128::from(end), 128)
+        }
+        _ => return Err(PrefixStoreError::InvalidRange),
+    };
+    if start > end {
+        return Err(PrefixStoreError::InvalidRange);
+    }
+
+    let mut prefixes = vec![];
+    let mut addr = start;
+    loop {
+        // The largest block that starts at `addr`, i.e. is aligned on it,
+        // and that does not extend beyond `end`.
+        let span = end - addr;
+        let max_by_span = match span.checked_add(1) {
+            Some(size) => 127 - size.leading_zeros(),
+            None => 128,
+        };
+        let host_bits = addr.trailing_zeros().min(max_by_span).min(bits);
+        let prefix_addr = match bits {
+            32 => IpAddr::V4(Ipv4Addr::from(addr as u32)),
+            _ => IpAddr::V6(Ipv6Addr::from(addr)),
+        };
+        prefixes.push(
+            Prefix::new(prefix_addr, (bits - host_bits) as u8)
+                .map_err(|_| PrefixStoreError::PrefixLengthInvalid)?,
+        );
+
+        if host_bits == 128 {
+            break;
+        }
+        let last = addr + ((1 << host_bits) - 1);
+        if last == end {
+            break;
+        }
+        addr = last + 1;
+    }
+
+    Ok(prefixes)
 }
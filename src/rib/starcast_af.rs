@@ -1,19 +1,30 @@
 use std::path::Path;
+use std::sync::Mutex;
 
 use inetnum::addr::Prefix;
-use log::{info, trace};
+use log::{error, info, trace};
+#[cfg(feature = "rayon")]
+use rayon::iter::{Either, IntoParallelIterator, ParallelIterator};
+use roaring::RoaringBitmap;
+use zerocopy::IntoBytes;
 
 use crate::prefix_record::Meta;
 use crate::rib::config::PersistStrategy;
-use crate::stats::{Counters, UpsertCounters, UpsertReport};
+use crate::stats::{
+    AfMemoryReport, BatchUpsertReport, Counters, EvictionCounters,
+    PruneReport, UpsertCounters, UpsertReport,
+};
 use crate::{epoch, Guard};
 
 use crate::errors::{FatalError, FatalResult};
-use crate::prefix_cht::cht::PrefixCht;
+use crate::lsm_tree::{HistoryPruner, LongKey, PersistBatch};
+use crate::prefix_cht::cht::{
+    EvictionPin, MultiMap, PathSelectionArgs, PrefixCht, StoredPrefix,
+};
 use crate::types::prefix_record::{ValueHeader, ZeroCopyRecord};
 use crate::types::{PrefixId, RouteStatus};
+use crate::LsmTree;
 use crate::TreeBitMap;
-use crate::{lsm_tree::LongKey, LsmTree};
 use crate::{types::errors::PrefixStoreError, types::prefix_record::Record};
 
 use crate::{IPv4, IPv6};
@@ -21,6 +32,10 @@ use crate::{IPv4, IPv6};
 use crate::AddressFamily;
 
 use super::config::Config;
+use super::path_selection::{DefaultPathSelector, SharedPathSelector};
+use super::snapshot::AfManifest;
+#[cfg(feature = "rayon")]
+use super::STRIDE_SIZE;
 
 //------------ StarCastAfRib -------------------------------------------------
 
@@ -53,6 +68,16 @@ pub(crate) struct StarCastAfRib<
     pub(crate) prefix_cht: PrefixCht<AF, M, P_ROOT_SIZE>,
     pub(crate) persist_tree: Option<LsmTree<AF, LongKey<AF>, KEY_SIZE>>,
     pub counters: Counters,
+    // The selector for the best and backup paths, shared with the other
+    // address family.
+    pub(crate) path_selector: SharedPathSelector<M>,
+    // Whether the path selections leave out the withdrawn records, for the
+    // automatic path selection.
+    pub(crate) active_paths_only: bool,
+    // The prefix length where the previous eviction pass of the Hybrid
+    // strategy stopped. It is locked for the duration of a pass, so that
+    // only one thread evicts at a time.
+    eviction_hand: Mutex<u8>,
 }
 
 impl<
@@ -84,18 +109,20 @@ impl<
                 let persist_path = if let Some(pp) = config.persist_path() {
                     pp
                 } else {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
+                    return Err(std::io::Error::other(
                         "Missing persistence path".to_string(),
                     )
                     .into());
                 };
                 let pp_ref = &Path::new(&persist_path);
-                Some(LsmTree::new(pp_ref).map_err(|_| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "Cannot create persistence store",
-                    )
+                // Only the historical records are looked up by mui.
+                let with_mui_index = matches!(
+                    config.persist_strategy(),
+                    PersistStrategy::WriteAhead
+                        | PersistStrategy::PersistHistory
+                );
+                Some(LsmTree::new(pp_ref, with_mui_index).map_err(|_| {
+                    std::io::Error::other("Cannot create persistence store")
                 })?)
             }
         };
@@ -106,6 +133,9 @@ impl<
             persist_tree,
             counters: Counters::default(),
             prefix_cht: PrefixCht::<AF, M, P_ROOT_SIZE>::init(),
+            path_selector: SharedPathSelector::new(DefaultPathSelector),
+            active_paths_only: false,
+            eviction_hand: Mutex::new(0),
         };
 
         Ok(store)
@@ -117,8 +147,106 @@ impl<
         record: Record<M>,
         update_path_selections: Option<M::TBI>,
     ) -> Result<UpsertReport, PrefixStoreError> {
-        trace!("try insertingf {:?}", prefix);
         let guard = &epoch::pin();
+        let removals = self.tree_bitmap.removals_finished();
+        let mui = record.multi_uniq_id;
+        self.write_ahead([(prefix, &record)]);
+        let mut batch = PersistBatch::default();
+        let report = self.insert_w_batch(
+            prefix,
+            record,
+            update_path_selections,
+            &mut batch,
+            guard,
+        );
+        self.write_batch(batch);
+        if report.is_ok() {
+            self.set_prefixes_exist_again(removals, prefix, mui)?;
+        }
+        self.enforce_memory_budget();
+
+        report
+    }
+
+    // Insert the records, with their position in the batch, using one
+    // guard, and writing all the records to persist in one batch at the
+    // end. The results are added to `report`.
+    pub(crate) fn insert_batch(
+        &self,
+        mut records: Vec<(usize, PrefixId<AF>, Record<M>)>,
+        update_path_selections: Option<M::TBI>,
+        report: &mut BatchUpsertReport,
+    ) {
+        // Sorted on prefix length first, like the keys in the persist tree,
+        // so that consecutive inserts mostly touch the same nodes. The sort
+        // is stable, so the records for a prefix keep their order.
+        records
+            .sort_by(|(_, a, _), (_, b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+        let guard = &epoch::pin();
+        let removals = self.tree_bitmap.removals_finished();
+        self.write_ahead(records.iter().map(|(_, p, r)| (*p, r)));
+        let mut batch = PersistBatch::default();
+        let mut inserted = vec![];
+        for (idx, prefix, record) in records {
+            let mui = record.multi_uniq_id;
+            match self.insert_w_batch(
+                prefix,
+                record,
+                update_path_selections,
+                &mut batch,
+                guard,
+            ) {
+                Ok(r) => {
+                    report.add(&r);
+                    inserted.push((idx, prefix, mui));
+                }
+                Err(e) => report.errors.push((idx, e)),
+            }
+        }
+        self.write_batch(batch);
+        for (idx, prefix, mui) in inserted {
+            if let Err(e) =
+                self.set_prefixes_exist_again(removals, prefix, mui)
+            {
+                report.errors.push((idx, e));
+            }
+        }
+        self.enforce_memory_budget();
+    }
+
+    // Set the prefix, and the mui in the indexes of the nodes, again in the
+    // tree bitmap, after the record for them was stored, if a removal ran
+    // since `removals` was read, that may have cleared them. See
+    // TreeBitMap::start_removal.
+    fn set_prefixes_exist_again(
+        &self,
+        removals: usize,
+        prefix: PrefixId<AF>,
+        mui: u32,
+    ) -> Result<(), PrefixStoreError> {
+        if !self.tree_bitmap.removal_overlapped(removals) {
+            return Ok(());
+        }
+        let (_retry_count, exists) =
+            self.tree_bitmap.set_prefix_exists(prefix, mui)?;
+        if !exists {
+            self.counters.inc_prefixes_count(prefix.len());
+        }
+        Ok(())
+    }
+
+    // Insert a record, adding the records to persist to the batch, instead
+    // of writing them.
+    fn insert_w_batch(
+        &self,
+        prefix: PrefixId<AF>,
+        record: Record<M>,
+        update_path_selections: Option<M::TBI>,
+        batch: &mut PersistBatch<AF>,
+        guard: &Guard,
+    ) -> Result<UpsertReport, PrefixStoreError> {
+        trace!("try insertingf {:?}", prefix);
         self.tree_bitmap
             .set_prefix_exists(prefix, record.multi_uniq_id)
             .and_then(|(retry_count, exists)| {
@@ -127,6 +255,7 @@ impl<
                     prefix,
                     record,
                     update_path_selections,
+                    batch,
                     guard,
                 )
                 .map(|mut report| {
@@ -138,24 +267,66 @@ impl<
                         self.counters.inc_prefixes_count(prefix.len());
                         report.prefix_new = true;
                     }
+                    self.counters.add_cas_count(report.cas_count);
                     report
                 })
             })
     }
 
+    // The arguments for a best and backup path selection with `tbi`.
+    pub(crate) fn path_selection_args<'a>(
+        &'a self,
+        tbi: M::TBI,
+        guard: &'a Guard,
+    ) -> PathSelectionArgs<'a, M> {
+        PathSelectionArgs {
+            tbi,
+            selector: &*self.path_selector,
+            active_only: self.active_paths_only,
+            withdrawn_muis: self.tree_bitmap.withdrawn_muis_bmin(guard),
+        }
+    }
+
+    fn write_batch(&self, batch: PersistBatch<AF>) {
+        if let Some(persist_tree) = &self.persist_tree {
+            persist_tree.write_batch(batch);
+        }
+    }
+
+    // Write the records to the persist tree in one batch, for the
+    // WriteAhead strategy, before they are stored in memory.
+    fn write_ahead<'r>(
+        &self,
+        records: impl IntoIterator<Item = (PrefixId<AF>, &'r Record<M>)>,
+    ) where
+        M: 'r,
+    {
+        if self.persist_strategy() != PersistStrategy::WriteAhead {
+            return;
+        }
+
+        let mut batch = PersistBatch::default();
+        for (prefix, record) in records {
+            batch.push_w_long_key(prefix, record);
+        }
+        self.write_batch(batch);
+    }
+
     fn upsert_prefix(
         &self,
         prefix: PrefixId<AF>,
         record: Record<M>,
         update_path_selections: Option<M::TBI>,
+        batch: &mut PersistBatch<AF>,
         guard: &Guard,
     ) -> Result<UpsertReport, PrefixStoreError> {
         let mui = record.multi_uniq_id;
+        let update_path_selections = update_path_selections
+            .map(|tbi| self.path_selection_args(tbi, guard));
         match self.config.persist_strategy() {
+            // The record was written to disk by `write_ahead`.
             PersistStrategy::WriteAhead => {
-                if let Some(persist_tree) = &self.persist_tree {
-                    persist_tree.persist_record_w_long_key(prefix, &record);
-
+                if self.persist_tree.is_some() {
                     self.prefix_cht
                         .upsert_prefix(
                             prefix,
@@ -173,8 +344,8 @@ impl<
                 .upsert_prefix(prefix, record, update_path_selections, guard)
                 .map(|(report, old_rec)| {
                     if let Some(rec) = old_rec {
-                        if let Some(persist_tree) = &self.persist_tree {
-                            persist_tree.persist_record_w_long_key(
+                        if self.persist_tree.is_some() {
+                            batch.push_w_long_key(
                                 prefix,
                                 &Record::from((mui, &rec)),
                             );
@@ -186,16 +357,40 @@ impl<
                 .prefix_cht
                 .upsert_prefix(prefix, record, update_path_selections, guard)
                 .map(|(report, _)| report),
+            PersistStrategy::Hybrid => {
+                let _pin = self
+                    .fault_in(prefix)
+                    .map_err(|_| PrefixStoreError::FatalError)?;
+                let bytes = MultiMap::<M>::record_bytes(&record.meta);
+                self.prefix_cht
+                    .upsert_prefix(
+                        prefix,
+                        record,
+                        update_path_selections,
+                        guard,
+                    )
+                    .map(|(report, old_rec)| {
+                        self.counters.add_resident_bytes(bytes);
+                        if let Some(rec) = old_rec {
+                            self.counters.sub_resident_bytes(
+                                MultiMap::<M>::record_bytes(rec.meta()),
+                            );
+                        }
+                        report
+                    })
+            }
             PersistStrategy::PersistOnly => {
                 if let Some(persist_tree) = &self.persist_tree {
-                    let (retry_count, exists) = self
-                        .tree_bitmap
-                        .set_prefix_exists(prefix, record.multi_uniq_id)?;
-                    persist_tree.persist_record_w_short_key(prefix, &record);
+                    // The prefix was already set to exist in the tree
+                    // bitmap by the caller, which also sets `prefix_new`.
+                    let mui_new = !persist_tree
+                        .has_records(prefix, Some(mui))
+                        && !batch.has_short_key(prefix, mui);
+                    batch.push_w_short_key(prefix, &record);
                     Ok(UpsertReport {
-                        cas_count: retry_count as usize,
-                        prefix_new: exists,
-                        mui_new: true,
+                        cas_count: 0,
+                        prefix_new: false,
+                        mui_new,
                         mui_count: 0,
                     })
                 } else {
@@ -213,6 +408,179 @@ impl<
         }
     }
 
+    // Whether there are any records for this prefix, or for this (prefix,
+    // mui) pair if a mui is specified, in either the in-memory or the
+    // persisted store.
+    fn has_records(&self, prefix: PrefixId<AF>, mui: Option<u32>) -> bool {
+        self.prefix_cht.has_records(prefix, mui)
+            || self
+                .persist_tree
+                .as_ref()
+                .is_some_and(|p| p.has_records(prefix, mui))
+    }
+
+    // Remove all the records, current and historical, for the (prefix, mui)
+    // pair from the store. If this was the last mui for the prefix, the
+    // prefix itself is removed from the tree bitmap as well.
+    //
+    // An insert for the same prefix, or the same mui, that runs
+    // concurrently keeps the prefix, and the mui in the indexes of the
+    // nodes, in the tree bitmap, if it stores its record. See
+    // TreeBitMap::start_removal.
+    pub(crate) fn remove(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+    ) -> Result<(), PrefixStoreError> {
+        let guard = &epoch::pin();
+        let _removal = self.tree_bitmap.start_removal();
+
+        if self.remove_records(prefix, mui, guard)? == 0 {
+            return Err(PrefixStoreError::PrefixNotFound);
+        }
+
+        self.tree_bitmap.remove_mui_from_rbm_index(
+            prefix,
+            mui,
+            |p| self.has_records(p, Some(mui)),
+            guard,
+        )
+    }
+
+    // Remove the records for the (prefix, mui) pair from the in-memory and
+    // persisted stores, and the prefix from the tree bitmap if it has no
+    // records left. The bitmap indexes of the nodes are left alone. Returns
+    // the number of records, current and historical, that were removed.
+    fn remove_records(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+        guard: &Guard,
+    ) -> Result<usize, PrefixStoreError> {
+        let in_memory = match self.persist_strategy() {
+            PersistStrategy::PersistOnly => false,
+            PersistStrategy::Hybrid => self
+                .prefix_cht
+                .remove_record(prefix, mui, guard)?
+                .inspect(|rec| {
+                    self.counters.sub_resident_bytes(
+                        MultiMap::<M>::record_bytes(&rec.meta),
+                    )
+                })
+                .is_some(),
+            _ => self.prefix_cht.remove_record(prefix, mui, guard)?.is_some(),
+        };
+        let persisted = if let Some(persist_tree) = &self.persist_tree {
+            persist_tree
+                .remove_records_for_prefix_mui(prefix, mui)
+                .map_err(|_| PrefixStoreError::FatalError)?
+        } else {
+            0
+        };
+
+        // The WriteAhead strategy has the current record on disk as well.
+        let removed = match self.persist_strategy() {
+            PersistStrategy::WriteAhead => persisted.max(in_memory as usize),
+            _ => persisted + in_memory as usize,
+        };
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        self.counters.dec_routes_count();
+
+        if !self.has_records(prefix, None)
+            && self.tree_bitmap.remove_prefix_exists(prefix)
+        {
+            self.counters.dec_prefixes_count(prefix.len());
+            // A concurrent insert may have stored a record in the meantime.
+            if self.has_records(prefix, None)
+                && !self.tree_bitmap.restore_prefix_exists(prefix)
+            {
+                self.counters.inc_prefixes_count(prefix.len());
+            }
+        }
+
+        Ok(removed)
+    }
+
+    // Remove all the records, current and historical, for this mui from the
+    // store, and clear the mui from the withdrawn muis. Returns the number
+    // of records that were removed.
+    //
+    // Other muis can be inserted concurrently. Records for this mui that
+    // are inserted while the purge is running may or may not survive it,
+    // but the ones that survive stay in the indexes of the nodes.
+    pub(crate) fn purge_mui(
+        &self,
+        mui: u32,
+    ) -> Result<usize, PrefixStoreError> {
+        let guard = &epoch::pin();
+        let _removal = self.tree_bitmap.start_removal();
+
+        let (prefixes, node_ids) =
+            self.tree_bitmap.prefixes_and_nodes_for_mui(mui);
+
+        let mut count = 0;
+        for prefix in prefixes {
+            count += self.remove_records(prefix, mui, guard)?;
+        }
+
+        self.tree_bitmap.purge_mui_from_rbm_indexes(
+            mui,
+            &node_ids,
+            |p| self.has_records(p, Some(mui)),
+            guard,
+        )?;
+        self.tree_bitmap.mark_mui_as_active(mui, guard)?;
+
+        Ok(count)
+    }
+
+    // The prefixes that have a record for this mui, found through the
+    // bitmap indexes of the nodes.
+    pub(crate) fn prefixes_for_mui(
+        &self,
+        mui: u32,
+    ) -> FatalResult<Vec<PrefixId<AF>>> {
+        let mut prefixes = vec![];
+        for prefix in self.tree_bitmap.prefixes_and_nodes_for_mui(mui).0 {
+            if self.local_status(prefix, mui)?.is_some() {
+                prefixes.push(prefix);
+            }
+        }
+        Ok(prefixes)
+    }
+
+    // Remove all the records for all the muis for this prefix, and the
+    // prefix itself, from the store. Returns the number of muis that were
+    // removed.
+    pub(crate) fn remove_prefix(
+        &self,
+        prefix: PrefixId<AF>,
+    ) -> Result<usize, PrefixStoreError> {
+        let mut muis = self.prefix_cht.muis_for_prefix(prefix);
+        if let Some(persist_tree) = &self.persist_tree {
+            muis.extend(
+                persist_tree
+                    .muis_for_prefix(prefix)
+                    .map_err(|_| PrefixStoreError::FatalError)?,
+            );
+        }
+        muis.sort_unstable();
+        muis.dedup();
+
+        if muis.is_empty() {
+            return Err(PrefixStoreError::PrefixNotFound);
+        }
+
+        for mui in &muis {
+            self.remove(prefix, *mui)?;
+        }
+
+        Ok(muis.len())
+    }
+
     pub fn get_nodes_count(&self) -> usize {
         self.tree_bitmap.nodes_count()
     }
@@ -226,7 +594,10 @@ impl<
         ltime: u64,
     ) -> Result<(), PrefixStoreError> {
         match self.persist_strategy() {
-            PersistStrategy::WriteAhead | PersistStrategy::MemoryOnly => {
+            PersistStrategy::MemoryOnly | PersistStrategy::Hybrid => {
+                let _pin = self
+                    .fault_in(prefix)
+                    .map_err(|_| PrefixStoreError::FatalError)?;
                 let (stored_prefix, exists) =
                     self.prefix_cht.non_recursive_retrieve_prefix_mut(prefix);
 
@@ -237,6 +608,31 @@ impl<
                     .record_map
                     .mark_as_withdrawn_for_mui(mui, ltime);
             }
+            PersistStrategy::WriteAhead => {
+                let (stored_prefix, exists) =
+                    self.prefix_cht.non_recursive_retrieve_prefix_mut(prefix);
+
+                if !exists {
+                    return Err(PrefixStoreError::PrefixNotFound);
+                }
+                stored_prefix
+                    .record_map
+                    .mark_as_withdrawn_for_mui(mui, ltime);
+
+                // The record itself was already written ahead, the status
+                // change is persisted as an empty record.
+                if stored_prefix.record_map.contains(mui) {
+                    self.persist_tree
+                        .as_ref()
+                        .ok_or(PrefixStoreError::StoreNotReadyError)?
+                        .insert_empty_record(
+                            prefix,
+                            mui,
+                            ltime,
+                            RouteStatus::Withdrawn,
+                        );
+                }
+            }
             PersistStrategy::PersistOnly => {
                 println!(
                     "mark as wd in persist tree {:?} for mui {:?}",
@@ -273,6 +669,12 @@ impl<
                 if !exists {
                     return Err(PrefixStoreError::StoreNotReadyError);
                 }
+
+                // The status change overwrites the ltime of the in-memory
+                // record, so persist it as a historical record first.
+                self.persist_before_status_change(stored_prefix, prefix, mui)
+                    .map_err(|_| PrefixStoreError::StoreNotReadyError)?;
+
                 stored_prefix
                     .record_map
                     .mark_as_withdrawn_for_mui(mui, ltime);
@@ -288,7 +690,12 @@ impl<
                             return Err(PrefixStoreError::StoreNotReadyError);
                         };
 
-                    p_tree.insert_empty_record(prefix, mui, ltime);
+                    p_tree.insert_empty_record(
+                        prefix,
+                        mui,
+                        ltime,
+                        RouteStatus::Withdrawn,
+                    );
                 }
             }
         }
@@ -305,7 +712,8 @@ impl<
         ltime: u64,
     ) -> FatalResult<()> {
         match self.persist_strategy() {
-            PersistStrategy::WriteAhead | PersistStrategy::MemoryOnly => {
+            PersistStrategy::MemoryOnly | PersistStrategy::Hybrid => {
+                let _pin = self.fault_in(prefix)?;
                 let (stored_prefix, exists) =
                     self.prefix_cht.non_recursive_retrieve_prefix_mut(prefix);
 
@@ -314,6 +722,29 @@ impl<
                 }
                 stored_prefix.record_map.mark_as_active_for_mui(mui, ltime);
             }
+            PersistStrategy::WriteAhead => {
+                let (stored_prefix, exists) =
+                    self.prefix_cht.non_recursive_retrieve_prefix_mut(prefix);
+
+                if !exists {
+                    return Err(FatalError);
+                }
+                stored_prefix.record_map.mark_as_active_for_mui(mui, ltime);
+
+                // The record itself was already written ahead, the status
+                // change is persisted as an empty record.
+                if stored_prefix.record_map.contains(mui) {
+                    self.persist_tree
+                        .as_ref()
+                        .ok_or(FatalError)?
+                        .insert_empty_record(
+                            prefix,
+                            mui,
+                            ltime,
+                            RouteStatus::Active,
+                        );
+                }
+            }
             PersistStrategy::PersistOnly => {
                 if let Some(p_tree) = self.persist_tree.as_ref() {
                     if let Ok(Some(record_b)) =
@@ -338,6 +769,15 @@ impl<
                 if !exists {
                     return Err(FatalError);
                 }
+
+                // The status change overwrites the ltime of the in-memory
+                // record, so persist it as a historical record first.
+                self.persist_before_status_change(
+                    stored_prefix,
+                    prefix,
+                    mui,
+                )?;
+
                 stored_prefix.record_map.mark_as_active_for_mui(mui, ltime);
 
                 // Use the record from the in-memory RIB to persist.
@@ -353,9 +793,15 @@ impl<
 
                     // Here we are keeping persisted history, so no removal of
                     // old (prefix, mui) records.
-                    // We are inserting an empty record, since this is a
-                    // withdrawal.
-                    p_tree.insert_empty_record(prefix, mui, ltime);
+                    // We are inserting an empty record, since this is only
+                    // a status change. It carries the new status, `Active`,
+                    // so that the history shows the record coming back.
+                    p_tree.insert_empty_record(
+                        prefix,
+                        mui,
+                        ltime,
+                        RouteStatus::Active,
+                    );
                 }
             }
         }
@@ -363,6 +809,28 @@ impl<
         Ok(())
     }
 
+    // Persist the in-memory record for a (prefix, mui) pair as a historical
+    // record, for the PersistHistory strategy, before its status, and with
+    // that its ltime, gets changed. Without this, the record as it was
+    // before the status change would not be part of the history.
+    fn persist_before_status_change(
+        &self,
+        stored_prefix: &StoredPrefix<AF, M>,
+        prefix: PrefixId<AF>,
+        mui: u32,
+    ) -> FatalResult<()> {
+        if let Some(record) =
+            stored_prefix.record_map.get_record_for_mui(mui, true)
+        {
+            self.persist_tree
+                .as_ref()
+                .ok_or(FatalError)?
+                .persist_record_w_long_key(prefix, &record);
+        }
+
+        Ok(())
+    }
+
     // Change the status of the mui globally to Withdrawn. Iterators and match
     // functions will by default not return any records for this mui.
     pub fn mark_mui_as_withdrawn(
@@ -457,6 +925,74 @@ impl<
         })
     }
 
+    pub fn prefixes_iter_sorted<'a>(
+        &'a self,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<(Prefix, Vec<Record<M>>)>> + 'a
+    {
+        self.tree_bitmap.sorted_prefixes_iter().map(|p| {
+            if let Ok(r) = self.get_value(p.into(), None, true, guard) {
+                Ok((p, r.unwrap_or_default()))
+            } else {
+                Err(FatalError)
+            }
+        })
+    }
+
+    // Parallel iterator over all the prefixes. The work is split over the
+    // roots of the prefix CHT, one for each prefix length, or, for the
+    // PersistOnly strategy, that keeps no records in the prefix CHT, over
+    // the roots of the node CHT, one for each stride. Every worker pins its
+    // own guard.
+    #[cfg(feature = "rayon")]
+    pub fn par_prefixes_iter(
+        &self,
+    ) -> impl ParallelIterator<Item = FatalResult<(Prefix, Vec<Record<M>>)>> + '_
+    where
+        AF: Send + Sync,
+        C: Sync,
+    {
+        match self.persist_strategy() {
+            PersistStrategy::PersistOnly => Either::Left(
+                (0..=AF::BITS / STRIDE_SIZE)
+                    .into_par_iter()
+                    .flat_map_iter(|i| {
+                        self.tree_bitmap
+                            .prefixes_for_node_len(i * STRIDE_SIZE)
+                    })
+                    .map_init(epoch::pin, |guard, p| {
+                        if let Ok(r) = self.get_value(p, None, true, guard) {
+                            Ok((p.into(), r.unwrap_or_default()))
+                        } else {
+                            Err(FatalError)
+                        }
+                    }),
+            ),
+            _ => Either::Right(
+                (0..=AF::BITS)
+                    .into_par_iter()
+                    .flat_map_iter(|len| {
+                        self.prefix_cht.stored_prefixes_for_len(len)
+                    })
+                    .map_init(epoch::pin, |guard, sp| {
+                        let _pin = match self.fault_in_stored_prefix(sp) {
+                            Ok(pin) => pin,
+                            Err(err) => return Some(Err(err)),
+                        };
+                        // Removed prefixes stay in the CHT, without records.
+                        sp.record_map
+                            .get_filtered_records(
+                                None,
+                                true,
+                                self.tree_bitmap.withdrawn_muis_bmin(guard),
+                            )
+                            .map(|r| Ok((sp.prefix.into(), r)))
+                    })
+                    .flatten(),
+            ),
+        }
+    }
+
     //-------- Persistence ---------------------------------------------------
 
     pub fn persist_strategy(&self) -> PersistStrategy {
@@ -505,6 +1041,193 @@ impl<
             .flatten()
     }
 
+    // Rebuild the in-memory state of this RIB from the records in the
+    // persist tree. This sets the prefix existence and the mui bitmaps in
+    // the tree bitmap, and the prefix and route counters, for every
+    // persisted (prefix, mui) pair. For the WriteAhead strategy the most
+    // recent record for each pair is also re-inserted into the prefix CHT.
+    //
+    // For the PersistHistory strategy the current records only live in
+    // memory, so they cannot be restored. Only the (prefix, mui) pairs
+    // that have history records on disk will be known to the tree bitmap.
+    // For the Hybrid strategy the persist tree holds the evicted records,
+    // and copies of the records that were in memory at the last shutdown.
+    // These are all restored as evicted records, and stay evicted until
+    // they are accessed.
+    pub(crate) fn restore_from_persist_tree(&self) -> FatalResult<()> {
+        let persist_tree = if let Some(p_tree) = &self.persist_tree {
+            p_tree
+        } else {
+            return Ok(());
+        };
+
+        self.restore_from_tree(
+            persist_tree,
+            self.persist_strategy() == PersistStrategy::WriteAhead,
+        )
+    }
+
+    // Rebuild the in-memory structures from the most recent record for
+    // every (prefix, mui) pair in `tree`. If `load_records` is set, these
+    // records are stored in memory as well.
+    fn restore_from_tree(
+        &self,
+        tree: &LsmTree<AF, LongKey<AF>, KEY_SIZE>,
+        load_records: bool,
+    ) -> FatalResult<()> {
+        let guard = &epoch::pin();
+
+        for rec_b in tree.most_recent_records_iter() {
+            let rec_b = rec_b?;
+            let rec = ZeroCopyRecord::<AF>::from_bytes(&rec_b)?;
+            let prefix = rec.prefix;
+            let mui = rec.multi_uniq_id;
+
+            let (_retry_count, exists) = self
+                .tree_bitmap
+                .set_prefix_exists(prefix, mui)
+                .map_err(|_| FatalError)?;
+            if !exists {
+                self.counters.inc_prefixes_count(prefix.len());
+            }
+            self.counters.inc_routes_count();
+
+            if load_records {
+                // The most recent record may be a status change, without
+                // metadata. It is loaded with its own status, and the
+                // metadata of the newest record with metadata before it.
+                if let Some((_, record)) = self.persisted_record(&rec_b)? {
+                    if self.persist_strategy() == PersistStrategy::Hybrid {
+                        self.counters.add_resident_bytes(
+                            MultiMap::<M>::record_bytes(&record.meta),
+                        );
+                    }
+                    self.prefix_cht
+                        .upsert_prefix(prefix, record, None, guard)
+                        .map_err(|_| FatalError)?;
+                }
+            } else if self.persist_strategy() == PersistStrategy::Hybrid {
+                self.prefix_cht
+                    .non_recursive_retrieve_prefix_mut(prefix)
+                    .0
+                    .set_spilled()?;
+            }
+        }
+
+        trace!(
+            "restored {} prefixes from persist tree",
+            self.counters.prefixes_count().iter().sum::<usize>()
+        );
+
+        Ok(())
+    }
+
+    // Write a snapshot of the persist tree to `dest`, and return the
+    // manifest for it. For the PersistHistory strategy the current records
+    // only live in memory, so these are written to a separate store in
+    // `current_dest`, before the historical records are copied. A record
+    // that is replaced while the snapshot is taken can then show up in both,
+    // but no record is missing. For the Hybrid strategy the records of every
+    // prefix are read while they cannot be evicted, or loaded back, and they
+    // are all written to `dest` as evicted records.
+    pub(crate) fn snapshot(
+        &self,
+        dest: &Path,
+        current_dest: &Path,
+    ) -> FatalResult<AfManifest> {
+        let guard = &epoch::pin();
+        let withdrawn_muis =
+            self.tree_bitmap.withdrawn_muis_bmin(guard).iter().collect();
+        let Some(persist_tree) = &self.persist_tree else {
+            return Err(FatalError);
+        };
+
+        let current = match self.persist_strategy() {
+            PersistStrategy::PersistHistory => {
+                let current = LsmTree::<AF, LongKey<AF>, KEY_SIZE>::new(
+                    current_dest,
+                    false,
+                )?;
+                let no_withdrawn_muis = RoaringBitmap::new();
+                for prefix in self.tree_bitmap.prefixes_iter() {
+                    let prefix = PrefixId::<AF>::from(prefix);
+                    for record in self
+                        .prefix_cht
+                        .get_records_for_prefix(
+                            prefix,
+                            None,
+                            true,
+                            &no_withdrawn_muis,
+                        )
+                        .into_iter()
+                        .flatten()
+                    {
+                        current.persist_record_w_long_key(prefix, &record);
+                    }
+                }
+                current.flush_to_disk().map_err(|_| FatalError)?;
+                persist_tree.snapshot_to(dest)?;
+                current
+            }
+            PersistStrategy::Hybrid => {
+                let snapshot =
+                    LsmTree::<AF, LongKey<AF>, KEY_SIZE>::new(dest, false)?;
+                for stored_prefix in (0..=AF::BITS).flat_map(|len| {
+                    self.prefix_cht.stored_prefixes_for_len(len)
+                }) {
+                    let prefix = stored_prefix.prefix;
+                    let mut batch = PersistBatch::default();
+                    for record in stored_prefix.all_records(|| {
+                        self.evicted_records(persist_tree, prefix)
+                    })? {
+                        batch.push_w_short_key(prefix, &record);
+                    }
+                    snapshot.write_batch(batch);
+                }
+                snapshot.flush_to_disk().map_err(|_| FatalError)?;
+                snapshot
+            }
+            _ => persist_tree.snapshot_to(dest)?,
+        };
+
+        let counters = Counters::default();
+        let mut last_prefix = None;
+        for rec_b in current.most_recent_records_iter() {
+            let rec_b = rec_b?;
+            let prefix = ZeroCopyRecord::<AF>::from_bytes(&rec_b)?.prefix;
+            if last_prefix != Some(prefix) {
+                counters.inc_prefixes_count(prefix.len());
+                last_prefix = Some(prefix);
+            }
+            counters.inc_routes_count();
+        }
+
+        Ok(AfManifest {
+            prefixes_count: counters.prefixes_count(),
+            routes_count: counters.routes_count(),
+            withdrawn_muis,
+        })
+    }
+
+    // Load the current records from a snapshot that were written by
+    // `snapshot` for the PersistHistory strategy.
+    pub(crate) fn restore_current_records(
+        &self,
+        current_dir: &Path,
+    ) -> FatalResult<()> {
+        let current =
+            LsmTree::<AF, LongKey<AF>, KEY_SIZE>::new(current_dir, false)?;
+
+        self.restore_from_tree(&current, true)
+    }
+
+    // Whether the counters of this RIB, that was restored from a snapshot,
+    // match the counters in the manifest of the snapshot.
+    pub(crate) fn matches_manifest(&self, manifest: &AfManifest) -> bool {
+        self.counters.prefixes_count() == manifest.prefixes_count
+            && self.counters.routes_count() == manifest.routes_count
+    }
+
     pub(crate) fn flush_to_disk(&self) -> Result<(), PrefixStoreError> {
         if let Some(p) = &self.persist_tree {
             p.flush_to_disk()
@@ -514,6 +1237,36 @@ impl<
         }
     }
 
+    // A pruner for the historical records of this RIB, if it keeps those,
+    // and has a retention policy.
+    pub(crate) fn history_pruner(&self) -> Option<HistoryPruner<AF>> {
+        self.config.retention_policy()?;
+        match self.persist_strategy() {
+            PersistStrategy::WriteAhead | PersistStrategy::PersistHistory => {
+                self.persist_tree.as_ref().map(|tree| {
+                    tree.history_pruner(
+                        self.persist_strategy()
+                            == PersistStrategy::WriteAhead,
+                    )
+                })
+            }
+            PersistStrategy::MemoryOnly
+            | PersistStrategy::PersistOnly
+            | PersistStrategy::Hybrid => None,
+        }
+    }
+
+    pub(crate) fn prune_history(
+        &self,
+    ) -> Result<PruneReport, PrefixStoreError> {
+        match (self.history_pruner(), self.config.retention_policy()) {
+            (Some(pruner), Some(policy)) => pruner
+                .prune(&policy)
+                .map_err(|_| PrefixStoreError::FatalError),
+            _ => Ok(PruneReport::default()),
+        }
+    }
+
     pub fn approx_persisted_items(&self) -> usize {
         if let Some(p) = &self.persist_tree {
             p.approximate_len()
@@ -522,6 +1275,14 @@ impl<
         }
     }
 
+    pub(crate) fn memory_report(&self) -> FatalResult<AfMemoryReport> {
+        let guard = &epoch::pin();
+        Ok(AfMemoryReport {
+            nodes: self.tree_bitmap.memory_report()?,
+            prefixes: self.prefix_cht.memory_report(guard),
+        })
+    }
+
     pub fn disk_space(&self) -> u64 {
         if let Some(p) = &self.persist_tree {
             p.disk_space()
@@ -529,6 +1290,165 @@ impl<
             0
         }
     }
+
+    //-------- Eviction ------------------------------------------------------
+
+    // Pin the records of this prefix in memory, and load them back from the
+    // persist tree if they were evicted, for the Hybrid strategy. Returns
+    // `None` for the other strategies, or if the prefix is not in the CHT.
+    pub(crate) fn fault_in(
+        &self,
+        prefix: PrefixId<AF>,
+    ) -> FatalResult<Option<EvictionPin<'_>>> {
+        if self.persist_strategy() != PersistStrategy::Hybrid {
+            return Ok(None);
+        }
+
+        match self.prefix_cht.non_recursive_retrieve_prefix(prefix) {
+            (Some(stored_prefix), _) => {
+                self.fault_in_stored_prefix(stored_prefix)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn fault_in_stored_prefix<'b>(
+        &self,
+        stored_prefix: &'b StoredPrefix<AF, M>,
+    ) -> FatalResult<Option<EvictionPin<'b>>> {
+        let (PersistStrategy::Hybrid, Some(persist_tree)) =
+            (self.persist_strategy(), &self.persist_tree)
+        else {
+            return Ok(None);
+        };
+
+        let pin = stored_prefix.pin();
+        let prefix = stored_prefix.prefix;
+        let (count, bytes) = stored_prefix.fault_in(|| {
+            let records = self.evicted_records(persist_tree, prefix)?;
+            persist_tree.remove_records_for_prefix(prefix)?;
+            Ok(records)
+        })?;
+        if count > 0 {
+            trace!("faulted in {} records for {:?}", count, prefix);
+            self.counters.add_faulted_in(count, bytes);
+        }
+
+        Ok(Some(pin))
+    }
+
+    // The records for a prefix that were evicted to the persist tree.
+    fn evicted_records(
+        &self,
+        persist_tree: &LsmTree<AF, LongKey<AF>, KEY_SIZE>,
+        prefix: PrefixId<AF>,
+    ) -> FatalResult<Vec<Record<M>>> {
+        persist_tree
+            .records_for_prefix(prefix, None, true, &RoaringBitmap::new())
+            .into_iter()
+            .flatten()
+            .map(|bytes| {
+                let bytes = bytes?;
+                let record = ZeroCopyRecord::<AF>::from_bytes(&bytes)?;
+                Ok(Record {
+                    multi_uniq_id: record.multi_uniq_id,
+                    ltime: record.ltime,
+                    status: record.status,
+                    meta: record.meta.to_vec().into(),
+                })
+            })
+            .collect()
+    }
+
+    // Evict the records of the prefixes that were not accessed since the
+    // previous pass to the persist tree, if the in-memory records take more
+    // than the memory budget, until they take less than 90% of it. A pass
+    // goes around the prefix lengths at most twice, starting at the length
+    // where the previous pass stopped. The first time around, prefixes that
+    // were accessed only lose their mark. Only one thread evicts at a time,
+    // the others carry on without waiting.
+    pub(crate) fn enforce_memory_budget(&self) {
+        let (PersistStrategy::Hybrid, Some(budget), Some(persist_tree)) = (
+            self.persist_strategy(),
+            self.config.memory_budget(),
+            &self.persist_tree,
+        ) else {
+            return;
+        };
+        if self.counters.resident_bytes() <= budget {
+            return;
+        }
+        let Ok(mut hand) = self.eviction_hand.try_lock() else {
+            return;
+        };
+
+        self.counters.inc_eviction_passes();
+        let low_watermark = budget - budget / 10;
+        let lens = (0..=AF::BITS)
+            .cycle()
+            .skip(*hand as usize)
+            .take(2 * (AF::BITS as usize + 1));
+        for len in lens {
+            *hand = len;
+            for stored_prefix in self.prefix_cht.stored_prefixes_for_len(len)
+            {
+                if self.counters.resident_bytes() < low_watermark {
+                    return;
+                }
+                if stored_prefix.take_referenced() {
+                    continue;
+                }
+
+                let prefix = stored_prefix.prefix;
+                match stored_prefix.evict(|records| {
+                    let mut batch = PersistBatch::default();
+                    for record in records {
+                        batch.push_w_short_key(prefix, record);
+                    }
+                    persist_tree.write_batch(batch);
+                }) {
+                    Ok((count, bytes)) => {
+                        self.counters.add_evicted(count, bytes)
+                    }
+                    Err(err) => {
+                        error!("eviction of {:?} failed: {}", prefix, err);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    // Write copies of all the records that are in memory to the persist
+    // tree, in the same way as evicted records, for the Hybrid strategy.
+    // The records stay in memory. Once the persist tree is flushed, every
+    // record can be restored when it is opened again.
+    pub(crate) fn spill_resident_records(&self) -> FatalResult<()> {
+        let (PersistStrategy::Hybrid, Some(persist_tree)) =
+            (self.persist_strategy(), &self.persist_tree)
+        else {
+            return Ok(());
+        };
+
+        for stored_prefix in (0..=AF::BITS)
+            .flat_map(|len| self.prefix_cht.stored_prefixes_for_len(len))
+        {
+            let prefix = stored_prefix.prefix;
+            stored_prefix.spill_resident(|records| {
+                let mut batch = PersistBatch::default();
+                for record in records {
+                    batch.push_w_short_key(prefix, record);
+                }
+                persist_tree.write_batch(batch);
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn eviction_counters(&self) -> EvictionCounters {
+        self.counters.eviction_counters()
+    }
 }
 
 impl<
@@ -4,13 +4,18 @@ use log::trace;
 use zerocopy::TryFromBytes;
 
 use crate::errors::{FatalError, FatalResult};
-use crate::match_options::{MatchOptions, MatchType, QueryResult};
+use crate::match_options::{
+    IncludeHistory, MatchOptions, MatchType, QueryResult,
+};
 use crate::prefix_record::RecordSet;
 use crate::types::prefix_record::ZeroCopyRecord;
-use crate::types::Record;
+use crate::types::{Record, RouteStatus};
 use crate::AddressFamily;
 use crate::{prefix_record::Meta, rib::starcast_af::StarCastAfRib};
 use inetnum::addr::Prefix;
+use roaring::RoaringBitmap;
+use std::collections::HashSet;
+use std::ops::RangeBounds;
 
 use crate::types::errors::PrefixStoreError;
 use crate::types::PrefixId;
@@ -74,6 +79,20 @@ impl<
                     })
                     .transpose()
             }
+            PersistStrategy::Hybrid => {
+                let pin = self.fault_in(prefix_id)?;
+                let records = self.prefix_cht.get_records_for_prefix(
+                    prefix_id,
+                    mui,
+                    include_withdrawn,
+                    self.tree_bitmap.withdrawn_muis_bmin(guard),
+                );
+                // Loading the records may have taken the RIB over its
+                // memory budget.
+                drop(pin);
+                self.enforce_memory_budget();
+                Ok(records)
+            }
             _ => Ok(self.prefix_cht.get_records_for_prefix(
                 prefix_id,
                 mui,
@@ -83,6 +102,273 @@ impl<
         }
     }
 
+    // The local status of the record for a (prefix, mui) pair, i.e.
+    // regardless of the global status of the mui, or `None` if there is no
+    // record.
+    pub(crate) fn local_status(
+        &self,
+        prefix: PrefixId<AF>,
+        mui: u32,
+    ) -> FatalResult<Option<RouteStatus>> {
+        let no_withdrawn_muis = RoaringBitmap::new();
+        match self.persist_strategy() {
+            PersistStrategy::PersistOnly => {
+                let Some(records) =
+                    self.persist_tree.as_ref().and_then(|tree| {
+                        tree.records_for_prefix(
+                            prefix,
+                            Some(mui),
+                            true,
+                            &no_withdrawn_muis,
+                        )
+                    })
+                else {
+                    return Ok(None);
+                };
+                let Some(record) = records.first() else {
+                    return Ok(None);
+                };
+                let record = record.as_ref().map_err(|_| FatalError)?;
+                ZeroCopyRecord::<AF>::try_ref_from_bytes(record)
+                    .map(|r| Some(r.status))
+                    .map_err(|_| FatalError)
+            }
+            _ => {
+                let _pin = self.fault_in(prefix)?;
+                Ok(self
+                    .prefix_cht
+                    .get_records_for_prefix(
+                        prefix,
+                        Some(mui),
+                        true,
+                        &no_withdrawn_muis,
+                    )
+                    .and_then(|records| records.first().map(|r| r.status)))
+            }
+        }
+    }
+
+    // Retrieve the historical records for a prefix from the persist tree,
+    // ordered by ltime. Only the WriteAhead and the PersistHistory
+    // strategies keep historical records, so for the other strategies this
+    // always returns `None`. Note that for WriteAhead the current record is
+    // part of the history, since it was written to disk on insert as well.
+    pub(crate) fn get_history(
+        &'a self,
+        prefix_id: PrefixId<AF>,
+        mui: Option<u32>,
+        include_withdrawn: bool,
+        guard: &'a Guard,
+    ) -> FatalResult<Option<Vec<Record<M>>>> {
+        if !matches!(
+            self.persist_strategy(),
+            PersistStrategy::WriteAhead | PersistStrategy::PersistHistory
+        ) {
+            return Ok(None);
+        }
+
+        self.persist_tree
+            .as_ref()
+            .and_then(|tree| {
+                tree.records_for_prefix(
+                    prefix_id,
+                    mui,
+                    include_withdrawn,
+                    self.tree_bitmap.withdrawn_muis_bmin(guard),
+                )
+            })
+            .map(|v| {
+                let mut records = v
+                    .into_iter()
+                    .map(|bytes| {
+                        let bytes = bytes?;
+                        let rec = ZeroCopyRecord::<AF>::from_bytes(&bytes)?;
+                        Ok(((rec.multi_uniq_id, rec.ltime), bytes))
+                    })
+                    .collect::<FatalResult<Vec<_>>>()?;
+                records.sort_by_key(|(k, _)| *k);
+
+                // Status changes get the metadata of the newest record for
+                // the mui before them, in one pass over the records ordered
+                // by (mui, ltime). Only if that record was not retrieved,
+                // because it was withdrawn, it is looked up.
+                let mut recs = vec![];
+                let mut last_meta: Option<(u32, Vec<u8>)> = None;
+                for ((mui, _), bytes) in records {
+                    let rec = ZeroCopyRecord::<AF>::from_bytes(&bytes)?;
+                    let meta = if !rec.meta.is_empty() {
+                        rec.meta.to_vec()
+                    } else if let Some((_, meta)) =
+                        last_meta.as_ref().filter(|(m, _)| *m == mui)
+                    {
+                        meta.clone()
+                    } else {
+                        recs.extend(
+                            self.persisted_record(&bytes)?.map(|r| r.1),
+                        );
+                        continue;
+                    };
+                    recs.push(Self::record_with_meta(rec, meta.clone()));
+                    last_meta = Some((mui, meta));
+                }
+                recs.sort_by_key(|r| r.ltime);
+                Ok(recs)
+            })
+            .transpose()
+    }
+
+    // All the historical records for a mui across all prefixes, with an
+    // ltime in `ltime_range`, ordered by ltime. These are retrieved through
+    // the mui index of the persist tree. As with `get_history`, only the
+    // WriteAhead and PersistHistory strategies keep historical records, so
+    // the other strategies return nothing.
+    pub(crate) fn history_for_mui(
+        &'a self,
+        mui: u32,
+        ltime_range: impl RangeBounds<u64> + 'a,
+    ) -> impl Iterator<Item = FatalResult<(PrefixId<AF>, Record<M>)>> + 'a
+    {
+        let records = match self.persist_strategy() {
+            PersistStrategy::WriteAhead | PersistStrategy::PersistHistory => {
+                self.persist_tree
+                    .as_ref()
+                    .map(|tree| tree.records_for_mui(mui, ltime_range))
+            }
+            PersistStrategy::MemoryOnly
+            | PersistStrategy::PersistOnly
+            | PersistStrategy::Hybrid => None,
+        };
+
+        records.into_iter().flatten().filter_map(|bytes| {
+            bytes.and_then(|b| self.persisted_record(&b)).transpose()
+        })
+    }
+
+    // Turn a persisted record with a long key into a Record. A status change
+    // is persisted as a record without metadata, it gets the metadata of the
+    // record it applies to. Returns `None` if that record cannot be found.
+    pub(crate) fn persisted_record(
+        &self,
+        bytes: &[u8],
+    ) -> FatalResult<Option<(PrefixId<AF>, Record<M>)>> {
+        let record = ZeroCopyRecord::<AF>::from_bytes(bytes)?;
+        let meta = if record.meta.is_empty() {
+            let Some(meta) = self.persist_tree.as_ref().and_then(|tree| {
+                tree.meta_before(
+                    record.prefix,
+                    record.multi_uniq_id,
+                    record.ltime,
+                )
+                .transpose()
+            }) else {
+                return Ok(None);
+            };
+            meta?
+        } else {
+            record.meta.to_vec()
+        };
+
+        Ok(Some((record.prefix, Self::record_with_meta(record, meta))))
+    }
+
+    fn record_with_meta(
+        record: &ZeroCopyRecord<AF>,
+        meta: Vec<u8>,
+    ) -> Record<M> {
+        Record::<M> {
+            multi_uniq_id: record.multi_uniq_id,
+            ltime: record.ltime,
+            status: record.status,
+            meta: meta.into(),
+        }
+    }
+
+    // The records for a prefix as they were at logical time `ltime`. For
+    // every mui this is the newest record with an ltime lower than, or equal
+    // to, `ltime`, with the status of the newest status change up to that
+    // moment. Only the WriteAhead and PersistHistory strategies keep
+    // historical records, for the other strategies only the current records
+    // that are not newer than `ltime` can be returned. The global status of
+    // muis is not taken into account, since its history is not kept.
+    pub(crate) fn get_value_at(
+        &'a self,
+        prefix_id: PrefixId<AF>,
+        mui: Option<u32>,
+        include_withdrawn: bool,
+        ltime: u64,
+    ) -> FatalResult<Option<Vec<Record<M>>>> {
+        // An empty bitmap, so that the stored statuses are not rewritten
+        // with the current global status of the muis.
+        let no_withdrawn_muis = RoaringBitmap::new();
+
+        let mut versions = vec![];
+        if let Some(tree) = &self.persist_tree {
+            for bytes in tree
+                .records_for_prefix(prefix_id, mui, true, &no_withdrawn_muis)
+                .into_iter()
+                .flatten()
+            {
+                let bytes = bytes?;
+                let record = ZeroCopyRecord::<AF>::from_bytes(&bytes)?;
+                // Status changes are persisted as records without metadata.
+                let meta = (!record.meta.is_empty())
+                    .then(|| M::from(record.meta.to_vec()));
+                versions.push((
+                    record.multi_uniq_id,
+                    record.ltime,
+                    record.status,
+                    meta,
+                ));
+            }
+        }
+        if self.persist_strategy() != PersistStrategy::PersistOnly {
+            versions.extend(
+                self.prefix_cht
+                    .get_records_for_prefix(
+                        prefix_id,
+                        mui,
+                        true,
+                        &no_withdrawn_muis,
+                    )
+                    .into_iter()
+                    .flatten()
+                    .map(|r| {
+                        (r.multi_uniq_id, r.ltime, r.status, Some(r.meta))
+                    }),
+            );
+        }
+
+        versions.retain(|r| r.1 <= ltime);
+        // This sort is stable, so for equal ltimes the in-memory record
+        // comes last, and wins.
+        versions.sort_by_key(|r| (r.0, r.1));
+
+        let mut records = vec![];
+        for mui_versions in versions.chunk_by(|a, b| a.0 == b.0) {
+            let (Some(last), Some(meta)) = (
+                mui_versions.last(),
+                mui_versions.iter().rev().find_map(|r| r.3.as_ref()),
+            ) else {
+                continue;
+            };
+            if !include_withdrawn && last.2 == RouteStatus::Withdrawn {
+                continue;
+            }
+            records.push(Record {
+                multi_uniq_id: last.0,
+                ltime: last.1,
+                status: last.2,
+                meta: meta.clone(),
+            });
+        }
+
+        Ok(if records.is_empty() {
+            None
+        } else {
+            Some(records)
+        })
+    }
+
     pub(crate) fn more_specifics_from(
         &'a self,
         prefix_id: PrefixId<AF>,
@@ -115,6 +401,7 @@ impl<
             match_type: MatchType::EmptyMatch,
             less_specifics: None,
             more_specifics,
+            history: None,
         })
     }
 
@@ -149,6 +436,7 @@ impl<
             match_type: MatchType::EmptyMatch,
             less_specifics,
             more_specifics: None,
+            history: None,
         })
     }
 
@@ -182,6 +470,78 @@ impl<
         .flatten()
     }
 
+    pub(crate) fn more_specifics_iter_from_sorted(
+        &'a self,
+        prefix_id: PrefixId<AF>,
+        mui: Option<u32>,
+        include_withdrawn: bool,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<(PrefixId<AF>, Vec<Record<M>>)>> + 'a
+    {
+        (if mui.is_some_and(|m| {
+            !include_withdrawn && self.mui_is_withdrawn(m, guard)
+        }) {
+            None
+        } else {
+            Some(
+                self.tree_bitmap
+                    .sorted_more_specific_prefix_iter_from(prefix_id)
+                    .filter_map(move |p| {
+                        self.get_value(p, mui, include_withdrawn, guard)
+                            .map(|res| res.map(|v| (p, v)))
+                            .transpose()
+                    }),
+            )
+        })
+        .into_iter()
+        .flatten()
+    }
+
+    // The more specifics of `prefix_id`, including itself, with all their
+    // records, in (address, length) order, and the records for a prefix
+    // ordered by mui, for pagination. The iteration starts right after the
+    // (prefix, mui) pair `after`, if set. Prefixes without records are
+    // skipped.
+    pub(crate) fn more_specifics_page_iter(
+        &'a self,
+        prefix_id: PrefixId<AF>,
+        after: Option<(PrefixId<AF>, u32)>,
+        guard: &'a Guard,
+    ) -> impl Iterator<Item = FatalResult<(PrefixId<AF>, Vec<Record<M>>)>> + 'a
+    {
+        // The records for the prefix of `after` that come after its mui.
+        let rest_of_after =
+            after.filter(|(p, _)| prefix_id.covers(*p)).map(|(p, mui)| {
+                self.get_value(p, None, true, guard).map(|recs| {
+                    let mut recs = recs.unwrap_or_default();
+                    recs.retain(|r| r.multi_uniq_id > mui);
+                    (p, recs)
+                })
+            });
+
+        rest_of_after
+            .into_iter()
+            .chain(
+                self.tree_bitmap
+                    .sorted_more_specific_prefix_iter_after(
+                        prefix_id,
+                        after.map(|(p, _)| p),
+                    )
+                    .map(move |p| {
+                        self.get_value(p, None, true, guard)
+                            .map(|recs| (p, recs.unwrap_or_default()))
+                    }),
+            )
+            .filter_map(|res| match res {
+                Ok((_, recs)) if recs.is_empty() => None,
+                Ok((p, mut recs)) => {
+                    recs.sort_by_key(|r| r.multi_uniq_id);
+                    Some(Ok((p, recs)))
+                }
+                Err(e) => Some(Err(e)),
+            })
+    }
+
     pub(crate) fn less_specifics_iter_from(
         &'a self,
         prefix_id: PrefixId<AF>,
@@ -281,14 +641,159 @@ impl<
                 .transpose()?;
         }
 
+        if options.include_history != IncludeHistory::None {
+            res.history =
+                self.history_for_match(search_pfx, &res, options, guard)?;
+        }
+
         Ok(res)
     }
 
-    pub(crate) fn best_path(
+    // Match a prefix against the RIB as it was at logical time `ltime`, see
+    // `get_value_at`. Prefixes that have no records at that moment do not
+    // match. The `include_history` option is ignored.
+    pub(crate) fn match_prefix_at(
         &'a self,
         search_pfx: PrefixId<AF>,
+        options: &MatchOptions,
+        ltime: u64,
+    ) -> FatalResult<QueryResult<M>> {
+        trace!(
+            "match_prefix_at rib {:?} {:?} {}",
+            search_pfx,
+            options,
+            ltime
+        );
+        let value_at = |p: PrefixId<AF>| {
+            self.get_value_at(
+                p,
+                options.mui,
+                options.include_withdrawn,
+                ltime,
+            )
+        };
+
+        let mut res = QueryResult::empty();
+
+        let candidates: Box<dyn Iterator<Item = PrefixId<AF>>> = match options
+            .match_type
+        {
+            MatchType::ExactMatch => Box::new(std::iter::once(search_pfx)),
+            _ => Box::new(std::iter::once(search_pfx).chain(
+                self.tree_bitmap.less_specific_prefix_iter(search_pfx),
+            )),
+        };
+        for pfx in candidates {
+            if let Some(records) = value_at(pfx)? {
+                res.prefix = Some(pfx.into());
+                res.records = records;
+                res.match_type = match options.match_type {
+                    MatchType::ExactMatch => MatchType::ExactMatch,
+                    _ => MatchType::LongestMatch,
+                };
+                break;
+            }
+        }
+
+        if options.include_less_specifics {
+            let mut less_specifics = RecordSet::new();
+            for pfx in self.tree_bitmap.less_specific_prefix_iter(search_pfx)
+            {
+                if let Some(records) = value_at(pfx)? {
+                    less_specifics.push(pfx.into(), records);
+                }
+            }
+            res.less_specifics = Some(less_specifics);
+        }
+
+        if options.include_more_specifics {
+            let mut more_specifics = RecordSet::new();
+            for pfx in self
+                .tree_bitmap
+                .more_specific_prefix_iter_from(search_pfx)
+                .filter(|p| p != &search_pfx)
+            {
+                if let Some(records) = value_at(pfx)? {
+                    more_specifics.push(pfx.into(), records);
+                }
+            }
+            res.more_specifics = Some(more_specifics);
+        }
+
+        Ok(res)
+    }
+
+    // Iterate over all prefixes with their records as they were at logical
+    // time `ltime`, see `get_value_at`. Withdrawn records are included.
+    pub(crate) fn prefixes_iter_at(
+        &'a self,
+        ltime: u64,
+    ) -> impl Iterator<Item = FatalResult<(Prefix, Vec<Record<M>>)>> + 'a
+    {
+        self.tree_bitmap.prefixes_iter().filter_map(move |p| {
+            self.get_value_at(p.into(), None, true, ltime)
+                .map(|res| res.map(|v| (p, v)))
+                .transpose()
+        })
+    }
+
+    // Collect the historical records for the prefixes in a query result, as
+    // requested by the `include_history` match option.
+    fn history_for_match(
+        &'a self,
+        search_pfx: PrefixId<AF>,
+        res: &QueryResult<M>,
+        options: &MatchOptions,
+        guard: &'a Guard,
+    ) -> FatalResult<Option<RecordSet<M>>> {
+        if !matches!(
+            self.persist_strategy(),
+            PersistStrategy::WriteAhead | PersistStrategy::PersistHistory
+        ) {
+            return Ok(None);
+        }
+
+        let mut prefixes = vec![search_pfx];
+        if options.include_history == IncludeHistory::All {
+            prefixes.extend(res.prefix.map(PrefixId::<AF>::from));
+            for rec_set in [&res.less_specifics, &res.more_specifics]
+                .into_iter()
+                .flatten()
+            {
+                prefixes.extend(
+                    rec_set.iter().map(|r| PrefixId::<AF>::from(r.prefix)),
+                );
+            }
+            // The search prefix can also be in the result, and the less
+            // and more specifics are ordered per address family.
+            let mut seen = HashSet::new();
+            prefixes.retain(|pfx| seen.insert(*pfx));
+        }
+
+        let mut history = RecordSet::new();
+        for pfx in prefixes {
+            if let Some(recs) = self.get_history(
+                pfx,
+                options.mui,
+                options.include_withdrawn,
+                guard,
+            )? {
+                history.push(pfx.into(), recs);
+            }
+        }
+
+        Ok(Some(history))
+    }
+
+    pub(crate) fn best_path(
+        &self,
+        search_pfx: PrefixId<AF>,
         guard: &Guard,
     ) -> Option<Result<Record<M>, PrefixStoreError>> {
+        let _pin = match self.fault_in(search_pfx) {
+            Ok(pin) => pin,
+            Err(_) => return Some(Err(PrefixStoreError::FatalError)),
+        };
         self.prefix_cht
             .non_recursive_retrieve_prefix(search_pfx)
             .0
@@ -311,11 +816,17 @@ impl<
         tbi: &<M as Meta>::TBI,
         guard: &Guard,
     ) -> Result<(Option<u32>, Option<u32>), PrefixStoreError> {
+        let _pin = self
+            .fault_in(search_pfx)
+            .map_err(|_| PrefixStoreError::FatalError)?;
         self.prefix_cht
             .non_recursive_retrieve_prefix(search_pfx)
             .0
             .map_or(Err(PrefixStoreError::StoreNotReadyError), |p_rec| {
-                p_rec.calculate_and_store_best_backup(tbi, guard)
+                p_rec.calculate_and_store_best_backup(
+                    &self.path_selection_args(*tbi, guard),
+                    guard,
+                )
             })
     }
 
@@ -331,6 +842,73 @@ impl<
                 Ok(p.is_ps_outdated(guard))
             })
     }
+
+    pub(crate) fn multipath(
+        &self,
+        search_pfx: PrefixId<AF>,
+        guard: &Guard,
+    ) -> Option<Result<Vec<Record<M>>, PrefixStoreError>> {
+        let _pin = match self.fault_in(search_pfx) {
+            Ok(pin) => pin,
+            Err(_) => return Some(Err(PrefixStoreError::FatalError)),
+        };
+        self.prefix_cht
+            .non_recursive_retrieve_prefix(search_pfx)
+            .0
+            .map(|p_rec| {
+                let withdrawn_muis =
+                    self.tree_bitmap.withdrawn_muis_bmin(guard);
+                p_rec.get_multipath(guard).map_or_else(
+                    || Err(PrefixStoreError::BestPathNotFound),
+                    |muis| {
+                        Ok(muis
+                            .into_iter()
+                            .filter(|mui| !withdrawn_muis.contains(*mui))
+                            .filter_map(|mui| {
+                                p_rec
+                                    .record_map
+                                    .get_record_for_mui(mui, false)
+                            })
+                            .collect())
+                    },
+                )
+            })
+    }
+
+    pub(crate) fn calculate_and_store_multipath(
+        &self,
+        search_pfx: PrefixId<AF>,
+        tbi: &<M as Meta>::TBI,
+        max_paths: usize,
+        guard: &Guard,
+    ) -> Result<Vec<u32>, PrefixStoreError> {
+        let _pin = self
+            .fault_in(search_pfx)
+            .map_err(|_| PrefixStoreError::FatalError)?;
+        self.prefix_cht
+            .non_recursive_retrieve_prefix(search_pfx)
+            .0
+            .map_or(Err(PrefixStoreError::StoreNotReadyError), |p_rec| {
+                p_rec.calculate_and_store_multipath(
+                    &self.path_selection_args(*tbi, guard),
+                    max_paths,
+                    guard,
+                )
+            })
+    }
+
+    pub(crate) fn is_multipath_outdated(
+        &self,
+        search_pfx: PrefixId<AF>,
+        guard: &Guard,
+    ) -> Result<bool, PrefixStoreError> {
+        self.prefix_cht
+            .non_recursive_retrieve_prefix(search_pfx)
+            .0
+            .map_or(Err(PrefixStoreError::StoreNotReadyError), |p| {
+                Ok(p.is_multipath_outdated(guard))
+            })
+    }
 }
 
 #[derive(Debug)]
@@ -355,6 +933,7 @@ impl<AF: AddressFamily, M: Meta> From<TreeQueryResult<AF>>
             more_specifics: value
                 .more_specifics
                 .map(|ms| ms.into_iter().map(|p| (p, vec![])).collect()),
+            history: None,
         }
     }
 }
@@ -397,6 +976,7 @@ impl<AF: AddressFamily, M: Meta> From<FamilyQueryResult<AF, M>>
             more_specifics: value
                 .more_specifics
                 .map(|ms| ms.into_iter().collect()),
+            history: None,
         }
     }
 }
@@ -0,0 +1,307 @@
+//------------ Subscriptions -------------------------------------------------
+
+// Subscribers receive the changes to a RIB as a stream of events, over a
+// bounded channel per subscriber. Events are sent from the thread that makes
+// the change, without ever blocking it: what happens if the channel of a
+// subscriber is full is determined by its OverflowPolicy.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{
+    sync_channel, Receiver, RecvTimeoutError, SyncSender, TryRecvError,
+    TrySendError,
+};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use inetnum::addr::Prefix;
+use log::trace;
+
+use crate::prefix_record::RouteStatus;
+
+/// The kind of change in the RIB that an event was sent for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RibEventKind {
+    /// A record was inserted, or an existing record was replaced.
+    Insert,
+    /// The local status of a record was changed, with
+    /// `mark_mui_as_withdrawn_for_prefix` or
+    /// `mark_mui_as_active_for_prefix`.
+    StatusChange,
+    /// The `mui` was withdrawn globally.
+    MuiWithdrawn,
+    /// The `mui` was activated globally.
+    MuiActivated,
+    /// The best path for a prefix changed to the record for `mui`.
+    /// `previous` holds the `mui` of the previous best path, if any.
+    BestPathChange { previous: Option<u32> },
+    /// The prefix has no best path anymore, e.g. because all its records
+    /// were withdrawn or removed. `mui` holds the `mui` of the previous
+    /// best path, the new status is `Withdrawn`.
+    BestPathLost,
+}
+
+/// A change in the RIB, as received by a [Subscription].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RibEvent {
+    pub kind: RibEventKind,
+    /// The prefix of the changed record. This is `None` for global status
+    /// changes of a `mui`, that apply to all its prefixes.
+    pub prefix: Option<Prefix>,
+    pub mui: u32,
+    /// The status before the change. For records this is the local status
+    /// of the record, or `None` if there was no record. For global changes
+    /// this is the global status of the `mui`.
+    pub old_status: Option<RouteStatus>,
+    /// The status after the change.
+    pub new_status: RouteStatus,
+    /// The logical time of the change. This is `None` for global status
+    /// changes of a `mui`, that do not have one.
+    pub ltime: Option<u64>,
+}
+
+/// The events a [Subscription] is interested in.
+///
+/// The default filter lets all the events through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubscriptionFilter {
+    /// Only events for this prefix, and its more specifics. Global status
+    /// changes of a `mui` pass this filter if they apply to the address
+    /// family of the prefix.
+    pub covering_prefix: Option<Prefix>,
+    /// Only events for this `mui`.
+    pub mui: Option<u32>,
+}
+
+impl SubscriptionFilter {
+    // Whether an event for this prefix, or for a global change in these
+    // address families if there is no prefix, and this mui, or any mui if
+    // it is None, passes the filter.
+    fn matches(
+        &self,
+        prefix: Option<&Prefix>,
+        families: Families,
+        mui: Option<u32>,
+    ) -> bool {
+        if let (Some(m), Some(mui)) = (self.mui, mui) {
+            if m != mui {
+                return false;
+            }
+        }
+        match (self.covering_prefix, prefix) {
+            (None, _) => true,
+            (Some(covering), Some(p)) => covering.covers(*p),
+            (Some(covering), None) => match families {
+                Families::V4 => covering.is_v4(),
+                Families::V6 => !covering.is_v4(),
+                Families::Both => true,
+            },
+        }
+    }
+}
+
+// The address families a global status change applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Families {
+    V4,
+    V6,
+    Both,
+}
+
+/// What happens to the events for a subscriber that does not keep up, i.e.
+/// when its channel is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The new event is dropped, and counted in
+    /// [missed_events](Subscription::missed_events). The subscription stays
+    /// open.
+    #[default]
+    DropNewest,
+    /// The subscription is closed. The subscriber receives the events that
+    /// are still in the channel, after which receiving returns a
+    /// `Disconnected` error, and
+    /// [is_overflowed](Subscription::is_overflowed) returns `true`. This is
+    /// useful for subscribers that cannot miss an event, and that would
+    /// rather resynchronize with the RIB.
+    Disconnect,
+}
+
+// The state of a subscription, shared between the RIB and the subscriber.
+#[derive(Debug, Default)]
+struct SubscriptionState {
+    missed_events: AtomicU64,
+    overflowed: AtomicBool,
+}
+
+/// A subscription to the changes in a RIB, created with
+/// [subscribe](crate::rib::StarCastRib::subscribe).
+///
+/// Dropping the subscription unsubscribes.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: Receiver<RibEvent>,
+    state: Arc<SubscriptionState>,
+}
+
+impl Subscription {
+    /// Wait for the next event.
+    ///
+    /// Returns an error if the subscription was closed, after all the
+    /// remaining events have been received.
+    pub fn recv(&self) -> Result<RibEvent, std::sync::mpsc::RecvError> {
+        self.receiver.recv()
+    }
+
+    /// Return the next event, if there is one, without waiting.
+    pub fn try_recv(&self) -> Result<RibEvent, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// Wait for the next event, for at most `timeout`.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<RibEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// Return an iterator over the events that are in the channel now,
+    /// without waiting for new ones.
+    pub fn try_iter(&self) -> impl Iterator<Item = RibEvent> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Request the number of events that were dropped, because the channel
+    /// was full.
+    pub fn missed_events(&self) -> u64 {
+        self.state.missed_events.load(Ordering::Relaxed)
+    }
+
+    /// Request whether the subscription was closed because the channel was
+    /// full, with the [Disconnect](OverflowPolicy::Disconnect) policy.
+    pub fn is_overflowed(&self) -> bool {
+        self.state.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+//------------ EventHub ------------------------------------------------------
+
+// The subscribers of a RIB, and the sending of events to them.
+
+struct Subscriber {
+    filter: SubscriptionFilter,
+    sender: SyncSender<RibEvent>,
+    overflow_policy: OverflowPolicy,
+    state: Arc<SubscriptionState>,
+    // The subscription was dropped, or it overflowed with the Disconnect
+    // policy. It gets removed from the hub.
+    closed: AtomicBool,
+}
+
+#[derive(Default)]
+pub(crate) struct EventHub {
+    subscribers: RwLock<Vec<Subscriber>>,
+    // The number of subscribers, so that the RIB can skip all the work for
+    // events when there are none, without taking the lock.
+    count: AtomicUsize,
+}
+
+impl std::fmt::Debug for EventHub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EventHub {{ subscribers: {:?} }}", self.count)
+    }
+}
+
+impl EventHub {
+    pub(crate) fn subscribe(
+        &self,
+        filter: SubscriptionFilter,
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Subscription {
+        let (sender, receiver) = sync_channel(capacity.max(1));
+        let state = Arc::new(SubscriptionState::default());
+        if let Ok(mut subscribers) = self.subscribers.write() {
+            subscribers.push(Subscriber {
+                filter,
+                sender,
+                overflow_policy,
+                state: state.clone(),
+                closed: AtomicBool::new(false),
+            });
+            self.count.store(subscribers.len(), Ordering::Release);
+        }
+
+        Subscription { receiver, state }
+    }
+
+    // Whether any subscriber is interested in an event, see
+    // SubscriptionFilter::matches, for any mui if `mui` is None. This is
+    // used to skip the work of creating an event, e.g. looking up the old
+    // status of a record.
+    pub(crate) fn wants(
+        &self,
+        prefix: Option<&Prefix>,
+        families: Families,
+        mui: Option<u32>,
+    ) -> bool {
+        if self.count.load(Ordering::Acquire) == 0 {
+            return false;
+        }
+        self.subscribers.read().is_ok_and(|subscribers| {
+            subscribers.iter().any(|s| {
+                !s.closed.load(Ordering::Acquire)
+                    && s.filter.matches(prefix, families, mui)
+            })
+        })
+    }
+
+    // Send the event to all the subscribers whose filter it passes.
+    pub(crate) fn send(&self, event: RibEvent, families: Families) {
+        if self.count.load(Ordering::Acquire) == 0 {
+            return;
+        }
+
+        let mut closed = false;
+        if let Ok(subscribers) = self.subscribers.read() {
+            for s in subscribers.iter() {
+                // A subscriber that was closed by another thread, but is
+                // not removed yet, gets no more events, also if its channel
+                // has room again.
+                if s.closed.load(Ordering::Acquire) {
+                    continue;
+                }
+                if !s.filter.matches(
+                    event.prefix.as_ref(),
+                    families,
+                    Some(event.mui),
+                ) {
+                    continue;
+                }
+                match s.sender.try_send(event) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        s.state.missed_events.fetch_add(1, Ordering::Relaxed);
+                        if s.overflow_policy == OverflowPolicy::Disconnect {
+                            s.state.overflowed.store(true, Ordering::Relaxed);
+                            s.closed.store(true, Ordering::Release);
+                            closed = true;
+                        }
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        s.closed.store(true, Ordering::Release);
+                        closed = true;
+                    }
+                }
+            }
+        }
+
+        // Dropping the sender of a subscriber closes its channel.
+        if closed {
+            if let Ok(mut subscribers) = self.subscribers.write() {
+                subscribers.retain(|s| !s.closed.load(Ordering::Acquire));
+                trace!("{} subscribers left", subscribers.len());
+                self.count.store(subscribers.len(), Ordering::Release);
+            }
+        }
+    }
+}
@@ -33,18 +33,37 @@ where
     fn load(&self) -> Self::InnerType;
     fn merge_with(&self, node: Self::InnerType) {
         let mut spinwait = SpinWait::new();
-        let current = self.load();
+        let mut current = self.load();
 
         fence(Ordering::Acquire);
 
-        let mut new = current | node;
         loop {
-            match self.compare_exchange(current, new) {
+            match self.compare_exchange(current, current | node) {
                 CasResult(Ok(_)) => {
                     return;
                 }
-                CasResult(Err(current)) => {
-                    new = current | node;
+                CasResult(Err(updated)) => {
+                    current = updated;
+                }
+            }
+            spinwait.spin_no_yield();
+        }
+    }
+
+    // Unset all the bits that are set in `node`.
+    fn clear_bits(&self, node: Self::InnerType) {
+        let mut spinwait = SpinWait::new();
+        let mut current = self.load();
+
+        fence(Ordering::Acquire);
+
+        loop {
+            match self.compare_exchange(current, current & !node) {
+                CasResult(Ok(_)) => {
+                    return;
+                }
+                CasResult(Err(updated)) => {
+                    current = updated;
                 }
             }
             spinwait.spin_no_yield();
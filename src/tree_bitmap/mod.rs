@@ -38,6 +38,8 @@ use zerocopy::FromZeros;
 // The chained (node|prefixes) are occupied at a first-come, first-serve
 // basis, and are not re-ordered on new insertions of (node|prefixes). This
 // may change in the future, since it prevents iterators from being ordered.
+// The iterators that do need an order, i.e. the `SortedPrefixIter`, walk
+// the nodes in the treebitmap in stride order instead.
 //
 // One of the nice things of having one table per (node|prefix)-length is that
 // a search can start directly at the prefix-length table it wishes, and go
@@ -219,17 +221,19 @@ use zerocopy::FromZeros;
 use crate::cht::{nodeset_size, prev_node_size, Cht, Value};
 use crate::errors::{FatalError, FatalResult};
 use crate::rib::STRIDE_SIZE;
-use crate::stats::Counters;
+use crate::stats::{ChtLevelReport, Counters};
 use crate::types::{BitSpan, PrefixId};
 #[cfg(test)]
 use crate::IPv6;
-use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
+use crossbeam_epoch::{Atomic, Guard, Owned};
 use log::{debug, error, log_enabled, trace};
 use node_cht::{NodeCht, NodeSet, StoredNode};
 use roaring::RoaringBitmap;
 use tree_bitmap_node::NewNodeOrIndex;
 
-use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering};
+use std::sync::atomic::{
+    AtomicBool, AtomicU16, AtomicU32, AtomicUsize, Ordering,
+};
 use std::{fmt::Debug, marker::PhantomData};
 
 use crate::types::AddressFamily;
@@ -256,6 +260,10 @@ pub(crate) struct TreeBitMap<AF: AddressFamily, const ROOT_SIZE: usize> {
     counters: Counters,
     // see the rant on update_default_route_prefix_meta
     default_route_exists: AtomicBool,
+    // The number of removals that were started, and that were finished,
+    // see start_removal.
+    removals_started: AtomicUsize,
+    removals_finished: AtomicUsize,
 }
 
 impl<AF: AddressFamily, const ROOT_SIZE: usize> TreeBitMap<AF, ROOT_SIZE> {
@@ -265,6 +273,8 @@ impl<AF: AddressFamily, const ROOT_SIZE: usize> TreeBitMap<AF, ROOT_SIZE> {
             withdrawn_muis_bmin: RoaringBitmap::new().into(),
             counters: Counters::default(),
             default_route_exists: AtomicBool::new(false),
+            removals_started: AtomicUsize::new(0),
+            removals_finished: AtomicUsize::new(0),
         };
 
         let _retry_count = tree_bitmap
@@ -443,6 +453,278 @@ Giving up this node. This shouldn't happen!",
         Ok(retry_and_exists)
     }
 
+    // Unsets the bit for the requested prefix in the corresponding
+    // pfxbitarr in the tree. Nodes are never removed from the tree, even
+    // if they do not host any prefixes anymore.
+    //
+    // Returns whether the prefix existed.
+    pub(crate) fn remove_prefix_exists(&self, pfx: PrefixId<AF>) -> bool {
+        if pfx.len() == 0 {
+            return self.default_route_exists.swap(false, Ordering::AcqRel);
+        }
+
+        let (node_id, bs) = self.node_id_for_prefix(&pfx);
+
+        match self.retrieve_node(node_id) {
+            Some(n) => {
+                let bit_pos = bs.into_bit_pos();
+                let existed = n.pfxbitarr.load() & bit_pos > 0;
+                n.pfxbitarr.clear_bits(bit_pos);
+                existed
+            }
+            None => false,
+        }
+    }
+
+    // Sets the bit for a prefix that was removed with remove_prefix_exists
+    // again, in the node that hosted it. The bitmap indexes of the nodes
+    // are left alone.
+    //
+    // Returns whether the prefix existed.
+    pub(crate) fn restore_prefix_exists(&self, pfx: PrefixId<AF>) -> bool {
+        if pfx.len() == 0 {
+            return self.default_route_exists.swap(true, Ordering::AcqRel);
+        }
+
+        let (node_id, bs) = self.node_id_for_prefix(&pfx);
+
+        match self.retrieve_node(node_id) {
+            Some(n) => {
+                let bit_pos = bs.into_bit_pos();
+                let existed = n.pfxbitarr.load() & bit_pos > 0;
+                n.pfxbitarr.merge_with(bit_pos);
+                existed
+            }
+            None => false,
+        }
+    }
+
+    // Marks the start of a removal of prefixes, or of muis from the bitmap
+    // indexes of the nodes, that lasts until the returned guard is dropped.
+    //
+    // A removal checks whether a prefix, or a mui, still has records, and
+    // clears its bit if it hasn't. An insert sets the bits before it stores
+    // its record, so a removal that runs concurrently can clear them after
+    // the insert set them. Therefore the removal checks again after
+    // clearing the bits, and sets them again if there are records. And
+    // the insert sets the bits again after storing its record, if a removal
+    // ran in the meantime, see removal_overlapped. Whichever of the two
+    // comes last then sees the bits cleared, or the record stored, and
+    // sets the bits.
+    pub(crate) fn start_removal(&self) -> RemovalGuard<'_> {
+        self.removals_started.fetch_add(1, Ordering::SeqCst);
+        RemovalGuard(&self.removals_finished)
+    }
+
+    // The number of finished removals, to be read before an insert sets
+    // the bits, and to be passed to removal_overlapped after it stored its
+    // record.
+    pub(crate) fn removals_finished(&self) -> usize {
+        self.removals_finished.load(Ordering::SeqCst)
+    }
+
+    // Whether a removal ran at some point since `finished` was read with
+    // removals_finished. All the removals that started before now, and
+    // that did not finish before then, overlap.
+    pub(crate) fn removal_overlapped(&self, finished: usize) -> bool {
+        self.removals_started.load(Ordering::SeqCst) > finished
+    }
+
+    // Removes a mui from the bitmap indexes of the nodes on the path from
+    // the root to the node hosting `pfx`, for as long as the sub-tree of a
+    // node does not contain any prefix with a record for this mui anymore.
+    // The tree bitmap only knows about the existence of prefixes, so the
+    // caller decides whether a prefix still has a record for the mui, with
+    // `has_mui`.
+    //
+    // The walk starts at the node hosting the prefix and stops at the first
+    // node whose sub-tree still has a prefix for this mui, all the nodes
+    // above that one will have to keep the mui in their index as well. The
+    // sub-tree of a node has the mui if one of the prefixes in the node
+    // itself has it, or if one of its child nodes has it in its index, so
+    // only the prefixes of the nodes on the path are checked.
+    //
+    // An insert for this mui into the same sub-tree may run concurrently,
+    // so the sub-tree of a node is checked again after the mui was removed
+    // from its index, and the mui is put back if the insert stored its
+    // record in the meantime. This has to run in a removal, see
+    // start_removal.
+    pub(crate) fn remove_mui_from_rbm_index(
+        &self,
+        pfx: PrefixId<AF>,
+        mui: u32,
+        has_mui: impl Fn(PrefixId<AF>) -> bool,
+        guard: &Guard,
+    ) -> Result<(), PrefixStoreError> {
+        let (host_node_id, _) = self.node_id_for_prefix(&pfx);
+        let mut node_len = host_node_id.len();
+
+        loop {
+            let node_id = NodeId::new_with_cleaned_id(pfx.bits(), node_len);
+
+            if self.sub_tree_has_mui(node_id, mui, &has_mui) {
+                return Ok(());
+            }
+
+            if let Some(stored_node) = self.retrieve_stored_node(node_id) {
+                stored_node.node_set.remove_from_rbm_index(mui, guard)?;
+                if self.sub_tree_has_mui(node_id, mui, &has_mui) {
+                    return Self::restore_rbm_index(
+                        &stored_node.node_set,
+                        mui,
+                    );
+                }
+            }
+
+            if node_len == 0 {
+                break;
+            }
+            node_len -= STRIDE_SIZE;
+        }
+
+        let root = self.node_cht.root_for_len(0);
+        root.remove_from_rbm_index(mui, guard)?;
+        if self.sub_tree_has_mui(self.get_root_node_id(), mui, &has_mui) {
+            return Self::restore_rbm_index(root, mui);
+        }
+
+        Ok(())
+    }
+
+    // Whether the sub-tree of a node has a prefix with a record for the
+    // mui. The default route is recorded in the index of the root node.
+    fn sub_tree_has_mui(
+        &self,
+        node_id: NodeId<AF>,
+        mui: u32,
+        has_mui: impl Fn(PrefixId<AF>) -> bool,
+    ) -> bool {
+        (node_id.len() == 0
+            && has_mui(PrefixId::new(<AF as AddressFamily>::zero(), 0)))
+            || self.node_has_mui(node_id, mui, &has_mui)
+    }
+
+    // Put a mui back in a bitmap index that it was removed from.
+    fn restore_rbm_index(
+        node_set: &NodeSet<AF>,
+        mui: u32,
+    ) -> Result<(), PrefixStoreError> {
+        node_set
+            .update_rbm_index(mui)
+            .map(|_| ())
+            .map_err(|_| PrefixStoreError::StoreNotReadyError)
+    }
+
+    // Whether the sub-tree of a node has a prefix with a record for the mui,
+    // going by the prefixes in the node itself and the indexes of its child
+    // nodes.
+    fn node_has_mui(
+        &self,
+        node_id: NodeId<AF>,
+        mui: u32,
+        has_mui: impl Fn(PrefixId<AF>) -> bool,
+    ) -> bool {
+        let Some(node) = self.retrieve_node(node_id) else {
+            return false;
+        };
+
+        node.more_specific_ptr_iter(node_id, BitSpan::new(0, 0))
+            .any(|child_id| {
+                self.retrieve_node_for_mui(child_id, mui).is_some()
+            })
+            || node
+                .more_specific_pfx_iter(node_id, BitSpan::new(0, 0))
+                .any(has_mui)
+    }
+
+    // The memory used by the nodes, per level of the node CHT.
+    pub(crate) fn memory_report(&self) -> FatalResult<Vec<ChtLevelReport>> {
+        let mut levels = vec![];
+        for root in self.node_cht.roots() {
+            root.add_memory(0, &mut levels)?;
+        }
+        Ok(levels)
+    }
+
+    pub(crate) fn default_route_exists(&self) -> bool {
+        self.default_route_exists.load(Ordering::Acquire)
+    }
+
+    // All the prefixes that live in the nodes that have this mui in their
+    // bitmap index, together with the ids of those nodes. The sub-trees of
+    // nodes that don't have the mui in their index are skipped. Since the
+    // index only tells whether a mui appears somewhere in the sub-tree of a
+    // node, the returned prefixes may include prefixes that don't have a
+    // record for this mui.
+    pub(crate) fn prefixes_and_nodes_for_mui(
+        &self,
+        mui: u32,
+    ) -> (Vec<PrefixId<AF>>, Vec<NodeId<AF>>) {
+        let mut prefixes = vec![];
+        let mut node_ids = vec![];
+
+        if self.default_route_exists.load(Ordering::Acquire) {
+            prefixes.push(PrefixId::new(<AF as AddressFamily>::zero(), 0));
+        }
+
+        let mut stack = vec![self.get_root_node_id()];
+        while let Some(node_id) = stack.pop() {
+            if let Some(node) = self.retrieve_node_for_mui(node_id, mui) {
+                prefixes.extend(
+                    node.more_specific_pfx_iter(node_id, BitSpan::new(0, 0)),
+                );
+                stack.extend(
+                    node.more_specific_ptr_iter(node_id, BitSpan::new(0, 0)),
+                );
+                node_ids.push(node_id);
+            }
+        }
+
+        (prefixes, node_ids)
+    }
+
+    // Remove the mui from the bitmap indexes of all the requested nodes,
+    // and from the index for the default route. Only to be used if all the
+    // prefixes in the sub-trees of these nodes have lost their records for
+    // the mui.
+    //
+    // Records for the mui may be inserted concurrently, so afterwards the
+    // sub-trees of the nodes are checked again, from the deepest nodes up,
+    // and the mui is put back in the index of the nodes whose sub-tree got
+    // a record for it in the meantime. This has to run in a removal, see
+    // start_removal.
+    pub(crate) fn purge_mui_from_rbm_indexes(
+        &self,
+        mui: u32,
+        node_ids: &[NodeId<AF>],
+        has_mui: impl Fn(PrefixId<AF>) -> bool,
+        guard: &Guard,
+    ) -> Result<(), PrefixStoreError> {
+        for node_id in node_ids {
+            if let Some(stored_node) = self.retrieve_stored_node(*node_id) {
+                stored_node.node_set.remove_from_rbm_index(mui, guard)?;
+            }
+        }
+        let root = self.node_cht.root_for_len(0);
+        root.remove_from_rbm_index(mui, guard)?;
+
+        let mut node_ids = node_ids.to_vec();
+        node_ids.sort_by_key(|id| std::cmp::Reverse(id.len()));
+        for node_id in node_ids {
+            if !self.sub_tree_has_mui(node_id, mui, &has_mui) {
+                continue;
+            }
+            if let Some(stored_node) = self.retrieve_stored_node(node_id) {
+                Self::restore_rbm_index(&stored_node.node_set, mui)?;
+            }
+        }
+        if self.sub_tree_has_mui(self.get_root_node_id(), mui, &has_mui) {
+            Self::restore_rbm_index(root, mui)?;
+        }
+
+        Ok(())
+    }
+
     pub fn prefix_exists(&self, prefix_id: PrefixId<AF>) -> bool {
         trace!("pe exists {:?}?", prefix_id);
         let (node_id, bs) = self.node_id_for_prefix(&prefix_id);
@@ -528,14 +810,12 @@ Giving up this node. This shouldn't happen!",
         mui: u32,
         guard: &Guard,
     ) -> Result<(), PrefixStoreError> {
-        let current = self.withdrawn_muis_bmin.load(Ordering::Acquire, guard);
-
-        let mut new = unsafe { current.as_ref() }
-            .ok_or(PrefixStoreError::StoreNotReadyError)?
-            .clone();
-
-        new.remove(mui);
-        self.update_withdrawn_muis_bmin(current, new, guard)
+        self.update_withdrawn_muis_bmin(
+            |bmin| {
+                bmin.remove(mui);
+            },
+            guard,
+        )
     }
 
     pub fn mark_mui_as_withdrawn(
@@ -543,24 +823,31 @@ Giving up this node. This shouldn't happen!",
         mui: u32,
         guard: &Guard,
     ) -> Result<(), PrefixStoreError> {
-        let current = self.withdrawn_muis_bmin.load(Ordering::Acquire, guard);
-
-        let mut new = unsafe { current.as_ref() }
-            .ok_or(PrefixStoreError::StoreNotReadyError)?
-            .clone();
-
-        new.insert(mui);
-
-        self.update_withdrawn_muis_bmin(current, new, guard)
+        self.update_withdrawn_muis_bmin(
+            |bmin| {
+                bmin.insert(mui);
+            },
+            guard,
+        )
     }
 
-    pub(crate) fn update_withdrawn_muis_bmin<'a>(
+    // Apply `update` to a copy of the current withdrawn muis bitmap and swap
+    // it in. If another thread swapped in its own bitmap in the meantime,
+    // `update` is applied again on that one.
+    pub(crate) fn update_withdrawn_muis_bmin(
         &self,
-        current: Shared<'a, RoaringBitmap>,
-        mut new: RoaringBitmap,
-        guard: &'a Guard,
+        update: impl Fn(&mut RoaringBitmap),
+        guard: &Guard,
     ) -> Result<(), PrefixStoreError> {
+        let mut current =
+            self.withdrawn_muis_bmin.load(Ordering::Acquire, guard);
+
         loop {
+            let mut new = unsafe { current.as_ref() }
+                .ok_or(PrefixStoreError::StoreNotReadyError)?
+                .clone();
+            update(&mut new);
+
             match self.withdrawn_muis_bmin.compare_exchange(
                 current,
                 Owned::new(new),
@@ -568,11 +855,12 @@ Giving up this node. This shouldn't happen!",
                 Ordering::Acquire,
                 guard,
             ) {
-                Ok(_) => return Ok(()),
+                Ok(_) => {
+                    unsafe { guard.defer_destroy(current) };
+                    return Ok(());
+                }
                 Err(updated) => {
-                    new = unsafe { updated.current.as_ref() }
-                        .ok_or(PrefixStoreError::StoreNotReadyError)?
-                        .clone();
+                    current = updated.current;
                 }
             }
         }
@@ -826,6 +1114,13 @@ Giving up this node. This shouldn't happen!",
         &self,
         id: NodeId<AF>,
     ) -> Option<&TreeBitMapNode<AF>> {
+        self.retrieve_stored_node(id).map(|n| &n.node)
+    }
+
+    fn retrieve_stored_node(
+        &self,
+        id: NodeId<AF>,
+    ) -> Option<&StoredNode<AF>> {
         // HASHING FUNCTION
         let mut level = 0;
         let mut node;
@@ -847,7 +1142,7 @@ Giving up this node. This shouldn't happen!",
                     node = this_node;
                     if id == this_node.node_id {
                         // YES, It's the one we're looking for!
-                        return Some(&this_node.node);
+                        return Some(this_node);
                     };
                 }
             }
@@ -887,28 +1182,31 @@ Giving up this node. This shouldn't happen!",
                     return None;
                 }
                 Some(this_node) => {
-                    // early return if the mui is not in the index
-                    // stored in this node, meaning the mui does not
-                    // appear anywhere in the sub-tree formed from
-                    // this node.
                     node = this_node;
 
-                    let bmin = match this_node.node_set.rbm().read() {
-                        Ok(bmin) => bmin,
-                        // if this lock is poisened, we are still going to
-                        // work with the bmin. The data in the bmin may be
-                        // stale, because of the lock poisoning, but this may
-                        // also happen because of delays in other parts of the
-                        // store in normal circumstances. We are counting on a
-                        // future call to a write method to actually propagate
-                        // a FatalError to the user of the store.
-                        Err(bmin) => bmin.into_inner(),
-                    };
-                    if !bmin.contains(mui) {
-                        return None;
-                    }
-
                     if id == this_node.node_id {
+                        // early return if the mui is not in the index
+                        // stored in this node, meaning the mui does not
+                        // appear anywhere in the sub-tree formed from
+                        // this node. The index of a node that we only
+                        // pass through because of a hash collision says
+                        // nothing about our sub-tree, so it's not used.
+                        let bmin = match this_node.node_set.rbm().read() {
+                            Ok(bmin) => bmin,
+                            // if this lock is poisened, we are still going
+                            // to work with the bmin. The data in the bmin
+                            // may be stale, because of the lock poisoning,
+                            // but this may also happen because of delays in
+                            // other parts of the store in normal
+                            // circumstances. We are counting on a future
+                            // call to a write method to actually propagate
+                            // a FatalError to the user of the store.
+                            Err(bmin) => bmin.into_inner(),
+                        };
+                        if !bmin.contains(mui) {
+                            return None;
+                        }
+
                         // YES, It's the one we're looking for!
                         return Some(&this_node.node);
                     };
@@ -1108,6 +1406,16 @@ fn test_hashing_node_id_valid_range() {
 // 5 - 5 - 5 - 4 - 4 - [4] - 5
 // startpos (2 ^ nibble length) - 1 + nibble as usize
 
+// The guard for a removal, that marks the end of the removal when it is
+// dropped, see TreeBitMap::start_removal.
+pub(crate) struct RemovalGuard<'a>(&'a AtomicUsize);
+
+impl Drop for RemovalGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
 // This implements the funky stats for a tree
 #[cfg(feature = "cli")]
 impl<AF: AddressFamily, const ROOT_SIZE: usize> std::fmt::Display
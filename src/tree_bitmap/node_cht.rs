@@ -7,6 +7,7 @@ use roaring::RoaringBitmap;
 use super::tree_bitmap_node::{NodeId, TreeBitMapNode};
 use crate::cht::{Cht, OnceBoxSlice, Value};
 use crate::errors::{FatalError, FatalResult};
+use crate::stats::ChtLevelReport;
 use crate::types::errors::PrefixStoreError;
 use crate::types::AddressFamily;
 
@@ -55,7 +56,7 @@ impl<AF: AddressFamily> NodeSet<AF> {
         Ok((try_count, !absent))
     }
 
-    pub(crate) fn _remove_from_rbm_index(
+    pub(crate) fn remove_from_rbm_index(
         &self,
         multi_uniq_id: u32,
         _guard: &crate::epoch::Guard,
@@ -77,6 +78,41 @@ impl<AF: AddressFamily> NodeSet<AF> {
     pub(crate) fn read(&self) -> &OnceBoxSlice<StoredNode<AF>> {
         &self.0
     }
+
+    // Add the memory used by this set, as a bucket at `level`, and by the
+    // sets of its nodes, at the levels below it, to `levels`.
+    pub(crate) fn add_memory(
+        &self,
+        level: u8,
+        levels: &mut Vec<ChtLevelReport>,
+    ) -> FatalResult<()> {
+        let slots = self.0.allocated_slots();
+        if slots == 0 {
+            return Ok(());
+        }
+        let bitmap_bytes =
+            self.1.read().map_err(|_| FatalError)?.statistics();
+        let nodes = self.0.iter().collect::<Vec<_>>();
+
+        let report = ChtLevelReport::for_level(levels, level);
+        report.add_bucket(
+            slots,
+            nodes.len(),
+            OnceBoxSlice::<StoredNode<AF>>::slot_bytes(),
+        );
+        report.value_bytes +=
+            nodes.len() * std::mem::size_of::<StoredNode<AF>>();
+        report.bitmap_bytes += (bitmap_bytes.n_bytes_array_containers
+            + bitmap_bytes.n_bytes_run_containers
+            + bitmap_bytes.n_bytes_bitset_containers)
+            as usize;
+
+        for node in nodes {
+            node.node_set.add_memory(level + 1, levels)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl<AF: AddressFamily> Value for NodeSet<AF> {
@@ -13,16 +13,23 @@
 // 3. a LessSpecificIterator, that just reduces the prefix size bit-by-bit and
 // looks in the prefix buckets for the diminuishing prefix.
 //
+// 4. a SortedPrefixIter, that returns the more-specifics for a given prefix
+// in (address, length) order, by walking the nodes in the TreeBitMap depth-
+// first.
+//
 // The Iterators that start from the root node of the TreeBitMap (which
 // is the only option for the single-threaded TreeBitMap) live in the
 // deprecated_node.rs file. They theoretically should be slower and cause more
 // contention, since every lookup has to go through the levels near the root
 // in the TreeBitMap.
 
+use crate::rib::STRIDE_SIZE;
+use crate::tree_bitmap::atomic_bitmap::AtomicBitmap;
 use crate::TreeBitMap;
 use crate::{
     tree_bitmap::tree_bitmap_node::{
-        NodeMoreSpecificChildIter, NodeMoreSpecificsPrefixIter,
+        ms_prefix_mask_arr, ptr_range, NodeId, NodeMoreSpecificChildIter,
+        NodeMoreSpecificsPrefixIter,
     },
     types::{AddressFamily, BitSpan, PrefixId},
 };
@@ -173,6 +180,158 @@ impl<'a, AF: AddressFamily + 'a, const ROOT_SIZE: usize> Iterator
     }
 }
 
+// ----------- SortedPrefixIter ---------------------------------------------
+
+// An iterator over all the more-specifics for a given prefix, including the
+// prefix itself, in canonical order: ordered by address, and for the same
+// address by length. This is a pre-order, depth-first, walk over the binary
+// trie, i.e. a prefix comes before its more-specifics, and the more-
+// specifics with a 0 as the next bit come before those with a 1.
+//
+// Inside a node the walk goes over the bit spans of the stride, and when it
+// reaches a bit span with the length of a stride, it continues with the
+// child node for that bit span, if any. The stack holds the bit spans that
+// still have to be visited, with the (copied) bitmaps of their node. Bit
+// spans that have no prefixes and no child nodes below them are never
+// pushed, so the stack stays small: at most STRIDE_SIZE + 1 entries per
+// stride. Only the path to the current node is kept, the RIB is never
+// collected into memory.
+//
+// The iterator can start right after a prefix, e.g. to resume an earlier
+// iteration. Instead of walking up to that prefix, the walk then skips
+// every bit span whose prefix comes before it, and that does not cover it,
+// since all of its more-specifics come before it as well. Only the bit spans
+// on the path to the prefix are visited, without returning them.
+
+struct SortedSpan<AF: AddressFamily> {
+    node_id: NodeId<AF>,
+    pfxbitarr: u32,
+    ptrbitarr: u16,
+    bs: BitSpan,
+}
+
+impl<AF: AddressFamily> SortedSpan<AF> {
+    // Whether there is a prefix for this bit span, or below it, or a child
+    // node below it.
+    fn is_occupied(&self) -> bool {
+        self.pfxbitarr
+            & (ms_prefix_mask_arr(self.bs) | self.bs.into_bit_pos())
+            != 0
+            || ptr_range(self.ptrbitarr, self.bs).0 != 0
+    }
+
+    fn with_bit_span(&self, bs: BitSpan) -> Self {
+        Self { bs, ..*self }
+    }
+
+    // The prefix for this bit span. A bit span of length 0 is the node
+    // itself.
+    fn prefix(&self) -> PrefixId<AF> {
+        match self.bs.len {
+            0 => self.node_id.into(),
+            _ => self.node_id.add_bit_span(self.bs).into(),
+        }
+    }
+}
+
+impl<AF: AddressFamily> Clone for SortedSpan<AF> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<AF: AddressFamily> Copy for SortedSpan<AF> {}
+
+pub(crate) struct SortedPrefixIter<
+    'a,
+    AF: AddressFamily,
+    const ROOT_SIZE: usize,
+> {
+    tree: &'a TreeBitMap<AF, ROOT_SIZE>,
+    // The default route does not live in a node, see the rant on
+    // update_default_route_prefix_meta, so it is returned separately.
+    default_route: Option<PrefixId<AF>>,
+    // Only return the prefixes that come after this one.
+    after: Option<PrefixId<AF>>,
+    stack: Vec<SortedSpan<AF>>,
+}
+
+impl<AF: AddressFamily, const ROOT_SIZE: usize>
+    SortedPrefixIter<'_, AF, ROOT_SIZE>
+{
+    // Push the span for the child node at this span, which must have the
+    // length of a stride, if it exists.
+    fn push_child_node(&mut self, span: SortedSpan<AF>) {
+        if ptr_range(span.ptrbitarr, span.bs).0 == 0 {
+            return;
+        }
+        let child_id = span.node_id.add_bit_span(BitSpan {
+            bits: span.bs.bits,
+            len: STRIDE_SIZE,
+        });
+        if let Some(node) = self.tree.retrieve_node(child_id) {
+            let child = SortedSpan {
+                node_id: child_id,
+                pfxbitarr: node.pfxbitarr.load(),
+                ptrbitarr: node.ptrbitarr.load(),
+                bs: BitSpan::new(0, 0),
+            };
+            if child.is_occupied() {
+                self.stack.push(child);
+            }
+        }
+    }
+}
+
+impl<AF: AddressFamily, const ROOT_SIZE: usize> Iterator
+    for SortedPrefixIter<'_, AF, ROOT_SIZE>
+{
+    type Item = PrefixId<AF>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(default_route) = self.default_route.take() {
+            return Some(default_route);
+        }
+
+        while let Some(span) = self.stack.pop() {
+            let prefix = span.prefix();
+            let is_before = self.after.is_some_and(|after| {
+                (prefix.bits(), prefix.len()) <= (after.bits(), after.len())
+            });
+            if is_before && !self.after.is_some_and(|a| prefix.covers(a)) {
+                continue;
+            }
+
+            // Push the more-specifics in reverse order, so that they are
+            // popped in order.
+            if span.bs.len < STRIDE_SIZE {
+                for bit in [1, 0] {
+                    let next = span.with_bit_span(BitSpan::new(
+                        span.bs.bits << 1 | bit,
+                        span.bs.len + 1,
+                    ));
+                    if next.is_occupied() {
+                        self.stack.push(next);
+                    }
+                }
+            } else {
+                self.push_child_node(span);
+            }
+
+            // A bit span of length 0 is the node itself, its prefix lives
+            // in the parent node, or, for 0/0, nowhere.
+            if !is_before
+                && span.bs.len > 0
+                && span.pfxbitarr & span.bs.into_bit_pos() != 0
+            {
+                return Some(prefix);
+            }
+        }
+
+        None
+    }
+}
+
 pub(crate) struct LMPrefixIter<'a, AF: AddressFamily, const ROOT_SIZE: usize>
 {
     tree: &'a TreeBitMap<AF, ROOT_SIZE>,
@@ -321,6 +480,56 @@ impl<'a, AF: AddressFamily, const ROOT_SIZE: usize>
         .flatten()
     }
 
+    // Iterator over all more-specific prefixes, including the given prefix
+    // itself, in (address, length) order.
+    pub fn sorted_more_specific_prefix_iter_from(
+        &'a self,
+        start_prefix_id: PrefixId<AF>,
+    ) -> impl Iterator<Item = PrefixId<AF>> + 'a {
+        self.sorted_more_specific_prefix_iter_after(start_prefix_id, None)
+    }
+
+    // Iterator over all more-specific prefixes, including the given prefix
+    // itself, in (address, length) order, that come after `after`, if set.
+    pub fn sorted_more_specific_prefix_iter_after(
+        &'a self,
+        start_prefix_id: PrefixId<AF>,
+        after: Option<PrefixId<AF>>,
+    ) -> impl Iterator<Item = PrefixId<AF>> + 'a {
+        trace!(
+            "sorted more specifics for {:?} after {:?}",
+            start_prefix_id,
+            after
+        );
+
+        let (node_id, bs) = self.node_id_for_prefix(&start_prefix_id);
+        let mut stack = vec![];
+        if let Some(node) = self.retrieve_node(node_id) {
+            let span = SortedSpan {
+                node_id,
+                pfxbitarr: node.pfxbitarr.load(),
+                ptrbitarr: node.ptrbitarr.load(),
+                bs,
+            };
+            if span.is_occupied() {
+                stack.push(span);
+            }
+        }
+
+        // The default route comes before all other prefixes.
+        let default_route = (start_prefix_id.len() == 0
+            && after.is_none()
+            && self.default_route_exists())
+        .then_some(start_prefix_id);
+
+        SortedPrefixIter {
+            tree: self,
+            default_route,
+            after,
+            stack,
+        }
+    }
+
     // Iterator over all less-specific prefixes, starting from the given
     // prefix at the given level and cursor.
     pub fn less_specific_prefix_iter(
@@ -358,4 +567,49 @@ impl<'a, AF: AddressFamily, const ROOT_SIZE: usize>
         ))
         .map(Prefix::from)
     }
+
+    // Iterator over all the prefixes that live in the nodes with this
+    // length, by walking the root for this length in the node CHT, and the
+    // buckets hanging off of it, depth-first. The default route is returned
+    // for length 0. The prefixes are in no particular order.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn prefixes_for_node_len(
+        &'a self,
+        len: u8,
+    ) -> impl Iterator<Item = PrefixId<AF>> + 'a {
+        let default_route = (len == 0 && self.default_route_exists())
+            .then(|| PrefixId::new(<AF as AddressFamily>::zero(), 0));
+        let mut stack = self
+            .node_cht
+            .root_for_len(len)
+            .read()
+            .iter()
+            .collect::<Vec<_>>();
+        let nodes = std::iter::from_fn(move || {
+            let stored_node = stack.pop()?;
+            stack.extend(stored_node.node_set.read().iter());
+            Some(stored_node)
+        });
+
+        default_route
+            .into_iter()
+            .chain(nodes.flat_map(|stored_node| {
+                stored_node.node.more_specific_pfx_iter(
+                    stored_node.node_id,
+                    BitSpan::new(0, 0),
+                )
+            }))
+    }
+
+    // Iterator over all the prefixes in the in_memory store, in (address,
+    // length) order.
+    pub fn sorted_prefixes_iter(
+        &'a self,
+    ) -> impl Iterator<Item = Prefix> + 'a {
+        self.sorted_more_specific_prefix_iter_from(PrefixId::new(
+            AF::new(0_u32.into()),
+            0,
+        ))
+        .map(Prefix::from)
+    }
 }
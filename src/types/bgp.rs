@@ -0,0 +1,207 @@
+//------------ BGP Path Attributes -------------------------------------------
+
+// A Meta implementation for BGP routes, that stores the path attributes of a
+// route in their wire format, and that orders them with the BGP Decision
+// Process from routecore, so that the best and backup path selection of the
+// RIB works on them without any further work.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::OnceLock;
+
+use inetnum::asn::Asn;
+use routecore::bgp::aspath::{Hop, HopPath};
+use routecore::bgp::message::PduParseInfo;
+use routecore::bgp::path_attributes::{OwnedPathAttributes, PaMap};
+use routecore::bgp::path_selection::{OrdRoute, Rfc4271, TiebreakerInfo};
+use routecore::bgp::types::{LocalPref, MultiExitDisc, Origin};
+
+use super::prefix_record::Meta;
+
+/// The BGP path attributes of a route, as the metadata of a record.
+///
+/// The path attributes are stored in their wire format, and are parsed with
+/// 32-bit AS numbers and without ADD-PATH, like the path attributes in the
+/// RIB entries of MRT TABLE_DUMP_V2 files.
+///
+/// For the best and backup path selection, the routes for a prefix are
+/// ordered with the BGP Decision Process as described in RFC 4271, with the
+/// [TiebreakerInfo] that is passed in for the selection. Routes that lack a
+/// mandatory path attribute are never preferred over routes that have all
+/// of them. Routes that are equally preferred are ordered by their
+/// `multi_uniq_id`, the lowest one first.
+///
+/// Routes are equivalent for a multipath if they are equally preferred up
+/// to, and including, the comparison of their interior cost in the
+/// Decision Process, i.e. if they have the same LOCAL_PREF, AS_PATH length
+/// and ORIGIN, and, for routes from the same neighbour AS, the same
+/// MULTI_EXIT_DISC.
+#[derive(Clone, Debug)]
+pub struct PathAttributesMeta {
+    pas: OwnedPathAttributes,
+    // The parsed path attributes, parsed on first use.
+    pa_map: OnceLock<PaMap>,
+}
+
+impl PathAttributesMeta {
+    /// Returns the path attributes in their wire format.
+    pub fn path_attributes(&self) -> &OwnedPathAttributes {
+        &self.pas
+    }
+
+    /// Returns the parsed path attributes. Parsing stops at the first
+    /// invalid path attribute.
+    pub fn pa_map(&self) -> &PaMap {
+        self.pa_map.get_or_init(|| {
+            let mut pa_map = PaMap::empty();
+            for pa in self.pas.iter().map_while(Result::ok) {
+                if let Ok(pa) = pa.to_owned() {
+                    pa_map.attributes_mut().insert(pa.type_code(), pa);
+                }
+            }
+            pa_map
+        })
+    }
+}
+
+impl From<OwnedPathAttributes> for PathAttributesMeta {
+    fn from(pas: OwnedPathAttributes) -> Self {
+        Self {
+            pas,
+            pa_map: OnceLock::new(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for PathAttributesMeta {
+    fn from(value: Vec<u8>) -> Self {
+        OwnedPathAttributes::new(PduParseInfo::modern(), value).into()
+    }
+}
+
+impl AsRef<[u8]> for PathAttributesMeta {
+    fn as_ref(&self) -> &[u8] {
+        self.pas.as_ref()
+    }
+}
+
+impl fmt::Display for PathAttributesMeta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pa_map().get::<HopPath>() {
+            Some(as_path) => write!(f, "AS_PATH {}", as_path),
+            None => write!(f, "no AS_PATH"),
+        }
+    }
+}
+
+impl Meta for PathAttributesMeta {
+    type Orderable<'a> = OrdPathAttributes<'a>;
+    type TBI = TiebreakerInfo;
+
+    fn as_orderable(&self, tbi: Self::TBI) -> OrdPathAttributes<'_> {
+        match OrdRoute::rfc4271(self.pa_map(), tbi) {
+            Ok(ord_route) => OrdPathAttributes::Eligible(ord_route),
+            Err(_) => OrdPathAttributes::Ineligible,
+        }
+    }
+
+    fn origin_asn(&self) -> Option<Asn> {
+        match self.pa_map().get::<HopPath>()?.origin()? {
+            Hop::Asn(asn) => Some(*asn),
+            Hop::Segment(_) => None,
+        }
+    }
+
+    fn multipath_equivalent<'a>(
+        a: &OrdPathAttributes<'a>,
+        b: &OrdPathAttributes<'a>,
+    ) -> bool
+    where
+        Self: 'a,
+    {
+        match (a, b) {
+            (
+                OrdPathAttributes::Eligible(a),
+                OrdPathAttributes::Eligible(b),
+            ) => {
+                multipath_key(a) == multipath_key(b)
+                    && (neighbour_asn(a) != neighbour_asn(b)
+                        || med(a) == med(b))
+            }
+            _ => false,
+        }
+    }
+}
+
+// The parts of a route that the Decision Process compares before it
+// breaks the ties between equally good routes, apart from the
+// MULTI_EXIT_DISC. The degree of preference of the routes for a prefix only
+// differs in their LOCAL_PREF, since they all share the same
+// TiebreakerInfo.
+fn multipath_key(
+    route: &OrdRoute<'_, Rfc4271>,
+) -> (Option<LocalPref>, Option<usize>, Option<Origin>) {
+    let pa_map = route.pa_map();
+    (
+        pa_map.get::<LocalPref>(),
+        pa_map
+            .get::<HopPath>()
+            .map(|as_path| as_path.hop_count_path_selection()),
+        pa_map.get::<Origin>(),
+    )
+}
+
+// The neighbour AS of a route, that the Decision Process only compares the
+// MULTI_EXIT_DISC of routes for. Routes without one, e.g. from iBGP, all
+// get the local AS from the TiebreakerInfo they share.
+fn neighbour_asn(route: &OrdRoute<'_, Rfc4271>) -> Option<Asn> {
+    route
+        .pa_map()
+        .get::<HopPath>()
+        .and_then(|as_path| as_path.neighbor_path_selection())
+}
+
+fn med(route: &OrdRoute<'_, Rfc4271>) -> MultiExitDisc {
+    route
+        .pa_map()
+        .get::<MultiExitDisc>()
+        .unwrap_or(MultiExitDisc(0))
+}
+
+//------------ OrdPathAttributes ---------------------------------------------
+
+/// The path attributes of a route, ordered by preference in the BGP
+/// Decision Process, the most preferred first.
+#[derive(Clone, Copy, Debug)]
+pub enum OrdPathAttributes<'a> {
+    /// A route that has all the mandatory path attributes.
+    Eligible(OrdRoute<'a, Rfc4271>),
+    /// A route that can not be selected, because it is missing a mandatory
+    /// path attribute.
+    Ineligible,
+}
+
+impl Ord for OrdPathAttributes<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Eligible(a), Self::Eligible(b)) => a.cmp(b),
+            (Self::Eligible(_), Self::Ineligible) => Ordering::Less,
+            (Self::Ineligible, Self::Eligible(_)) => Ordering::Greater,
+            (Self::Ineligible, Self::Ineligible) => Ordering::Equal,
+        }
+    }
+}
+
+impl PartialOrd for OrdPathAttributes<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for OrdPathAttributes<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrdPathAttributes<'_> {}
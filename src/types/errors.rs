@@ -41,6 +41,12 @@ pub enum PrefixStoreError {
     PersistFailed,
     /// A status for a record was requested, but it was never set.
     StatusUnknown,
+    /// The requested address range is invalid: its start lies after its
+    /// end, or its start and end are from different address families.
+    InvalidRange,
+    /// A secondary index was requested by a name, and for a type of index,
+    /// that was not registered on the RIB.
+    IndexNotFound,
 }
 
 impl std::error::Error for PrefixStoreError {}
@@ -98,6 +104,20 @@ impl fmt::Display for PrefixStoreError {
                     counters cannot be reported for persist only strategy."
                 )
             }
+            PrefixStoreError::InvalidRange => {
+                write!(
+                    f,
+                    "Error: The start of the address range lies after its \
+                    end, or belongs to another address family."
+                )
+            }
+            PrefixStoreError::IndexNotFound => {
+                write!(
+                    f,
+                    "Error: No secondary index with this name and type is \
+                    registered."
+                )
+            }
             PrefixStoreError::FatalError => {
                 write!(
                     f,
@@ -84,6 +84,17 @@ pub enum IncludeHistory {
     All,
 }
 
+/// Option to select the prefixes that are returned for an address range by
+/// [prefixes_in_range](crate::rib::StarCastRib::prefixes_in_range).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RangeMatch {
+    /// Return all the prefixes that have at least one address in the range,
+    /// including the less specific prefixes that cover (part of) the range.
+    Overlapping,
+    /// Return only the prefixes that lie completely within the range.
+    Contained,
+}
+
 //------------- QueryResult -------------------------------------------------
 
 /// The type that is returned by a query.
@@ -107,6 +118,11 @@ pub struct QueryResult<M: Meta> {
     /// The more-specifics of the resulting prefix together with their meta
     //// data
     pub more_specifics: Option<RecordSet<M>>,
+    /// The historical records for the prefixes requested with the
+    /// `include_history` match option, ordered by `ltime`. `None` if no
+    /// history was requested, or if the persist strategy of the store does
+    /// not keep historical records.
+    pub history: Option<RecordSet<M>>,
 }
 
 impl<M: Meta> QueryResult<M> {
@@ -117,6 +133,7 @@ impl<M: Meta> QueryResult<M> {
             records: vec![],
             less_specifics: None,
             more_specifics: None,
+            history: None,
         }
     }
 }
@@ -155,6 +172,15 @@ impl<M: Meta> fmt::Display for QueryResult<M> {
             } else {
                 "".to_string()
             }
+        )?;
+        writeln!(
+            f,
+            "history: {{ {} }}",
+            if let Some(h) = self.history.as_ref() {
+                format!("{}", h)
+            } else {
+                "".to_string()
+            }
         )
     }
 }
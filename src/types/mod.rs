@@ -3,6 +3,8 @@ mod prefix_id;
 mod tests;
 
 pub(crate) mod af;
+#[cfg(feature = "bgp")]
+pub mod bgp;
 pub mod match_options;
 pub mod prefix_record;
 pub(crate) mod route_status;
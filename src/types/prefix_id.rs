@@ -51,6 +51,11 @@ impl<AF: AddressFamily> PrefixId<AF> {
             len,
         }
     }
+
+    // Whether `other` is equal to, or a more-specific of, self.
+    pub(crate) fn covers(&self, other: PrefixId<AF>) -> bool {
+        self.len <= other.len && other.truncate_to_len(self.len) == *self
+    }
 }
 
 // There is no reasonable way for this to panic, PrefixId and inetnum's Prefix
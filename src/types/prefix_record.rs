@@ -3,6 +3,7 @@ use std::fmt::Debug;
 
 use crate::{errors::FatalError, types::AddressFamily};
 use inetnum::addr::Prefix;
+use inetnum::asn::Asn;
 use zerocopy::{Immutable, IntoBytes, KnownLayout, TryFromBytes, Unaligned};
 
 use super::PrefixId;
@@ -29,6 +30,32 @@ where
     type TBI: Copy;
 
     fn as_orderable(&self, tbi: Self::TBI) -> Self::Orderable<'_>;
+
+    /// Returns the origin AS of the route, to validate it against a
+    /// [VrpTable](crate::rib::rov::VrpTable). `None` means that the route
+    /// has no single origin AS, e.g. if its AS path ends in an AS_SET.
+    ///
+    /// The default implementation returns `None`.
+    fn origin_asn(&self) -> Option<Asn> {
+        None
+    }
+
+    /// Returns whether two routes for the same prefix are equally good to
+    /// be used together as a multipath, e.g. for BGP multipath or add-path
+    /// export. It is called with the best route as `a`, and every other
+    /// route, in order of preference, as `b`.
+    ///
+    /// The default implementation only takes routes that are ordered the
+    /// same as equivalent.
+    fn multipath_equivalent<'a>(
+        a: &Self::Orderable<'a>,
+        b: &Self::Orderable<'a>,
+    ) -> bool
+    where
+        Self: 'a,
+    {
+        a.cmp(b) == std::cmp::Ordering::Equal
+    }
 }
 
 //------------ PublicRecord --------------------------------------------------
@@ -186,7 +213,7 @@ impl<M: Meta> RecordSet<M> {
         self.v4.is_empty() && self.v6.is_empty()
     }
 
-    pub fn iter(&self) -> RecordSetIter<M> {
+    pub fn iter(&self) -> RecordSetIter<'_, M> {
         RecordSetIter {
             v4: if self.v4.is_empty() {
                 None
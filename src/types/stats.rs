@@ -1,95 +1,11 @@
 //------------ Types for Statistics -----------------------------------------
 
 use std::{
-    fmt::{Debug, Display},
-    marker::PhantomData,
+    fmt::Debug,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::{rib::STRIDE_SIZE, types::AddressFamily};
-
-pub(crate) struct StrideStats<AF: AddressFamily> {
-    pub(crate) created_nodes: Vec<CreatedNodes>,
-    pub(crate) _prefixes_num: Vec<CreatedNodes>,
-    _af: PhantomData<AF>,
-}
-
-impl<AF: AddressFamily> StrideStats<AF> {
-    pub fn new() -> Self {
-        Self {
-            created_nodes: Self::nodes_vec(AF::BITS / STRIDE_SIZE),
-            _prefixes_num: Self::nodes_vec(AF::BITS / STRIDE_SIZE),
-            _af: PhantomData,
-        }
-    }
-
-    pub fn mem_usage(&self) -> usize {
-        STRIDE_SIZE as usize
-            * self.created_nodes.iter().fold(0, |mut acc, c| {
-                acc += c.count;
-                acc
-            })
-    }
-
-    fn nodes_vec(num_depth_levels: u8) -> Vec<CreatedNodes> {
-        let mut vec: Vec<CreatedNodes> = vec![];
-        for n in 0..num_depth_levels {
-            vec.push(CreatedNodes {
-                depth_level: n,
-                count: 0,
-            })
-        }
-        vec
-    }
-
-    pub fn _inc(&mut self, depth_level: u8) {
-        if let Some(n) = self.created_nodes.get_mut(depth_level as usize) {
-            n.count += 1
-        }
-    }
-
-    pub fn _inc_prefix_count(&mut self, depth_level: u8) {
-        if let Some(p) = self._prefixes_num.get_mut(depth_level as usize) {
-            p.count += 1;
-        }
-    }
-}
-
-impl<AF: AddressFamily> Default for StrideStats<AF> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl<AF: AddressFamily> Debug for StrideStats<AF> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Stride4:{:>8?} {:?} ({}k)",
-            &self.created_nodes.iter().fold(0, |mut a, n| {
-                a += n.count;
-                a
-            }),
-            &self.created_nodes,
-            &self.mem_usage() / 1024
-        )
-    }
-}
-
-impl<AF: AddressFamily> Display for StrideStats<AF> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Stride4:{:>8?} {:?} ({}k)",
-            &self.created_nodes.iter().fold(0, |mut a, n| {
-                a += n.count;
-                a
-            }),
-            &self.created_nodes,
-            &self.mem_usage() / 1024
-        )
-    }
-}
+use crate::errors::PrefixStoreError;
 
 #[derive(Copy, Clone)]
 pub struct CreatedNodes {
@@ -121,6 +37,18 @@ pub(crate) struct Counters {
     prefixes: [AtomicUsize; 129],
     // number of unique (prefix, mui) values inserted in the tree.
     routes: AtomicUsize,
+    // cumulative number of CAS retries of all the upserts, as reported in
+    // their UpsertReports.
+    cas_count: AtomicUsize,
+    // The estimated number of bytes of the in-memory records, for the
+    // Hybrid strategy.
+    resident_bytes: AtomicUsize,
+    // The cumulative number of records evicted to the persist tree, and
+    // loaded back from it, and the number of eviction passes, for the
+    // Hybrid strategy.
+    evicted_records: AtomicUsize,
+    faulted_in_records: AtomicUsize,
+    eviction_passes: AtomicUsize,
 }
 
 impl Counters {
@@ -145,12 +73,26 @@ impl Counters {
         }
     }
 
-    pub fn _dec_prefixes_count(&self, len: u8) {
+    // Decrementing never goes below zero, a counter that is off (because
+    // of contention) should not wrap around.
+    pub fn dec_prefixes_count(&self, len: u8) {
         if let Some(p) = self.prefixes.get(len as usize) {
-            p.fetch_sub(1, Ordering::Relaxed);
+            let _ =
+                p.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                    c.checked_sub(1)
+                });
         }
     }
 
+    // The number of prefixes for every prefix length that has prefixes.
+    pub fn prefixes_per_len(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        self.prefixes
+            .iter()
+            .enumerate()
+            .map(|(len, count)| (len as u8, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count != 0)
+    }
+
     pub fn prefix_stats(&self) -> Vec<CreatedNodes> {
         self.prefixes
             .iter()
@@ -176,6 +118,67 @@ impl Counters {
     pub fn inc_routes_count(&self) {
         self.routes.fetch_add(1, Ordering::Relaxed);
     }
+
+    pub fn dec_routes_count(&self) {
+        let _ = self.routes.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |c| c.checked_sub(1),
+        );
+    }
+
+    pub fn cas_count(&self) -> usize {
+        self.cas_count.load(Ordering::Relaxed)
+    }
+
+    pub fn add_cas_count(&self, count: usize) {
+        self.cas_count.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn resident_bytes(&self) -> usize {
+        self.resident_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn add_resident_bytes(&self, bytes: usize) {
+        self.resident_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    // Like the other decrements, this never goes below zero.
+    pub fn sub_resident_bytes(&self, bytes: usize) {
+        let _ = self.resident_bytes.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |b| Some(b.saturating_sub(bytes)),
+        );
+    }
+
+    // Account for `count` records of `bytes` that were evicted.
+    pub fn add_evicted(&self, count: usize, bytes: usize) {
+        self.evicted_records.fetch_add(count, Ordering::Relaxed);
+        self.sub_resident_bytes(bytes);
+    }
+
+    // Account for `count` records of `bytes` that were loaded back into
+    // memory.
+    pub fn add_faulted_in(&self, count: usize, bytes: usize) {
+        self.faulted_in_records.fetch_add(count, Ordering::Relaxed);
+        self.add_resident_bytes(bytes);
+    }
+
+    pub fn inc_eviction_passes(&self) {
+        self.eviction_passes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn eviction_counters(&self) -> EvictionCounters {
+        EvictionCounters {
+            resident_bytes: self.resident_bytes(),
+            evicted_records: self.evicted_records.load(Ordering::Relaxed),
+            faulted_in_records: self
+                .faulted_in_records
+                .load(Ordering::Relaxed),
+            eviction_passes: self.eviction_passes.load(Ordering::Relaxed),
+        }
+    }
 }
 
 // How can this unwrap in here ever fail?
@@ -191,6 +194,11 @@ impl Default for Counters {
             nodes: AtomicUsize::new(0),
             prefixes: prefixes.try_into().unwrap(),
             routes: AtomicUsize::new(0),
+            cas_count: AtomicUsize::new(0),
+            resident_bytes: AtomicUsize::new(0),
+            evicted_records: AtomicUsize::new(0),
+            faulted_in_records: AtomicUsize::new(0),
+            eviction_passes: AtomicUsize::new(0),
         }
     }
 }
@@ -252,6 +260,34 @@ impl std::ops::Add for UpsertCounters {
     }
 }
 
+//------------ EvictionCounters ----------------------------------------------
+
+/// The counters for the eviction of in-memory records to disk by the
+/// `Hybrid` strategy, as returned by [eviction_counters](
+/// crate::rib::StarCastRib::eviction_counters). For the other strategies
+/// these are all zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvictionCounters {
+    /// The estimated number of bytes of the records in memory.
+    pub resident_bytes: usize,
+    /// The number of records that were evicted to disk.
+    pub evicted_records: usize,
+    /// The number of evicted records that were loaded back into memory.
+    pub faulted_in_records: usize,
+    /// The number of times the records took more than the memory budget,
+    /// and had to be evicted.
+    pub eviction_passes: usize,
+}
+
+impl std::ops::AddAssign for EvictionCounters {
+    fn add_assign(&mut self, rhs: Self) {
+        self.resident_bytes += rhs.resident_bytes;
+        self.evicted_records += rhs.evicted_records;
+        self.faulted_in_records += rhs.faulted_in_records;
+        self.eviction_passes += rhs.eviction_passes;
+    }
+}
+
 //------------ StoreStats ----------------------------------------------------
 
 #[derive(Debug)]
@@ -277,3 +313,168 @@ pub struct UpsertReport {
     // The number of mui records for this prefix after the upsert operation.
     pub mui_count: usize,
 }
+
+//------------ BatchUpsertReport ---------------------------------------------
+
+#[derive(Debug, Default)]
+pub struct BatchUpsertReport {
+    // The number of records that were inserted.
+    pub inserted: usize,
+    // The total number of Atomic Compare-and-Swap operations for all the
+    // inserts.
+    pub cas_count: usize,
+    // The number of inserts that created a new prefix.
+    pub prefixes_new: usize,
+    // The number of inserts that created a new (prefix, mui) pair.
+    pub muis_new: usize,
+    // The records that could not be inserted, as their position in the
+    // batch, and the error, ordered by position.
+    pub errors: Vec<(usize, PrefixStoreError)>,
+}
+
+impl BatchUpsertReport {
+    pub(crate) fn add(&mut self, report: &UpsertReport) {
+        self.inserted += 1;
+        self.cas_count += report.cas_count;
+        self.prefixes_new += usize::from(report.prefix_new);
+        self.muis_new += usize::from(report.mui_new);
+    }
+}
+
+//------------ MemoryReport --------------------------------------------------
+
+/// The memory used by the in-memory data structures of a RIB, per address
+/// family, as returned by [memory_report](
+/// crate::rib::StarCastRib::memory_report).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub v4: AfMemoryReport,
+    pub v6: AfMemoryReport,
+}
+
+impl MemoryReport {
+    /// Returns the total number of bytes for both address families.
+    pub fn total_bytes(&self) -> usize {
+        self.v4.total_bytes() + self.v6.total_bytes()
+    }
+}
+
+/// The memory used by the in-memory data structures for one address family
+/// of a RIB, per level of its chained hash tables.
+///
+/// Both the nodes of the tree bitmap and the prefixes with their records
+/// live in a chained hash table. Level 0 of a table holds the buckets that
+/// hang off of its roots, level 1 the buckets that hang off of the values
+/// in level 0, and so on. Levels that have no buckets are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AfMemoryReport {
+    /// The levels of the table with the nodes of the tree bitmap.
+    pub nodes: Vec<ChtLevelReport>,
+    /// The levels of the table with the prefixes and their records.
+    pub prefixes: Vec<ChtLevelReport>,
+}
+
+impl AfMemoryReport {
+    /// Returns the total number of bytes for all the levels of both tables.
+    pub fn total_bytes(&self) -> usize {
+        self.nodes
+            .iter()
+            .chain(self.prefixes.iter())
+            .map(|l| l.total_bytes())
+            .sum()
+    }
+}
+
+/// The memory used by one level of a chained hash table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChtLevelReport {
+    pub level: u8,
+    /// The number of allocated buckets.
+    pub buckets: usize,
+    /// The number of slots in the allocated buckets.
+    pub slots: usize,
+    /// The number of slots that hold a value, i.e. a node or a prefix.
+    pub occupied: usize,
+    /// The bytes of the allocated buckets, including the empty slots.
+    pub bucket_bytes: usize,
+    /// The bytes of the empty slots in the allocated buckets. This is part
+    /// of `bucket_bytes`.
+    pub slack_bytes: usize,
+    /// The bytes of the values in the occupied slots, i.e. the tree bitmap
+    /// nodes, or the prefixes with their path selections.
+    pub value_bytes: usize,
+    /// The bytes of the containers of the bitmap indexes of the `mui`s in
+    /// the buckets, for the nodes only.
+    pub bitmap_bytes: usize,
+    /// The number of records, for the prefixes only.
+    pub records: usize,
+    /// The bytes of the hash maps that hold the records, including their
+    /// metadata, for the prefixes only.
+    pub record_map_bytes: usize,
+    /// The total length of the byte representation of the metadata of the
+    /// records, for the prefixes only. For metadata that is stored inline
+    /// this is part of `record_map_bytes`, metadata that is stored on the
+    /// heap comes on top of it.
+    pub meta_bytes: usize,
+}
+
+impl ChtLevelReport {
+    /// Returns the total number of bytes for this level, that is all the
+    /// bytes, except for `meta_bytes`.
+    pub fn total_bytes(&self) -> usize {
+        self.bucket_bytes
+            + self.value_bytes
+            + self.bitmap_bytes
+            + self.record_map_bytes
+    }
+
+    // The report for this level in `levels`, added if it isn't there yet.
+    pub(crate) fn for_level(
+        levels: &mut Vec<ChtLevelReport>,
+        level: u8,
+    ) -> &mut ChtLevelReport {
+        while levels.len() <= level as usize {
+            let level = levels.len() as u8;
+            levels.push(ChtLevelReport {
+                level,
+                ..Default::default()
+            });
+        }
+        // The loop above guarantees that this exists.
+        #[allow(clippy::indexing_slicing)]
+        &mut levels[level as usize]
+    }
+
+    // Add an allocated bucket with `slots` slots of `slot_bytes` each, of
+    // which `occupied` hold a value.
+    pub(crate) fn add_bucket(
+        &mut self,
+        slots: usize,
+        occupied: usize,
+        slot_bytes: usize,
+    ) {
+        self.buckets += 1;
+        self.slots += slots;
+        self.occupied += occupied;
+        self.bucket_bytes += slots * slot_bytes;
+        self.slack_bytes += (slots - occupied) * slot_bytes;
+    }
+}
+
+//------------ PruneReport ---------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    // The number of persisted records that were removed.
+    pub removed_records: usize,
+    // The number of bytes of the keys and values of the removed records.
+    // The disk space is reclaimed when the tree is compacted.
+    pub reclaimed_bytes: u64,
+}
+
+impl std::ops::AddAssign for PruneReport {
+    fn add_assign(&mut self, rhs: Self) {
+        self.removed_records += rhs.removed_records;
+        self.reclaimed_bytes += rhs.reclaimed_bytes;
+    }
+}
@@ -65,6 +65,10 @@ impl Meta for BeBytesAsn {
     fn as_orderable(&self, _tbi: Self::TBI) -> Asn {
         u32::from_be_bytes(self.0).into()
     }
+
+    fn origin_asn(&self) -> Option<Asn> {
+        Some(self.as_orderable(()))
+    }
 }
 
 impl std::fmt::Display for BeBytesAsn {
@@ -110,6 +114,10 @@ impl Meta for PrefixAs {
     fn as_orderable(&self, _tbi: Self::TBI) -> Asn {
         u32::from_le_bytes(self.0).into()
     }
+
+    fn origin_asn(&self) -> Option<Asn> {
+        Some(self.asn())
+    }
 }
 
 impl AsRef<[u8]> for PrefixAs {
@@ -0,0 +1,194 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    rib::{
+        config::{Config, MemoryOnlyConfig, MetaConfig, WriteAheadConfig},
+        subscription::{OverflowPolicy, RibEventKind, SubscriptionFilter},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+use common::record;
+
+// Create a fresh, stable store directory for a test.
+fn store_dir(name: &str) -> String {
+    let dir = format!("/tmp/rotonda/auto-{}-{}", name, std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn best<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    pfx: &Prefix,
+) -> Result<u32, Box<dyn Error>> {
+    let guard = &epoch::pin();
+    assert!(!tree_bitmap.is_ps_outdated(pfx, guard)?);
+    Ok(tree_bitmap
+        .best_path(pfx, guard)
+        .ok_or("no prefix")??
+        .multi_uniq_id)
+}
+
+#[test]
+fn test_auto_path_selection() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_auto_path_selection(Some(()));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    let v4 = Prefix::from_str("192.0.2.0/24")?;
+    let v6 = Prefix::from_str("2001:db8::/32")?;
+
+    // For PrefixAs the lowest AS is the best path.
+    for pfx in [v4, v6] {
+        tree_bitmap.insert(&pfx, record(1, 1, 65002), None)?;
+        assert_eq!(best(&tree_bitmap, &pfx)?, 1);
+        tree_bitmap.insert(&pfx, record(2, 1, 65000), None)?;
+        tree_bitmap.insert_batch([(pfx, record(3, 1, 65001))], None);
+        assert_eq!(best(&tree_bitmap, &pfx)?, 2);
+
+        // Local status changes.
+        tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 2, 2)?;
+        assert_eq!(best(&tree_bitmap, &pfx)?, 3);
+        tree_bitmap.mark_mui_as_active_for_prefix(&pfx, 2, 3)?;
+        assert_eq!(best(&tree_bitmap, &pfx)?, 2);
+    }
+
+    // Global status changes, per address family.
+    tree_bitmap.mark_mui_as_withdrawn(2)?;
+    assert_eq!(best(&tree_bitmap, &v4)?, 3);
+    assert_eq!(best(&tree_bitmap, &v6)?, 3);
+    tree_bitmap.mark_mui_as_active_v4(2)?;
+    assert_eq!(best(&tree_bitmap, &v4)?, 2);
+    assert_eq!(best(&tree_bitmap, &v6)?, 3);
+    tree_bitmap.mark_mui_as_active_v6(2)?;
+    assert_eq!(best(&tree_bitmap, &v6)?, 2);
+
+    // Removals.
+    tree_bitmap.remove(&v4, 2)?;
+    assert_eq!(best(&tree_bitmap, &v4)?, 3);
+    tree_bitmap.purge_mui(3)?;
+    assert_eq!(best(&tree_bitmap, &v4)?, 1);
+    assert_eq!(best(&tree_bitmap, &v6)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_auto_path_selection_events() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_auto_path_selection(Some(()));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    let sub = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        100,
+        OverflowPolicy::DropNewest,
+    );
+    let best_path_changes = || {
+        sub.try_iter()
+            .filter_map(|e| match e.kind {
+                RibEventKind::BestPathChange { previous } => {
+                    Some((e.prefix, previous, e.mui))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let pfx = Prefix::from_str("198.51.100.0/24")?;
+    let other = Prefix::from_str("203.0.113.0/24")?;
+    tree_bitmap.insert(&pfx, record(1, 1, 65001), None)?;
+    assert_eq!(best_path_changes(), vec![(Some(pfx), None, 1)]);
+    tree_bitmap.insert(&pfx, record(2, 1, 65000), None)?;
+    tree_bitmap.insert(&other, record(2, 1, 65000), None)?;
+    assert_eq!(
+        best_path_changes(),
+        vec![(Some(pfx), Some(1), 2), (Some(other), None, 2)]
+    );
+
+    // A global withdrawal changes the best path of the prefixes that have
+    // another record.
+    tree_bitmap.mark_mui_as_withdrawn(2)?;
+    assert_eq!(best_path_changes(), vec![(Some(pfx), Some(2), 1)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_auto_path_selection_reopen() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(store_dir("reopen"));
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_auto_path_selection(Some(()));
+
+    let pfx = Prefix::from_str("192.0.2.0/24")?;
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create_with_meta_config(
+            config.clone(),
+            meta_config.clone(),
+        )?;
+    tree_bitmap.insert(&pfx, record(1, 1, 65001), None)?;
+    tree_bitmap.insert(&pfx, record(2, 1, 65000), None)?;
+    tree_bitmap.flush_to_disk()?;
+    drop(tree_bitmap);
+
+    // The reopened RIB keeps selecting the best paths on changes, and
+    // leaves out the withdrawn records.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open_with_meta_config(
+        config,
+        meta_config,
+    )?;
+    tree_bitmap.insert(&pfx, record(3, 2, 65002), None)?;
+    assert_eq!(best(&tree_bitmap, &pfx)?, 2);
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 2, 3)?;
+    assert_eq!(best(&tree_bitmap, &pfx)?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_auto_path_selection_concurrent() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_auto_path_selection(Some(()));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    let pfx = Prefix::from_str("192.0.2.0/24")?;
+
+    // The inserts for the same prefix race to store their path selection,
+    // none of them fails on it.
+    std::thread::scope(|s| {
+        for t in 0..8_u32 {
+            let tree_bitmap = &tree_bitmap;
+            s.spawn(move || {
+                for i in 0..1_000_u64 {
+                    tree_bitmap
+                        .insert(&pfx, record(t, i, 65000 + t), None)
+                        .unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(best(&tree_bitmap, &pfx)?, 0);
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{
+            Config, FlushConfig, PersistHistoryConfig, WriteAheadConfig,
+        },
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn insert_some<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    for (ltime, pfx) in ["185.34.0.0/16", "185.34.10.0/24", "2a04:b900::/29"]
+        .into_iter()
+        .enumerate()
+    {
+        for mui in [1, 2] {
+            tree_bitmap.insert(
+                &Prefix::from_str(pfx)?,
+                Record::new(
+                    mui,
+                    ltime as u64,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(mui),
+                ),
+                None,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// Wait for the disk space used by the store to become non-zero.
+fn wait_for_flush<C: Config>(tree_bitmap: &StarCastRib<PrefixAs, C>) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        if tree_bitmap.disk_space() > 0 {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn test_flush_on_interval() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_flush_config(Some(FlushConfig {
+        memtable_size: u32::MAX,
+        interval: Duration::from_millis(50),
+    }));
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(config)?;
+    insert_some(&tree_bitmap)?;
+
+    assert!(wait_for_flush(&tree_bitmap));
+    tree_bitmap.shutdown()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_flush_on_memtable_size() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = PersistHistoryConfig::default();
+    config.set_flush_config(Some(FlushConfig {
+        memtable_size: 1,
+        interval: Duration::from_secs(3600),
+    }));
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(config)?;
+    // Only the second versions of records get persisted for this strategy.
+    insert_some(&tree_bitmap)?;
+    insert_some(&tree_bitmap)?;
+
+    assert!(wait_for_flush(&tree_bitmap));
+    tree_bitmap.shutdown()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_shutdown_flushes() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let dir = format!("/tmp/rotonda/flush-shutdown-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(dir);
+    config.set_flush_config(Some(FlushConfig {
+        memtable_size: u32::MAX,
+        interval: Duration::from_secs(3600),
+    }));
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    insert_some(&tree_bitmap)?;
+    assert_eq!(tree_bitmap.disk_space(), 0);
+
+    tree_bitmap.shutdown()?;
+    assert!(tree_bitmap.disk_space() > 0);
+
+    // A second shutdown is harmless.
+    tree_bitmap.shutdown()?;
+    drop(tree_bitmap);
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_eq!(tree_bitmap.routes_count().total(), 6);
+
+    Ok(())
+}
+
+#[test]
+fn test_zero_interval() {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_flush_config(Some(FlushConfig {
+        memtable_size: u32::MAX,
+        interval: Duration::ZERO,
+    }));
+
+    assert!(StarCastRib::<PrefixAs, _>::new_with_config(config).is_err());
+}
+
+// A flush drops the removed records from disk, for good.
+#[test]
+fn test_flush_drops_removed() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let dir = format!("/tmp/rotonda/flush-removed-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(dir);
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    let record = |ltime| {
+        Record::new(1, ltime, RouteStatus::Active, PrefixAs::new_from_u32(1))
+    };
+    let prefixes = (0..2_000_u32)
+        .map(|i| Prefix::new(Ipv4Addr::from(0x0a00_0000 | i << 8).into(), 24))
+        .collect::<Result<Vec<_>, _>>()?;
+    for pfx in &prefixes {
+        tree_bitmap.insert(pfx, record(1), None)?;
+    }
+    tree_bitmap.flush_to_disk()?;
+    let full = tree_bitmap.disk_space();
+
+    for pfx in &prefixes[1..] {
+        tree_bitmap.remove(pfx, 1)?;
+    }
+
+    // Enough flushes for the segments with the removed records and the
+    // tombstones to be compacted.
+    let other = Prefix::from_str("192.0.2.0/24")?;
+    for ltime in 0..4 {
+        tree_bitmap.flush_to_disk()?;
+        tree_bitmap.insert(&other, record(ltime), None)?;
+    }
+    tree_bitmap.shutdown()?;
+    assert!(tree_bitmap.disk_space() < full);
+    drop(tree_bitmap);
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_eq!(tree_bitmap.routes_count().total(), 2);
+    assert_eq!(tree_bitmap.persist_prefixes_iter().count(), 2);
+
+    Ok(())
+}
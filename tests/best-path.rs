@@ -53,16 +53,7 @@ impl From<Vec<u8>> for Ipv4Route {
     }
 }
 
-mod common {
-    use std::io::Write;
-
-    pub fn init() {
-        let _ = env_logger::builder()
-            .format(|buf, record| writeln!(buf, "{}", record.args()))
-            .is_test(true)
-            .try_init();
-    }
-}
+mod common;
 
 // rotonda_store::all_strategies![
 //     best_path;
@@ -0,0 +1,206 @@
+#![cfg(feature = "bgp")]
+
+use inetnum::addr::Prefix;
+use inetnum::asn::Asn;
+use rotonda_store::{
+    bgp::{OrdPathAttributes, PathAttributesMeta},
+    epoch,
+    prefix_record::{Meta, Record, RouteStatus},
+    rib::{config::MemoryOnlyConfig, StarCastRib},
+};
+use routecore::bgp::aspath::HopPath;
+use routecore::bgp::path_attributes::BgpIdentifier;
+use routecore::bgp::path_selection::{RouteSource, TiebreakerInfo};
+use routecore::bgp::types::LocalPref;
+
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+mod common;
+
+// The wire format of ORIGIN (if `with_origin`), AS_PATH (as one
+// AS_SEQUENCE of 4-octet ASNs), NEXT_HOP and LOCAL_PREF (if any).
+fn path_attributes(
+    with_origin: bool,
+    as_path: &[u32],
+    local_pref: Option<u32>,
+) -> PathAttributesMeta {
+    let mut raw = vec![];
+    if with_origin {
+        raw.extend_from_slice(&[0x40, 1, 1, 0]);
+    }
+    raw.extend_from_slice(&[0x40, 2, 2 + 4 * as_path.len() as u8]);
+    raw.extend_from_slice(&[2, as_path.len() as u8]);
+    for asn in as_path {
+        raw.extend_from_slice(&asn.to_be_bytes());
+    }
+    raw.extend_from_slice(&[0x40, 3, 4, 192, 0, 2, 1]);
+    if let Some(local_pref) = local_pref {
+        raw.extend_from_slice(&[0x40, 5, 4]);
+        raw.extend_from_slice(&local_pref.to_be_bytes());
+    }
+    PathAttributesMeta::from(raw)
+}
+
+// The path attributes with a MULTI_EXIT_DISC added.
+fn with_med(pas: PathAttributesMeta, med: u32) -> PathAttributesMeta {
+    let mut raw = pas.as_ref().to_vec();
+    raw.extend_from_slice(&[0x80, 4, 4]);
+    raw.extend_from_slice(&med.to_be_bytes());
+    PathAttributesMeta::from(raw)
+}
+
+fn tbi(source: RouteSource) -> TiebreakerInfo {
+    TiebreakerInfo::new(
+        source,
+        None,
+        Asn::from_u32(65000),
+        BgpIdentifier::from([192, 0, 2, 254]),
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, 254)),
+    )
+}
+
+#[test]
+fn test_path_attributes_meta() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let pas = path_attributes(true, &[65001, 65002, 65003], Some(100));
+    assert_eq!(pas.pa_map().get::<LocalPref>(), Some(LocalPref(100)));
+    assert_eq!(
+        pas.pa_map().get::<HopPath>().map(|p| p.hop_count()),
+        Some(3)
+    );
+    assert_eq!(pas.origin_asn(), Some(Asn::from_u32(65003)));
+    assert_eq!(pas.to_string(), "AS_PATH AS65001 AS65002 AS65003");
+
+    // Stored and read back as bytes.
+    let bytes = pas.as_ref().to_vec();
+    let read_back = PathAttributesMeta::from(bytes.clone());
+    assert_eq!(read_back.as_ref(), &bytes[..]);
+    assert_eq!(read_back.origin_asn(), Some(Asn::from_u32(65003)));
+
+    // Without ORIGIN the route can't be selected, and sorts after all the
+    // eligible ones.
+    let no_origin = path_attributes(false, &[65001], Some(1000));
+    let tbi = tbi(RouteSource::Ibgp);
+    assert!(matches!(
+        no_origin.as_orderable(tbi),
+        OrdPathAttributes::Ineligible
+    ));
+    assert!(pas.as_orderable(tbi) < no_origin.as_orderable(tbi));
+
+    let empty = PathAttributesMeta::from(vec![]);
+    assert_eq!(empty.origin_asn(), None);
+    assert_eq!(empty.to_string(), "no AS_PATH");
+
+    Ok(())
+}
+
+#[test]
+fn test_best_path_selection() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap = StarCastRib::<PathAttributesMeta, _>::new_with_config(
+        MemoryOnlyConfig,
+    )?;
+    let pfx = Prefix::from_str("198.51.100.0/24")?;
+
+    let routes = [
+        (1, path_attributes(true, &[65001, 65002, 65003], Some(100))),
+        (2, path_attributes(true, &[65001, 65004], Some(100))),
+        (
+            3,
+            path_attributes(true, &[65001, 65002, 65003, 65005], Some(200)),
+        ),
+        (4, path_attributes(false, &[65001], Some(300))),
+    ];
+    for (mui, pas) in routes {
+        tree_bitmap.insert(
+            &pfx,
+            Record::new(mui, 1, RouteStatus::Active, pas),
+            None,
+        )?;
+    }
+    let guard = &epoch::pin();
+
+    // iBGP: the highest LOCAL_PREF wins, then the shortest AS_PATH.
+    assert_eq!(
+        tree_bitmap.calculate_and_store_best_and_backup_path(
+            &pfx,
+            &tbi(RouteSource::Ibgp),
+            guard
+        )?,
+        (Some(3), Some(2))
+    );
+    assert_eq!(
+        tree_bitmap
+            .best_path(&pfx, guard)
+            .ok_or("no prefix")??
+            .multi_uniq_id,
+        3
+    );
+
+    // eBGP: LOCAL_PREF is not used, the shortest AS_PATH wins.
+    assert_eq!(
+        tree_bitmap.calculate_and_store_best_and_backup_path(
+            &pfx,
+            &tbi(RouteSource::Ebgp),
+            guard
+        )?,
+        (Some(2), Some(1))
+    );
+
+    // Multipath: the same LOCAL_PREF and AS_PATH length.
+    let pfx = Prefix::from_str("203.0.113.0/24")?;
+    let routes = [
+        (1, path_attributes(true, &[65001, 65002], Some(100))),
+        (2, path_attributes(true, &[65003, 65004], Some(100))),
+        (3, path_attributes(true, &[65005, 65002], Some(100))),
+        (4, path_attributes(true, &[65001], Some(90))),
+        (5, path_attributes(true, &[65001, 65002, 65003], Some(100))),
+    ];
+    for (mui, pas) in routes {
+        tree_bitmap.insert(
+            &pfx,
+            Record::new(mui, 1, RouteStatus::Active, pas),
+            None,
+        )?;
+    }
+    assert_eq!(
+        tree_bitmap.calculate_and_store_multipath(
+            &pfx,
+            &tbi(RouteSource::Ibgp),
+            4,
+            guard
+        )?,
+        vec![1, 2, 3]
+    );
+
+    // The MULTI_EXIT_DISC only counts for routes from the same neighbour
+    // AS.
+    let pfx = Prefix::from_str("203.0.113.0/25")?;
+    let routes = [
+        (1, path_attributes(true, &[65001, 65002], Some(100)), 10),
+        (2, path_attributes(true, &[65003, 65004], Some(100)), 50),
+        (3, path_attributes(true, &[65001, 65005], Some(100)), 20),
+    ];
+    for (mui, pas, med) in routes {
+        tree_bitmap.insert(
+            &pfx,
+            Record::new(mui, 1, RouteStatus::Active, with_med(pas, med)),
+            None,
+        )?;
+    }
+    assert_eq!(
+        tree_bitmap.calculate_and_store_multipath(
+            &pfx,
+            &tbi(RouteSource::Ibgp),
+            4,
+            guard
+        )?,
+        vec![1, 2]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// Not every test uses all of these helpers.
+#![allow(dead_code)]
+
+use std::error::Error;
+use std::io::Write;
+
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{config::Config, StarCastRib},
+    test_types::PrefixAs,
+};
+
+pub fn init() {
+    let _ = env_logger::builder()
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .is_test(true)
+        .try_init();
+}
+
+// The (mui, ltime, status, asn) of a record.
+pub type RecordEntry = (u32, u64, RouteStatus, u32);
+
+// The records for a prefix, including the withdrawn ones, ordered by mui.
+pub fn records<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    pfx: &Prefix,
+) -> Result<Vec<RecordEntry>, Box<dyn Error>> {
+    let mut recs = tree_bitmap
+        .get_records_for_prefix(pfx, None, true)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|r| (r.multi_uniq_id, r.ltime, r.status, r.meta.asn().into()))
+        .collect::<Vec<_>>();
+    recs.sort_by_key(|r| r.0);
+    Ok(recs)
+}
+
+// An active record for `mui`, with `asn` as its metadata.
+pub fn record(mui: u32, ltime: u64, asn: u32) -> Record<PrefixAs> {
+    Record::new(mui, ltime, RouteStatus::Active, PrefixAs::new_from_u32(asn))
+}
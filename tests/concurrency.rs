@@ -12,16 +12,7 @@ use rotonda_store::{
     test_types::{BeBytesAsn, NoMeta},
 };
 
-mod common {
-    use std::io::Write;
-
-    pub fn init() {
-        let _ = env_logger::builder()
-            .format(|buf, record| writeln!(buf, "{}", record.args()))
-            .is_test(true)
-            .try_init();
-    }
-}
+mod common;
 
 rotonda_store::all_strategies![
     test_cc_updates_1;
@@ -0,0 +1,169 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, PersistStrategy, WriteAheadConfig},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+// The (prefix, ltime, status, asn) of a historical record.
+type HistoryEntry = (Prefix, u64, RouteStatus, u32);
+
+fn history<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    mui: u32,
+    ltime_range: impl std::ops::RangeBounds<u64>,
+) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    Ok(tree_bitmap
+        .history_for_mui(mui, ltime_range)
+        .map(|r| {
+            r.map(|(p, rec)| {
+                (p, rec.ltime, rec.status, rec.meta.asn().into())
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+fn insert_history<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<[Prefix; 3], Box<dyn Error>> {
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let more_specific = Prefix::from_str("185.49.140.0/24")?;
+    let pfx_v6 = Prefix::from_str("2a04:b900::/29")?;
+
+    for (p, mui, ltime, asn) in [
+        (pfx, 1, 10, 100),
+        (pfx_v6, 1, 12, 120),
+        (pfx, 2, 15, 150),
+        (pfx, 1, 20, 200),
+    ] {
+        tree_bitmap.insert(
+            &p,
+            Record::new(
+                mui,
+                ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(asn),
+            ),
+            None,
+        )?;
+    }
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 25)?;
+    tree_bitmap.insert(
+        &more_specific,
+        Record::new(1, 30, RouteStatus::Active, PrefixAs::new_from_u32(300)),
+        None,
+    )?;
+
+    Ok([pfx, more_specific, pfx_v6])
+}
+
+rotonda_store::all_strategies![
+    test_history_1;
+    test_history_for_mui;
+    PrefixAs
+];
+
+fn test_history_for_mui<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let [pfx, more_specific, pfx_v6] = insert_history(&tree_bitmap)?;
+    let active = RouteStatus::Active;
+    let withdrawn = RouteStatus::Withdrawn;
+
+    match tree_bitmap.persist_strategy() {
+        PersistStrategy::WriteAhead => {
+            assert_eq!(
+                history(&tree_bitmap, 1, ..)?,
+                vec![
+                    (pfx, 10, active, 100),
+                    (pfx_v6, 12, active, 120),
+                    (pfx, 20, active, 200),
+                    (pfx, 25, withdrawn, 200),
+                    (more_specific, 30, active, 300),
+                ]
+            );
+            assert_eq!(
+                history(&tree_bitmap, 1, 12..25)?,
+                vec![(pfx_v6, 12, active, 120), (pfx, 20, active, 200)]
+            );
+            assert_eq!(
+                history(&tree_bitmap, 2, ..)?,
+                vec![(pfx, 15, active, 150)]
+            );
+
+            tree_bitmap.flush_to_disk()?;
+            tree_bitmap.remove(&pfx, 1)?;
+            assert_eq!(
+                history(&tree_bitmap, 1, ..)?,
+                vec![
+                    (pfx_v6, 12, active, 120),
+                    (more_specific, 30, active, 300),
+                ]
+            );
+        }
+        PersistStrategy::PersistHistory => {
+            // The current records are not part of the history.
+            assert_eq!(
+                history(&tree_bitmap, 1, ..)?,
+                vec![
+                    (pfx, 10, active, 100),
+                    (pfx, 20, active, 200),
+                    (pfx, 25, withdrawn, 200),
+                ]
+            );
+            assert_eq!(
+                history(&tree_bitmap, 1, 21..)?,
+                vec![(pfx, 25, withdrawn, 200)]
+            );
+            assert!(history(&tree_bitmap, 2, ..)?.is_empty());
+
+            tree_bitmap.flush_to_disk()?;
+            tree_bitmap.remove(&pfx, 1)?;
+            assert!(history(&tree_bitmap, 1, ..)?.is_empty());
+        }
+        PersistStrategy::MemoryOnly
+        | PersistStrategy::PersistOnly
+        | PersistStrategy::Hybrid => {
+            assert!(history(&tree_bitmap, 1, ..)?.is_empty());
+        }
+    }
+
+    assert!(history(&tree_bitmap, 3, ..)?.is_empty());
+    assert!(history(&tree_bitmap, 1, 40..)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_history_for_mui_after_reopen() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let dir = format!("/tmp/rotonda/history-for-mui-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(dir);
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    insert_history(&tree_bitmap)?;
+    let before = history(&tree_bitmap, 1, ..)?;
+    assert_eq!(before.len(), 5);
+    tree_bitmap.shutdown()?;
+    drop(tree_bitmap);
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_eq!(history(&tree_bitmap, 1, ..)?, before);
+
+    Ok(())
+}
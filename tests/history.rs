@@ -0,0 +1,174 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    match_options::{IncludeHistory, MatchOptions, MatchType},
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, PersistHistoryConfig, PersistStrategy},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+rotonda_store::all_strategies![
+    test_hist_1;
+    test_history;
+    PrefixAs
+];
+
+fn test_history<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let more_specific = Prefix::from_str("185.49.140.0/24")?;
+
+    // Three versions for mui 1, inserted out of ltime order, and one for
+    // mui 2.
+    for (mui, ltime, asn) in [(1, 10, 100), (1, 30, 300), (1, 20, 200)] {
+        tree_bitmap.insert(
+            &pfx,
+            Record::new(
+                mui,
+                ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(asn),
+            ),
+            None,
+        )?;
+    }
+    tree_bitmap.insert(
+        &pfx,
+        Record::new(2, 15, RouteStatus::Active, PrefixAs::new_from_u32(150)),
+        None,
+    )?;
+    for (ltime, asn) in [(5, 50), (6, 60)] {
+        tree_bitmap.insert(
+            &more_specific,
+            Record::new(
+                1,
+                ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(asn),
+            ),
+            None,
+        )?;
+    }
+
+    let guard = &epoch::pin();
+    let mut options = MatchOptions {
+        match_type: MatchType::ExactMatch,
+        include_withdrawn: false,
+        include_less_specifics: false,
+        include_more_specifics: true,
+        mui: None,
+        include_history: IncludeHistory::None,
+    };
+
+    let res = tree_bitmap.match_prefix(&pfx, &options, guard)?;
+    assert!(res.history.is_none());
+    assert_eq!(res.records.len(), 2);
+
+    options.include_history = IncludeHistory::SearchPrefix;
+    let res = tree_bitmap.match_prefix(&pfx, &options, guard)?;
+
+    // The current records are unaffected by the history option.
+    assert_eq!(res.records.len(), 2);
+
+    let ltimes =
+        |res: &rotonda_store::match_options::QueryResult<PrefixAs>,
+         p: &Prefix| {
+            res.history
+                .as_ref()
+                .and_then(|h| h.iter().find(|r| r.prefix == *p))
+                .map(|r| r.meta.iter().map(|r| r.ltime).collect::<Vec<_>>())
+        };
+
+    match tree_bitmap.persist_strategy() {
+        PersistStrategy::WriteAhead => {
+            // Every record was written ahead, including the current ones.
+            assert_eq!(ltimes(&res, &pfx), Some(vec![10, 15, 20, 30]));
+            assert_eq!(res.history.as_ref().map(|h| h.len()), Some(1));
+        }
+        PersistStrategy::PersistHistory => {
+            // Only the records that were superseded.
+            assert_eq!(ltimes(&res, &pfx), Some(vec![10, 30]));
+            assert_eq!(res.history.as_ref().map(|h| h.len()), Some(1));
+        }
+        PersistStrategy::MemoryOnly
+        | PersistStrategy::PersistOnly
+        | PersistStrategy::Hybrid => {
+            assert!(res.history.is_none());
+        }
+    }
+
+    options.include_history = IncludeHistory::All;
+    options.mui = Some(1);
+    let res = tree_bitmap.match_prefix(&pfx, &options, guard)?;
+
+    match tree_bitmap.persist_strategy() {
+        PersistStrategy::WriteAhead => {
+            assert_eq!(ltimes(&res, &pfx), Some(vec![10, 20, 30]));
+            assert_eq!(ltimes(&res, &more_specific), Some(vec![5, 6]));
+        }
+        PersistStrategy::PersistHistory => {
+            assert_eq!(ltimes(&res, &pfx), Some(vec![10, 30]));
+            assert_eq!(ltimes(&res, &more_specific), Some(vec![5]));
+        }
+        PersistStrategy::MemoryOnly
+        | PersistStrategy::PersistOnly
+        | PersistStrategy::Hybrid => {
+            assert!(res.history.is_none());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_history_status_changes() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let dir = format!("/tmp/rotonda/history-status-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = PersistHistoryConfig::default();
+    config.set_persist_path(dir);
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(config)?;
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    tree_bitmap.insert(
+        &pfx,
+        Record::new(1, 10, RouteStatus::Active, PrefixAs::new_from_u32(100)),
+        None,
+    )?;
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 20)?;
+    tree_bitmap.mark_mui_as_active_for_prefix(&pfx, 1, 30)?;
+
+    let options = MatchOptions {
+        match_type: MatchType::ExactMatch,
+        include_withdrawn: true,
+        include_less_specifics: false,
+        include_more_specifics: false,
+        mui: Some(1),
+        include_history: IncludeHistory::SearchPrefix,
+    };
+    let res = tree_bitmap.match_prefix(&pfx, &options, &epoch::pin())?;
+    let history = res
+        .history
+        .iter()
+        .flat_map(|h| h.iter())
+        .flat_map(|r| r.meta.into_iter().map(|r| (r.ltime, r.status)))
+        .collect::<Vec<_>>();
+
+    // Every status change is persisted with the new status.
+    assert_eq!(history.last(), Some(&(30, RouteStatus::Active)));
+    assert!(history.contains(&(20, RouteStatus::Withdrawn)));
+
+    Ok(())
+}
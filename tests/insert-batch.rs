@@ -0,0 +1,140 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, WriteAheadConfig},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+mod common;
+
+use common::records;
+
+// The prefixes with their records to insert.
+type Batch = Vec<(Prefix, Record<PrefixAs>)>;
+
+fn batch() -> Result<Batch, Box<dyn Error>> {
+    let mut batch = vec![];
+    for i in 0..=255 {
+        for mui in [1, 2] {
+            batch.push((
+                Prefix::new(Ipv4Addr::new(10, i, 0, 0).into(), 16)?,
+                Record::new(
+                    mui,
+                    1,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(mui),
+                ),
+            ));
+        }
+    }
+    // Interleaved address families and prefix lengths, and a second version
+    // for a (prefix, mui) pair that comes after the first one.
+    for (pfx, ltime, asn) in [
+        ("2a04:b900::/29", 1, 100),
+        ("10.0.0.0/8", 1, 200),
+        ("2a04:b900::/29", 2, 300),
+        ("10.0.0.0/24", 1, 400),
+    ] {
+        batch.push((
+            Prefix::from_str(pfx)?,
+            Record::new(
+                1,
+                ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(asn),
+            ),
+        ));
+    }
+
+    Ok(batch)
+}
+
+rotonda_store::all_strategies![
+    test_batch_1;
+    test_insert_batch;
+    PrefixAs
+];
+
+fn test_insert_batch<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let batch = batch()?;
+    let report = tree_bitmap.insert_batch(batch.clone(), None);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert_eq!(report.inserted, batch.len());
+    assert_eq!(report.prefixes_new, 256 + 3);
+
+    // The same records inserted one by one.
+    let expected = StarCastRib::<PrefixAs, C>::try_default()?;
+    for (pfx, rec) in &batch {
+        expected.insert(pfx, rec.clone(), None)?;
+    }
+    for (pfx, _) in &batch {
+        assert_eq!(records(&tree_bitmap, pfx)?, records(&expected, pfx)?);
+    }
+    assert_eq!(
+        records(&tree_bitmap, &Prefix::from_str("2a04:b900::/29")?)?,
+        vec![(1, 2, RouteStatus::Active, 300)]
+    );
+    assert_eq!(tree_bitmap.prefixes_count().total(), 256 + 3);
+
+    // The second version for a (prefix, mui) pair is not a new route.
+    assert_eq!(report.muis_new, batch.len() - 1);
+    assert_eq!(tree_bitmap.routes_count().total(), batch.len() - 1);
+
+    // An empty batch does nothing.
+    let report = tree_bitmap.insert_batch(vec![], None);
+    assert_eq!(report.inserted, 0);
+    assert!(report.errors.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_batch_history() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(
+        WriteAheadConfig::default(),
+    )?;
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let report = tree_bitmap.insert_batch(
+        [3, 1, 2].map(|ltime| {
+            (
+                pfx,
+                Record::new(
+                    1,
+                    ltime,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(ltime as u32),
+                ),
+            )
+        }),
+        None,
+    );
+    assert_eq!(report.inserted, 3);
+    assert_eq!(report.muis_new, 1);
+
+    // All the versions are persisted, the last one in the batch is the
+    // current record.
+    let history = tree_bitmap
+        .history_for_mui(1, ..)
+        .map(|r| r.map(|(_, rec)| rec.ltime))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(history, vec![1, 2, 3]);
+    assert_eq!(
+        records(&tree_bitmap, &pfx)?,
+        vec![(1, 2, RouteStatus::Active, 2)]
+    );
+
+    Ok(())
+}
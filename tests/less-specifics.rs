@@ -9,16 +9,7 @@ use rotonda_store::{
 
 use std::error::Error;
 
-mod common {
-    use std::io::Write;
-
-    pub fn init() {
-        let _ = env_logger::builder()
-            .format(|buf, record| writeln!(buf, "{}", record.args()))
-            .is_test(true)
-            .try_init();
-    }
-}
+mod common;
 
 rotonda_store::all_strategies![
     test_ms_1;
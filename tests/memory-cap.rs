@@ -0,0 +1,256 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    prefix_record::RouteStatus,
+    rib::{
+        config::{Config, HybridConfig},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr};
+
+mod common;
+
+use common::record;
+
+const BUDGET: usize = 16 * 1024;
+
+fn prefix(i: u32) -> Result<Prefix, Box<dyn Error>> {
+    Ok(Prefix::new(
+        IpAddr::V4(Ipv4Addr::from(0x0a00_0000 | (i << 8))),
+        24,
+    )?)
+}
+
+fn hybrid_config(name: &str) -> HybridConfig {
+    let dir =
+        format!("/tmp/rotonda/memory-cap-{}-{}", name, std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut config = HybridConfig::default();
+    config.set_persist_path(dir);
+    config.set_memory_budget(BUDGET);
+    config
+}
+
+fn hybrid_rib(
+    name: &str,
+) -> Result<StarCastRib<PrefixAs, HybridConfig>, Box<dyn Error>> {
+    StarCastRib::<PrefixAs, _>::new_with_config(hybrid_config(name))
+}
+
+#[test]
+fn test_memory_cap() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap = hybrid_rib("cap")?;
+    for i in 0..2_000 {
+        let pfx = prefix(i)?;
+        tree_bitmap.insert(&pfx, record(1, 1, 65000 + i), None)?;
+        tree_bitmap.insert(&pfx, record(2, 1, 65000 + i), None)?;
+        assert!(tree_bitmap.eviction_counters().resident_bytes <= BUDGET);
+    }
+
+    let counters = tree_bitmap.eviction_counters();
+    assert!(counters.eviction_passes > 0);
+    assert!(counters.evicted_records > 2_000);
+    assert_eq!(counters.faulted_in_records, 0);
+    assert_eq!(tree_bitmap.routes_count_v4().total(), 4_000);
+
+    // Evicted records are loaded back when they are read.
+    for i in 0..2_000 {
+        let recs = tree_bitmap
+            .get_records_for_prefix(&prefix(i)?, None, false)?
+            .ok_or("no records")?;
+        let muis = recs.iter().map(|r| r.multi_uniq_id).collect::<Vec<_>>();
+        assert_eq!(muis.len(), 2, "{}", prefix(i)?);
+        assert!(muis.contains(&1) && muis.contains(&2));
+        assert!(recs
+            .iter()
+            .all(|r| r.meta == PrefixAs::new_from_u32(65000 + i)));
+        assert!(tree_bitmap.eviction_counters().resident_bytes <= BUDGET);
+    }
+    assert!(tree_bitmap.eviction_counters().faulted_in_records > 0);
+
+    let guard = &epoch::pin();
+    let mut seen = HashSet::new();
+    for rec in tree_bitmap.prefixes_iter(guard) {
+        let rec = rec?;
+        assert_eq!(rec.meta.len(), 2, "{}", rec.prefix);
+        seen.insert(rec.prefix);
+    }
+    assert_eq!(seen.len(), 2_000);
+
+    let metrics = tree_bitmap.metrics();
+    assert!(metrics
+        .contains("rotonda_store_evicted_records_total{family=\"ipv4\"}"));
+    assert!(
+        metrics.contains("rotonda_store_resident_bytes{family=\"ipv6\"} 0")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_memory_cap_mutations() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap = hybrid_rib("mutations")?;
+    let first = prefix(0)?;
+    tree_bitmap.insert(&first, record(1, 1, 65002), None)?;
+    tree_bitmap.insert(&first, record(2, 1, 65001), None)?;
+    tree_bitmap.insert(&first, record(3, 1, 65000), None)?;
+    for i in 1..1_000 {
+        tree_bitmap.insert(&prefix(i)?, record(1, 1, 65000), None)?;
+    }
+    assert!(tree_bitmap.eviction_counters().evicted_records > 0);
+
+    // A newer record for an evicted prefix wins over the evicted one.
+    tree_bitmap.insert(&first, record(1, 1, 65003), None)?;
+    let recs = tree_bitmap
+        .get_records_for_prefix(&first, Some(1), false)?
+        .ok_or("no records")?;
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].meta, PrefixAs::new_from_u32(65003));
+    assert!(tree_bitmap.eviction_counters().faulted_in_records >= 3);
+
+    for i in 1..1_000 {
+        tree_bitmap.insert(&prefix(i)?, record(2, 1, 65000), None)?;
+    }
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&first, 3, 2)?;
+    for i in 1..1_000 {
+        tree_bitmap.insert(&prefix(i)?, record(3, 1, 65000), None)?;
+    }
+    tree_bitmap.remove(&first, 2)?;
+    for i in 1..1_000 {
+        tree_bitmap.insert(&prefix(i)?, record(4, 1, 65000), None)?;
+    }
+
+    let recs = tree_bitmap
+        .get_records_for_prefix(&first, None, true)?
+        .ok_or("no records")?;
+    let mut status = recs
+        .iter()
+        .map(|r| (r.multi_uniq_id, r.status))
+        .collect::<Vec<_>>();
+    status.sort_by_key(|(mui, _)| *mui);
+    assert_eq!(
+        status,
+        vec![(1, RouteStatus::Active), (3, RouteStatus::Withdrawn)]
+    );
+
+    // Path selection on an evicted prefix.
+    for i in 1..1_000 {
+        tree_bitmap.insert(&prefix(i)?, record(5, 1, 65000), None)?;
+    }
+    let guard = &epoch::pin();
+    tree_bitmap.mark_mui_as_active_for_prefix(&first, 3, 3)?;
+    tree_bitmap.calculate_and_store_best_and_backup_path(
+        &first,
+        &(),
+        guard,
+    )?;
+    for i in 1..1_000 {
+        tree_bitmap.insert(&prefix(i)?, record(6, 1, 65000), None)?;
+    }
+    assert_eq!(
+        tree_bitmap
+            .best_path(&first, guard)
+            .ok_or("no prefix")??
+            .multi_uniq_id,
+        3
+    );
+    assert!(tree_bitmap.eviction_counters().resident_bytes <= BUDGET);
+
+    Ok(())
+}
+
+#[test]
+fn test_memory_cap_concurrent() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap = hybrid_rib("concurrent")?;
+    for i in 0..2_000 {
+        let pfx = prefix(i)?;
+        tree_bitmap.insert(&pfx, record(1, 1, 65000 + i), None)?;
+        tree_bitmap.insert(&pfx, record(2, 1, 65000 + i), None)?;
+    }
+
+    // Every read loads records back, and evicts others, so the readers
+    // keep evicting the prefixes that the other readers are reading. They
+    // never see a prefix without its records.
+    std::thread::scope(|s| {
+        let readers = (0..8)
+            .map(|t| {
+                let tree_bitmap = &tree_bitmap;
+                s.spawn(move || -> Result<(), String> {
+                    for i in (0..3_000).map(|i| (i + t * 50) % 2_000) {
+                        let pfx = prefix(i).map_err(|e| e.to_string())?;
+                        let len = tree_bitmap
+                            .get_records_for_prefix(&pfx, None, false)
+                            .map_err(|e| e.to_string())?
+                            .map_or(0, |recs| recs.len());
+                        if len != 2 {
+                            return Err(format!("{}: {} records", pfx, len));
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for reader in readers {
+            reader.join().map_err(|_| "reader panicked")??;
+        }
+        Ok::<_, Box<dyn Error>>(())
+    })?;
+
+    let counters = tree_bitmap.eviction_counters();
+    assert!(counters.faulted_in_records > 4_000);
+    assert!(counters.resident_bytes <= BUDGET);
+
+    Ok(())
+}
+
+#[test]
+fn test_memory_cap_reopen() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let config = hybrid_config("reopen");
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    for i in 0..1_000 {
+        let pfx = prefix(i)?;
+        tree_bitmap.insert(&pfx, record(1, 1, 65000 + i), None)?;
+        tree_bitmap.insert(&pfx, record(2, 1, 65000 + i), None)?;
+    }
+    let counters = tree_bitmap.eviction_counters();
+    assert!(counters.evicted_records > 0);
+    assert!(counters.resident_bytes > 0);
+    tree_bitmap.shutdown()?;
+    drop(tree_bitmap);
+
+    // The records that were in memory are restored as well as the evicted
+    // ones.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_eq!(tree_bitmap.routes_count_v4().total(), 2_000);
+    assert_eq!(tree_bitmap.eviction_counters().resident_bytes, 0);
+    for i in 0..1_000 {
+        let recs = tree_bitmap
+            .get_records_for_prefix(&prefix(i)?, None, false)?
+            .ok_or("no records")?;
+        let mut muis =
+            recs.iter().map(|r| r.multi_uniq_id).collect::<Vec<_>>();
+        muis.sort();
+        assert_eq!(muis, vec![1, 2], "{}", prefix(i)?);
+        assert!(recs
+            .iter()
+            .all(|r| r.meta == PrefixAs::new_from_u32(65000 + i)));
+    }
+
+    Ok(())
+}
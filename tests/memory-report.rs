@@ -0,0 +1,104 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    rib::{config::MemoryOnlyConfig, StarCastRib},
+    stats::ChtLevelReport,
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+mod common;
+
+use common::record;
+
+fn check_levels(levels: &[ChtLevelReport]) {
+    for (i, level) in levels.iter().enumerate() {
+        assert_eq!(level.level as usize, i);
+        assert!(level.buckets > 0);
+        assert!(level.occupied <= level.slots);
+        assert!(level.slack_bytes <= level.bucket_bytes);
+        assert_eq!(
+            level.slack_bytes * level.slots,
+            level.bucket_bytes * (level.slots - level.occupied)
+        );
+    }
+}
+
+#[test]
+fn test_memory_report() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let empty = tree_bitmap.memory_report()?;
+    assert!(empty.v4.prefixes.iter().all(|l| l.occupied == 0));
+
+    for i in 0..200_u32 {
+        let len = 24 - (i % 9) as u8;
+        let v4 = Prefix::new(
+            IpAddr::V4(Ipv4Addr::from(
+                (0x0a00_0000 | (i << 8)) & (u32::MAX << (32 - len)),
+            )),
+            len,
+        )?;
+        let v6 = Prefix::new(
+            IpAddr::V6(Ipv6Addr::from(
+                (0x2001_0db8_u128 << 96) | (i as u128) << 80,
+            )),
+            48,
+        )?;
+        for mui in 0..=(i % 3) {
+            tree_bitmap.insert(&v4, record(mui, 1, 65000), None)?;
+            tree_bitmap.insert(&v6, record(mui, 1, 65000), None)?;
+        }
+    }
+
+    let report = tree_bitmap.memory_report()?;
+    for af in [&report.v4, &report.v6] {
+        check_levels(&af.nodes);
+        check_levels(&af.prefixes);
+        assert!(af.nodes.iter().all(|l| l.records == 0));
+        assert!(af.prefixes.iter().all(|l| l.bitmap_bytes == 0));
+    }
+
+    // Every prefix and every record is accounted for.
+    let prefixes = |levels: &[ChtLevelReport]| {
+        levels.iter().map(|l| l.occupied).sum::<usize>()
+    };
+    let records = |levels: &[ChtLevelReport]| {
+        levels.iter().map(|l| l.records).sum::<usize>()
+    };
+    assert_eq!(
+        prefixes(&report.v4.prefixes),
+        tree_bitmap.prefixes_v4_count().in_memory()
+    );
+    assert_eq!(
+        prefixes(&report.v6.prefixes),
+        tree_bitmap.prefixes_v6_count().in_memory()
+    );
+    assert_eq!(
+        records(&report.v4.prefixes),
+        tree_bitmap.routes_count_v4().total()
+    );
+    assert_eq!(
+        records(&report.v6.prefixes),
+        tree_bitmap.routes_count_v6().total()
+    );
+    let meta_bytes: usize =
+        report.v6.prefixes.iter().map(|l| l.meta_bytes).sum();
+    assert_eq!(
+        records(&report.v6.prefixes)
+            * PrefixAs::new_from_u32(1).as_ref().len(),
+        meta_bytes
+    );
+    assert!(report.v4.nodes.iter().any(|l| l.bitmap_bytes > 0));
+
+    assert_eq!(
+        report.total_bytes(),
+        report.v4.total_bytes() + report.v6.total_bytes()
+    );
+    assert!(report.total_bytes() > empty.total_bytes());
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    rib::{config::MemoryOnlyConfig, StarCastRib},
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+use common::record;
+
+// The value of the sample with this name and labels.
+fn sample(metrics: &str, series: &str) -> Option<u64> {
+    metrics
+        .lines()
+        .find_map(|l| l.strip_prefix(series)?.strip_prefix(' '))
+        .and_then(|v| v.parse().ok())
+}
+
+#[test]
+fn test_metrics() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    for (pfx, mui) in [
+        ("192.0.2.0/24", 1),
+        ("192.0.2.0/24", 2),
+        ("198.51.100.0/24", 1),
+        ("10.0.0.0/8", 1),
+        ("2001:db8::/32", 1),
+    ] {
+        tree_bitmap.insert(
+            &Prefix::from_str(pfx)?,
+            record(mui, 1, 65000),
+            None,
+        )?;
+    }
+    tree_bitmap.mark_mui_as_withdrawn_v4(2)?;
+
+    // The CAS retries of concurrent inserts for the same prefixes add up.
+    let cas_count = std::thread::scope(|s| {
+        let handles = (10..14)
+            .map(|mui| {
+                let tree_bitmap = &tree_bitmap;
+                s.spawn(move || {
+                    let mut cas_count = 0;
+                    for pfx in ["192.0.2.0/24", "198.51.100.0/24"] {
+                        let pfx = Prefix::from_str(pfx).unwrap();
+                        for _ in 0..100 {
+                            cas_count += tree_bitmap
+                                .insert(&pfx, record(mui, 1, 65000), None)
+                                .unwrap()
+                                .cas_count;
+                        }
+                    }
+                    cas_count
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .sum::<usize>()
+    });
+
+    let metrics = tree_bitmap.metrics();
+    let value = |series| sample(&metrics, series);
+    assert_eq!(
+        value(r#"rotonda_store_prefixes{family="ipv4",len="24"}"#),
+        Some(2)
+    );
+    assert_eq!(
+        value(r#"rotonda_store_prefixes{family="ipv4",len="8"}"#),
+        Some(1)
+    );
+    assert_eq!(
+        value(r#"rotonda_store_prefixes{family="ipv6",len="32"}"#),
+        Some(1)
+    );
+    assert_eq!(
+        value(r#"rotonda_store_prefixes{family="ipv6",len="48"}"#),
+        None
+    );
+    assert_eq!(value(r#"rotonda_store_routes{family="ipv4"}"#), Some(12));
+    assert_eq!(value(r#"rotonda_store_routes{family="ipv6"}"#), Some(1));
+    assert_eq!(
+        value(r#"rotonda_store_nodes{family="ipv4"}"#),
+        Some(tree_bitmap.nodes_v4_count() as u64)
+    );
+    assert_eq!(
+        value(r#"rotonda_store_persisted_items{family="ipv4"}"#),
+        Some(0)
+    );
+    assert_eq!(value(r#"rotonda_store_disk_bytes{family="ipv6"}"#), Some(0));
+    assert_eq!(
+        value(r#"rotonda_store_withdrawn_muis{family="ipv4"}"#),
+        Some(1)
+    );
+    assert_eq!(
+        value(r#"rotonda_store_withdrawn_muis{family="ipv6"}"#),
+        Some(0)
+    );
+    assert_eq!(
+        value(r#"rotonda_store_cas_retries_total{family="ipv4"}"#),
+        Some(cas_count as u64)
+    );
+
+    // Every sample comes after the TYPE line of its metric.
+    let mut metric = None;
+    for line in metrics.lines() {
+        if let Some(t) = line.strip_prefix("# TYPE ") {
+            metric = t.split(' ').next();
+        } else if !line.starts_with('#') {
+            let name = line.split(['{', ' ']).next();
+            assert_eq!(name, metric, "{line}");
+        }
+    }
+
+    // Rendering into a reused buffer.
+    let mut buf = String::new();
+    tree_bitmap.write_metrics(&mut buf)?;
+    assert_eq!(buf, metrics);
+    buf.clear();
+    tree_bitmap.write_metrics(&mut buf)?;
+    assert_eq!(buf, metrics);
+
+    Ok(())
+}
@@ -8,16 +8,7 @@ use rotonda_store::prefix_record::{Record, RouteStatus};
 use rotonda_store::rib::{config::Config, StarCastRib};
 use rotonda_store::test_types::PrefixAs;
 
-mod common {
-    use std::io::Write;
-
-    pub fn init() {
-        let _ = env_logger::builder()
-            .format(|buf, record| writeln!(buf, "{}", record.args()))
-            .is_test(true)
-            .try_init();
-    }
-}
+mod common;
 
 rotonda_store::all_strategies![
     test_ms_w_ls_1;
@@ -0,0 +1,191 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, MemoryOnlyConfig},
+        mrt::{MrtPeer, MrtPeerIndex},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+use routecore::mrt::MrtFile;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+mod common;
+
+// The (prefix, mui, asn) of an active record.
+type RibEntry = (Prefix, u32, u32);
+
+fn peer_index() -> Result<MrtPeerIndex, Box<dyn Error>> {
+    let mut peers = BTreeMap::new();
+    for (mui, addr) in
+        [(1, "192.0.2.1"), (2, "2001:db8::2"), (3, "192.0.2.3")]
+    {
+        peers.insert(
+            mui,
+            MrtPeer {
+                bgp_id: Ipv4Addr::new(10, 0, 0, mui as u8),
+                addr: addr.parse()?,
+                asn: 65000 + mui,
+            },
+        );
+    }
+
+    Ok(MrtPeerIndex {
+        collector_bgp_id: Ipv4Addr::new(192, 0, 2, 254),
+        view_name: "test".to_string(),
+        peers,
+    })
+}
+
+fn rib_entries<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<Vec<RibEntry>, Box<dyn Error>> {
+    let guard = &rotonda_store::epoch::pin();
+    let mut entries = vec![];
+    for pfx_rec in tree_bitmap.prefixes_iter(guard) {
+        let pfx_rec = pfx_rec?;
+        for rec in pfx_rec.meta {
+            if rec.status == RouteStatus::Active
+                && !tree_bitmap.mui_is_withdrawn_v4(rec.multi_uniq_id)
+                && !tree_bitmap.mui_is_withdrawn_v6(rec.multi_uniq_id)
+            {
+                entries.push((
+                    pfx_rec.prefix,
+                    rec.multi_uniq_id,
+                    rec.meta.asn().into(),
+                ));
+            }
+        }
+    }
+    entries.sort();
+
+    Ok(entries)
+}
+
+rotonda_store::all_strategies![
+    test_export_1;
+    test_export_and_reload;
+    PrefixAs
+];
+
+fn test_export_and_reload<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let prefixes = [
+        Prefix::from_str("192.0.2.1/32")?,
+        Prefix::from_str("185.49.140.0/22")?,
+        Prefix::from_str("185.49.142.0/23")?,
+        Prefix::from_str("2a04:b900::/29")?,
+        Prefix::from_str("2a04:b907::/48")?,
+    ];
+    for (i, pfx) in prefixes.iter().enumerate() {
+        for mui in [1, 2, 4] {
+            tree_bitmap.insert(
+                pfx,
+                Record::new(
+                    mui,
+                    i as u64 + 10,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(mui * 100 + i as u32),
+                ),
+                None,
+            )?;
+        }
+    }
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&prefixes[1], 1, 20)?;
+    tree_bitmap.mark_mui_as_withdrawn(2)?;
+
+    let mut mrt = vec![];
+    let written = tree_bitmap.export_mrt(&mut mrt, &peer_index()?)?;
+
+    // Records for mui 4 have no peer, the ones for mui 2 are all withdrawn,
+    // and one of the records for mui 1 is withdrawn.
+    let expected = rib_entries(&tree_bitmap)?
+        .into_iter()
+        .filter(|e| e.1 != 4)
+        .collect::<Vec<_>>();
+    assert_eq!(expected.len(), prefixes.len() - 1);
+    assert_eq!(written, expected.len());
+
+    let mrt_file = MrtFile::new(&mrt);
+    let peers = mrt_file.pi().map_err(|e| e.to_string())?;
+    assert_eq!(peers.len(), 3);
+    assert_eq!(u32::from(peers[2].asn), 65003);
+
+    // Reload the file, with the peer indexes as muis, like load_mrt does.
+    let reloaded = StarCastRib::<PrefixAs, MemoryOnlyConfig>::try_default()?;
+    for (_, peer_idx, peer, pfx, attributes) in
+        mrt_file.rib_entries().map_err(|e| e.to_string())?
+    {
+        let mui = u32::from(peer_idx) + 1;
+        assert_eq!(u32::from(peer.asn), 65000 + mui);
+        reloaded.insert(
+            &pfx,
+            Record::new(
+                mui,
+                1,
+                RouteStatus::Active,
+                PrefixAs::from(attributes),
+            ),
+            None,
+        )?;
+    }
+    assert_eq!(rib_entries(&reloaded)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_export_ltime_too_large() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    tree_bitmap.insert(
+        &Prefix::from_str("185.49.140.0/22")?,
+        Record::new(
+            1,
+            u64::from(u32::MAX) + 1,
+            RouteStatus::Active,
+            PrefixAs::new_from_u32(100),
+        ),
+        None,
+    )?;
+
+    let mut mrt = vec![];
+    assert!(tree_bitmap.export_mrt(&mut mrt, &peer_index()?).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_export_empty_rib() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let mut peer_index = peer_index()?;
+    peer_index.peers.clear();
+
+    let mut mrt = vec![];
+    assert_eq!(tree_bitmap.export_mrt(&mut mrt, &peer_index)?, 0);
+
+    // Only the PEER_INDEX_TABLE, with a 12 byte header, 4 bytes of
+    // collector id, the view name with its length, and the peer count.
+    assert_eq!(mrt.len(), 12 + 4 + 2 + 4 + 2);
+    let mrt_file = MrtFile::new(&mrt);
+    assert!(mrt_file.pi().map_err(|e| e.to_string())?.is_empty());
+    assert_eq!(
+        mrt_file.rib_entries().map_err(|e| e.to_string())?.count(),
+        0
+    );
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    errors::PrefixStoreError,
+    rib::{config::MemoryOnlyConfig, StarCastRib},
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+use common::record;
+
+#[test]
+fn test_multipath() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let guard = &epoch::pin();
+
+    // For PrefixAs the lowest AS is the best path, and the paths with the
+    // same AS are equivalent.
+    for pfx in ["192.0.2.0/24", "2001:db8::/32"] {
+        let pfx = Prefix::from_str(pfx)?;
+        for (mui, asn) in
+            [(1, 65001), (5, 65000), (2, 65000), (4, 65002), (3, 65000)]
+        {
+            tree_bitmap.insert(&pfx, record(mui, 1, asn), None)?;
+        }
+
+        assert_eq!(
+            tree_bitmap.multipath(&pfx, guard).ok_or("no prefix")?.err(),
+            Some(PrefixStoreError::BestPathNotFound)
+        );
+
+        assert_eq!(
+            tree_bitmap.calculate_and_store_multipath(&pfx, &(), 8, guard)?,
+            vec![2, 3, 5]
+        );
+        assert!(!tree_bitmap.is_multipath_outdated(&pfx, guard)?);
+        assert_eq!(
+            tree_bitmap.calculate_and_store_multipath(&pfx, &(), 2, guard)?,
+            vec![2, 3]
+        );
+        let muis = tree_bitmap
+            .multipath(&pfx, guard)
+            .ok_or("no prefix")??
+            .iter()
+            .map(|r| r.multi_uniq_id)
+            .collect::<Vec<_>>();
+        assert_eq!(muis, vec![2, 3]);
+
+        // Withdrawn records are left out.
+        tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 3, 2)?;
+        let muis = tree_bitmap
+            .multipath(&pfx, guard)
+            .ok_or("no prefix")??
+            .iter()
+            .map(|r| r.multi_uniq_id)
+            .collect::<Vec<_>>();
+        assert_eq!(muis, vec![2]);
+
+        // Inserts make the multipath outdated.
+        tree_bitmap.insert(&pfx, record(6, 1, 64999), None)?;
+        assert!(tree_bitmap.is_multipath_outdated(&pfx, guard)?);
+        assert_eq!(
+            tree_bitmap.calculate_and_store_multipath(&pfx, &(), 8, guard)?,
+            vec![6]
+        );
+        assert!(!tree_bitmap.is_multipath_outdated(&pfx, guard)?);
+
+        // Withdrawn records are no candidates, locally or globally.
+        tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 6, 2)?;
+        assert_eq!(
+            tree_bitmap.calculate_and_store_multipath(&pfx, &(), 8, guard)?,
+            vec![2, 5]
+        );
+        tree_bitmap.mark_mui_as_withdrawn(2)?;
+        let muis = tree_bitmap
+            .multipath(&pfx, guard)
+            .ok_or("no prefix")??
+            .iter()
+            .map(|r| r.multi_uniq_id)
+            .collect::<Vec<_>>();
+        assert_eq!(muis, vec![5]);
+        assert_eq!(
+            tree_bitmap.calculate_and_store_multipath(&pfx, &(), 8, guard)?,
+            vec![5]
+        );
+        tree_bitmap.mark_mui_as_active_v4(2)?;
+        tree_bitmap.mark_mui_as_active_v6(2)?;
+
+        // The best and backup path are tracked separately.
+        assert!(tree_bitmap.is_ps_outdated(&pfx, guard)?);
+    }
+
+    let unknown = Prefix::from_str("198.51.100.0/24")?;
+    assert!(tree_bitmap.multipath(&unknown, guard).is_none());
+    assert_eq!(
+        tree_bitmap
+            .calculate_and_store_multipath(&unknown, &(), 8, guard)
+            .err(),
+        Some(PrefixStoreError::StoreNotReadyError)
+    );
+
+    Ok(())
+}
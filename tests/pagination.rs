@@ -0,0 +1,201 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    rib::{
+        config::{Config, MemoryOnlyConfig},
+        pagination::PageCursor,
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+mod common;
+
+use common::record;
+
+fn insert_prefixes<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    for i in 0..100_u32 {
+        let v4 = Prefix::new(
+            IpAddr::V4(Ipv4Addr::from(0x0a00_0000 | (i << 16))),
+            16 + (i % 5) as u8,
+        )?;
+        let v6 = Prefix::new(
+            IpAddr::V6(Ipv6Addr::from(
+                (0x2001_0db8_u128 << 96) | (i as u128) << 80,
+            )),
+            48,
+        )?;
+        for mui in 0..=(i % 3) {
+            tree_bitmap.insert(&v4, record(mui, 1, mui), None)?;
+            tree_bitmap.insert(&v6, record(mui + 10, 1, mui + 10), None)?;
+        }
+    }
+    tree_bitmap.insert(
+        &Prefix::from_str("192.0.2.0/24")?,
+        record(1, 1, 1),
+        None,
+    )?;
+
+    Ok(())
+}
+
+// All the (prefix, mui) pairs in the order of the pages.
+fn expected<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    search_pfx: Option<Prefix>,
+) -> Result<Vec<(Prefix, u32)>, Box<dyn Error>> {
+    let guard = &epoch::pin();
+    let prefix_records = match search_pfx {
+        None => tree_bitmap
+            .prefixes_iter_sorted(guard)
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(pfx) => tree_bitmap
+            .more_specifics_iter_from_sorted(&pfx, None, true, guard)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let mut pairs = vec![];
+    for pr in prefix_records {
+        let mut muis =
+            pr.meta.iter().map(|r| r.multi_uniq_id).collect::<Vec<_>>();
+        muis.sort();
+        pairs.extend(muis.into_iter().map(|mui| (pr.prefix, mui)));
+    }
+    Ok(pairs)
+}
+
+// Page through the RIB, passing the cursor through its serialized form.
+fn all_pages<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    search_pfx: Option<Prefix>,
+    limit: usize,
+) -> Result<Vec<(Prefix, u32)>, Box<dyn Error>> {
+    let mut pairs = vec![];
+    let mut token: Option<String> = None;
+    loop {
+        let cursor = token
+            .as_deref()
+            .map(serde_json::from_str::<PageCursor>)
+            .transpose()?;
+        let page = match search_pfx {
+            None => tree_bitmap.prefixes_page(cursor.as_ref(), limit)?,
+            Some(pfx) => tree_bitmap.more_specifics_page(
+                &pfx,
+                cursor.as_ref(),
+                limit,
+            )?,
+        };
+        let count =
+            page.records.iter().map(|pr| pr.meta.len()).sum::<usize>();
+        assert!(count <= limit);
+        for pr in page.records {
+            pairs
+                .extend(pr.meta.iter().map(|r| (pr.prefix, r.multi_uniq_id)));
+        }
+        match page.next_cursor {
+            Some(cursor) => {
+                assert_eq!(count, limit);
+                token = Some(serde_json::to_string(&cursor)?);
+            }
+            None => return Ok(pairs),
+        }
+    }
+}
+
+rotonda_store::all_strategies![
+    test_pages_1;
+    test_pages;
+    PrefixAs
+];
+
+fn test_pages<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    insert_prefixes(&tree_bitmap)?;
+
+    let expected_all = expected(&tree_bitmap, None)?;
+    assert_eq!(expected_all.len(), 2 * 199 + 1);
+    for limit in [1, 2, 7, 1000] {
+        assert_eq!(all_pages(&tree_bitmap, None, limit)?, expected_all);
+    }
+
+    for search_pfx in ["10.0.0.0/8", "10.64.0.0/10", "2001:db8::/32"] {
+        let search_pfx = Prefix::from_str(search_pfx)?;
+        let expected = expected(&tree_bitmap, Some(search_pfx))?;
+        assert!(!expected.is_empty());
+        assert!(expected.iter().all(|(p, _)| search_pfx.covers(*p)));
+        for limit in [1, 5, 1000] {
+            assert_eq!(
+                all_pages(&tree_bitmap, Some(search_pfx), limit)?,
+                expected
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pages_with_inserts() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    insert_prefixes(&tree_bitmap)?;
+
+    let first = tree_bitmap.prefixes_page(None, 100)?;
+    let cursor = first.next_cursor.ok_or("no next page")?;
+
+    // Inserts before and after the cursor, and a new mui for the prefix of
+    // the cursor, that sorts after the mui of the cursor.
+    let before = Prefix::from_str("10.0.0.0/8")?;
+    let after = Prefix::from_str("2001:db8:ffff::/48")?;
+    let last_prefix = first.records.last().ok_or("empty page")?.prefix;
+    tree_bitmap.insert(&before, record(1, 1, 1), None)?;
+    tree_bitmap.insert(&after, record(1, 1, 1), None)?;
+    tree_bitmap.insert(&last_prefix, record(100, 1, 100), None)?;
+
+    let mut pairs = first
+        .records
+        .iter()
+        .flat_map(|pr| pr.meta.iter().map(|r| (pr.prefix, r.multi_uniq_id)))
+        .collect::<Vec<_>>();
+    let mut cursor = Some(cursor);
+    while let Some(c) = cursor {
+        let page = tree_bitmap.prefixes_page(Some(&c), 100)?;
+        for pr in page.records {
+            pairs
+                .extend(pr.meta.iter().map(|r| (pr.prefix, r.multi_uniq_id)));
+        }
+        cursor = page.next_cursor;
+    }
+
+    // No duplicates, still in order, and only the inserts after the cursor
+    // show up.
+    let mut expected = expected(&tree_bitmap, None)?;
+    expected.retain(|(p, _)| *p != before);
+    assert_eq!(pairs, expected);
+    assert!(pairs.contains(&(after, 1)));
+    assert!(pairs.contains(&(last_prefix, 100)));
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_cursor() {
+    assert!(serde_json::from_str::<PageCursor>(
+        r#"{"addr":"10.0.0.1","len":8,"mui":1}"#
+    )
+    .is_err());
+    assert!(serde_json::from_str::<PageCursor>(
+        r#"{"addr":"10.0.0.0","len":8,"mui":1}"#
+    )
+    .is_ok());
+}
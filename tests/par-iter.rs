@@ -0,0 +1,94 @@
+#![cfg(feature = "rayon")]
+
+use inetnum::addr::Prefix;
+use rayon::iter::ParallelIterator;
+use rotonda_store::{
+    epoch,
+    prefix_record::{Record, RouteStatus},
+    rib::{config::Config, StarCastRib},
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+mod common;
+
+rotonda_store::all_strategies![
+    test_par_iter_1;
+    test_par_prefixes_iter;
+    PrefixAs
+];
+
+fn test_par_prefixes_iter<C: Config + Sync>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut prefixes = vec![
+        Prefix::from_str("0.0.0.0/0")?,
+        Prefix::from_str("255.255.255.255/32")?,
+        Prefix::from_str("2001:db8::/32")?,
+    ];
+    for i in 0..1000_u32 {
+        let len = 8 + (i % 25) as u8;
+        let addr = i.wrapping_mul(0x9e37_79b9) & (u32::MAX << (32 - len));
+        prefixes.push(Prefix::new(IpAddr::V4(Ipv4Addr::from(addr)), len)?);
+        let len = 16 + (i % 113) as u8;
+        let addr = (u128::from(i.wrapping_mul(0x85eb_ca6b)) << 96)
+            & (u128::MAX << (128 - len));
+        prefixes.push(Prefix::new(IpAddr::V6(Ipv6Addr::from(addr)), len)?);
+    }
+    for (i, pfx) in prefixes.iter().enumerate() {
+        for mui in 0..(i % 3) as u32 + 1 {
+            tree_bitmap.insert(
+                pfx,
+                Record::new(
+                    mui,
+                    1,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(mui),
+                ),
+                None,
+            )?;
+        }
+    }
+    tree_bitmap.mark_mui_as_withdrawn(1)?;
+
+    let mut par = tree_bitmap
+        .par_prefixes_iter()
+        .map(|r| r.map(|p| (p.prefix, p.meta)))
+        .collect::<Result<Vec<_>, _>>()?;
+    par.sort_by_key(|(p, _)| (p.addr(), p.len()));
+
+    let guard = &epoch::pin();
+    let sorted = tree_bitmap
+        .prefixes_iter_sorted(guard)
+        .map(|r| r.map(|p| (p.prefix, p.meta)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(par.len(), sorted.len());
+    for ((par_pfx, par_recs), (pfx, recs)) in par.iter().zip(sorted.iter()) {
+        assert_eq!(par_pfx, pfx);
+        let mut par_recs = par_recs
+            .iter()
+            .map(|r| (r.multi_uniq_id, r.status))
+            .collect::<Vec<_>>();
+        par_recs.sort_by_key(|(mui, _)| *mui);
+        let mut recs = recs
+            .iter()
+            .map(|r| (r.multi_uniq_id, r.status))
+            .collect::<Vec<_>>();
+        recs.sort_by_key(|(mui, _)| *mui);
+        assert_eq!(par_recs, recs);
+        assert!(recs.contains(&(0, RouteStatus::Active)));
+    }
+    assert!(par.iter().any(|(p, _)| p.len() == 0));
+    assert!(par
+        .iter()
+        .flat_map(|(_, recs)| recs)
+        .any(|r| r.multi_uniq_id == 1 && r.status == RouteStatus::Withdrawn));
+
+    Ok(())
+}
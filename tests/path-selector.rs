@@ -0,0 +1,193 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    prefix_record::RouteStatus,
+    rib::{
+        config::{Config, MemoryOnlyConfig, MetaConfig, WriteAheadConfig},
+        path_selection::{PathCandidate, PathSelector},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+use common::record;
+
+// Create a fresh, stable store directory for a test.
+fn store_dir(name: &str) -> String {
+    let dir =
+        format!("/tmp/rotonda/selector-{}-{}", name, std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// Prefers the most recently changed active record, and never selects a
+// withdrawn one.
+struct NewestActive;
+
+impl PathSelector<PrefixAs> for NewestActive {
+    fn best_backup(
+        &self,
+        candidates: &[PathCandidate<'_, PrefixAs>],
+        _tbi: (),
+    ) -> (Option<u32>, Option<u32>) {
+        let mut active = candidates
+            .iter()
+            .filter(|c| c.status == RouteStatus::Active)
+            .collect::<Vec<_>>();
+        active.sort_by_key(|c| std::cmp::Reverse(c.ltime));
+        (
+            active.first().map(|c| c.multi_uniq_id),
+            active.get(1).map(|c| c.multi_uniq_id),
+        )
+    }
+}
+
+#[test]
+fn test_default_path_selector() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let guard = &epoch::pin();
+
+    // The lowest AS is the best path, whatever the status of the records.
+    for pfx in ["192.0.2.0/24", "2001:db8::/32"] {
+        let pfx = Prefix::from_str(pfx)?;
+        for (mui, ltime, asn) in [(1, 3, 65002), (2, 2, 65000), (3, 1, 65001)]
+        {
+            tree_bitmap.insert(&pfx, record(mui, ltime, asn), None)?;
+        }
+        tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 2, 4)?;
+        assert_eq!(
+            tree_bitmap.calculate_and_store_best_and_backup_path(
+                &pfx,
+                &(),
+                guard
+            )?,
+            (Some(2), Some(3))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_path_selector() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_path_selector(NewestActive);
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    let guard = &epoch::pin();
+
+    for pfx in ["192.0.2.0/24", "2001:db8::/32"] {
+        let pfx = Prefix::from_str(pfx)?;
+        for (mui, ltime, asn) in [(1, 3, 65002), (2, 2, 65000), (3, 1, 65001)]
+        {
+            tree_bitmap.insert(&pfx, record(mui, ltime, asn), None)?;
+        }
+        assert_eq!(
+            tree_bitmap.calculate_and_store_best_and_backup_path(
+                &pfx,
+                &(),
+                guard
+            )?,
+            (Some(1), Some(2))
+        );
+        assert_eq!(
+            tree_bitmap
+                .best_path(&pfx, guard)
+                .ok_or("no prefix")??
+                .multi_uniq_id,
+            1
+        );
+
+        // Records that are withdrawn, locally or globally, are skipped.
+        tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 4)?;
+        assert_eq!(
+            tree_bitmap.calculate_and_store_best_and_backup_path(
+                &pfx,
+                &(),
+                guard
+            )?,
+            (Some(2), Some(3))
+        );
+        tree_bitmap.mark_mui_as_withdrawn(2)?;
+        assert_eq!(
+            tree_bitmap.calculate_and_store_best_and_backup_path(
+                &pfx,
+                &(),
+                guard
+            )?,
+            (Some(3), None)
+        );
+        tree_bitmap.mark_mui_as_active_v4(2)?;
+        tree_bitmap.mark_mui_as_active_v6(2)?;
+
+        // Inserts that update the path selections use the selector too.
+        tree_bitmap.insert(&pfx, record(3, 5, 65001), Some(()))?;
+        assert!(!tree_bitmap.is_ps_outdated(&pfx, guard)?);
+        assert_eq!(
+            tree_bitmap
+                .best_path(&pfx, guard)
+                .ok_or("no prefix")??
+                .multi_uniq_id,
+            3
+        );
+
+        // The multipath starts with the best path of the selector.
+        tree_bitmap.insert(&pfx, record(4, 1, 65001), None)?;
+        assert_eq!(
+            tree_bitmap.calculate_and_store_multipath(&pfx, &(), 8, guard)?,
+            vec![3, 4]
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_path_selector_reopen() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(store_dir("reopen"));
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_path_selector(NewestActive);
+
+    let pfx = Prefix::from_str("192.0.2.0/24")?;
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create_with_meta_config(
+            config.clone(),
+            meta_config.clone(),
+        )?;
+    for (mui, ltime, asn) in [(1, 3, 65002), (2, 2, 65000), (3, 1, 65001)] {
+        tree_bitmap.insert(&pfx, record(mui, ltime, asn), None)?;
+    }
+    tree_bitmap.flush_to_disk()?;
+    drop(tree_bitmap);
+
+    // A reopened RIB uses the selector of its configuration.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open_with_meta_config(
+        config,
+        meta_config,
+    )?;
+    assert_eq!(
+        tree_bitmap.calculate_and_store_best_and_backup_path(
+            &pfx,
+            &(),
+            &epoch::pin()
+        )?,
+        (Some(1), Some(2))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    match_options::{IncludeHistory, MatchOptions, MatchType},
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{
+            Config, MemoryOnlyConfig, PersistOnlyConfig, WriteAheadConfig,
+        },
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+// Create a fresh, stable store directory for a test.
+fn store_dir(name: &str) -> String {
+    let dir = format!("/tmp/rotonda/reopen-{}-{}", name, std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn prefixes() -> Result<Vec<Prefix>, Box<dyn Error>> {
+    Ok(vec![
+        Prefix::from_str("185.34.0.0/16")?,
+        Prefix::from_str("185.34.10.0/24")?,
+        Prefix::from_str("185.34.11.0/24")?,
+        Prefix::from_str("2a04:b900::/29")?,
+        Prefix::from_str("2a04:b907::/48")?,
+    ])
+}
+
+fn insert_and_flush<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    for (i, pfx) in prefixes()?.iter().enumerate() {
+        for mui in [1, 2] {
+            tree_bitmap.insert(
+                pfx,
+                Record::new(
+                    mui,
+                    i as u64,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(mui * 1000 + i as u32),
+                ),
+                None,
+            )?;
+        }
+    }
+    tree_bitmap.flush_to_disk()?;
+
+    Ok(())
+}
+
+fn assert_restored<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    let pfxs = prefixes()?;
+
+    assert_eq!(tree_bitmap.prefixes_count().total(), pfxs.len());
+    assert_eq!(tree_bitmap.routes_count().total(), pfxs.len() * 2);
+    assert_eq!(tree_bitmap.prefixes_v4_count_for_len(24)?.total(), 2);
+
+    for (i, pfx) in pfxs.iter().enumerate() {
+        assert!(tree_bitmap.contains(pfx, None));
+        assert!(tree_bitmap.contains(pfx, Some(1)));
+        assert!(tree_bitmap.contains(pfx, Some(2)));
+        assert!(!tree_bitmap.contains(pfx, Some(3)));
+
+        let recs = tree_bitmap
+            .get_records_for_prefix(pfx, Some(2), false)?
+            .unwrap_or_default();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].multi_uniq_id, 2);
+        assert_eq!(recs[0].meta, PrefixAs::new_from_u32(2000 + i as u32));
+    }
+
+    // The per-node mui bitmaps should be rebuilt as well.
+    let guard = &rotonda_store::epoch::pin();
+    assert_eq!(
+        tree_bitmap
+            .iter_records_for_mui_v4(1, false, guard)
+            .collect::<Result<Vec<_>, _>>()?
+            .len(),
+        3
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_reopen_persist_only() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = PersistOnlyConfig::default();
+    config.set_persist_path(store_dir("persist-only"));
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    insert_and_flush(&tree_bitmap)?;
+    drop(tree_bitmap);
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_restored(&tree_bitmap)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_reopen_write_ahead() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(store_dir("write-ahead"));
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    insert_and_flush(&tree_bitmap)?;
+    drop(tree_bitmap);
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_restored(&tree_bitmap)?;
+
+    // The most recent records are back in memory.
+    assert_eq!(tree_bitmap.prefixes_count().in_memory(), 5);
+
+    Ok(())
+}
+
+#[test]
+fn test_reopen_write_ahead_withdrawn() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(store_dir("write-ahead-withdrawn"));
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+    insert_and_flush(&tree_bitmap)?;
+    let pfx = Prefix::from_str("185.34.10.0/24")?;
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 2, 10)?;
+    tree_bitmap.flush_to_disk()?;
+    drop(tree_bitmap);
+
+    // The withdrawal is persisted as a record without metadata, the route
+    // is restored with the metadata of the record before it.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_eq!(tree_bitmap.routes_count().total(), 10);
+    assert!(tree_bitmap.contains(&pfx, Some(2)));
+
+    let guard = &epoch::pin();
+    let mut options = MatchOptions {
+        match_type: MatchType::ExactMatch,
+        include_withdrawn: true,
+        include_less_specifics: false,
+        include_more_specifics: false,
+        mui: None,
+        include_history: IncludeHistory::None,
+    };
+    let res = tree_bitmap.match_prefix(&pfx, &options, guard)?;
+    let mut records = res
+        .records
+        .iter()
+        .map(|r| (r.multi_uniq_id, r.ltime, r.status, r.meta))
+        .collect::<Vec<_>>();
+    records.sort_by_key(|r| r.0);
+    assert_eq!(
+        records,
+        vec![
+            (1, 1, RouteStatus::Active, PrefixAs::new_from_u32(1001)),
+            (2, 10, RouteStatus::Withdrawn, PrefixAs::new_from_u32(2001)),
+        ]
+    );
+
+    options.include_withdrawn = false;
+    let res = tree_bitmap.match_prefix(&pfx, &options, guard)?;
+    assert_eq!(res.records.len(), 1);
+    assert_eq!(res.records[0].multi_uniq_id, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_open_missing_store() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = PersistOnlyConfig::default();
+    config.set_persist_path(store_dir("missing"));
+    assert!(StarCastRib::<PrefixAs, _>::open(config).is_err());
+
+    assert!(StarCastRib::<PrefixAs, _>::open(MemoryOnlyConfig).is_err());
+    assert!(
+        StarCastRib::<PrefixAs, _>::open_or_create(MemoryOnlyConfig).is_err()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    errors::PrefixStoreError,
+    match_options::RangeMatch,
+    prefix_record::{Record, RouteStatus},
+    rib::{config::Config, StarCastRib},
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+mod common;
+
+fn prefixes_in_range<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    start: &str,
+    end: &str,
+    range_match: RangeMatch,
+    mui: Option<u32>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let guard = &epoch::pin();
+    let mut prefixes = tree_bitmap
+        .prefixes_in_range(
+            IpAddr::from_str(start)?,
+            IpAddr::from_str(end)?,
+            range_match,
+            mui,
+            false,
+            guard,
+        )?
+        .map(|r| r.map(|p| p.prefix.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    prefixes.sort();
+    Ok(prefixes)
+}
+
+rotonda_store::all_strategies![
+    test_range_1;
+    test_prefixes_in_range;
+    PrefixAs
+];
+
+fn test_prefixes_in_range<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    for (pfx, mui) in [
+        // Covering the whole range.
+        ("10.0.0.0/8", 1),
+        // Partly overlapping the start of the range.
+        ("10.0.3.0/24", 1),
+        ("10.0.3.16/28", 2),
+        // Contained in the range.
+        ("10.0.3.32/27", 1),
+        ("10.0.4.0/22", 2),
+        ("10.0.9.0/25", 1),
+        // Partly overlapping the end of the range.
+        ("10.0.8.0/23", 1),
+        // Outside of the range.
+        ("10.0.3.0/28", 1),
+        ("10.0.10.0/24", 1),
+        ("11.0.0.0/8", 1),
+        ("2a04:b900::/29", 1),
+    ] {
+        tree_bitmap.insert(
+            &Prefix::from_str(pfx)?,
+            Record::new(
+                mui,
+                1,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(mui),
+            ),
+            None,
+        )?;
+    }
+
+    assert_eq!(
+        prefixes_in_range(
+            &tree_bitmap,
+            "10.0.3.17",
+            "10.0.9.200",
+            RangeMatch::Overlapping,
+            None
+        )?,
+        vec![
+            "10.0.0.0/8",
+            "10.0.3.0/24",
+            "10.0.3.16/28",
+            "10.0.3.32/27",
+            "10.0.4.0/22",
+            "10.0.8.0/23",
+            "10.0.9.0/25",
+        ]
+    );
+    assert_eq!(
+        prefixes_in_range(
+            &tree_bitmap,
+            "10.0.3.17",
+            "10.0.9.200",
+            RangeMatch::Contained,
+            None
+        )?,
+        vec!["10.0.3.32/27", "10.0.4.0/22", "10.0.9.0/25"]
+    );
+    assert_eq!(
+        prefixes_in_range(
+            &tree_bitmap,
+            "10.0.3.17",
+            "10.0.9.200",
+            RangeMatch::Overlapping,
+            Some(2)
+        )?,
+        vec!["10.0.3.16/28", "10.0.4.0/22"]
+    );
+
+    // A range that is exactly one prefix.
+    assert_eq!(
+        prefixes_in_range(
+            &tree_bitmap,
+            "10.0.4.0",
+            "10.0.7.255",
+            RangeMatch::Contained,
+            None
+        )?,
+        vec!["10.0.4.0/22"]
+    );
+
+    // A single address.
+    assert_eq!(
+        prefixes_in_range(
+            &tree_bitmap,
+            "10.0.3.20",
+            "10.0.3.20",
+            RangeMatch::Overlapping,
+            None
+        )?,
+        vec!["10.0.0.0/8", "10.0.3.0/24", "10.0.3.16/28"]
+    );
+
+    assert_eq!(
+        prefixes_in_range(
+            &tree_bitmap,
+            "2a04:b900::",
+            "2a04:b907:ffff:ffff:ffff:ffff:ffff:ffff",
+            RangeMatch::Contained,
+            None
+        )?,
+        vec!["2a04:b900::/29"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_range() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(
+        rotonda_store::rib::config::MemoryOnlyConfig,
+    )?;
+    let guard = &epoch::pin();
+
+    for (start, end) in
+        [("10.0.9.200", "10.0.3.17"), ("10.0.3.17", "2a04:b900::")]
+    {
+        assert_eq!(
+            tree_bitmap
+                .prefixes_in_range(
+                    IpAddr::from_str(start)?,
+                    IpAddr::from_str(end)?,
+                    RangeMatch::Overlapping,
+                    None,
+                    false,
+                    guard,
+                )
+                .err(),
+            Some(PrefixStoreError::InvalidRange)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, PersistStrategy},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+mod common;
+
+fn v4_prefixes(second_octet: u8) -> Vec<Prefix> {
+    (0..=255_u8)
+        .flat_map(|i| {
+            [
+                Prefix::new(Ipv4Addr::new(10, second_octet, i, 0).into(), 24),
+                Prefix::new(
+                    Ipv4Addr::new(10, second_octet, i, 128).into(),
+                    25,
+                ),
+            ]
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+rotonda_store::all_strategies![
+    test_purge_1;
+    test_purge_mui;
+    PrefixAs
+];
+
+fn test_purge_mui<C: Config + Sync>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let shared = v4_prefixes(1);
+    let pfx_v6 = Prefix::new(
+        Ipv6Addr::new(0x2a04, 0xb900, 0, 0, 0, 0, 0, 0).into(),
+        29,
+    )?;
+
+    for pfx in &shared {
+        for mui in [1, 2] {
+            tree_bitmap.insert(
+                pfx,
+                Record::new(
+                    mui,
+                    1,
+                    RouteStatus::Active,
+                    PrefixAs::new_from_u32(mui),
+                ),
+                None,
+            )?;
+        }
+    }
+    // Some prefixes only mui 1 has, and a second version of one of them.
+    let only_mui_1 = v4_prefixes(2);
+    for (ltime, pfx) in [(1, &only_mui_1[0])]
+        .into_iter()
+        .chain(only_mui_1.iter().map(|p| (2, p)))
+        .chain([(1, &pfx_v6)])
+    {
+        tree_bitmap.insert(
+            pfx,
+            Record::new(
+                1,
+                ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(1),
+            ),
+            None,
+        )?;
+    }
+    tree_bitmap.mark_mui_as_withdrawn(1)?;
+
+    assert_eq!(
+        tree_bitmap.prefixes_count().total(),
+        shared.len() + only_mui_1.len() + 1
+    );
+
+    // Insert mui 3 from another thread, while purging mui 1.
+    let for_mui_3 = v4_prefixes(3);
+    let count = std::thread::scope(|s| {
+        s.spawn(|| {
+            for pfx in &for_mui_3 {
+                tree_bitmap
+                    .insert(
+                        pfx,
+                        Record::new(
+                            3,
+                            1,
+                            RouteStatus::Active,
+                            PrefixAs::new_from_u32(3),
+                        ),
+                        None,
+                    )
+                    .unwrap();
+            }
+        });
+        tree_bitmap.purge_mui(1)
+    })?;
+
+    // The strategies that keep history also removed the older version.
+    let history = match tree_bitmap.persist_strategy() {
+        PersistStrategy::WriteAhead | PersistStrategy::PersistHistory => 1,
+        _ => 0,
+    };
+    assert_eq!(count, shared.len() + only_mui_1.len() + 1 + history);
+    assert!(!tree_bitmap.mui_is_withdrawn_v4(1));
+    assert!(!tree_bitmap.mui_is_withdrawn_v6(1));
+
+    assert_eq!(
+        tree_bitmap.prefixes_count().total(),
+        shared.len() + for_mui_3.len()
+    );
+    assert_eq!(
+        tree_bitmap.routes_count().total(),
+        shared.len() + for_mui_3.len()
+    );
+    assert!(!tree_bitmap.contains(&only_mui_1[0], None));
+    assert!(!tree_bitmap.contains(&pfx_v6, None));
+
+    let guard = &epoch::pin();
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(1, true, guard).count(),
+        0
+    );
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v6(1, true, guard).count(),
+        0
+    );
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(2, false, guard).count(),
+        shared.len()
+    );
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(3, false, guard).count(),
+        for_mui_3.len()
+    );
+    for pfx in &shared {
+        assert!(!tree_bitmap.contains(pfx, Some(1)));
+        assert!(tree_bitmap.contains(pfx, Some(2)));
+    }
+
+    if tree_bitmap.persist_strategy() != PersistStrategy::MemoryOnly {
+        tree_bitmap.flush_to_disk()?;
+        assert!(tree_bitmap.persist_prefixes_iter().all(|r| r
+            .unwrap()
+            .meta
+            .iter()
+            .all(|r| r.multi_uniq_id != 1)));
+    }
+
+    // Nothing left to purge.
+    assert_eq!(tree_bitmap.purge_mui(1)?, 0);
+
+    Ok(())
+}
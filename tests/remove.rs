@@ -0,0 +1,243 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    errors::PrefixStoreError,
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, PersistStrategy},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+rotonda_store::all_strategies![
+    test_remove_1;
+    test_remove;
+    PrefixAs
+];
+
+fn test_remove<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let more_specific = Prefix::from_str("185.49.140.0/24")?;
+    let pfx_v6 = Prefix::from_str("2a04:b900::/29")?;
+
+    for (p, mui, ltime) in [
+        (pfx, 1, 10),
+        (pfx, 1, 20),
+        (pfx, 2, 10),
+        (more_specific, 2, 10),
+        (pfx_v6, 1, 10),
+    ] {
+        tree_bitmap.insert(
+            &p,
+            Record::new(
+                mui,
+                ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(mui),
+            ),
+            None,
+        )?;
+    }
+    if tree_bitmap.persist_strategy() != PersistStrategy::MemoryOnly {
+        tree_bitmap.flush_to_disk()?;
+    }
+
+    assert_eq!(tree_bitmap.prefixes_count().total(), 3);
+    assert_eq!(tree_bitmap.routes_count().total(), 4);
+
+    // Nothing to remove for this combination.
+    assert!(matches!(
+        tree_bitmap.remove(&pfx, 3),
+        Err(PrefixStoreError::PrefixNotFound)
+    ));
+
+    tree_bitmap.remove(&pfx, 1)?;
+
+    assert!(tree_bitmap.contains(&pfx, None));
+    assert!(!tree_bitmap.contains(&pfx, Some(1)));
+    assert!(tree_bitmap.contains(&pfx, Some(2)));
+    assert!(tree_bitmap.contains(&pfx_v6, Some(1)));
+    assert_eq!(tree_bitmap.prefixes_count().total(), 3);
+    assert_eq!(tree_bitmap.routes_count().total(), 3);
+
+    let recs = tree_bitmap
+        .get_records_for_prefix(&pfx, None, false)?
+        .unwrap_or_default();
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].multi_uniq_id, 2);
+
+    let guard = &epoch::pin();
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(1, false, guard).count(),
+        0
+    );
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v6(1, false, guard).count(),
+        1
+    );
+
+    // The last mui for a prefix removes the prefix as well.
+    assert_eq!(tree_bitmap.remove_prefix(&pfx)?, 1);
+    assert!(!tree_bitmap.contains(&pfx, None));
+    assert!(tree_bitmap.contains(&more_specific, Some(2)));
+    assert_eq!(tree_bitmap.prefixes_count().total(), 2);
+    assert_eq!(tree_bitmap.prefixes_v4_count_for_len(22)?.total(), 0);
+    assert_eq!(tree_bitmap.routes_count().total(), 2);
+    assert!(matches!(
+        tree_bitmap.remove_prefix(&pfx),
+        Err(PrefixStoreError::PrefixNotFound)
+    ));
+
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(2, false, guard).count(),
+        1
+    );
+
+    tree_bitmap.remove(&more_specific, 2)?;
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(2, false, guard).count(),
+        0
+    );
+    assert_eq!(tree_bitmap.prefixes_count().total(), 1);
+
+    match tree_bitmap.persist_strategy() {
+        PersistStrategy::MemoryOnly => {}
+        _ => {
+            tree_bitmap.flush_to_disk()?;
+            assert_eq!(tree_bitmap.persist_prefixes_iter_v4().count(), 0);
+        }
+    }
+
+    // A removed prefix can be inserted again.
+    tree_bitmap.insert(
+        &pfx,
+        Record::new(1, 30, RouteStatus::Active, PrefixAs::new_from_u32(1)),
+        None,
+    )?;
+    assert!(tree_bitmap.contains(&pfx, Some(1)));
+    assert_eq!(tree_bitmap.prefixes_count().total(), 2);
+    assert_eq!(tree_bitmap.routes_count().total(), 2);
+    let recs = tree_bitmap
+        .get_records_for_prefix(&pfx, None, false)?
+        .unwrap_or_default();
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].ltime, 30);
+
+    Ok(())
+}
+
+rotonda_store::all_strategies![
+    test_remove_2;
+    test_remove_from_index;
+    PrefixAs
+];
+
+// The mui index of a node covers the child nodes, several strides down.
+fn test_remove_from_index<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let less_specific = Prefix::from_str("10.0.0.0/8")?;
+    let more_specific = Prefix::from_str("10.1.2.128/25")?;
+    let other = Prefix::from_str("10.1.0.0/16")?;
+    for (p, mui) in [(less_specific, 1), (more_specific, 1), (other, 2)] {
+        tree_bitmap.insert(
+            &p,
+            Record::new(
+                mui,
+                10,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(mui),
+            ),
+            None,
+        )?;
+    }
+
+    let guard = &epoch::pin();
+    let records =
+        |mui| tree_bitmap.iter_records_for_mui_v4(mui, false, guard);
+
+    tree_bitmap.remove(&less_specific, 1)?;
+    assert_eq!(records(1).count(), 1);
+    tree_bitmap.remove(&more_specific, 1)?;
+    assert_eq!(records(1).count(), 0);
+    assert_eq!(records(2).count(), 1);
+
+    tree_bitmap.insert(
+        &more_specific,
+        Record::new(1, 20, RouteStatus::Active, PrefixAs::new_from_u32(1)),
+        None,
+    )?;
+    assert_eq!(records(1).count(), 1);
+
+    Ok(())
+}
+
+rotonda_store::all_strategies![
+    test_remove_3;
+    test_remove_concurrent_insert;
+    PrefixAs
+];
+
+// Removing prefixes for a mui, while other prefixes for that mui are
+// inserted in the same sub-trees, keeps the mui in the indexes of the nodes
+// for the inserted prefixes.
+fn test_remove_concurrent_insert<C: Config + Sync>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let prefixes = |thread: u8| {
+        (0..64_u8)
+            .map(|i| Prefix::from_str(&format!("10.{}.{}.0/24", i, thread)))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let removed = prefixes(0)?;
+    let inserted = prefixes(1)?;
+    let record = |ltime| {
+        Record::new(1, ltime, RouteStatus::Active, PrefixAs::new_from_u32(1))
+    };
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for ltime in 0..32 {
+                for pfx in &removed {
+                    tree_bitmap.insert(pfx, record(ltime), None).unwrap();
+                }
+                for pfx in &removed {
+                    tree_bitmap.remove(pfx, 1).unwrap();
+                }
+            }
+        });
+        s.spawn(|| {
+            for ltime in 0..32 {
+                for pfx in &inserted {
+                    tree_bitmap.remove(pfx, 1).ok();
+                }
+                for pfx in &inserted {
+                    tree_bitmap.insert(pfx, record(ltime), None).unwrap();
+                }
+            }
+        });
+    });
+
+    let guard = &epoch::pin();
+    assert_eq!(
+        tree_bitmap.iter_records_for_mui_v4(1, false, guard).count(),
+        inserted.len()
+    );
+    assert_eq!(tree_bitmap.prefixes_count().total(), inserted.len());
+
+    Ok(())
+}
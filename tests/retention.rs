@@ -0,0 +1,268 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{
+            Config, MemoryOnlyConfig, PersistHistoryConfig, RetentionPolicy,
+            WriteAheadConfig,
+        },
+        StarCastRib,
+    },
+    stats::PruneReport,
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn insert_versions<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    pfx: &Prefix,
+    mui: u32,
+    ltimes: &[u64],
+) -> Result<(), Box<dyn Error>> {
+    for ltime in ltimes {
+        tree_bitmap.insert(
+            pfx,
+            Record::new(
+                mui,
+                *ltime,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(*ltime as u32),
+            ),
+            None,
+        )?;
+    }
+
+    Ok(())
+}
+
+// The (ltime, status) of the persisted historical records for a mui.
+fn history<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    mui: u32,
+) -> Result<Vec<(u64, RouteStatus)>, Box<dyn Error>> {
+    Ok(tree_bitmap
+        .history_for_mui(mui, ..)
+        .map(|r| r.map(|(_, rec)| (rec.ltime, rec.status)))
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+#[test]
+fn test_max_versions() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_retention_policy(Some(RetentionPolicy {
+        max_versions: Some(2),
+        ..Default::default()
+    }));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(config)?;
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let pfx_v6 = Prefix::from_str("2a04:b900::/29")?;
+    insert_versions(&tree_bitmap, &pfx, 1, &[1, 2, 3, 4, 5])?;
+    insert_versions(&tree_bitmap, &pfx, 2, &[1])?;
+    insert_versions(&tree_bitmap, &pfx_v6, 1, &[6, 7, 8])?;
+    tree_bitmap.flush_to_disk()?;
+
+    let report = tree_bitmap.prune_history()?;
+    assert_eq!(report.removed_records, 4);
+    assert!(report.reclaimed_bytes > 0);
+    assert_eq!(
+        history(&tree_bitmap, 1)?,
+        vec![
+            (4, RouteStatus::Active),
+            (5, RouteStatus::Active),
+            (7, RouteStatus::Active),
+            (8, RouteStatus::Active)
+        ]
+    );
+    assert_eq!(history(&tree_bitmap, 2)?, vec![(1, RouteStatus::Active)]);
+
+    // The current records are untouched.
+    let recs = tree_bitmap
+        .get_records_for_prefix(&pfx, Some(1), false)?
+        .unwrap_or_default();
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].ltime, 5);
+
+    // Nothing left to prune.
+    assert_eq!(tree_bitmap.prune_history()?, PruneReport::default());
+
+    Ok(())
+}
+
+#[test]
+fn test_keep_status_change_target() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let dir = format!("/tmp/rotonda/retention-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(dir);
+    config.set_retention_policy(Some(RetentionPolicy {
+        max_versions: Some(1),
+        ..Default::default()
+    }));
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    insert_versions(&tree_bitmap, &pfx, 1, &[5, 10])?;
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 20)?;
+
+    // The version at 10 holds the metadata for the status change at 20.
+    assert_eq!(tree_bitmap.prune_history()?.removed_records, 1);
+    assert_eq!(
+        history(&tree_bitmap, 1)?,
+        vec![(10, RouteStatus::Active), (20, RouteStatus::Withdrawn)]
+    );
+    tree_bitmap.shutdown()?;
+    drop(tree_bitmap);
+
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    let recs = tree_bitmap
+        .get_records_for_prefix(&pfx, Some(1), true)?
+        .unwrap_or_default();
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].status, RouteStatus::Withdrawn);
+    assert_eq!(recs[0].meta, PrefixAs::new_from_u32(10));
+
+    Ok(())
+}
+
+#[test]
+fn test_max_age_in_background() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = PersistHistoryConfig::default();
+    config.set_retention_policy(Some(RetentionPolicy {
+        max_age: Some(10),
+        max_versions: None,
+        prune_interval: Some(Duration::from_millis(20)),
+    }));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(config)?;
+
+    // For this strategy the current record, at 40, is not persisted, so the
+    // age is relative to 30.
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    insert_versions(&tree_bitmap, &pfx, 1, &[1, 5, 20, 30, 40])?;
+
+    let start = Instant::now();
+    while history(&tree_bitmap, 1)?.len() > 2 {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(
+        history(&tree_bitmap, 1)?,
+        vec![(20, RouteStatus::Active), (30, RouteStatus::Active)]
+    );
+    tree_bitmap.shutdown()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_reclaims_disk_space() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let dir = format!("/tmp/rotonda/retention-space-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(dir);
+    config.set_retention_policy(Some(RetentionPolicy {
+        max_versions: Some(1),
+        ..Default::default()
+    }));
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+
+    let prefixes = (0..500_u32)
+        .map(|i| Prefix::new(Ipv4Addr::from(0x0a00_0000 | i << 8).into(), 24))
+        .collect::<Result<Vec<_>, _>>()?;
+    for pfx in &prefixes {
+        insert_versions(&tree_bitmap, pfx, 1, &[1, 2, 3, 4])?;
+    }
+    tree_bitmap.flush_to_disk()?;
+    let full = tree_bitmap.disk_space();
+
+    let report = tree_bitmap.prune_history()?;
+    assert_eq!(report.removed_records, 3 * prefixes.len());
+
+    // Enough flushes for the segments with the pruned records and the
+    // tombstones to be compacted.
+    let other = Prefix::from_str("192.0.2.0/24")?;
+    for ltime in 0..4 {
+        tree_bitmap.flush_to_disk()?;
+        insert_versions(&tree_bitmap, &other, 2, &[ltime])?;
+    }
+    tree_bitmap.shutdown()?;
+    assert!(
+        tree_bitmap.disk_space() < full,
+        "{} >= {}",
+        tree_bitmap.disk_space(),
+        full
+    );
+    drop(tree_bitmap);
+
+    // The pruned records stay gone.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open(config)?;
+    assert_eq!(
+        history(&tree_bitmap, 1)?,
+        vec![(4, RouteStatus::Active); prefixes.len()]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_zero_prune_interval() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_retention_policy(Some(RetentionPolicy {
+        max_versions: Some(1),
+        prune_interval: Some(Duration::ZERO),
+        ..Default::default()
+    }));
+    assert!(StarCastRib::<PrefixAs, _>::new_with_config(config).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_no_history_to_prune() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    insert_versions(
+        &tree_bitmap,
+        &Prefix::from_str("185.49.140.0/22")?,
+        1,
+        &[1, 2],
+    )?;
+    assert_eq!(tree_bitmap.prune_history()?, PruneReport::default());
+
+    // Without a retention policy nothing is removed.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_config(
+        WriteAheadConfig::default(),
+    )?;
+    insert_versions(
+        &tree_bitmap,
+        &Prefix::from_str("185.49.140.0/22")?,
+        1,
+        &[1, 2],
+    )?;
+    assert_eq!(tree_bitmap.prune_history()?, PruneReport::default());
+    assert_eq!(history(&tree_bitmap, 1)?.len(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use inetnum::addr::Prefix;
+use inetnum::asn::Asn;
+use rotonda_store::{
+    epoch,
+    errors::PrefixStoreError,
+    match_options::{IncludeHistory, MatchOptions, MatchType},
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::Config,
+        rov::{RovStatus, Vrp, VrpTable},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+const VRPS_CSV: &str = "\
+ASN,IP Prefix,Max Length,Trust Anchor,Expires
+AS65000,192.0.2.0/24,24,ripe,1700000000
+AS65001,198.51.100.0/22,24,arin,1700000000
+AS0,203.0.113.0/24,24,apnic,1700000000
+AS65002,2001:db8::/32,48,ripe,1700000000
+AS65002,2001:db8::/32,48,ripe,1700000000
+";
+
+const VRPS_JSON: &str = r#"{
+    "metadata": { "generated": 1700000000 },
+    "roas": [
+        { "asn": 65000, "prefix": "192.0.2.0/24", "maxLength": 24,
+          "ta": "ripe", "expires": 1700000000 },
+        { "asn": "AS65001", "prefix": "198.51.100.0/22", "maxLength": 24,
+          "ta": "arin" },
+        { "asn": "AS0", "prefix": "203.0.113.0/24", "maxLength": 24,
+          "ta": "apnic" },
+        { "asn": 65002, "prefix": "2001:db8::/32", "maxLength": 48,
+          "ta": "ripe" }
+    ]
+}"#;
+
+fn validate(
+    vrps: &VrpTable,
+    prefix: &str,
+    asn: u32,
+) -> Result<RovStatus, Box<dyn Error>> {
+    Ok(vrps.validate(&Prefix::from_str(prefix)?, Asn::from_u32(asn)))
+}
+
+#[test]
+fn test_validate() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    for vrps in [
+        VrpTable::from_csv_reader(VRPS_CSV.as_bytes())?,
+        VrpTable::from_json_reader(VRPS_JSON.as_bytes())?,
+    ] {
+        assert_eq!(vrps.len(), 4);
+
+        assert_eq!(validate(&vrps, "192.0.2.0/24", 65000)?, RovStatus::Valid);
+        assert_eq!(
+            validate(&vrps, "192.0.2.0/25", 65000)?,
+            RovStatus::Invalid
+        );
+        assert_eq!(
+            validate(&vrps, "192.0.2.0/24", 65001)?,
+            RovStatus::Invalid
+        );
+        assert_eq!(
+            validate(&vrps, "198.51.101.0/24", 65001)?,
+            RovStatus::Valid
+        );
+        assert_eq!(
+            validate(&vrps, "198.51.100.0/22", 65001)?,
+            RovStatus::Valid
+        );
+        // A less-specific of a VRP is not covered by it.
+        assert_eq!(
+            validate(&vrps, "198.51.96.0/19", 65001)?,
+            RovStatus::NotFound
+        );
+        assert_eq!(validate(&vrps, "203.0.113.0/24", 0)?, RovStatus::Invalid);
+        assert_eq!(
+            validate(&vrps, "2001:db8:1::/48", 65002)?,
+            RovStatus::Valid
+        );
+        assert_eq!(
+            validate(&vrps, "2001:db8:1::/64", 65002)?,
+            RovStatus::Invalid
+        );
+        assert_eq!(
+            validate(&vrps, "10.0.0.0/8", 65000)?,
+            RovStatus::NotFound
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_more_vrps() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut vrps = VrpTable::new()?;
+    assert!(vrps.is_empty());
+    let vrp = |prefix: &str, max_len: u8, asn: u32| {
+        Prefix::from_str(prefix).map(|prefix| Vrp {
+            prefix,
+            max_len,
+            asn: Asn::from_u32(asn),
+        })
+    };
+
+    // A more specific VRP for another origin, and a VRP for AS0 that
+    // covers everything.
+    vrps.insert(vrp("10.0.0.0/8", 24, 65000)?)?;
+    vrps.insert(vrp("10.1.0.0/16", 16, 65001)?)?;
+    vrps.insert(vrp("0.0.0.0/0", 32, 0)?)?;
+    assert_eq!(vrps.len(), 3);
+
+    assert_eq!(validate(&vrps, "10.1.0.0/16", 65000)?, RovStatus::Valid);
+    assert_eq!(validate(&vrps, "10.1.0.0/16", 65001)?, RovStatus::Valid);
+    assert_eq!(validate(&vrps, "10.1.0.0/17", 65001)?, RovStatus::Invalid);
+    assert_eq!(validate(&vrps, "11.0.0.0/8", 65000)?, RovStatus::Invalid);
+    assert_eq!(validate(&vrps, "2001:db8::/32", 1)?, RovStatus::NotFound);
+
+    assert_eq!(
+        vrps.insert(vrp("10.0.0.0/8", 7, 65000)?),
+        Err(PrefixStoreError::PrefixLengthInvalid)
+    );
+    assert_eq!(
+        vrps.insert(vrp("10.0.0.0/8", 33, 65000)?),
+        Err(PrefixStoreError::PrefixLengthInvalid)
+    );
+
+    assert!(VrpTable::from_csv_reader(
+        "AS65000,192.0.2.0/24,24\nAS65000,192.0.2.0/24\n".as_bytes()
+    )
+    .is_err());
+    assert!(VrpTable::from_csv_reader(
+        "AS65000,192.0.2.0/24,24\nASx,192.0.2.0/24,24\n".as_bytes()
+    )
+    .is_err());
+
+    Ok(())
+}
+
+rotonda_store::all_strategies![
+    test_rov_1;
+    test_match_prefix_with_rov;
+    PrefixAs
+];
+
+fn test_match_prefix_with_rov<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let vrps = VrpTable::from_csv_reader(VRPS_CSV.as_bytes())?;
+    for (pfx, mui, asn) in [
+        ("198.51.100.0/22", 1, 65001),
+        ("198.51.100.0/22", 2, 65666),
+        ("198.51.100.0/24", 1, 65001),
+        ("198.51.100.0/25", 1, 65001),
+        ("198.51.0.0/16", 1, 65001),
+    ] {
+        tree_bitmap.insert(
+            &Prefix::from_str(pfx)?,
+            Record::new(
+                mui,
+                1,
+                RouteStatus::Active,
+                PrefixAs::new_from_u32(asn),
+            ),
+            None,
+        )?;
+    }
+
+    let res = tree_bitmap.match_prefix_with_rov(
+        &Prefix::from_str("198.51.100.0/22")?,
+        &MatchOptions {
+            match_type: MatchType::ExactMatch,
+            include_withdrawn: false,
+            include_less_specifics: true,
+            include_more_specifics: true,
+            mui: None,
+            include_history: IncludeHistory::None,
+        },
+        &vrps,
+        &epoch::pin(),
+    )?;
+
+    let mut records = res
+        .records
+        .iter()
+        .map(|r| (r.record.multi_uniq_id, r.rov_status))
+        .collect::<Vec<_>>();
+    records.sort_by_key(|(mui, _)| *mui);
+    assert_eq!(
+        records,
+        vec![(1, RovStatus::Valid), (2, RovStatus::Invalid)]
+    );
+
+    let mut more_specifics = res
+        .more_specifics
+        .ok_or("no more specifics")?
+        .iter()
+        .flat_map(|pr| {
+            pr.records
+                .iter()
+                .map(|r| (pr.prefix.to_string(), r.rov_status))
+        })
+        .collect::<Vec<_>>();
+    more_specifics.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        more_specifics,
+        vec![
+            ("198.51.100.0/24".to_string(), RovStatus::Valid),
+            ("198.51.100.0/25".to_string(), RovStatus::Invalid),
+        ]
+    );
+
+    let less_specifics = res
+        .less_specifics
+        .ok_or("no less specifics")?
+        .iter()
+        .flat_map(|pr| {
+            pr.records
+                .iter()
+                .map(|r| (pr.prefix.to_string(), r.rov_status))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        less_specifics,
+        vec![("198.51.0.0/16".to_string(), RovStatus::NotFound)]
+    );
+
+    Ok(())
+}
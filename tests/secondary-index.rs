@@ -0,0 +1,308 @@
+use inetnum::addr::Prefix;
+use inetnum::asn::Asn;
+use rotonda_store::{
+    epoch,
+    errors::PrefixStoreError,
+    prefix_record::{Meta, RouteStatus},
+    rib::{
+        config::{
+            Config, MemoryOnlyConfig, MetaConfig, PersistHistoryConfig,
+            PersistOnlyConfig, WriteAheadConfig,
+        },
+        index::SecondaryIndex,
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+mod common;
+
+use common::record;
+
+// An index on the origin AS of a route.
+struct OriginIndex;
+
+impl SecondaryIndex<PrefixAs> for OriginIndex {
+    type Key = Asn;
+
+    fn keys(&self, meta: &PrefixAs) -> Vec<Asn> {
+        meta.origin_asn().into_iter().collect()
+    }
+}
+
+// Create a fresh, stable store directory for a test.
+fn store_dir(name: &str) -> String {
+    let dir = format!("/tmp/rotonda/index-{}-{}", name, std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn insert_prefixes<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    for i in 0..50_u32 {
+        let v4 = Prefix::new(
+            IpAddr::V4(Ipv4Addr::from(0x0a00_0000 | (i << 16))),
+            16,
+        )?;
+        let v6 = Prefix::new(
+            IpAddr::V6(Ipv6Addr::from(
+                (0x2001_0db8_u128 << 96) | (i as u128) << 80,
+            )),
+            48,
+        )?;
+        for mui in 0..=(i % 3) {
+            tree_bitmap.insert(&v4, record(mui, 1, 65000 + i % 5), None)?;
+            tree_bitmap.insert(
+                &v6,
+                record(mui, 1, 65000 + (i + mui) % 5),
+                None,
+            )?;
+        }
+    }
+    tree_bitmap.insert(
+        &Prefix::from_str("0.0.0.0/0")?,
+        record(1, 1, 1),
+        None,
+    )?;
+
+    Ok(())
+}
+
+// The (prefix, mui) pairs for an origin AS, the hard way.
+fn scan<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    asn: u32,
+) -> Result<Vec<(Prefix, u32)>, Box<dyn Error>> {
+    let guard = &epoch::pin();
+    let mut pairs = vec![];
+    for pr in tree_bitmap.prefixes_iter_sorted(guard) {
+        let pr = pr?;
+        let mut muis = pr
+            .meta
+            .iter()
+            .filter(|r| r.status == RouteStatus::Active)
+            .filter(|r| r.meta.asn() == Asn::from_u32(asn))
+            .map(|r| r.multi_uniq_id)
+            .collect::<Vec<_>>();
+        muis.sort();
+        pairs.extend(muis.into_iter().map(|mui| (pr.prefix, mui)));
+    }
+    Ok(pairs)
+}
+
+fn lookup<C: Config>(
+    tree_bitmap: &StarCastRib<PrefixAs, C>,
+    asn: u32,
+    include_withdrawn: bool,
+) -> Result<Vec<(Prefix, u32)>, Box<dyn Error>> {
+    let mut pairs = vec![];
+    for pr in tree_bitmap.lookup_index(
+        "origin",
+        &Asn::from_u32(asn),
+        include_withdrawn,
+    )? {
+        let mut muis =
+            pr.meta.iter().map(|r| r.multi_uniq_id).collect::<Vec<_>>();
+        muis.sort();
+        pairs.extend(muis.into_iter().map(|mui| (pr.prefix, mui)));
+    }
+    Ok(pairs)
+}
+
+#[test]
+fn test_index_all_strategies() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    test_index(MemoryOnlyConfig)?;
+    test_index(PersistOnlyConfig::default())?;
+    test_index(PersistHistoryConfig::default())?;
+    test_index(WriteAheadConfig::default())
+}
+
+fn test_index<C: Config>(config: C) -> Result<(), Box<dyn Error>> {
+    let mut meta_config = MetaConfig::default();
+    meta_config.add_index("origin", OriginIndex);
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        config,
+        meta_config,
+    )?;
+    insert_prefixes(&tree_bitmap)?;
+
+    for asn in 65000..65005 {
+        let expected = scan(&tree_bitmap, asn)?;
+        assert!(!expected.is_empty());
+        assert_eq!(lookup(&tree_bitmap, asn, false)?, expected);
+    }
+    assert_eq!(
+        lookup(&tree_bitmap, 1, false)?,
+        vec![(Prefix::from_str("0.0.0.0/0")?, 1)]
+    );
+    assert!(lookup(&tree_bitmap, 65005, false)?.is_empty());
+
+    // A new origin for a record moves it to another key.
+    let pfx = Prefix::from_str("10.2.0.0/16")?;
+    assert!(lookup(&tree_bitmap, 65002, false)?.contains(&(pfx, 1)));
+    tree_bitmap.insert(&pfx, record(1, 1, 65005), None)?;
+    assert!(!lookup(&tree_bitmap, 65002, false)?.contains(&(pfx, 1)));
+    assert_eq!(lookup(&tree_bitmap, 65005, false)?, vec![(pfx, 1)]);
+
+    // Withdrawn records are only returned on request.
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 2)?;
+    assert!(lookup(&tree_bitmap, 65005, false)?.is_empty());
+    assert_eq!(lookup(&tree_bitmap, 65005, true)?, vec![(pfx, 1)]);
+    tree_bitmap.mark_mui_as_active_for_prefix(&pfx, 1, 3)?;
+    assert_eq!(lookup(&tree_bitmap, 65005, false)?, vec![(pfx, 1)]);
+
+    // Removed records are gone from the index.
+    tree_bitmap.remove(&pfx, 1)?;
+    assert!(lookup(&tree_bitmap, 65005, true)?.is_empty());
+    let pfx = Prefix::from_str("10.4.0.0/16")?;
+    tree_bitmap.remove_prefix(&pfx)?;
+    assert!(lookup(&tree_bitmap, 65004, true)?
+        .iter()
+        .all(|(p, _)| *p != pfx));
+    tree_bitmap.purge_mui(2)?;
+    for asn in 65000..65005 {
+        let pairs = lookup(&tree_bitmap, asn, true)?;
+        assert!(pairs.iter().all(|(_, mui)| *mui != 2));
+        assert_eq!(pairs, scan(&tree_bitmap, asn)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_index_registration() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.add_index("origin", OriginIndex);
+    meta_config.add_index("private", |meta: &PrefixAs| {
+        vec![(64512..=65534).contains(&meta.asn().into_u32())]
+    });
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config.clone(),
+    )?;
+    insert_prefixes(&tree_bitmap)?;
+    tree_bitmap.insert(
+        &Prefix::from_str("192.0.2.0/24")?,
+        record(7, 1, 64496),
+        None,
+    )?;
+
+    // Indexes from closures are found by the type of their keys.
+    let public = tree_bitmap.lookup_index("private", &false, false)?;
+    assert_eq!(public.len(), 2);
+    assert_eq!(public[0].prefix, Prefix::from_str("0.0.0.0/0")?);
+    assert_eq!(public[1].prefix, Prefix::from_str("192.0.2.0/24")?);
+
+    // Global withdrawals are honoured.
+    tree_bitmap.mark_mui_as_withdrawn(7)?;
+    assert_eq!(tree_bitmap.lookup_index("private", &false, false)?.len(), 1);
+
+    // Unknown names, and known names with another type of keys.
+    assert_eq!(
+        tree_bitmap
+            .lookup_index("peer", &Asn::from_u32(1), false)
+            .err(),
+        Some(PrefixStoreError::IndexNotFound)
+    );
+    assert_eq!(
+        tree_bitmap.lookup_index("origin", &true, false).err(),
+        Some(PrefixStoreError::IndexNotFound)
+    );
+
+    // Every RIB gets its own indexes.
+    let other = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    assert!(lookup(&other, 65000, true)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_index_reopen() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(store_dir("reopen"));
+    let mut meta_config = MetaConfig::default();
+    meta_config.add_index("origin", OriginIndex);
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create_with_meta_config(
+            config.clone(),
+            meta_config.clone(),
+        )?;
+    insert_prefixes(&tree_bitmap)?;
+    tree_bitmap.flush_to_disk()?;
+    drop(tree_bitmap);
+
+    // The indexes are filled with the restored records.
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open_with_meta_config(
+        config,
+        meta_config,
+    )?;
+    for asn in 65000..65005 {
+        let expected = scan(&tree_bitmap, asn)?;
+        assert!(!expected.is_empty());
+        assert_eq!(lookup(&tree_bitmap, asn, false)?, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_index_concurrent() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.add_index("origin", OriginIndex);
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    let pfx = Prefix::from_str("10.0.0.0/16")?;
+
+    // Every thread writes its own origin for the same records, the index
+    // ends up with the origins of the records that were written last.
+    std::thread::scope(|s| {
+        for t in 0..8_u32 {
+            let tree_bitmap = &tree_bitmap;
+            s.spawn(move || {
+                for i in 0..500_u32 {
+                    tree_bitmap
+                        .insert(
+                            &pfx,
+                            record(i % 4, i as u64, 65000 + t),
+                            None,
+                        )
+                        .unwrap();
+                }
+            });
+        }
+        let tree_bitmap = &tree_bitmap;
+        s.spawn(move || {
+            for _ in 0..50 {
+                tree_bitmap.purge_mui(3).unwrap();
+            }
+        });
+    });
+
+    for asn in 65000..65008 {
+        assert_eq!(
+            lookup(&tree_bitmap, asn, true)?,
+            scan(&tree_bitmap, asn)?
+        );
+    }
+
+    Ok(())
+}
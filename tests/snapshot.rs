@@ -0,0 +1,200 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{
+            Config, HybridConfig, MemoryOnlyConfig, PersistHistoryConfig,
+            PersistOnlyConfig, PersistStrategy, WriteAheadConfig,
+        },
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+mod common;
+
+use common::records;
+
+fn snapshot_and_restore<C: Config + Sync>(
+    mut config: C,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let base = format!(
+        "/tmp/rotonda/snapshot-{:?}-{}",
+        config.persist_strategy(),
+        std::process::id()
+    );
+    let _ = std::fs::remove_dir_all(&base);
+    let snapshot_dir = format!("{}/snapshot", base);
+
+    config.set_persist_path(format!("{}/store", base));
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::open_or_create(config.clone())?;
+
+    let prefixes = [
+        Prefix::from_str("185.49.140.0/22")?,
+        Prefix::from_str("185.49.140.0/24")?,
+        Prefix::from_str("2a04:b900::/29")?,
+    ];
+    for (ltime, pfx) in prefixes.iter().enumerate() {
+        for mui in [1, 2] {
+            for version in [0, 10] {
+                tree_bitmap.insert(
+                    pfx,
+                    Record::new(
+                        mui,
+                        ltime as u64 + version,
+                        RouteStatus::Active,
+                        PrefixAs::new_from_u32(mui + version as u32),
+                    ),
+                    None,
+                )?;
+            }
+        }
+    }
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&prefixes[0], 1, 20)?;
+    tree_bitmap.mark_mui_as_withdrawn(2)?;
+
+    // Keep on inserting while the snapshot is taken.
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..=255 {
+                tree_bitmap
+                    .insert(
+                        &Prefix::new(Ipv4Addr::new(10, 0, i, 0).into(), 24)
+                            .unwrap(),
+                        Record::new(
+                            3,
+                            1,
+                            RouteStatus::Active,
+                            PrefixAs::new_from_u32(3),
+                        ),
+                        None,
+                    )
+                    .unwrap();
+            }
+        });
+        tree_bitmap.snapshot(&snapshot_dir)
+    })
+    .map_err(|e| e.to_string())?;
+
+    // The destination of a snapshot has to be empty.
+    assert!(tree_bitmap.snapshot(&snapshot_dir).is_err());
+
+    let mut restore_config = config.clone();
+    restore_config.set_persist_path(format!("{}/restored", base));
+    let restored =
+        StarCastRib::<PrefixAs, _>::restore(&snapshot_dir, restore_config)?;
+
+    for pfx in &prefixes {
+        assert!(!records(&restored, pfx)?.is_empty());
+        assert_eq!(records(&restored, pfx)?, records(&tree_bitmap, pfx)?);
+    }
+    assert!(restored.mui_is_withdrawn_v4(2));
+    assert!(restored.mui_is_withdrawn_v6(2));
+    assert!(!restored.mui_is_withdrawn_v4(1));
+    assert!(
+        restored.prefixes_count().total() >= prefixes.len(),
+        "{}",
+        restored.prefixes_count().total()
+    );
+
+    if matches!(
+        config.persist_strategy(),
+        PersistStrategy::WriteAhead | PersistStrategy::PersistHistory
+    ) {
+        let history = |rib: &StarCastRib<PrefixAs, C>| {
+            rib.history_for_mui(1, ..20)
+                .map(|r| r.map(|(p, rec)| (p, rec.ltime)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        assert!(!history(&restored)?.is_empty());
+        assert_eq!(history(&restored)?, history(&tree_bitmap)?);
+    }
+
+    // The snapshot is left untouched, but a store cannot be overwritten.
+    assert!(
+        StarCastRib::<PrefixAs, _>::restore(&snapshot_dir, config).is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_write_ahead() -> Result<(), Box<dyn Error>> {
+    snapshot_and_restore(WriteAheadConfig::default())
+}
+
+#[test]
+fn test_snapshot_persist_history() -> Result<(), Box<dyn Error>> {
+    snapshot_and_restore(PersistHistoryConfig::default())
+}
+
+#[test]
+fn test_snapshot_persist_only() -> Result<(), Box<dyn Error>> {
+    snapshot_and_restore(PersistOnlyConfig::default())
+}
+
+#[test]
+fn test_snapshot_hybrid() -> Result<(), Box<dyn Error>> {
+    // Small enough to evict some of the records while inserting.
+    let mut config = HybridConfig::default();
+    config.set_memory_budget(2 * 1024);
+    snapshot_and_restore(config)
+}
+
+#[test]
+fn test_snapshot_errors() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let base = format!("/tmp/rotonda/snapshot-errors-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&base);
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    assert!(tree_bitmap.snapshot(format!("{}/memory", base)).is_err());
+
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(format!("{}/store", base));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::open_or_create(config)?;
+    tree_bitmap.snapshot(format!("{}/snapshot", base))?;
+
+    // Another strategy than the snapshot was taken with.
+    let mut config = PersistHistoryConfig::default();
+    config.set_persist_path(format!("{}/restored", base));
+    assert!(StarCastRib::<PrefixAs, _>::restore(
+        format!("{}/snapshot", base),
+        config.clone()
+    )
+    .is_err());
+
+    // No snapshot at all.
+    assert!(StarCastRib::<PrefixAs, _>::restore(
+        format!("{}/nothing", base),
+        config
+    )
+    .is_err());
+
+    // A manifest that does not match the records.
+    let manifest = format!("{}/snapshot/manifest.json", base);
+    let contents = std::fs::read_to_string(&manifest)?;
+    assert!(contents.contains("\"routes_count\": 0"));
+    std::fs::write(
+        &manifest,
+        contents.replacen("\"routes_count\": 0", "\"routes_count\": 1", 1),
+    )?;
+    let mut config = WriteAheadConfig::default();
+    config.set_persist_path(format!("{}/damaged", base));
+    assert!(StarCastRib::<PrefixAs, _>::restore(
+        format!("{}/snapshot", base),
+        config
+    )
+    .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    prefix_record::{Record, RouteStatus},
+    rib::{config::Config, StarCastRib},
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+mod common;
+
+// A spread of prefixes of all lengths, with many more specifics inside
+// 10.0.0.0/8 and 2001:db8::/32, generated with a simple linear congruential
+// generator, so that the test is deterministic.
+fn prefixes() -> Result<Vec<Prefix>, Box<dyn Error>> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        state
+    };
+
+    let mut prefixes = vec![
+        Prefix::from_str("0.0.0.0/0")?,
+        Prefix::from_str("::/0")?,
+        Prefix::from_str("10.0.0.0/8")?,
+        Prefix::from_str("255.255.255.255/32")?,
+        Prefix::from_str("2001:db8::/32")?,
+    ];
+    for _ in 0..2000 {
+        let r = next();
+        let len = (r % 33) as u8;
+        let mut addr = (next() >> 32) as u32;
+        if r % 2 == 0 {
+            addr = 0x0a00_0000 | (addr & 0x00ff_ffff);
+        }
+        let addr = if len == 0 {
+            0
+        } else {
+            addr & (u32::MAX << (32 - len))
+        };
+        prefixes
+            .push(Prefix::new(IpAddr::V4(Ipv4Addr::from(addr)), len.max(8))?);
+    }
+    for _ in 0..2000 {
+        let r = next();
+        let len = (r % 129).max(32) as u8;
+        let mut addr = (u128::from(next()) << 64) | u128::from(next());
+        if r % 2 == 0 {
+            addr = (0x2001_0db8 << 96) | (addr & (u128::MAX >> 32));
+        }
+        let addr = addr & (u128::MAX << (128 - len as u32));
+        prefixes.push(Prefix::new(IpAddr::V6(Ipv6Addr::from(addr)), len)?);
+    }
+
+    Ok(prefixes)
+}
+
+// The canonical order: by address, and for the same address by length.
+fn sorted(mut prefixes: Vec<Prefix>) -> Vec<Prefix> {
+    prefixes.sort_by_key(|p| (p.addr(), p.len()));
+    prefixes.dedup();
+    prefixes
+}
+
+rotonda_store::all_strategies![
+    test_sorted_1;
+    test_sorted_iterators;
+    PrefixAs
+];
+
+fn test_sorted_iterators<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let prefixes = prefixes()?;
+    for pfx in &prefixes {
+        tree_bitmap.insert(
+            pfx,
+            Record::new(1, 1, RouteStatus::Active, PrefixAs::new_from_u32(1)),
+            None,
+        )?;
+    }
+    let guard = &epoch::pin();
+
+    let all = tree_bitmap
+        .prefixes_iter_sorted(guard)
+        .map(|r| r.map(|p| p.prefix))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(all, sorted(prefixes.clone()));
+
+    // The same prefixes as the unordered iterator, that leaves out 0/0.
+    let mut unordered = tree_bitmap
+        .prefixes_iter(guard)
+        .map(|r| r.map(|p| p.prefix))
+        .collect::<Result<Vec<_>, _>>()?;
+    unordered.retain(|p| p.len() != 0);
+    let mut all_but_default = all.clone();
+    all_but_default.retain(|p| p.len() != 0);
+    assert_eq!(sorted(unordered), all_but_default);
+
+    for search_pfx in [
+        "10.0.0.0/8",
+        "10.128.0.0/9",
+        "10.0.0.0/12",
+        "2001:db8::/32",
+        "2001:db8:8000::/33",
+        "0.0.0.0/0",
+    ] {
+        let search_pfx = Prefix::from_str(search_pfx)?;
+        let more_specifics = tree_bitmap
+            .more_specifics_iter_from_sorted(&search_pfx, None, false, guard)
+            .map(|r| r.map(|p| p.prefix))
+            .collect::<Result<Vec<_>, _>>()?;
+        let expected = sorted(
+            prefixes
+                .iter()
+                .filter(|p| search_pfx.covers(**p))
+                .copied()
+                .collect(),
+        );
+        assert!(!expected.is_empty());
+        assert_eq!(more_specifics, expected, "{}", search_pfx);
+    }
+
+    // A prefix without more specifics.
+    assert_eq!(
+        tree_bitmap
+            .more_specifics_iter_from_sorted(
+                &Prefix::from_str("255.255.255.255/32")?,
+                None,
+                false,
+                guard
+            )
+            .count(),
+        1
+    );
+
+    Ok(())
+}
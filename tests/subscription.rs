@@ -0,0 +1,323 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    epoch,
+    prefix_record::RouteStatus,
+    rib::{
+        config::{Config, MemoryOnlyConfig, MetaConfig},
+        subscription::{
+            OverflowPolicy, RibEvent, RibEventKind, SubscriptionFilter,
+        },
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::mpsc::TryRecvError;
+
+mod common;
+
+use common::record;
+
+fn event(
+    kind: RibEventKind,
+    prefix: Option<Prefix>,
+    mui: u32,
+    old_status: Option<RouteStatus>,
+    new_status: RouteStatus,
+    ltime: Option<u64>,
+) -> RibEvent {
+    RibEvent {
+        kind,
+        prefix,
+        mui,
+        old_status,
+        new_status,
+        ltime,
+    }
+}
+
+rotonda_store::all_strategies![
+    test_events_1;
+    test_events;
+    PrefixAs
+];
+
+fn test_events<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    use RibEventKind::*;
+    use RouteStatus::*;
+
+    let sub = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        100,
+        OverflowPolicy::default(),
+    );
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let pfx6 = Prefix::from_str("2a04:b900::/29")?;
+
+    tree_bitmap.insert(&pfx, record(1, 1, 100), None)?;
+    tree_bitmap.insert(&pfx, record(1, 2, 200), None)?;
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 3)?;
+    tree_bitmap.mark_mui_as_active_for_prefix(&pfx, 1, 4)?;
+    tree_bitmap.mark_mui_as_withdrawn(1)?;
+    tree_bitmap.mark_mui_as_active_v4(1)?;
+    let report = tree_bitmap.insert_batch(
+        [(pfx6, record(2, 5, 300)), (pfx6, record(2, 6, 400))],
+        None,
+    );
+    assert!(report.errors.is_empty());
+
+    assert_eq!(
+        sub.try_iter().collect::<Vec<_>>(),
+        vec![
+            event(Insert, Some(pfx), 1, None, Active, Some(1)),
+            event(Insert, Some(pfx), 1, Some(Active), Active, Some(2)),
+            event(
+                StatusChange,
+                Some(pfx),
+                1,
+                Some(Active),
+                Withdrawn,
+                Some(3)
+            ),
+            event(
+                StatusChange,
+                Some(pfx),
+                1,
+                Some(Withdrawn),
+                Active,
+                Some(4)
+            ),
+            event(MuiWithdrawn, None, 1, Some(Active), Withdrawn, None),
+            event(MuiActivated, None, 1, Some(Withdrawn), Active, None),
+            event(Insert, Some(pfx6), 2, None, Active, Some(5)),
+            event(Insert, Some(pfx6), 2, Some(Active), Active, Some(6)),
+        ]
+    );
+
+    // A status change for a record that does not exist does not send an
+    // event.
+    let _ = tree_bitmap.mark_mui_as_withdrawn_for_prefix(
+        &Prefix::from_str("192.0.2.0/24")?,
+        1,
+        7,
+    );
+    assert_eq!(sub.try_recv(), Err(TryRecvError::Empty));
+
+    Ok(())
+}
+
+#[test]
+fn test_filters() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let covering = Prefix::from_str("10.0.0.0/8")?;
+    let sub = tree_bitmap.subscribe(
+        SubscriptionFilter {
+            covering_prefix: Some(covering),
+            mui: Some(2),
+        },
+        100,
+        OverflowPolicy::DropNewest,
+    );
+
+    let more_specific = Prefix::from_str("10.1.0.0/16")?;
+    tree_bitmap.insert(&more_specific, record(1, 1, 100), None)?;
+    tree_bitmap.insert(&more_specific, record(2, 1, 100), None)?;
+    tree_bitmap.insert(&covering, record(2, 1, 100), None)?;
+    tree_bitmap.insert(
+        &Prefix::from_str("11.0.0.0/8")?,
+        record(2, 1, 100),
+        None,
+    )?;
+    tree_bitmap.insert(
+        &Prefix::from_str("0.0.0.0/0")?,
+        record(2, 1, 100),
+        None,
+    )?;
+    tree_bitmap.mark_mui_as_withdrawn_v6(2)?;
+    tree_bitmap.mark_mui_as_withdrawn_v4(2)?;
+
+    let events = sub.try_iter().collect::<Vec<_>>();
+    assert_eq!(
+        events
+            .iter()
+            .map(|e| (e.kind, e.prefix, e.mui))
+            .collect::<Vec<_>>(),
+        vec![
+            (RibEventKind::Insert, Some(more_specific), 2),
+            (RibEventKind::Insert, Some(covering), 2),
+            (RibEventKind::MuiWithdrawn, None, 2),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_overflow() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let drop_newest = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        2,
+        OverflowPolicy::DropNewest,
+    );
+    let disconnect = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        2,
+        OverflowPolicy::Disconnect,
+    );
+    let dropped = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        2,
+        OverflowPolicy::Disconnect,
+    );
+    drop(dropped);
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    for ltime in 1..=5 {
+        tree_bitmap.insert(&pfx, record(1, ltime, 100), None)?;
+    }
+
+    // The oldest events are kept, the newest ones dropped.
+    let ltimes = drop_newest.try_iter().map(|e| e.ltime).collect::<Vec<_>>();
+    assert_eq!(ltimes, vec![Some(1), Some(2)]);
+    assert_eq!(drop_newest.missed_events(), 3);
+    assert!(!drop_newest.is_overflowed());
+
+    // The subscription stays open.
+    tree_bitmap.insert(&pfx, record(1, 6, 100), None)?;
+    assert_eq!(drop_newest.try_recv()?.ltime, Some(6));
+
+    // The events in the channel can still be received, after that the
+    // subscription is closed.
+    assert!(disconnect.is_overflowed());
+    assert_eq!(disconnect.recv()?.ltime, Some(1));
+    assert_eq!(disconnect.recv()?.ltime, Some(2));
+    assert!(disconnect.recv().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_best_path_change() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let tree_bitmap =
+        StarCastRib::<PrefixAs, _>::new_with_config(MemoryOnlyConfig)?;
+    let sub = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        100,
+        OverflowPolicy::DropNewest,
+    );
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+
+    let best_path_changes = || {
+        sub.try_iter()
+            .filter_map(|e| match e.kind {
+                RibEventKind::BestPathChange { previous } => {
+                    Some((previous, e.mui))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The best path is only selected on insert for existing prefixes.
+    tree_bitmap.insert(&pfx, record(1, 1, 100), Some(()))?;
+    assert_eq!(best_path_changes(), vec![]);
+    tree_bitmap.calculate_and_store_best_and_backup_path(
+        &pfx,
+        &(),
+        &epoch::pin(),
+    )?;
+    assert_eq!(best_path_changes(), vec![(None, 1)]);
+
+    // The best path does not change.
+    tree_bitmap.insert(&pfx, record(1, 2, 100), Some(()))?;
+    assert_eq!(best_path_changes(), vec![]);
+
+    tree_bitmap.insert(&pfx, record(2, 3, 50), Some(()))?;
+    let best = tree_bitmap
+        .best_path(&pfx, &epoch::pin())
+        .ok_or("no best path")??
+        .multi_uniq_id;
+    assert_eq!(best, 2);
+    assert_eq!(best_path_changes(), vec![(Some(1), 2)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_best_path_lost() -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let mut meta_config = MetaConfig::default();
+    meta_config.set_auto_path_selection(Some(()));
+    let tree_bitmap = StarCastRib::<PrefixAs, _>::new_with_meta_config(
+        MemoryOnlyConfig,
+        meta_config,
+    )?;
+    let sub = tree_bitmap.subscribe(
+        SubscriptionFilter::default(),
+        100,
+        OverflowPolicy::DropNewest,
+    );
+    let best_path_events = || {
+        sub.try_iter()
+            .filter(|e| {
+                matches!(
+                    e.kind,
+                    RibEventKind::BestPathChange { .. }
+                        | RibEventKind::BestPathLost
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let other = Prefix::from_str("185.49.144.0/22")?;
+
+    tree_bitmap.insert(&pfx, record(1, 1, 100), None)?;
+    tree_bitmap.insert(&other, record(1, 1, 100), None)?;
+    assert_eq!(best_path_events().len(), 2);
+
+    // A withdrawal of the only record loses the best path.
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 2)?;
+    assert_eq!(
+        best_path_events(),
+        vec![event(
+            RibEventKind::BestPathLost,
+            Some(pfx),
+            1,
+            None,
+            RouteStatus::Withdrawn,
+            None
+        )]
+    );
+
+    // And so does the removal of the only record.
+    tree_bitmap.remove(&other, 1)?;
+    assert_eq!(
+        best_path_events(),
+        vec![event(
+            RibEventKind::BestPathLost,
+            Some(other),
+            1,
+            None,
+            RouteStatus::Withdrawn,
+            None
+        )]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use inetnum::addr::Prefix;
+use rotonda_store::{
+    match_options::{IncludeHistory, MatchOptions, MatchType, QueryResult},
+    prefix_record::{Record, RouteStatus},
+    rib::{
+        config::{Config, PersistStrategy},
+        StarCastRib,
+    },
+    test_types::PrefixAs,
+};
+
+use std::error::Error;
+use std::str::FromStr;
+
+mod common;
+
+rotonda_store::all_strategies![
+    test_tt_1;
+    test_time_travel;
+    PrefixAs
+];
+
+// The (mui, asn, status) triples in the records of a query result.
+fn records(res: &QueryResult<PrefixAs>) -> Vec<(u32, PrefixAs, RouteStatus)> {
+    let mut recs = res
+        .records
+        .iter()
+        .map(|r| (r.multi_uniq_id, r.meta, r.status))
+        .collect::<Vec<_>>();
+    recs.sort_by_key(|r| r.0);
+    recs
+}
+
+fn test_time_travel<C: Config>(
+    tree_bitmap: StarCastRib<PrefixAs, C>,
+) -> Result<(), Box<dyn Error>> {
+    crate::common::init();
+
+    let pfx = Prefix::from_str("185.49.140.0/22")?;
+    let more_specific = Prefix::from_str("185.49.140.0/24")?;
+    let asn = PrefixAs::new_from_u32;
+
+    for (p, mui, ltime, a) in [
+        (pfx, 1, 10, 100),
+        (more_specific, 1, 12, 120),
+        (pfx, 2, 15, 150),
+        (pfx, 1, 20, 200),
+    ] {
+        tree_bitmap.insert(
+            &p,
+            Record::new(mui, ltime, RouteStatus::Active, asn(a)),
+            None,
+        )?;
+    }
+    tree_bitmap.mark_mui_as_withdrawn_for_prefix(&pfx, 1, 25)?;
+    tree_bitmap.insert(
+        &pfx,
+        Record::new(1, 30, RouteStatus::Active, asn(300)),
+        None,
+    )?;
+
+    let mut options = MatchOptions {
+        match_type: MatchType::ExactMatch,
+        include_withdrawn: false,
+        include_less_specifics: false,
+        include_more_specifics: false,
+        mui: None,
+        include_history: IncludeHistory::None,
+    };
+
+    // The current state is the same for all strategies.
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 35)?;
+    assert_eq!(res.match_type, MatchType::ExactMatch);
+    assert_eq!(
+        records(&res),
+        vec![
+            (1, asn(300), RouteStatus::Active),
+            (2, asn(150), RouteStatus::Active)
+        ]
+    );
+
+    // Nothing at all yet.
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 5)?;
+    assert_eq!(res.match_type, MatchType::EmptyMatch);
+    assert!(res.prefix.is_none());
+
+    match tree_bitmap.persist_strategy() {
+        PersistStrategy::WriteAhead | PersistStrategy::PersistHistory => {}
+        PersistStrategy::MemoryOnly
+        | PersistStrategy::PersistOnly
+        | PersistStrategy::Hybrid => {
+            // Only the current records are known.
+            let res = tree_bitmap.match_prefix_at(&pfx, &options, 16)?;
+            assert_eq!(
+                records(&res),
+                vec![(2, asn(150), RouteStatus::Active)]
+            );
+            return Ok(());
+        }
+    }
+
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 12)?;
+    assert_eq!(records(&res), vec![(1, asn(100), RouteStatus::Active)]);
+
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 16)?;
+    assert_eq!(
+        records(&res),
+        vec![
+            (1, asn(100), RouteStatus::Active),
+            (2, asn(150), RouteStatus::Active)
+        ]
+    );
+
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 22)?;
+    assert_eq!(
+        records(&res),
+        vec![
+            (1, asn(200), RouteStatus::Active),
+            (2, asn(150), RouteStatus::Active)
+        ]
+    );
+
+    // Withdrawn at 25.
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 26)?;
+    assert_eq!(records(&res), vec![(2, asn(150), RouteStatus::Active)]);
+
+    options.include_withdrawn = true;
+    options.mui = Some(1);
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 26)?;
+    assert_eq!(records(&res), vec![(1, asn(200), RouteStatus::Withdrawn)]);
+
+    // The more specific didn't exist yet at 11, so the longest match is the
+    // less specific.
+    options.match_type = MatchType::LongestMatch;
+    options.include_more_specifics = true;
+    let res = tree_bitmap.match_prefix_at(&more_specific, &options, 11)?;
+    assert_eq!(res.match_type, MatchType::LongestMatch);
+    assert_eq!(res.prefix, Some(pfx));
+    assert_eq!(records(&res), vec![(1, asn(100), RouteStatus::Active)]);
+
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 11)?;
+    assert_eq!(res.more_specifics.map(|ms| ms.len()), Some(0));
+    let res = tree_bitmap.match_prefix_at(&pfx, &options, 12)?;
+    assert_eq!(res.more_specifics.map(|ms| ms.len()), Some(1));
+
+    let all = tree_bitmap
+        .prefixes_iter_at(11)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].prefix, pfx);
+    assert_eq!(all[0].meta[0].meta, asn(100));
+
+    let all = tree_bitmap
+        .prefixes_iter_at(26)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(all.len(), 2);
+
+    Ok(())
+}